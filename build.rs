@@ -1,6 +1,117 @@
-use std::{env, fs, process};
+use std::{
+    env, fs,
+    path::Path,
+    process::{self, Command},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// User programs in `user/` that the kernel embeds.
+const USER_PROGRAMS: [&str; 1] = ["args"];
+
+/// Build a user program into an ELF file in `OUT_DIR`.
+fn build_user_program(name: &str) {
+    let manifest_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+    let user_dir = Path::new(&manifest_dir).join("user");
+    let out_dir = env::var("OUT_DIR").unwrap();
+
+    let source = user_dir.join(format!("{}.rs", name));
+    let status = Command::new(env::var("RUSTC").unwrap())
+        .arg("--target")
+        .arg(env::var("TARGET").unwrap())
+        .args(["--edition", "2021", "--crate-type", "bin"])
+        .args([
+            "-C",
+            "opt-level=s",
+            "-C",
+            "panic=abort",
+            "-C",
+            "strip=debuginfo",
+        ])
+        .arg("-C")
+        .arg(format!(
+            "link-arg=--script={}",
+            user_dir.join("user.ld").display()
+        ))
+        // Segments start on pages of their own in memory, but need no padding in the file.
+        .args(["-C", "link-arg=-zmax-page-size=4096"])
+        .arg("-o")
+        .arg(Path::new(&out_dir).join(name))
+        .arg(&source)
+        .status()
+        .unwrap();
+
+    if !status.success() {
+        panic!("Building user program {} failed", name);
+    }
+
+    println!("cargo:rerun-if-changed={}", source.display());
+}
+
+/// Hand the chainloader's Ed25519 public key to the kernel, hex encoded. The key file, named by
+/// `CHAINLOADER_PUBLIC_KEY`, holds the key in hex as written by `common/serial/sign.rb`.
+fn embed_chainloader_public_key() {
+    println!("cargo:rerun-if-env-changed=CHAINLOADER_PUBLIC_KEY");
+
+    let key = match env::var("CHAINLOADER_PUBLIC_KEY") {
+        Ok(path) if !path.is_empty() => {
+            println!("cargo:rerun-if-changed={}", path);
+
+            let key = fs::read_to_string(&path)
+                .unwrap_or_else(|e| panic!("Reading the public key {} failed: {}", path, e));
+            let key = key.trim().to_string();
+            if key.len() != 64 || !key.chars().all(|c| c.is_ascii_hexdigit()) {
+                panic!("{} does not hold a hex encoded Ed25519 public key", path);
+            }
+
+            key
+        }
+        // Only a development build of the chainloader, which takes unsigned images, can do without.
+        _ if env::var_os("CARGO_FEATURE_CHAINLOADER").is_some()
+            && env::var_os("CARGO_FEATURE_CHAINLOADER_UNSIGNED").is_none() =>
+        {
+            panic!(
+                "The chainloader needs CHAINLOADER_PUBLIC_KEY, or the chainloader_unsigned feature"
+            )
+        }
+        _ => String::new(),
+    };
+
+    println!("cargo:rustc-env=RPOS_CHAINLOADER_PUBLIC_KEY={}", key);
+}
 
 fn main() {
+    // Build timestamp as a fallback for the kernel's wall clock. Honor SOURCE_DATE_EPOCH for
+    // reproducible builds.
+    let build_unix_time = match env::var("SOURCE_DATE_EPOCH") {
+        Ok(var) => var,
+        _ => SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            .to_string(),
+    };
+    println!("cargo:rustc-env=RPOS_BUILD_UNIX_TIME={}", build_unix_time);
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+
+    // Upper bound for the kernel heap in percent of RAM.
+    let heap_ram_percent = env::var("HEAP_RAM_PERCENT").unwrap_or_else(|_| "50".into());
+    match heap_ram_percent.parse::<u8>() {
+        Ok(1..=90) => (),
+        _ => panic!("HEAP_RAM_PERCENT must be between 1 and 90"),
+    }
+    println!("cargo:rustc-env=RPOS_HEAP_RAM_PERCENT={}", heap_ram_percent);
+    println!("cargo:rerun-if-env-changed=HEAP_RAM_PERCENT");
+
+    embed_chainloader_public_key();
+
+    // Only the demos embed the user programs.
+    if env::var_os("CARGO_FEATURE_DEMOS").is_some() {
+        for name in USER_PROGRAMS {
+            build_user_program(name);
+        }
+        println!("cargo:rerun-if-changed=user/user.ld");
+    }
+
     let ld_script_path = match env::var("LD_SCRIPT_PATH") {
         Ok(var) => var,
         _ => process::exit(0),
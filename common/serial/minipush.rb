@@ -8,19 +8,47 @@
 require_relative 'miniterm'
 require 'ruby-progressbar'
 require_relative 'minipush/progressbar_patch'
+require 'digest'
+require 'optparse'
 require 'timeout'
+require 'zlib'
 
 class ProtocolError < StandardError; end
 
 # The main class
+#
+# The payload goes out in frames, each one checked with a CRC-32 and acknowledged by the target. See
+# the kernel's `src/console/transfer.rs` for the protocol.
 class MiniPush < MiniTerm
-    def initialize(serial_name, payload_path)
+    SOH = 0x01
+    ACK = 0x06
+    NAK = 0x15
+    CAN = 0x18
+
+    BLOCK_SIZE = 1024
+    FLAG_PLACEMENT = 1
+    SIGNATURE_SIZE = 64
+
+    # Seconds to wait for the target's answer to a frame before sending it again.
+    ANSWER_TIMEOUT = 1
+
+    # Failed attempts in a row after which the transfer is given up.
+    MAX_RETRIES = 16
+
+    def initialize(serial_name, payload_path, placement = nil, signature_path = nil)
         super(serial_name)
 
         @name_short = 'MP' # override
         @payload_path = payload_path
         @payload_size = nil
         @payload_data = nil
+
+        # Load address and entry point, if the target should not use its defaults.
+        @placement = placement
+
+        # Ed25519 signature of the payload, see sign.rb.
+        @signature_path = signature_path
+        @signature = nil
     end
 
     private
@@ -57,11 +85,51 @@ class MiniPush < MiniTerm
     def load_payload
         @payload_size = File.size(@payload_path)
         @payload_data = File.binread(@payload_path)
+
+        return unless @signature_path
+
+        @signature = File.binread(@signature_path)
+        raise ArgumentError, 'Not an Ed25519 signature' if @signature.bytesize != SIGNATURE_SIZE
+    end
+
+    def frame(kind, block, data)
+        body = [kind, block, data.bytesize].pack('aVv') + data
+
+        [SOH].pack('C') + body + [Zlib.crc32(body)].pack('V')
     end
 
-    def send_size
-        @target_serial.print([@payload_size].pack('L<'))
-        raise ProtocolError if @target_serial.read(2) != 'OK'
+    # Kind and data of every block. Block 0 is the header, the last one holds the image hash and the
+    # signature.
+    def blocks
+        load_addr, entry = @placement || [0, 0]
+        flags = @placement ? FLAG_PLACEMENT : 0
+        host_time = Process.clock_gettime(Process::CLOCK_REALTIME, :nanosecond)
+        header = [@payload_size, flags, load_addr, entry, host_time].pack('VVQ<Q<Q<')
+
+        data = (0...@payload_size).step(BLOCK_SIZE).map do |offset|
+            ['D', @payload_data.byteslice(offset, BLOCK_SIZE)]
+        end
+
+        [['H', header]] + data + [['E', Digest::SHA256.digest(@payload_data) + @signature.to_s]]
+    end
+
+    # Wait for the answer to `block`. Returns the answer code and block number, or nil on timeout.
+    #
+    # ACKs for earlier blocks are late answers to frames that were sent again. They are skipped.
+    def read_answer(block)
+        Timeout.timeout(ANSWER_TIMEOUT) do
+            loop do
+                code = @target_serial.getbyte
+                raise ConnectionError if code.nil?
+                raise ProtocolError, 'Target cancelled the transfer' if code == CAN
+                next unless [ACK, NAK].include?(code)
+
+                number = @target_serial.read(4).unpack1('V')
+                return [code, number] unless code == ACK && number < block
+            end
+        end
+    rescue Timeout::Error
+        nil
     end
 
     def send_payload
@@ -73,10 +141,26 @@ class MiniPush < MiniTerm
             output: $stdout
         )
 
-        # Send in 512 byte chunks.
-        while pb.progress < pb.total
-            part = @payload_data.slice(pb.progress, 512)
-            pb.progress += @target_serial.write(part)
+        frames = blocks
+        block = 0
+        retries = 0
+        while block < frames.size
+            kind, data = frames[block]
+            @target_serial.write(frame(kind, block, data))
+
+            code, number = read_answer(block)
+            if code == ACK && number == block
+                block += 1
+                retries = 0
+            else
+                # A NAK says which block the target expects, continue from there.
+                block = number if code == NAK && number < frames.size
+                retries += 1
+                raise ProtocolError, 'Too many transmission errors' if retries > MAX_RETRIES
+            end
+
+            # Data blocks start at 1.
+            pb.progress = ((block - 1) * BLOCK_SIZE).clamp(0, @payload_size)
         end
     end
 
@@ -98,7 +182,6 @@ class MiniPush < MiniTerm
         open_serial
         wait_for_payload_request
         load_payload
-        send_size
         send_payload
         terminal
     rescue ConnectionError, EOFError, Errno::EIO, ProtocolError, Timeout::Error => e
@@ -118,7 +201,7 @@ end
 ##--------------------------------------------------------------------------------------------------
 if __FILE__ == $PROGRAM_NAME
     puts
-    puts 'Minipush 1.0'.cyan
+    puts 'Minipush 2.0'.cyan
     puts
 
     # CTRL + C handler. Only here to suppress Ruby's default exception print.
@@ -127,5 +210,25 @@ if __FILE__ == $PROGRAM_NAME
         exit
     end
 
-    MiniPush.new(ARGV[0], ARGV[1]).run
+    placement = nil
+    signature_path = nil
+    OptionParser.new do |opts|
+        opts.banner = 'Usage: minipush.rb [options] SERIAL PAYLOAD'
+
+        opts.on('--load-addr ADDR', Integer, 'Where the target should put the payload') do |addr|
+            placement = [addr, placement&.last || addr]
+        end
+        opts.on('--entry ADDR', Integer, 'Where the target should start it') do |addr|
+            placement = [placement&.first, addr]
+        end
+        opts.on('--signature FILE', 'Ed25519 signature of the payload, see sign.rb') do |path|
+            signature_path = path
+        end
+    end.parse!
+
+    abort('--entry needs --load-addr') if placement && placement.first.nil?
+    # The signature covers the payload only, so the target refuses a placement for signed images.
+    abort('--load-addr and --entry do not work with --signature') if placement && signature_path
+
+    MiniPush.new(ARGV[0], ARGV[1], placement, signature_path).run
 end
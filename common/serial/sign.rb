@@ -0,0 +1,48 @@
+#!/usr/bin/env ruby
+# frozen_string_literal: true
+
+# Ed25519 keys and signatures for the kernel's chainloader, see `src/chainloader.rs`.
+#
+#   sign.rb genkey PRIVATE_KEY PUBLIC_KEY
+#       Create a key pair. The private key is written as PEM. The public key is written in hex, as
+#       the chainloader build expects it in CHAINLOADER_PUBLIC_KEY.
+#
+#   sign.rb sign PRIVATE_KEY IMAGE SIGNATURE
+#       Write the raw 64 byte signature of IMAGE, for `minipush.rb --signature`.
+#
+# Needs Ruby's openssl 3.0 or newer.
+
+require 'openssl'
+
+def genkey(private_path, public_path)
+    key = OpenSSL::PKey.generate_key('ED25519')
+
+    File.write(private_path, key.private_to_pem, perm: 0o600)
+
+    # The DER encoding ends with the raw 32 byte key.
+    File.write(public_path, "#{key.public_to_der[-32..].unpack1('H*')}\n")
+end
+
+def sign(private_path, image_path, signature_path)
+    key = OpenSSL::PKey.read(File.read(private_path))
+
+    File.binwrite(signature_path, key.sign(nil, File.binread(image_path)))
+end
+
+##--------------------------------------------------------------------------------------------------
+## Execution starts here
+##--------------------------------------------------------------------------------------------------
+if __FILE__ == $PROGRAM_NAME
+    command, *args = ARGV
+
+    case command
+    when 'genkey'
+        abort('Usage: sign.rb genkey PRIVATE_KEY PUBLIC_KEY') unless args.size == 2
+        genkey(*args)
+    when 'sign'
+        abort('Usage: sign.rb sign PRIVATE_KEY IMAGE SIGNATURE') unless args.size == 3
+        sign(*args)
+    else
+        abort('Usage: sign.rb genkey|sign ...')
+    end
+end
@@ -0,0 +1,89 @@
+//! Architectural chainloader code.
+//!
+//! The received kernel expects to start in EL2, like it does after the firmware. The chainloader
+//! runs in EL1 though, so it returns to EL2 with an `hvc`. The EL2 vector table from chainloader.s
+//! takes it to the code that copies the kernel into place and jumps to it.
+//!
+//! # Orientation
+//!
+//! Since arch modules are imported into generic modules using the path attribute, the path of this
+//! file is:
+//!
+//! crate::chainloader::arch_chainloader
+
+use crate::{cpu, exception, memory};
+use aarch64_cpu::registers::*;
+use core::{
+    arch::{asm, global_asm},
+    cell::UnsafeCell,
+    time::Duration,
+};
+use tock_registers::interfaces::Writeable;
+
+// Assembly counterpart to this file.
+global_asm!(include_str!("chainloader.s"));
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Install the EL2 vector table that `boot_payload()` needs.
+///
+/// # Safety
+///
+/// - Must be called in EL2, before the transition to EL1.
+/// - The `bss` section might not be initialized yet. The code must not use or reference it.
+#[inline(always)]
+pub unsafe fn init_el2_handover() {
+    // Provided by chainloader.s.
+    extern "Rust" {
+        static __chainloader_el2_vectors: UnsafeCell<()>;
+    }
+
+    VBAR_EL2.set(__chainloader_el2_vectors.get() as u64);
+}
+
+/// Copy `image` to `load_addr` and start it at `entry` in EL2.
+///
+/// `wall_clock_offset`, the Unix time at uptime zero, is passed on to a kernel of this kind, see
+/// `cpu::boot::handover_time()`.
+///
+/// # Safety
+///
+/// - `init_el2_handover()` must have been called during boot.
+/// - `load_addr` must be 8 byte aligned, and `entry` must point into the copied image.
+/// - `image` and the destination must not overlap, and neither may overlap the chainloader.
+/// - The destination must be mapped in the running kernel's translation tables.
+/// - Nothing of the running kernel is needed afterwards. The other cores must not run.
+/// - The interrupt sources and the interrupt controller should be disabled already. Only the
+///   executing core's timer is taken care of here.
+pub unsafe fn boot_payload(
+    image: &[u8],
+    load_addr: usize,
+    entry: usize,
+    wall_clock_offset: Option<Duration>,
+) -> ! {
+    exception::asynchronous::local_irq_mask();
+
+    // The next kernel must not find an armed timer.
+    CNTP_CTL_EL0.write(CNTP_CTL_EL0::ENABLE::CLEAR + CNTP_CTL_EL0::IMASK::SET);
+
+    // EL2 reads the image with the caches off.
+    memory::cache::clean_dcache_range(image.as_ptr() as usize, image.len());
+
+    // EL2 also writes the destination with the caches off. Stale lines, dirty ones in particular,
+    // must not be left to shadow or overwrite the copy. The copy runs up to 7 bytes past the end.
+    let copy_len = (image.len() + 7) & !7;
+    memory::cache::clean_and_invalidate_dcache_range(load_addr, copy_len);
+
+    asm!(
+        "hvc #0",
+        in("x0") image.as_ptr(),
+        in("x1") image.len(),
+        in("x2") load_addr,
+        in("x3") entry,
+        in("x6") wall_clock_offset.map_or(0, |offset| offset.as_nanos() as u64),
+        in("x7") cpu::boot::HANDOVER_TIME_MAGIC,
+        options(noreturn, nostack)
+    )
+}
@@ -0,0 +1,79 @@
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+.section .text._chainloader
+
+//------------------------------------------------------------------------------
+// The EL2 exception vector table.
+//
+// Only the HVC from boot_payload() is expected. Anything else parks the core.
+//------------------------------------------------------------------------------
+.balign 0x800
+__chainloader_el2_vectors:
+
+// Current exception level, with SP_EL0 and with SP_EL2.
+.rept 8
+	.balign 0x80
+	b	.L_el2_parking_loop
+.endr
+
+// Lower exception level, AArch64, synchronous.
+	.balign 0x80
+	b	__chainloader_handover
+
+// The rest of the lower exception level, AArch64, and all of AArch32.
+.rept 7
+	.balign 0x80
+	b	.L_el2_parking_loop
+.endr
+
+.global	__chainloader_el2_vectors
+
+//------------------------------------------------------------------------------
+// fn __chainloader_handover(image: *const u8, size: usize, load_addr: usize, entry: usize) -> !
+//
+// x6 and x7 carry the wall clock offset and its magic for the image, see boot_payload().
+//------------------------------------------------------------------------------
+__chainloader_handover:
+	// The MMU and caches are off in EL2. boot_payload() cleaned the image to memory and dropped
+	// the destination from the caches beforehand.
+	//
+	// Copy in u64 chunks, up to 7 bytes past the end of the image.
+	add	x4, x0, x1
+
+.L_image_copy_loop:
+	cmp	x0, x4
+	b.hs	.L_image_start
+	ldr	x5, [x0], #8
+	str	x5, [x2], #8
+	b	.L_image_copy_loop
+
+.L_image_start:
+	// Drop instructions of the old image that might still be cached.
+	ic	iallu
+	dsb	sy
+	isb
+
+	// Put EL1 back into its reset state, with the MMU and caches off.
+	mov	x0, #0x0800
+	movk	x0, #0x30d0, lsl #16
+	msr	SCTLR_EL1, x0
+	isb
+
+	// Start the image in EL2, like the firmware does. There is no device tree to pass on. Instead,
+	// x1 and x2 carry the wall clock offset and its magic, which the firmware leaves zero.
+	mov	x0, xzr
+	mov	x1, x6
+	mov	x2, x7
+	mov	x4, xzr
+	mov	x5, xzr
+	mov	x6, xzr
+	mov	x7, xzr
+	br	x3
+
+.L_el2_parking_loop:
+	wfe
+	b	.L_el2_parking_loop
+
+.size	__chainloader_handover, . - __chainloader_handover
+.type	__chainloader_handover, function
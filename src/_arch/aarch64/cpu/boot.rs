@@ -11,22 +11,133 @@
 //!
 //! crate::cpu::boot::arch_boot
 
-use core::arch::global_asm;
+use aarch64_cpu::{asm, registers::*};
+use core::{
+    arch::global_asm,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+use tock_registers::interfaces::Writeable;
 
 // Assembly counterpart to this file.
 global_asm!(
     include_str!("boot.s"),
-    CONST_CORE_ID_MASK = const 0b11
+    CONST_CURRENTEL_EL2 = const 0b1000,
+    CONST_CORE_ID_MASK = const 0b11,
+    CONST_SYS_TIMER_CLOCKSOURCE = const cfg!(feature = "sys_timer_clocksource") as u8
 );
 
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Tells a wall clock offset from a chainloader apart from the zero that the firmware leaves. Spells
+/// `RPOSTIME`.
+pub const HANDOVER_TIME_MAGIC: u64 = u64::from_le_bytes(*b"RPOSTIME");
+
+//--------------------------------------------------------------------------------------------------
+// Global instances
+//--------------------------------------------------------------------------------------------------
+
+/// The wall clock offset in nanoseconds that the chainloader handed over, 0 if none.
+static HANDOVER_TIME: AtomicU64 = AtomicU64::new(0);
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+/// Prepares the transition from EL2 to EL1.
+///
+/// # Safety
+///
+/// - The `bss` section is not initialized yet. The code must not use or reference it in any way.
+/// - The HW state of EL1 must be prepared in a sound way.
+#[inline(always)]
+unsafe fn prepare_el2_to_el1_transition(
+    phys_stack_end_exclusive_addr: u64,
+    el1_entry: unsafe fn() -> !,
+) {
+    // Enable timer counter registers for EL1.
+    CNTHCTL_EL2.write(CNTHCTL_EL2::EL1PCEN::SET + CNTHCTL_EL2::EL1PCTEN::SET);
+
+    // No offset for reading the counters.
+    CNTVOFF_EL2.set(0);
+
+    // Set EL1 execution state to AArch64.
+    HCR_EL2.write(HCR_EL2::RW::EL1IsAarch64);
+
+    // Set up a simulated exception return.
+    //
+    // First, fake a saved program status where all interrupts were masked and SP_EL1 was used as a
+    // stack pointer.
+    SPSR_EL2.write(
+        SPSR_EL2::D::Masked
+            + SPSR_EL2::A::Masked
+            + SPSR_EL2::I::Masked
+            + SPSR_EL2::F::Masked
+            + SPSR_EL2::M::EL1h,
+    );
+
+    // Second, let the link register point to the EL1 entry.
+    ELR_EL2.set(el1_entry as *const () as u64);
+
+    // Set up SP_EL1 (stack pointer), which will be used by EL1 once we "return" to it. Since there
+    // are no plans to ever return to EL2, just re-use the same stack.
+    SP_EL1.set(phys_stack_end_exclusive_addr);
+}
+
 //--------------------------------------------------------------------------------------------------
 // Public Code
 //--------------------------------------------------------------------------------------------------
 
 /// The Rust entry of the `kernel` binary.
 ///
-/// The function is called from the assembly `_start` function.
+/// The function is called from the assembly `_start` function. A chainloader passes its wall clock
+/// offset and `HANDOVER_TIME_MAGIC` along, see `chainloader.s`.
+///
+/// # Safety
+///
+/// - Exception return from EL2 must must continue execution in EL1 with `kernel_init()`.
 #[no_mangle]
-pub unsafe fn _start_rust() -> ! {
-    crate::kernel_init()
+pub unsafe extern "C" fn _start_rust(
+    phys_boot_core_stack_end_exclusive_addr: u64,
+    handover_time: u64,
+    handover_magic: u64,
+) -> ! {
+    // The bss is zeroed already. The store is a plain one with the MMU off.
+    if handover_magic == HANDOVER_TIME_MAGIC {
+        HANDOVER_TIME.store(handover_time, Ordering::Relaxed);
+    }
+
+    // The chainloader comes back to EL2 to start the kernel that it received.
+    #[cfg(feature = "chainloader")]
+    crate::chainloader::init_el2_handover();
+
+    prepare_el2_to_el1_transition(phys_boot_core_stack_end_exclusive_addr, crate::kernel_init);
+
+    // Use `eret` to "return" to EL1. This results in execution of kernel_init() in EL1.
+    asm::eret()
+}
+
+/// The Rust entry of the secondary cores.
+///
+/// The function is called from the assembly `_start_secondary` function.
+///
+/// # Safety
+///
+/// - Exception return from EL2 must must continue execution in EL1 with `kernel_init_secondary()`.
+#[no_mangle]
+pub unsafe extern "C" fn _start_rust_secondary(phys_stack_end_exclusive_addr: u64) -> ! {
+    prepare_el2_to_el1_transition(phys_stack_end_exclusive_addr, crate::kernel_init_secondary);
+
+    // Use `eret` to "return" to EL1. This results in execution of kernel_init_secondary() in EL1.
+    asm::eret()
+}
+
+/// The wall clock of the chainloader that started the kernel, as the Unix time at uptime zero.
+pub fn handover_time() -> Option<Duration> {
+    match HANDOVER_TIME.load(Ordering::Relaxed) {
+        0 => None,
+        nanos => Some(Duration::from_nanos(nanos)),
+    }
 }
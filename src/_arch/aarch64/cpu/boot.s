@@ -18,6 +18,18 @@
 	add	\register, \register, #:lo12:\symbol
 .endm
 
+// Load the address of a symbol into a register, absolute.
+//
+// # Resources
+//
+// - https://sourceware.org/binutils/docs-2.36/as/AArch64_002dRelocations.html
+.macro ADR_ABS register, symbol
+	movz	\register, #:abs_g3:\symbol
+	movk	\register, #:abs_g2_nc:\symbol
+	movk	\register, #:abs_g1_nc:\symbol
+	movk	\register, #:abs_g0_nc:\symbol
+.endm
+
 //--------------------------------------------------------------------------------------------------
 // Public Code
 //--------------------------------------------------------------------------------------------------
@@ -27,6 +39,16 @@
 // fn _start()
 //------------------------------------------------------------------------------
 _start:
+	// Keep what a chainloader might hand over in x1 and x2 for _start_rust(). The firmware leaves
+	// both zero.
+	mov	x19, x1
+	mov	x20, x2
+
+	// Only proceed if the core executes in EL2. Park it otherwise.
+	mrs	x0, CurrentEL
+	cmp	x0, {CONST_CURRENTEL_EL2}
+	b.ne	.L_parking_loop
+
 	// Only proceed on the boot core. Park it otherwise.
 	mrs	x0, MPIDR_EL1
 	and	x0, x0, {CONST_CORE_ID_MASK}
@@ -36,9 +58,23 @@ _start:
 
 	// If execution reaches here, it is the boot core.
 
-	// Initialize DRAM.
-	ADR_REL	x0, __bss_start
-	ADR_REL x1, __bss_end_exclusive
+	// Relocate the binary if it is linked elsewhere than it was loaded to. Only the chainloader is.
+	ADR_REL	x0, __binary_nonzero_start         // The address the binary got loaded to.
+	ADR_ABS	x1, __binary_nonzero_start         // The address the binary was linked to.
+	ADR_ABS	x2, __binary_nonzero_end_exclusive
+	cmp	x0, x1
+	b.eq	.L_bss_init
+
+.L_copy_loop:
+	ldr	x3, [x0], #8
+	str	x3, [x1], #8
+	cmp	x1, x2
+	b.lo	.L_copy_loop
+
+	// Initialize DRAM. From here on, only the linked addresses are used.
+.L_bss_init:
+	ADR_ABS	x0, __bss_start
+	ADR_ABS x1, __bss_end_exclusive
 
 .L_bss_init_loop:
 	cmp	x0, x1
@@ -48,20 +84,30 @@ _start:
 
 	// Prepare the jump to Rust code.
 .L_prepare_rust:
-	// Set the stack pointer.
-	ADR_REL	x0, __boot_core_stack_end_exclusive
+	// Set the stack pointer. This ensures that any code in EL2 that needs the stack will work.
+	ADR_ABS	x0, __boot_core_stack_end_exclusive
 	mov	sp, x0
 
 	// Read the CPU's timer counter frequency and store it in ARCH_TIMER_COUNTER_FREQUENCY.
-	// Abort if the frequency read back as 0.
-	ADR_REL	x1, ARCH_TIMER_COUNTER_FREQUENCY // provided by aarch64/time.rs
+	// Abort if the frequency read back as 0, unless the System Timer is selected as clock source.
+	// ARCH_TIMER_COUNTER_FREQUENCY then keeps its dummy value.
+	ADR_ABS	x1, ARCH_TIMER_COUNTER_FREQUENCY // provided by aarch64/time.rs
 	mrs	x2, CNTFRQ_EL0
 	cmp	x2, xzr
+.if {CONST_SYS_TIMER_CLOCKSOURCE}
+	b.eq	.L_jump_to_rust
+.else
 	b.eq	.L_parking_loop
+.endif
 	str	w2, [x1]
 
-	// Jump to Rust code.
-	b	_start_rust
+.L_jump_to_rust:
+	// Jump to the relocated Rust code. x0 to x2 hold the function arguments provided to
+	// _start_rust().
+	mov	x1, x19
+	mov	x2, x20
+	ADR_ABS	x3, _start_rust
+	br	x3
 
 	// Infinitely wait for events (aka "park the core").
 .L_parking_loop:
@@ -71,3 +117,27 @@ _start:
 .size	_start, . - _start
 .type	_start, function
 .global	_start
+
+//------------------------------------------------------------------------------
+// fn _start_secondary()
+//------------------------------------------------------------------------------
+_start_secondary:
+	// Only proceed if the core executes in EL2. Park it otherwise.
+	mrs	x0, CurrentEL
+	cmp	x0, {CONST_CURRENTEL_EL2}
+	b.ne	.L_parking_loop
+
+	// Load the stack that the boot core prepared for this core. Park the core if there is none.
+	mrs	x0, MPIDR_EL1
+	and	x0, x0, {CONST_CORE_ID_MASK}
+	ADR_REL	x1, SECONDARY_CORE_STACK_END // provided by aarch64/cpu/smp.rs
+	ldr	x0, [x1, x0, lsl #3]
+	cbz	x0, .L_parking_loop
+	mov	sp, x0
+
+	// Jump to Rust code. x0 holds the function argument provided to _start_rust_secondary().
+	b	_start_rust_secondary
+
+.size	_start_secondary, . - _start_secondary
+.type	_start_secondary, function
+.global	_start_secondary
@@ -0,0 +1,72 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2018-2022 Andre Richter <andre.o.richter@gmail.com>
+
+//! Architectural symmetric multiprocessing.
+//!
+//! # Orientation
+//!
+//! Since arch modules are imported into generic modules using the path attribute, the path of this
+//! file is:
+//!
+//! crate::cpu::smp::arch_smp
+
+use crate::bsp;
+use aarch64_cpu::registers::*;
+use tock_registers::interfaces::Readable;
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Initial stack of each secondary core. Read by `_start_secondary` in boot.s, which runs with the
+/// MMU and caching still off.
+#[no_mangle]
+static mut SECONDARY_CORE_STACK_END: [u64; bsp::cpu::NUM_CORES] = [0; bsp::cpu::NUM_CORES];
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Return the executing core's id.
+#[inline(always)]
+pub fn core_id<T>() -> T
+where
+    T: From<u8>,
+{
+    const CORE_MASK: u64 = 0b11;
+
+    T::from((MPIDR_EL1.get() & CORE_MASK) as u8)
+}
+
+/// Release a secondary core that is parked in the firmware's spin table.
+///
+/// The core starts executing `_start_secondary` with the given stack.
+///
+/// # Safety
+///
+/// - `release_addr` must be the core's spin-table release address.
+/// - The stack must be reserved for the core and must not be in any cache.
+#[cfg(not(feature = "chainloader"))]
+pub unsafe fn start_core(core_id: usize, release_addr: usize, stack_end_exclusive: usize) {
+    use crate::memory;
+    use aarch64_cpu::asm;
+    use core::{cell::UnsafeCell, ptr};
+
+    // Provided by boot.s.
+    extern "Rust" {
+        static _start_secondary: UnsafeCell<()>;
+    }
+
+    // The core still runs with the MMU and caching off, so everything it reads must be written back
+    // to memory first.
+    let stack_slot = ptr::addr_of_mut!(SECONDARY_CORE_STACK_END[core_id]);
+    stack_slot.write_volatile(stack_end_exclusive as u64);
+    memory::cache::clean_dcache_range(stack_slot as usize, 8);
+
+    (release_addr as *mut u64).write_volatile(_start_secondary.get() as u64);
+    memory::cache::clean_dcache_range(release_addr, 8);
+
+    // The cleaning finished with a DSB, so the core sees the new values once it wakes up.
+    asm::sev();
+}
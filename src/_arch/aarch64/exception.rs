@@ -0,0 +1,472 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2018-2022 Andre Richter <andre.o.richter@gmail.com>
+
+//! Architectural synchronous and asynchronous exception handling.
+//!
+//! # Orientation
+//!
+//! Since arch modules are imported into generic modules using the path attribute, the path of this
+//! file is:
+//!
+//! crate::exception::arch_exception
+
+use crate::{bsp, cpu, exception, thread};
+
+#[cfg(not(feature = "chainloader"))]
+use crate::process;
+use aarch64_cpu::{asm::barrier, registers::*};
+use core::{arch::global_asm, cell::UnsafeCell, fmt};
+use tock_registers::{
+    interfaces::{Readable, Writeable},
+    registers::InMemoryRegister,
+};
+
+// Assembly counterpart to this file.
+global_asm!(include_str!("exception.s"));
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Size of the stack that synchronous exceptions from the kernel itself are handled on.
+const EXCEPTION_STACK_SIZE: usize = 16 * 1024;
+
+/// Stack for synchronous exceptions taken from EL1, installed in SP_EL0.
+#[repr(C, align(16))]
+struct ExceptionStack([u8; EXCEPTION_STACK_SIZE]);
+
+const EMPTY_EXCEPTION_STACK: ExceptionStack = ExceptionStack([0; EXCEPTION_STACK_SIZE]);
+
+/// One exception stack per core.
+static mut EXCEPTION_STACKS: [ExceptionStack; cpu::smp::NUM_CORES] =
+    [EMPTY_EXCEPTION_STACK; cpu::smp::NUM_CORES];
+
+/// Wrapper structs for memory copies of registers.
+#[repr(transparent)]
+struct SpsrEL1(InMemoryRegister<u64, SPSR_EL1::Register>);
+struct EsrEL1(InMemoryRegister<u64, ESR_EL1::Register>);
+
+/// The exception context as it is stored on the stack on exception entry.
+#[repr(C)]
+struct ExceptionContext {
+    /// General Purpose Registers.
+    gpr: [u64; 30],
+
+    /// The link register, aka x30.
+    lr: u64,
+
+    /// Exception link register. The program counter at the time the exception happened.
+    elr_el1: u64,
+
+    /// Saved program status.
+    spsr_el1: SpsrEL1,
+
+    /// Exception syndrome register.
+    esr_el1: EsrEL1,
+}
+
+/// A fault of a user process, in short.
+struct UserFault<'a>(&'a ExceptionContext);
+
+/// The top of the executing core's exception stack.
+fn exception_stack_end_exclusive() -> u64 {
+    let exception_stack = unsafe { &EXCEPTION_STACKS[cpu::smp::core_id::<usize>()] };
+
+    exception_stack.0.as_ptr_range().end as u64
+}
+
+/// Run the handler of an exception from EL0.
+///
+/// SP_EL0 holds the user's stack pointer on entry. It is swapped for the exception stack while the
+/// kernel runs, and restored on the way back to EL0. The handler may switch threads or end the
+/// thread, so the user's stack pointer is kept on the thread's kernel stack.
+fn with_exception_stack(f: impl FnOnce()) {
+    let user_sp = SP_EL0.get();
+    SP_EL0.set(exception_stack_end_exclusive());
+
+    f();
+
+    // IRQs are masked on every path back to here.
+    SP_EL0.set(user_sp);
+}
+
+/// Prints verbose information about the exception and then panics.
+fn default_exception_handler(exc: &ExceptionContext) {
+    if exc.is_stack_overflow() {
+        panic!("Kernel stack overflow!\n\n{}", exc);
+    }
+
+    panic!("CPU Exception!\n\n{}", exc);
+}
+
+//------------------------------------------------------------------------------
+// Current, EL0
+//------------------------------------------------------------------------------
+
+/// SP_EL0 is only used while a synchronous exception from EL1 is handled, so this is a nested
+/// exception.
+#[no_mangle]
+extern "C" fn current_el0_synchronous(e: &mut ExceptionContext) {
+    panic!("Nested CPU Exception!\n\n{}", e)
+}
+
+#[no_mangle]
+extern "C" fn current_el0_irq(_e: &mut ExceptionContext) {
+    panic!("Should not be here. Use of SP_EL0 in EL1 is not supported.")
+}
+
+#[no_mangle]
+extern "C" fn current_el0_serror(_e: &mut ExceptionContext) {
+    panic!("Should not be here. Use of SP_EL0 in EL1 is not supported.")
+}
+
+//------------------------------------------------------------------------------
+// Current, ELx
+//------------------------------------------------------------------------------
+
+#[no_mangle]
+extern "C" fn current_elx_synchronous(e: &mut ExceptionContext) {
+    default_exception_handler(e);
+}
+
+#[no_mangle]
+extern "C" fn current_elx_irq(_e: &mut ExceptionContext) {
+    let token = unsafe { &exception::asynchronous::IRQContext::new() };
+    exception::asynchronous::handle_pending_irqs(token);
+
+    // All IRQs are acknowledged at this point, so the thread can be switched out.
+    thread::preempt_on_irq_exit(token);
+}
+
+#[no_mangle]
+extern "C" fn current_elx_serror(e: &mut ExceptionContext) {
+    default_exception_handler(e);
+}
+
+//------------------------------------------------------------------------------
+// Lower, AArch64
+//------------------------------------------------------------------------------
+
+/// Syscalls and faults of user processes. A fault kills only the process.
+#[cfg(not(feature = "chainloader"))]
+#[no_mangle]
+extern "C" fn lower_aarch64_synchronous(e: &mut ExceptionContext) {
+    with_exception_stack(|| {
+        if !matches!(e.exception_class(), Some(ESR_EL1::EC::Value::SVC64)) {
+            process::kill_current(&UserFault(e));
+        }
+
+        let mut args = [0; 6];
+        args.copy_from_slice(&e.gpr[0..6]);
+
+        // Syscalls may take a while or block, so they run with IRQs unmasked.
+        exception::asynchronous::local_irq_unmask();
+        e.gpr[0] = process::handle_syscall(e.gpr[8], args) as u64;
+        exception::asynchronous::local_irq_mask();
+    })
+}
+
+/// The chainloader runs no processes.
+#[cfg(feature = "chainloader")]
+#[no_mangle]
+extern "C" fn lower_aarch64_synchronous(e: &mut ExceptionContext) {
+    default_exception_handler(e);
+}
+
+#[no_mangle]
+extern "C" fn lower_aarch64_irq(_e: &mut ExceptionContext) {
+    with_exception_stack(|| {
+        let token = unsafe { &exception::asynchronous::IRQContext::new() };
+        exception::asynchronous::handle_pending_irqs(token);
+
+        thread::preempt_on_irq_exit(token);
+    })
+}
+
+#[no_mangle]
+extern "C" fn lower_aarch64_serror(e: &mut ExceptionContext) {
+    default_exception_handler(e);
+}
+
+//------------------------------------------------------------------------------
+// Lower, AArch32
+//------------------------------------------------------------------------------
+
+#[no_mangle]
+extern "C" fn lower_aarch32_synchronous(e: &mut ExceptionContext) {
+    default_exception_handler(e);
+}
+
+#[no_mangle]
+extern "C" fn lower_aarch32_irq(e: &mut ExceptionContext) {
+    default_exception_handler(e);
+}
+
+#[no_mangle]
+extern "C" fn lower_aarch32_serror(e: &mut ExceptionContext) {
+    default_exception_handler(e);
+}
+
+//------------------------------------------------------------------------------
+// Misc
+//------------------------------------------------------------------------------
+
+/// Human readable SPSR_EL1.
+#[rustfmt::skip]
+impl fmt::Display for SpsrEL1 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Raw value.
+        writeln!(f, "SPSR_EL1: {:#010x}", self.0.get())?;
+
+        let to_flag_str = |x| -> _ {
+            if x { "Set" } else { "Not set" }
+         };
+
+        writeln!(f, "      Flags:")?;
+        writeln!(f, "            Negative (N): {}", to_flag_str(self.0.is_set(SPSR_EL1::N)))?;
+        writeln!(f, "            Zero     (Z): {}", to_flag_str(self.0.is_set(SPSR_EL1::Z)))?;
+        writeln!(f, "            Carry    (C): {}", to_flag_str(self.0.is_set(SPSR_EL1::C)))?;
+        writeln!(f, "            Overflow (V): {}", to_flag_str(self.0.is_set(SPSR_EL1::V)))?;
+
+        let to_mask_str = |x| -> _ {
+            if x { "Masked" } else { "Unmasked" }
+        };
+
+        writeln!(f, "      Exception handling state:")?;
+        writeln!(f, "            Debug  (D): {}", to_mask_str(self.0.is_set(SPSR_EL1::D)))?;
+        writeln!(f, "            SError (A): {}", to_mask_str(self.0.is_set(SPSR_EL1::A)))?;
+        writeln!(f, "            IRQ    (I): {}", to_mask_str(self.0.is_set(SPSR_EL1::I)))?;
+        writeln!(f, "            FIQ    (F): {}", to_mask_str(self.0.is_set(SPSR_EL1::F)))?;
+
+        write!(f, "      Illegal Execution State (IL): {}",
+            to_flag_str(self.0.is_set(SPSR_EL1::IL))
+        )
+    }
+}
+
+impl EsrEL1 {
+    #[inline(always)]
+    fn exception_class(&self) -> Option<ESR_EL1::EC::Value> {
+        self.0.read_as_enum(ESR_EL1::EC)
+    }
+}
+
+/// Human readable ESR_EL1.
+#[rustfmt::skip]
+impl fmt::Display for EsrEL1 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Raw print of whole register.
+        writeln!(f, "ESR_EL1: {:#010x}", self.0.get())?;
+
+        // Raw print of exception class.
+        write!(f, "      Exception Class         (EC) : {:#x}", self.0.read(ESR_EL1::EC))?;
+
+        // Exception class.
+        writeln!(f, " - {}", self.exception_class_name())?;
+
+        // Raw print of instruction specific syndrome.
+        write!(f, "      Instr Specific Syndrome (ISS): {:#x}", self.0.read(ESR_EL1::ISS))?;
+
+        if let Some(fault) = self.abort_fault_description() {
+            write!(f, "\n      Fault: {}", fault)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl EsrEL1 {
+    fn exception_class_name(&self) -> &'static str {
+        use ESR_EL1::EC::Value::*;
+
+        match self.exception_class() {
+            Some(DataAbortCurrentEL) => "Data Abort, current EL",
+            Some(InstrAbortCurrentEL) => "Instruction Abort, current EL",
+            Some(DataAbortLowerEL) => "Data Abort, lower EL",
+            Some(InstrAbortLowerEL) => "Instruction Abort, lower EL",
+            Some(PCAlignmentFault) => "PC Alignment Fault",
+            Some(SPAlignmentFault) => "SP Alignment Fault",
+            Some(TrappedFP) => "Trapped FP/SIMD access",
+            Some(Unknown) => "Unknown reason, e.g. an undefined instruction",
+            Some(Brk64) => "BRK instruction",
+            _ => "N/A",
+        }
+    }
+
+    /// Decode the fault status code of instruction and data aborts.
+    fn abort_fault_description(&self) -> Option<AbortFault> {
+        use ESR_EL1::EC::Value::*;
+
+        let is_data_abort = match self.exception_class()? {
+            DataAbortCurrentEL | DataAbortLowerEL => true,
+            InstrAbortCurrentEL | InstrAbortLowerEL => false,
+            _ => return None,
+        };
+
+        let iss = self.0.read(ESR_EL1::ISS);
+
+        Some(AbortFault {
+            status_code: (iss & 0b11_1111) as u8,
+            // Write not Read, only meaningful for data aborts.
+            write: is_data_abort && (iss & (1 << 6)) != 0,
+            is_data_abort,
+        })
+    }
+}
+
+/// Decoded fault status of an abort.
+struct AbortFault {
+    status_code: u8,
+    write: bool,
+    is_data_abort: bool,
+}
+
+impl fmt::Display for AbortFault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let level = self.status_code & 0b11;
+        let kind = match self.status_code >> 2 {
+            0b0001 => "Translation fault",
+            0b0010 => "Access flag fault",
+            0b0011 => "Permission fault",
+            _ => "Other fault",
+        };
+
+        write!(f, "{}, level {} ({:#04x})", kind, level, self.status_code)?;
+
+        if self.is_data_abort {
+            write!(f, " on {}", if self.write { "write" } else { "read" })?;
+        }
+
+        Ok(())
+    }
+}
+
+impl ExceptionContext {
+    #[inline(always)]
+    fn exception_class(&self) -> Option<ESR_EL1::EC::Value> {
+        self.esr_el1.exception_class()
+    }
+
+    #[inline(always)]
+    fn fault_address_valid(&self) -> bool {
+        use ESR_EL1::EC::Value::*;
+
+        match self.exception_class() {
+            None => false,
+            Some(ec) => matches!(
+                ec,
+                InstrAbortLowerEL
+                    | InstrAbortCurrentEL
+                    | PCAlignmentFault
+                    | DataAbortLowerEL
+                    | DataAbortCurrentEL
+                    | WatchpointLowerEL
+                    | WatchpointCurrentEL
+            ),
+        }
+    }
+
+    /// True if the kernel faulted on the boot core's stack guard page.
+    fn is_stack_overflow(&self) -> bool {
+        let is_kernel_data_abort = matches!(
+            self.exception_class(),
+            Some(ESR_EL1::EC::Value::DataAbortCurrentEL)
+        );
+
+        is_kernel_data_abort
+            && bsp::memory::mmu::boot_core_stack_guard_page().contains(&(FAR_EL1.get() as usize))
+    }
+}
+
+impl fmt::Display for UserFault<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0.esr_el1.exception_class_name())?;
+
+        if let Some(fault) = self.0.esr_el1.abort_fault_description() {
+            write!(f, ", {}", fault)?;
+        }
+
+        write!(f, " at {:#x}", self.0.elr_el1)?;
+
+        if self.0.fault_address_valid() {
+            write!(f, ", FAR {:#x}", FAR_EL1.get())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Human readable print of the exception context.
+impl fmt::Display for ExceptionContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", self.esr_el1)?;
+
+        if self.fault_address_valid() {
+            let far = FAR_EL1.get() as usize;
+            writeln!(f, "FAR_EL1: {:#018x}", far)?;
+
+            if let Some(region) = bsp::memory::mmu::virt_mem_layout().region_name(far) {
+                writeln!(f, "      Region: {}", region)?;
+            }
+        }
+
+        writeln!(f, "{}", self.spsr_el1)?;
+        writeln!(f, "ELR_EL1: {:#018x}", self.elr_el1)?;
+        writeln!(f)?;
+        writeln!(f, "General purpose register:")?;
+
+        #[rustfmt::skip]
+        let alternating = |x| -> _ {
+            if x % 2 == 0 { "   " } else { "\n" }
+        };
+
+        // Print two registers per line.
+        for (i, reg) in self.gpr.iter().enumerate() {
+            write!(f, "      x{: <2}: {: >#018x}{}", i, reg, alternating(i))?;
+        }
+        write!(f, "      lr : {:#018x}", self.lr)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+#[cfg(not(feature = "chainloader"))]
+use crate::exception::PrivilegeLevel;
+
+/// The processing element's current privilege level.
+#[cfg(not(feature = "chainloader"))]
+pub fn current_privilege_level() -> (PrivilegeLevel, &'static str) {
+    let el = CurrentEL.read_as_enum(CurrentEL::EL);
+    match el {
+        Some(CurrentEL::EL::Value::EL2) => (PrivilegeLevel::Hypervisor, "EL2"),
+        Some(CurrentEL::EL::Value::EL1) => (PrivilegeLevel::Kernel, "EL1"),
+        Some(CurrentEL::EL::Value::EL0) => (PrivilegeLevel::User, "EL0"),
+        _ => (PrivilegeLevel::Unknown, "Unknown"),
+    }
+}
+
+/// Init exception handling by setting the exception vector base address register. Must be called
+/// on every core.
+///
+/// # Safety
+///
+/// - Changes the HW state of the executing core.
+/// - The vector table and the symbol `__exception_vector_table_start` from the linker script must
+///   adhere to the alignment and size constraints demanded by the ARMv8-A Architecture Reference
+///   Manual.
+pub unsafe fn handling_init() {
+    // Provided by exception.S.
+    extern "Rust" {
+        static __exception_vector_start: UnsafeCell<()>;
+    }
+
+    VBAR_EL1.set(__exception_vector_start.get() as u64);
+
+    // Synchronous exceptions from EL1 are handled on a dedicated stack. See exception.s.
+    SP_EL0.set(exception_stack_end_exclusive());
+
+    // Force VBAR update to complete before next instruction.
+    barrier::isb(barrier::SY);
+}
@@ -0,0 +1,81 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2022 Andre Richter <andre.o.richter@gmail.com>
+
+//! Architectural data cache maintenance.
+//!
+//! # Orientation
+//!
+//! Since arch modules are imported into generic modules using the path attribute, the path of this
+//! file is:
+//!
+//! crate::memory::cache::arch_cache
+
+use aarch64_cpu::asm::barrier;
+use core::arch::asm;
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+/// The smallest data cache line size in bytes, as reported by CTR_EL0.DminLine.
+#[inline(always)]
+fn dcache_line_size() -> usize {
+    let ctr: u64;
+
+    unsafe {
+        asm!("mrs {}, ctr_el0", out(reg) ctr, options(nomem, nostack));
+    }
+
+    // DminLine is log2 of the number of 4-byte words.
+    4 << ((ctr >> 16) & 0xF)
+}
+
+/// Apply the data cache maintenance instruction `op` to every line touching the range, then wait
+/// for completion.
+macro_rules! for_each_dcache_line {
+    ($op:literal, $start:expr, $size:expr) => {{
+        let line_size = dcache_line_size();
+        let end = $start + $size;
+        let mut addr = $start & !(line_size - 1);
+
+        while addr < end {
+            unsafe {
+                asm!(concat!("dc ", $op, ", {}"), in(reg) addr, options(nostack));
+            }
+            addr += line_size;
+        }
+
+        barrier::dsb(barrier::SY);
+    }};
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Write back dirty lines of the range to the point of coherency.
+pub fn clean_dcache_range(start: usize, size: usize) {
+    for_each_dcache_line!("cvac", start, size);
+}
+
+/// Write back dirty lines of the range to the point of coherency and drop them from the cache, so
+/// that subsequent reads fetch from memory.
+pub fn clean_and_invalidate_dcache_range(start: usize, size: usize) {
+    for_each_dcache_line!("civac", start, size);
+}
+
+/// Make instructions that were written through the data cache visible to instruction fetches on
+/// all cores.
+#[cfg(not(feature = "chainloader"))]
+pub fn sync_icache_range(start: usize, size: usize) {
+    // Clean to the point of unification, where the instruction fetches look.
+    for_each_dcache_line!("cvau", start, size);
+
+    unsafe {
+        asm!("ic ialluis", options(nostack));
+    }
+
+    barrier::dsb(barrier::ISH);
+    barrier::isb(barrier::SY);
+}
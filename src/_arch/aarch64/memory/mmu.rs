@@ -0,0 +1,265 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2018-2022 Andre Richter <andre.o.richter@gmail.com>
+
+//! Memory Management Unit Driver.
+//!
+//! Only 64 KiB granule is supported.
+//!
+//! # Orientation
+//!
+//! Since arch modules are imported into generic modules using the path attribute, the path of this
+//! file is:
+//!
+//! crate::memory::mmu::arch_mmu
+
+use crate::{
+    bsp, memory,
+    memory::mmu::{translation_table::KernelTranslationTable, TranslationGranule, UserSpaceToken},
+};
+use aarch64_cpu::{asm::barrier, registers::*};
+use core::{arch::asm, intrinsics::unlikely, ops::RangeInclusive};
+use tock_registers::interfaces::{ReadWriteable, Readable, Writeable};
+
+#[cfg(not(feature = "chainloader"))]
+use crate::memory::mmu::translation_table::UserTranslationTable;
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Memory Management Unit type.
+struct MemoryManagementUnit;
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+pub type Granule512MiB = TranslationGranule<{ 512 * 1024 * 1024 }>;
+pub type Granule64KiB = TranslationGranule<{ 64 * 1024 }>;
+
+/// The granule the kernel's tables use for their page descriptors.
+pub type KernelGranule = Granule64KiB;
+
+#[cfg(not(feature = "chainloader"))]
+/// The largest ASID. TCR_EL1 is left at 8 bit ASIDs. ASID 0 is reserved for the kernel's tables.
+pub const MAX_ASID: u16 = 255;
+
+/// Constants for indexing the MAIR_EL1.
+pub mod mair {
+    pub const DEVICE: u64 = 0;
+    pub const NORMAL: u64 = 1;
+    pub const NORMAL_NON_CACHEABLE: u64 = 2;
+}
+
+//--------------------------------------------------------------------------------------------------
+// Global instances
+//--------------------------------------------------------------------------------------------------
+
+/// The kernel translation tables.
+///
+/// # Safety
+///
+/// - Supposed to land in `.bss`. Therefore, ensure that all initial member values boil down to "0".
+static mut KERNEL_TABLES: KernelTranslationTable = KernelTranslationTable::new();
+
+static MMU: MemoryManagementUnit = MemoryManagementUnit;
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+impl<const AS_SIZE: usize> memory::mmu::AddressSpace<AS_SIZE> {
+    /// Checks for architectural restrictions.
+    pub const fn arch_address_space_size_sanity_checks() {
+        // Size must be at least one full 512 MiB table.
+        assert!((AS_SIZE % Granule512MiB::SIZE) == 0);
+
+        // Check for 48 bit virtual address size as maximum, which is supported by any ARMv8
+        // version.
+        assert!(AS_SIZE <= (1 << 48));
+    }
+}
+
+impl MemoryManagementUnit {
+    /// Setup function for the MAIR_EL1 register.
+    fn set_up_mair(&self) {
+        // Define the memory types being mapped.
+        MAIR_EL1.write(
+            // Attribute 2 - Normal non-cacheable DRAM. Writes may be gathered (write-combining).
+            MAIR_EL1::Attr2_Normal_Outer::NonCacheable
+                + MAIR_EL1::Attr2_Normal_Inner::NonCacheable
+
+            // Attribute 1 - Cacheable normal DRAM.
+                + MAIR_EL1::Attr1_Normal_Outer::WriteBack_NonTransient_ReadWriteAlloc
+                + MAIR_EL1::Attr1_Normal_Inner::WriteBack_NonTransient_ReadWriteAlloc
+
+            // Attribute 0 - Device.
+                + MAIR_EL1::Attr0_Device::nonGathering_nonReordering_EarlyWriteAck,
+        );
+    }
+
+    /// Configure various settings of stage 1 of the EL1 translation regime.
+    fn configure_translation_control(&self) {
+        let t0sz = (64 - bsp::memory::mmu::KernelAddrSpace::SIZE_SHIFT) as u64;
+
+        TCR_EL1.write(
+            TCR_EL1::TBI0::Used
+                + TCR_EL1::IPS::Bits_40
+                + TCR_EL1::TG0::KiB_64
+                + TCR_EL1::SH0::Inner
+                + TCR_EL1::ORGN0::WriteBack_ReadAlloc_WriteAlloc_Cacheable
+                + TCR_EL1::IRGN0::WriteBack_ReadAlloc_WriteAlloc_Cacheable
+                + TCR_EL1::EPD0::EnableTTBR0Walks
+                + TCR_EL1::A1::TTBR0
+                + TCR_EL1::T0SZ.val(t0sz)
+                + TCR_EL1::EPD1::DisableTTBR1Walks,
+        );
+    }
+
+    /// Install the kernel translation tables and switch the MMU and caching on for the executing
+    /// core.
+    unsafe fn switch_on(&self) {
+        // Prepare the memory attribute indirection register.
+        self.set_up_mair();
+
+        // Set the "Translation Table Base Register".
+        TTBR0_EL1.set_baddr(KERNEL_TABLES.phys_base_address());
+
+        self.configure_translation_control();
+
+        // Switch the MMU on.
+        //
+        // First, force all previous changes to be seen before the MMU is enabled.
+        barrier::isb(barrier::SY);
+
+        // Enable the MMU and turn on data and instruction caching.
+        SCTLR_EL1.modify(SCTLR_EL1::M::Enable + SCTLR_EL1::C::Cacheable + SCTLR_EL1::I::Cacheable);
+
+        // Force MMU init to complete before next instruction.
+        barrier::isb(barrier::SY);
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Return a reference to the MMU instance.
+pub fn mmu() -> &'static impl memory::mmu::interface::MMU {
+    &MMU
+}
+
+/// Invalidate the executing core's TLB entries for all pages in `virt_range`.
+pub fn local_tlb_invalidate_range(virt_range: &RangeInclusive<usize>) {
+    // Make preceding translation table writes visible to the table walker.
+    barrier::dsb(barrier::ISHST);
+
+    for virt_addr in virt_range.clone().step_by(KernelGranule::SIZE) {
+        // The operand holds VA[55:12], even when a larger granule is used.
+        let operand = (virt_addr >> 12) as u64;
+
+        unsafe {
+            asm!("tlbi vaae1, {}", in(reg) operand, options(nostack));
+        }
+    }
+
+    barrier::dsb(barrier::ISH);
+    barrier::isb(barrier::SY);
+}
+
+/// Create the translation tables for a new user address space.
+#[cfg(not(feature = "chainloader"))]
+pub fn new_user_tables() -> Result<UserTranslationTable, &'static str> {
+    // The kernel tables are only read, and they were populated during early init.
+    UserTranslationTable::new(unsafe { &*core::ptr::addr_of!(KERNEL_TABLES) })
+}
+
+/// Switch the executing core to the tables of a user address space, or back to the kernel's.
+///
+/// The kernel's own mappings are the same in all tables, so this can be done anywhere in the kernel.
+///
+/// # Safety
+///
+/// - The user address space must stay alive while its tables are installed.
+pub unsafe fn activate_user_space(token: Option<UserSpaceToken>) {
+    let ttbr = match token {
+        Some(token) => token.phys_base | ((token.asid as u64) << 48),
+        None => KERNEL_TABLES.phys_base_address(),
+    };
+
+    TTBR0_EL1.set(ttbr);
+    barrier::isb(barrier::SY);
+}
+
+/// Invalidate the TLB entries of `asid` on all cores.
+#[cfg(not(feature = "chainloader"))]
+pub fn tlb_invalidate_asid(asid: u16) {
+    // Make preceding translation table writes visible to the table walker.
+    barrier::dsb(barrier::ISHST);
+
+    unsafe {
+        asm!("tlbi aside1is, {}", in(reg) (asid as u64) << 48, options(nostack));
+    }
+
+    barrier::dsb(barrier::ISH);
+    barrier::isb(barrier::SY);
+}
+
+//------------------------------------------------------------------------------
+// OS Interface Code
+//------------------------------------------------------------------------------
+use memory::mmu::{AttributeFields, MMUEnableError};
+
+impl memory::mmu::interface::MMU for MemoryManagementUnit {
+    unsafe fn enable_mmu_and_caching(&self) -> Result<(), MMUEnableError> {
+        if unlikely(self.is_enabled()) {
+            return Err(MMUEnableError::AlreadyEnabled);
+        }
+
+        // Fail early if translation granule is not supported.
+        if unlikely(!ID_AA64MMFR0_EL1.matches_all(ID_AA64MMFR0_EL1::TGran64::Supported)) {
+            return Err(MMUEnableError::Other(
+                "Translation granule not supported in HW",
+            ));
+        }
+
+        // Populate translation tables.
+        KERNEL_TABLES
+            .populate_tt_entries()
+            .map_err(MMUEnableError::Other)?;
+
+        self.switch_on();
+
+        Ok(())
+    }
+
+    unsafe fn enable_mmu_and_caching_secondary(&self) -> Result<(), MMUEnableError> {
+        if unlikely(self.is_enabled()) {
+            return Err(MMUEnableError::AlreadyEnabled);
+        }
+
+        // The tables are shared with the boot core and must not be populated again. The data
+        // cache is switched on together with the MMU, so table walks see the boot core's writes.
+        self.switch_on();
+
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn is_enabled(&self) -> bool {
+        SCTLR_EL1.matches_all(SCTLR_EL1::M::Enable)
+    }
+
+    unsafe fn remap(
+        &self,
+        virt_range: RangeInclusive<usize>,
+        attribute_fields: &AttributeFields,
+    ) -> Result<(), &'static str> {
+        if unlikely(!self.is_enabled()) {
+            return Err("MMU is not enabled");
+        }
+
+        KERNEL_TABLES.remap(virt_range, attribute_fields)
+    }
+}
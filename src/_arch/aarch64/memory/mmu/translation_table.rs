@@ -0,0 +1,573 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2021-2022 Andre Richter <andre.o.richter@gmail.com>
+
+//! Architectural translation table.
+//!
+//! Only 64 KiB granule is supported.
+//!
+//! # Orientation
+//!
+//! Since arch modules are imported into generic modules using the path attribute, the path of this
+//! file is:
+//!
+//! crate::memory::mmu::translation_table::arch_translation_table
+
+use crate::{
+    bsp, memory,
+    memory::mmu::{
+        arch_mmu::{mair, Granule512MiB, Granule64KiB},
+        AccessPermissions, AttributeFields, MemAttributes,
+    },
+};
+use aarch64_cpu::asm::barrier;
+
+#[cfg(not(feature = "chainloader"))]
+use crate::memory::frame::{self, frame_allocator};
+use core::{convert, ops::RangeInclusive};
+use tock_registers::{
+    fields::FieldValue,
+    interfaces::{ReadWriteable, Readable, Writeable},
+    register_bitfields,
+    registers::InMemoryRegister,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+// A table descriptor, as per ARMv8-A Architecture Reference Manual Figure D5-15.
+register_bitfields! {u64,
+    STAGE1_TABLE_DESCRIPTOR [
+        /// Physical address of the next descriptor.
+        NEXT_LEVEL_TABLE_ADDR_64KiB OFFSET(16) NUMBITS(32) [], // [47:16]
+
+        TYPE  OFFSET(1) NUMBITS(1) [
+            Block = 0,
+            Table = 1
+        ],
+
+        VALID OFFSET(0) NUMBITS(1) [
+            False = 0,
+            True = 1
+        ]
+    ]
+}
+
+// A level 3 page descriptor, as per ARMv8-A Architecture Reference Manual Figure D5-17.
+register_bitfields! {u64,
+    STAGE1_PAGE_DESCRIPTOR [
+        /// Unprivileged execute-never.
+        UXN      OFFSET(54) NUMBITS(1) [
+            False = 0,
+            True = 1
+        ],
+
+        /// Privileged execute-never.
+        PXN      OFFSET(53) NUMBITS(1) [
+            False = 0,
+            True = 1
+        ],
+
+        /// Physical address of the next table descriptor (lvl2) or the page descriptor (lvl3).
+        OUTPUT_ADDR_64KiB OFFSET(16) NUMBITS(32) [], // [47:16]
+
+        /// Not global. The translation only applies to the address space with the current ASID.
+        nG       OFFSET(11) NUMBITS(1) [
+            False = 0,
+            True = 1
+        ],
+
+        /// Access flag.
+        AF       OFFSET(10) NUMBITS(1) [
+            False = 0,
+            True = 1
+        ],
+
+        /// Shareability field.
+        SH       OFFSET(8) NUMBITS(2) [
+            OuterShareable = 0b10,
+            InnerShareable = 0b11
+        ],
+
+        /// Access Permissions.
+        AP       OFFSET(6) NUMBITS(2) [
+            RW_EL1 = 0b00,
+            RW_EL1_EL0 = 0b01,
+            RO_EL1 = 0b10,
+            RO_EL1_EL0 = 0b11
+        ],
+
+        /// Memory attributes index into the MAIR_EL1 register.
+        AttrIndx OFFSET(2) NUMBITS(3) [],
+
+        TYPE     OFFSET(1) NUMBITS(1) [
+            Reserved_Invalid = 0,
+            Page = 1
+        ],
+
+        VALID    OFFSET(0) NUMBITS(1) [
+            False = 0,
+            True = 1
+        ]
+    ]
+}
+
+/// A table descriptor for 64 KiB aperture.
+///
+/// The output points to the next table.
+#[derive(Copy, Clone)]
+#[repr(C)]
+struct TableDescriptor {
+    value: u64,
+}
+
+/// A page descriptor with 64 KiB aperture.
+///
+/// The output points to physical memory.
+#[derive(Copy, Clone)]
+#[repr(C)]
+struct PageDescriptor {
+    value: u64,
+}
+
+trait StartAddr {
+    fn phys_start_addr_u64(&self) -> u64;
+    fn phys_start_addr_usize(&self) -> usize;
+}
+
+const NUM_LVL2_TABLES: usize = bsp::memory::mmu::KernelAddrSpace::SIZE >> Granule512MiB::SHIFT;
+
+/// Order of the frame run that holds a level 3 table.
+#[cfg(not(feature = "chainloader"))]
+const LVL3_TABLE_ORDER: usize = Granule64KiB::SHIFT - frame::FRAME_SIZE.trailing_zeros() as usize;
+
+#[cfg(not(feature = "chainloader"))]
+type Lvl2Table = [TableDescriptor; NUM_LVL2_TABLES];
+#[cfg(not(feature = "chainloader"))]
+type Lvl3Table = [PageDescriptor; 8192];
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Big monolithic struct for storing the translation tables. Individual levels must be 64 KiB
+/// aligned, so the lvl3 is put first.
+#[repr(C)]
+#[repr(align(65536))]
+pub struct FixedSizeTranslationTable<const NUM_TABLES: usize> {
+    /// Page descriptors, covering 64 KiB windows per entry.
+    lvl3: [[PageDescriptor; 8192]; NUM_TABLES],
+
+    /// Table descriptors, covering 512 MiB windows.
+    lvl2: [TableDescriptor; NUM_TABLES],
+}
+
+/// A translation table type for the kernel space.
+pub type KernelTranslationTable = FixedSizeTranslationTable<NUM_LVL2_TABLES>;
+
+/// The translation tables of a user address space.
+///
+/// The level 2 table is a copy of the kernel's, except for the entry that covers the user window.
+/// It points to a level 3 table of the address space's own. All other level 3 tables are shared
+/// with the kernel. Both tables live in frames from the frame allocator, which are identity mapped.
+///
+/// The chainloader runs no processes and has no use for it.
+#[cfg(not(feature = "chainloader"))]
+pub struct UserTranslationTable {
+    lvl2_phys: usize,
+    lvl3_phys: usize,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+// The binary is still identity mapped, so we don't need to convert here.
+impl<T, const N: usize> StartAddr for [T; N] {
+    fn phys_start_addr_u64(&self) -> u64 {
+        self as *const T as u64
+    }
+
+    fn phys_start_addr_usize(&self) -> usize {
+        self as *const _ as usize
+    }
+}
+
+impl TableDescriptor {
+    /// Create an instance.
+    ///
+    /// Descriptors must be initialized to zero.
+    pub const fn new_zeroed() -> Self {
+        Self { value: 0 }
+    }
+
+    /// Create an instance pointing to the supplied address.
+    pub fn from_next_lvl_table_addr(phys_next_lvl_table_addr: usize) -> Self {
+        let val = InMemoryRegister::<u64, STAGE1_TABLE_DESCRIPTOR::Register>::new(0);
+
+        let shifted = phys_next_lvl_table_addr >> Granule64KiB::SHIFT;
+        val.write(
+            STAGE1_TABLE_DESCRIPTOR::NEXT_LEVEL_TABLE_ADDR_64KiB.val(shifted as u64)
+                + STAGE1_TABLE_DESCRIPTOR::TYPE::Table
+                + STAGE1_TABLE_DESCRIPTOR::VALID::True,
+        );
+
+        TableDescriptor { value: val.get() }
+    }
+}
+
+/// Convert the kernel's generic memory attributes to HW-specific memory type and shareability.
+fn mem_attribute_fields(
+    mem_attributes: MemAttributes,
+) -> FieldValue<u64, STAGE1_PAGE_DESCRIPTOR::Register> {
+    match mem_attributes {
+        MemAttributes::CacheableDRAM => {
+            STAGE1_PAGE_DESCRIPTOR::SH::InnerShareable
+                + STAGE1_PAGE_DESCRIPTOR::AttrIndx.val(mair::NORMAL)
+        }
+        MemAttributes::NonCacheableDRAM => {
+            STAGE1_PAGE_DESCRIPTOR::SH::OuterShareable
+                + STAGE1_PAGE_DESCRIPTOR::AttrIndx.val(mair::NORMAL_NON_CACHEABLE)
+        }
+        MemAttributes::Device => {
+            STAGE1_PAGE_DESCRIPTOR::SH::OuterShareable
+                + STAGE1_PAGE_DESCRIPTOR::AttrIndx.val(mair::DEVICE)
+        }
+    }
+}
+
+/// Convert the kernel's generic memory attributes to HW-specific attributes of the MMU.
+impl convert::From<AttributeFields> for FieldValue<u64, STAGE1_PAGE_DESCRIPTOR::Register> {
+    fn from(attribute_fields: AttributeFields) -> Self {
+        // Memory attributes.
+        let mut desc = mem_attribute_fields(attribute_fields.mem_attributes);
+
+        // Access Permissions.
+        desc += match attribute_fields.acc_perms {
+            AccessPermissions::ReadOnly => STAGE1_PAGE_DESCRIPTOR::AP::RO_EL1,
+            AccessPermissions::ReadWrite => STAGE1_PAGE_DESCRIPTOR::AP::RW_EL1,
+        };
+
+        // The execute-never attribute is mapped to PXN in AArch64.
+        desc += if attribute_fields.execute_never {
+            STAGE1_PAGE_DESCRIPTOR::PXN::True
+        } else {
+            STAGE1_PAGE_DESCRIPTOR::PXN::False
+        };
+
+        // Kernel pages are never executable from EL0.
+        desc += STAGE1_PAGE_DESCRIPTOR::UXN::True;
+
+        desc
+    }
+}
+
+impl PageDescriptor {
+    /// Create an instance.
+    ///
+    /// Descriptors must be initialized to zero.
+    pub const fn new_zeroed() -> Self {
+        Self { value: 0 }
+    }
+
+    /// Create an instance.
+    pub fn from_output_addr(phys_output_addr: usize, attribute_fields: &AttributeFields) -> Self {
+        let val = InMemoryRegister::<u64, STAGE1_PAGE_DESCRIPTOR::Register>::new(0);
+
+        let shifted = phys_output_addr as u64 >> Granule64KiB::SHIFT;
+        val.write(
+            STAGE1_PAGE_DESCRIPTOR::OUTPUT_ADDR_64KiB.val(shifted)
+                + STAGE1_PAGE_DESCRIPTOR::AF::True
+                + STAGE1_PAGE_DESCRIPTOR::TYPE::Page
+                + STAGE1_PAGE_DESCRIPTOR::VALID::True
+                + (*attribute_fields).into(),
+        );
+
+        Self { value: val.get() }
+    }
+
+    /// Create an instance that is accessible from EL0.
+    ///
+    /// The execute-never attribute is mapped to UXN. The kernel can never execute user pages, and
+    /// their translations are tagged with the address space's ASID.
+    #[cfg(not(feature = "chainloader"))]
+    pub fn from_user_output_addr(
+        phys_output_addr: usize,
+        attribute_fields: &AttributeFields,
+    ) -> Self {
+        let val = InMemoryRegister::<u64, STAGE1_PAGE_DESCRIPTOR::Register>::new(0);
+
+        let ap = match attribute_fields.acc_perms {
+            AccessPermissions::ReadOnly => STAGE1_PAGE_DESCRIPTOR::AP::RO_EL1_EL0,
+            AccessPermissions::ReadWrite => STAGE1_PAGE_DESCRIPTOR::AP::RW_EL1_EL0,
+        };
+
+        let uxn = if attribute_fields.execute_never {
+            STAGE1_PAGE_DESCRIPTOR::UXN::True
+        } else {
+            STAGE1_PAGE_DESCRIPTOR::UXN::False
+        };
+
+        let shifted = phys_output_addr as u64 >> Granule64KiB::SHIFT;
+        val.write(
+            STAGE1_PAGE_DESCRIPTOR::OUTPUT_ADDR_64KiB.val(shifted)
+                + STAGE1_PAGE_DESCRIPTOR::AF::True
+                + STAGE1_PAGE_DESCRIPTOR::nG::True
+                + STAGE1_PAGE_DESCRIPTOR::TYPE::Page
+                + STAGE1_PAGE_DESCRIPTOR::VALID::True
+                + STAGE1_PAGE_DESCRIPTOR::PXN::True
+                + mem_attribute_fields(attribute_fields.mem_attributes)
+                + ap
+                + uxn,
+        );
+
+        Self { value: val.get() }
+    }
+
+    /// Returns the valid bit.
+    fn is_valid(&self) -> bool {
+        InMemoryRegister::<u64, STAGE1_PAGE_DESCRIPTOR::Register>::new(self.value)
+            .is_set(STAGE1_PAGE_DESCRIPTOR::VALID)
+    }
+
+    /// Return the physical output address.
+    fn output_addr(&self) -> usize {
+        let val = InMemoryRegister::<u64, STAGE1_PAGE_DESCRIPTOR::Register>::new(self.value);
+
+        (val.read(STAGE1_PAGE_DESCRIPTOR::OUTPUT_ADDR_64KiB) as usize) << Granule64KiB::SHIFT
+    }
+
+    /// Return a copy with the valid bit cleared.
+    ///
+    /// The HW ignores all other bits of an invalid descriptor, so the output address survives for
+    /// the "make" step of a break-before-make sequence.
+    fn invalidated(&self) -> Self {
+        let val = InMemoryRegister::<u64, STAGE1_PAGE_DESCRIPTOR::Register>::new(self.value);
+        val.modify(STAGE1_PAGE_DESCRIPTOR::VALID::False);
+
+        Self { value: val.get() }
+    }
+}
+
+impl<const NUM_TABLES: usize> FixedSizeTranslationTable<NUM_TABLES> {
+    /// The level 3 descriptor that maps `virt_addr`.
+    fn entry(&mut self, virt_addr: usize) -> &mut PageDescriptor {
+        let l2_nr = virt_addr >> Granule512MiB::SHIFT;
+        let l3_nr = (virt_addr & (Granule512MiB::SIZE - 1)) >> Granule64KiB::SHIFT;
+
+        &mut self.lvl3[l2_nr][l3_nr]
+    }
+}
+
+#[cfg(not(feature = "chainloader"))]
+impl UserTranslationTable {
+    /// The level 2 entry that covers the user window.
+    fn user_lvl2_index() -> Result<usize, &'static str> {
+        let user_range = bsp::memory::mmu::user_range_inclusive();
+        let start = *user_range.start();
+
+        if (start % Granule512MiB::SIZE != 0)
+            || (user_range.end() - start + 1 != Granule512MiB::SIZE)
+        {
+            return Err("User window must be covered by exactly one level 3 table");
+        }
+
+        Ok(start >> Granule512MiB::SHIFT)
+    }
+
+    fn lvl2(&mut self) -> &mut Lvl2Table {
+        unsafe { &mut *(self.lvl2_phys as *mut Lvl2Table) }
+    }
+
+    fn lvl3(&mut self) -> &mut Lvl3Table {
+        unsafe { &mut *(self.lvl3_phys as *mut Lvl3Table) }
+    }
+}
+
+#[cfg(not(feature = "chainloader"))]
+impl Drop for UserTranslationTable {
+    fn drop(&mut self) {
+        // Both were handed out by the frame allocator, freeing them can't fail.
+        let _ = frame_allocator().free(self.lvl2_phys);
+        let _ = frame_allocator().free_contiguous(self.lvl3_phys, LVL3_TABLE_ORDER);
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl<const NUM_TABLES: usize> FixedSizeTranslationTable<NUM_TABLES> {
+    /// Create an instance.
+    pub const fn new() -> Self {
+        // Can't have a zero-sized address space.
+        assert!(NUM_TABLES > 0);
+
+        Self {
+            lvl3: [[PageDescriptor::new_zeroed(); 8192]; NUM_TABLES],
+            lvl2: [TableDescriptor::new_zeroed(); NUM_TABLES],
+        }
+    }
+
+    /// Iterates over all static translation table entries and fills them at once.
+    ///
+    /// # Safety
+    ///
+    /// - Modifies a `static mut`. Ensure it only happens from here.
+    pub unsafe fn populate_tt_entries(&mut self) -> Result<(), &'static str> {
+        for (l2_nr, l2_entry) in self.lvl2.iter_mut().enumerate() {
+            *l2_entry =
+                TableDescriptor::from_next_lvl_table_addr(self.lvl3[l2_nr].phys_start_addr_usize());
+
+            for (l3_nr, l3_entry) in self.lvl3[l2_nr].iter_mut().enumerate() {
+                let virt_addr = (l2_nr << Granule512MiB::SHIFT) + (l3_nr << Granule64KiB::SHIFT);
+
+                *l3_entry =
+                    match bsp::memory::mmu::virt_mem_layout().virt_addr_properties(virt_addr)? {
+                        Some((phys_output_addr, attribute_fields)) => {
+                            PageDescriptor::from_output_addr(phys_output_addr, &attribute_fields)
+                        }
+                        None => PageDescriptor::new_zeroed(),
+                    };
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Change the attributes of all pages in `virt_range`, keeping their output addresses.
+    ///
+    /// Uses a break-before-make sequence, as required by the architecture when the memory type of a
+    /// live mapping changes. All pages of the range are broken at once, so that a single TLB
+    /// shootdown covers them.
+    ///
+    /// # Safety
+    ///
+    /// - Modifies a `static mut` that is in use by the HW.
+    pub unsafe fn remap(
+        &mut self,
+        virt_range: RangeInclusive<usize>,
+        attribute_fields: &AttributeFields,
+    ) -> Result<(), &'static str> {
+        let start = *virt_range.start();
+        let end_exclusive = virt_range.end().checked_add(1).ok_or("Range too large")?;
+
+        if (start % Granule64KiB::SIZE != 0) || (end_exclusive % Granule64KiB::SIZE != 0) {
+            return Err("Range is not page aligned");
+        }
+
+        if end_exclusive > bsp::memory::mmu::KernelAddrSpace::SIZE {
+            return Err("Range exceeds the kernel address space");
+        }
+
+        let pages = (start..end_exclusive).step_by(Granule64KiB::SIZE);
+
+        // Check the whole range before anything is changed.
+        if pages
+            .clone()
+            .any(|virt_addr| !self.entry(virt_addr).is_valid())
+        {
+            return Err("Range contains unmapped pages");
+        }
+
+        // Break.
+        for virt_addr in pages.clone() {
+            let entry = self.entry(virt_addr);
+            core::ptr::write_volatile(entry, entry.invalidated());
+        }
+
+        // The shootdown starts with a barrier that makes the invalid descriptors visible to all
+        // table walks.
+        memory::mmu::tlb_shootdown(&virt_range);
+
+        // Make.
+        for virt_addr in pages {
+            let entry = self.entry(virt_addr);
+            let new = PageDescriptor::from_output_addr(entry.output_addr(), attribute_fields);
+
+            core::ptr::write_volatile(entry, new);
+        }
+
+        barrier::dsb(barrier::ISH);
+        barrier::isb(barrier::SY);
+
+        Ok(())
+    }
+
+    /// The translation table's base address to be used for programming the MMU.
+    pub fn phys_base_address(&self) -> u64 {
+        self.lvl2.phys_start_addr_u64()
+    }
+}
+
+#[cfg(not(feature = "chainloader"))]
+impl UserTranslationTable {
+    /// Create an instance with an empty user window.
+    pub fn new(kernel_tables: &KernelTranslationTable) -> Result<Self, &'static str> {
+        // A level 2 table must fit into a frame, which also provides the required alignment.
+        const _: () = assert!(core::mem::size_of::<Lvl2Table>() <= frame::FRAME_SIZE);
+
+        let user_lvl2_index = Self::user_lvl2_index()?;
+
+        let lvl3_phys = frame_allocator().alloc_contiguous(LVL3_TABLE_ORDER)?;
+        let lvl2_phys = match frame_allocator().alloc() {
+            Ok(addr) => addr,
+            Err(x) => {
+                let _ = frame_allocator().free_contiguous(lvl3_phys, LVL3_TABLE_ORDER);
+                return Err(x);
+            }
+        };
+
+        let mut tables = Self {
+            lvl2_phys,
+            lvl3_phys,
+        };
+
+        // Zeroed descriptors are invalid. Written in place, the table is too large for a stack.
+        unsafe { core::ptr::write_bytes(lvl3_phys as *mut Lvl3Table, 0, 1) };
+
+        let lvl2 = tables.lvl2();
+        *lvl2 = kernel_tables.lvl2;
+        lvl2[user_lvl2_index] = TableDescriptor::from_next_lvl_table_addr(lvl3_phys);
+
+        Ok(tables)
+    }
+
+    /// Map the page at `virt_addr` in the user window to `phys_output_addr`.
+    ///
+    /// Only unmapped pages can be mapped, so no TLB maintenance is needed.
+    pub fn map_page(
+        &mut self,
+        virt_addr: usize,
+        phys_output_addr: usize,
+        attribute_fields: &AttributeFields,
+    ) -> Result<(), &'static str> {
+        if !bsp::memory::mmu::user_range_inclusive().contains(&virt_addr) {
+            return Err("Address is outside of the user window");
+        }
+
+        if (virt_addr % Granule64KiB::SIZE != 0) || (phys_output_addr % Granule64KiB::SIZE != 0) {
+            return Err("Address is not page aligned");
+        }
+
+        let l3_nr = (virt_addr & (Granule512MiB::SIZE - 1)) >> Granule64KiB::SHIFT;
+        let entry = &mut self.lvl3()[l3_nr];
+        if entry.is_valid() {
+            return Err("Page is already mapped");
+        }
+
+        let new = PageDescriptor::from_user_output_addr(phys_output_addr, attribute_fields);
+        unsafe { core::ptr::write_volatile(entry, new) };
+
+        // Make the descriptor visible to table walks before the page is used.
+        barrier::dsb(barrier::ISHST);
+
+        Ok(())
+    }
+
+    /// The translation table's base address to be used for programming the MMU.
+    pub fn phys_base_address(&self) -> u64 {
+        self.lvl2_phys as u64
+    }
+}
@@ -0,0 +1,126 @@
+//! Architectural user process support.
+//!
+//! Processes run in EL0t, i.e. on SP_EL0. Syscalls are made with `svc #0`. The syscall number is
+//! passed in x8 and up to six arguments in x0 to x5. The result is returned in x0, all other
+//! registers are preserved.
+//!
+//! # Orientation
+//!
+//! Since arch modules are imported into generic modules using the path attribute, the path of this
+//! file is:
+//!
+//! crate::process::arch_process
+
+use crate::exception;
+use aarch64_cpu::registers::*;
+use core::arch::asm;
+#[cfg(feature = "demos")]
+use core::{arch::global_asm, cell::UnsafeCell, slice};
+use tock_registers::interfaces::Writeable;
+
+// Demo programs, see `demo_programs()`.
+#[cfg(feature = "demos")]
+global_asm!(include_str!("process/demo.s"));
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+/// A program between two symbols from demo.s.
+#[cfg(feature = "demos")]
+fn program(start: &UnsafeCell<()>, end_exclusive: &UnsafeCell<()>) -> &'static [u8] {
+    let start = start.get() as *const u8;
+    let len = end_exclusive.get() as usize - start as usize;
+
+    unsafe { slice::from_raw_parts(start, len) }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Drop to EL0 and continue at `entry`, with the given stack pointer and `args` in x0 to x2.
+///
+/// All other general purpose registers are cleared, so that nothing of the kernel leaks into the
+/// process. The process runs with all exceptions unmasked.
+pub fn enter_user(entry: usize, stack_pointer: usize, args: [usize; 3]) -> ! {
+    // From here on, an IRQ would overwrite the return state.
+    exception::asynchronous::local_irq_mask();
+
+    // The AAPCS64 requires a 16 byte aligned stack pointer.
+    SP_EL0.set((stack_pointer & !0xf) as u64);
+    ELR_EL1.set(entry as u64);
+    SPSR_EL1.write(
+        SPSR_EL1::D::Unmasked
+            + SPSR_EL1::A::Unmasked
+            + SPSR_EL1::I::Unmasked
+            + SPSR_EL1::F::Unmasked
+            + SPSR_EL1::M::EL0t,
+    );
+
+    unsafe {
+        asm!(
+            "mov x3,  xzr",
+            "mov x4,  xzr",
+            "mov x5,  xzr",
+            "mov x6,  xzr",
+            "mov x7,  xzr",
+            "mov x8,  xzr",
+            "mov x9,  xzr",
+            "mov x10, xzr",
+            "mov x11, xzr",
+            "mov x12, xzr",
+            "mov x13, xzr",
+            "mov x14, xzr",
+            "mov x15, xzr",
+            "mov x16, xzr",
+            "mov x17, xzr",
+            "mov x18, xzr",
+            "mov x19, xzr",
+            "mov x20, xzr",
+            "mov x21, xzr",
+            "mov x22, xzr",
+            "mov x23, xzr",
+            "mov x24, xzr",
+            "mov x25, xzr",
+            "mov x26, xzr",
+            "mov x27, xzr",
+            "mov x28, xzr",
+            "mov x29, xzr",
+            "mov x30, xzr",
+            "eret",
+            in("x0") args[0],
+            in("x1") args[1],
+            in("x2") args[2],
+            options(noreturn)
+        )
+    }
+}
+
+/// Small programs to try out processes with. Returns names and images.
+///
+/// - `hello` greets, sleeps, touches memory from `mmap` and exits with the time it slept in ms.
+/// - `crash` reads from a kernel address and gets killed.
+#[cfg(feature = "demos")]
+pub fn demo_programs() -> [(&'static str, &'static [u8]); 2] {
+    // Provided by demo.s.
+    extern "Rust" {
+        static __demo_hello_start: UnsafeCell<()>;
+        static __demo_hello_end_exclusive: UnsafeCell<()>;
+        static __demo_crash_start: UnsafeCell<()>;
+        static __demo_crash_end_exclusive: UnsafeCell<()>;
+    }
+
+    unsafe {
+        [
+            (
+                "hello",
+                program(&__demo_hello_start, &__demo_hello_end_exclusive),
+            ),
+            (
+                "crash",
+                program(&__demo_crash_start, &__demo_crash_end_exclusive),
+            ),
+        ]
+    }
+}
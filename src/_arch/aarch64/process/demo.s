@@ -0,0 +1,85 @@
+//--------------------------------------------------------------------------------------------------
+// Definitions
+//--------------------------------------------------------------------------------------------------
+
+// Syscall numbers, see process.rs.
+.equ SYS_EXIT,     0
+.equ SYS_WRITE,    1
+.equ SYS_SLEEP,    3
+.equ SYS_GET_TIME, 4
+.equ SYS_MMAP,     5
+
+/// Make syscall `\nr`. The arguments must be in place already.
+.macro SYSCALL nr
+	mov	x8, #\nr
+	svc	#0
+.endm
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+// The programs are copied into user memory before they run, so they must be position independent.
+// They are data to the kernel.
+.section .rodata.demo_programs, "a"
+.balign 4
+
+//------------------------------------------------------------------------------
+// hello
+//------------------------------------------------------------------------------
+__demo_hello_start:
+	adr	x0, 1f
+	mov	x1, #(2f - 1f)
+	SYSCALL	SYS_WRITE
+
+	SYSCALL	SYS_GET_TIME
+	mov	x19, x0
+
+	// Keep the start time in mmap'ed memory while sleeping, to show that it works.
+	mov	x0, #0x10000
+	SYSCALL	SYS_MMAP
+	tbnz	x0, #63, 3f
+	mov	x20, x0
+	str	x19, [x20]
+
+	mov	x0, #100
+	SYSCALL	SYS_SLEEP
+
+	SYSCALL	SYS_GET_TIME
+	ldr	x19, [x20]
+	sub	x0, x0, x19
+
+	// Exit with the elapsed time in ms.
+	movz	x1, #(1000000 & 0xffff)
+	movk	x1, #(1000000 >> 16), lsl #16
+	udiv	x0, x0, x1
+	SYSCALL	SYS_EXIT
+
+	// mmap failed.
+3:	mov	x0, #-1
+	SYSCALL	SYS_EXIT
+
+1:	.ascii	"Hello from EL0\n"
+2:
+.balign 4
+__demo_hello_end_exclusive:
+
+//------------------------------------------------------------------------------
+// crash
+//------------------------------------------------------------------------------
+__demo_crash_start:
+	adr	x0, 1f
+	mov	x1, #(2f - 1f)
+	SYSCALL	SYS_WRITE
+
+	// Address 0 belongs to the kernel. The process must be killed here.
+	mov	x0, #0
+	ldr	x0, [x0]
+
+	mov	x0, #0
+	SYSCALL	SYS_EXIT
+
+1:	.ascii	"About to read kernel memory\n"
+2:
+.balign 4
+__demo_crash_end_exclusive:
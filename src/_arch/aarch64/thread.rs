@@ -0,0 +1,88 @@
+//! Architectural kernel thread support.
+//!
+//! # Orientation
+//!
+//! Since arch modules are imported into generic modules using the path attribute, the path of this
+//! file is:
+//!
+//! crate::thread::arch_thread
+
+use core::arch::global_asm;
+
+// Assembly counterpart to this file.
+global_asm!(include_str!("thread.s"));
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// The registers of a thread that is switched out.
+///
+/// Threads only switch inside `switch_context()`, which is an ordinary function call. The caller
+/// has already saved the temporary registers, so only the callee-saved ones are stored here. The
+/// layout is shared with thread.s.
+#[repr(C)]
+pub struct Context {
+    /// x19 to x28.
+    callee_saved: [u64; 10],
+
+    /// The frame pointer, aka x29.
+    fp: u64,
+
+    /// The link register, aka x30. Where `switch_context()` returns to.
+    lr: u64,
+
+    sp: u64,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl Context {
+    /// An empty context. It is filled in when the thread is switched out for the first time.
+    #[cfg(not(feature = "chainloader"))]
+    pub const fn empty() -> Self {
+        Self {
+            callee_saved: [0; 10],
+            fp: 0,
+            lr: 0,
+            sp: 0,
+        }
+    }
+
+    /// A context that starts executing `entry` on the given stack.
+    #[cfg(not(feature = "chainloader"))]
+    pub fn new(stack_end_exclusive: usize, entry: extern "C" fn() -> !) -> Self {
+        Self {
+            callee_saved: [0; 10],
+            fp: 0,
+            lr: entry as usize as u64,
+            // The AAPCS64 requires a 16 byte aligned stack pointer.
+            sp: (stack_end_exclusive & !0xf) as u64,
+        }
+    }
+}
+
+/// Save the running thread's registers to `from` and continue with the thread in `to`.
+///
+/// Returns once another thread switches back to `from`.
+///
+/// # Safety
+///
+/// - `from` and `to` must stay valid until `to` has been switched out again.
+/// - `to` must have been filled in by `new()` or a previous switch.
+pub unsafe fn switch_context(from: *mut Context, to: *const Context) {
+    extern "C" {
+        fn __switch_context(from: *mut Context, to: *const Context);
+    }
+
+    __switch_context(from, to)
+}
+
+/// Sleep until an IRQ is pending.
+#[inline(always)]
+#[cfg(not(feature = "chainloader"))]
+pub fn wait_for_interrupt() {
+    aarch64_cpu::asm::wfi()
+}
@@ -0,0 +1,40 @@
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+// Own section, so that it can't shift the vector table in exception.s if both end up in the same
+// assembly unit.
+.section .text.__switch_context
+
+//------------------------------------------------------------------------------
+// fn __switch_context(from: *mut Context, to: *const Context)
+//------------------------------------------------------------------------------
+//
+// Save the callee-saved registers and the stack pointer of the running thread to `from`, then load
+// the ones of `to`. Returns into the thread described by `to`.
+__switch_context:
+	mov	x9,  sp
+
+	stp	x19, x20, [x0, #16 * 0]
+	stp	x21, x22, [x0, #16 * 1]
+	stp	x23, x24, [x0, #16 * 2]
+	stp	x25, x26, [x0, #16 * 3]
+	stp	x27, x28, [x0, #16 * 4]
+	stp	x29, lr,  [x0, #16 * 5]
+	str	x9,       [x0, #16 * 6]
+
+	ldp	x19, x20, [x1, #16 * 0]
+	ldp	x21, x22, [x1, #16 * 1]
+	ldp	x23, x24, [x1, #16 * 2]
+	ldp	x25, x26, [x1, #16 * 3]
+	ldp	x27, x28, [x1, #16 * 4]
+	ldp	x29, lr,  [x1, #16 * 5]
+	ldr	x9,       [x1, #16 * 6]
+
+	mov	sp,  x9
+
+	ret
+
+.size	__switch_context, . - __switch_context
+.type	__switch_context, function
+.global	__switch_context
@@ -11,14 +11,14 @@
 //!
 //! crate::time::arch_time
 
-use crate::warn;
+use crate::{time, warn};
 use aarch64_cpu::{asm::barrier, registers::*};
 use core::{
     num::{NonZeroU128, NonZeroU32, NonZeroU64},
     ops::{Add, Div},
     time::Duration,
 };
-use tock_registers::interfaces::Readable;
+use tock_registers::interfaces::{Readable, Writeable};
 
 //--------------------------------------------------------------------------------------------------
 // Private Definitions
@@ -29,12 +29,20 @@ const NANOSEC_PER_SEC: NonZeroU64 = NonZeroU64::new(1_000_000_000).unwrap();
 #[derive(Copy, Clone, PartialOrd, PartialEq)]
 struct GenericTimerCounterValue(u64);
 
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// The ARMv8 Generic Timer, exposed as clock source and alarm.
+pub struct GenericTimer;
+
 //--------------------------------------------------------------------------------------------------
 // Global instances
 //--------------------------------------------------------------------------------------------------
 
 /// Boot assembly code overwrites this value with the value of CNTFRQ_EL0 before any Rust code is
-/// executed. This given value here is just a (safe) dummy.
+/// executed. This given value here is just a (safe) dummy. It stays if CNTFRQ_EL0 is 0 and the
+/// System Timer is the clock source.
 #[no_mangle]
 static ARCH_TIMER_COUNTER_FREQUENCY: NonZeroU32 = NonZeroU32::MIN;
 
@@ -130,6 +138,13 @@ fn read_cntpct() -> GenericTimerCounterValue {
 // Public Code
 //--------------------------------------------------------------------------------------------------
 
+/// Whether the boot code found a counter frequency. Firmware sets it to several MHz, so the dummy of
+/// 1 Hz means there was none, and the timer can not tell the time.
+#[cfg(not(feature = "chainloader"))]
+pub fn has_frequency() -> bool {
+    arch_timer_counter_frequency() != NonZeroU32::MIN
+}
+
 /// The timer's resolution.
 pub fn resolution() -> Duration {
     Duration::from(GenericTimerCounterValue(1))
@@ -160,3 +175,54 @@ pub fn spin_for(duration: Duration) {
     // Read CNTPCT_EL0 directly to avoid the ISB that is part of [`read_cntpct`].
     while GenericTimerCounterValue(CNTPCT_EL0.get()) < counter_value_target {}
 }
+
+/// Program the physical timer to fire an IRQ after `delay`.
+///
+/// `CNTP_TVAL_EL0` is a signed 32 bit down-counter. Longer delays are clamped, so the IRQ fires
+/// early and the handler is expected to program the timer again.
+pub fn set_timeout_irq(delay: Duration) {
+    let tval = match GenericTimerCounterValue::try_from(delay) {
+        Ok(val) => val.0.min(i32::MAX as u64),
+        Err(_) => i32::MAX as u64,
+    };
+
+    CNTP_TVAL_EL0.set(tval);
+    CNTP_CTL_EL0.write(CNTP_CTL_EL0::ENABLE::SET + CNTP_CTL_EL0::IMASK::CLEAR);
+}
+
+/// Stop the physical timer from asserting its IRQ.
+pub fn conclude_timeout_irq() {
+    CNTP_CTL_EL0.write(CNTP_CTL_EL0::ENABLE::CLEAR + CNTP_CTL_EL0::IMASK::SET);
+}
+
+//------------------------------------------------------------------------------
+// OS Interface Code
+//------------------------------------------------------------------------------
+
+impl time::interface::ClockSource for GenericTimer {
+    fn name(&self) -> &'static str {
+        "ARM Generic Timer"
+    }
+
+    fn resolution(&self) -> Duration {
+        resolution()
+    }
+
+    fn uptime(&self) -> Duration {
+        uptime()
+    }
+
+    fn spin_for(&self, duration: Duration) {
+        spin_for(duration)
+    }
+}
+
+impl time::interface::Alarm for GenericTimer {
+    fn set_alarm(&self, delay: Duration) {
+        set_timeout_irq(delay)
+    }
+
+    fn conclude_alarm(&self) {
+        conclude_timeout_irq()
+    }
+}
@@ -0,0 +1,102 @@
+//! Block devices.
+//!
+//! Storage drivers implement [`BlockDevice`] and register their devices during kernel init. The
+//! rest of the kernel looks them up by name. The partitions of a device are registered as devices
+//! of their own, see [`partition`].
+
+pub mod partition;
+
+use crate::{
+    info,
+    synchronization::{interface::ReadWriteEx, InitStateLock},
+};
+use alloc::vec::Vec;
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// A device that is read and written in blocks of a fixed size.
+pub trait BlockDevice {
+    /// Name of the device, e.g. `sd0`.
+    fn name(&self) -> &str;
+
+    /// Size of a block in bytes.
+    fn block_size(&self) -> usize;
+
+    /// Number of blocks on the device.
+    fn num_blocks(&self) -> u64;
+
+    /// Read whole blocks, starting at `first_block`, until `buf` is full.
+    ///
+    /// The length of `buf` must be a multiple of the block size.
+    fn read_blocks(&self, first_block: u64, buf: &mut [u8]) -> Result<(), &'static str>;
+
+    /// Write whole blocks from `buf`, starting at `first_block`.
+    ///
+    /// The length of `buf` must be a multiple of the block size.
+    fn write_blocks(&self, first_block: u64, buf: &[u8]) -> Result<(), &'static str>;
+}
+
+//--------------------------------------------------------------------------------------------------
+// Global instances
+//--------------------------------------------------------------------------------------------------
+
+static DEVICES: InitStateLock<Vec<&'static (dyn BlockDevice + Sync)>> =
+    InitStateLock::new(Vec::new());
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Check that a transfer of `len` bytes starting at `first_block` stays on `device`. Returns the
+/// number of blocks.
+pub fn check_range(
+    device: &(impl BlockDevice + ?Sized),
+    first_block: u64,
+    len: usize,
+) -> Result<u64, &'static str> {
+    if len % device.block_size() != 0 {
+        return Err("Buffer is not a multiple of the block size");
+    }
+
+    let count = (len / device.block_size()) as u64;
+    match first_block.checked_add(count) {
+        Some(end) if end <= device.num_blocks() => Ok(count),
+        _ => Err("Blocks out of range"),
+    }
+}
+
+/// Make a device known to the kernel.
+///
+/// Only during kernel init.
+pub fn register_device(device: &'static (dyn BlockDevice + Sync)) {
+    DEVICES.write(|devices| devices.push(device));
+}
+
+/// Look up a registered device by its name.
+pub fn device(name: &str) -> Option<&'static (dyn BlockDevice + Sync)> {
+    DEVICES.read(|devices| devices.iter().find(|device| device.name() == name).copied())
+}
+
+/// Print all registered devices with their size.
+pub fn print_devices() {
+    DEVICES.read(|devices| {
+        if devices.is_empty() {
+            info!("      None");
+        }
+
+        for (i, device) in devices.iter().enumerate() {
+            let size = device.num_blocks() * device.block_size() as u64;
+
+            info!(
+                "      {}. {}: {} blocks of {} bytes, {} MiB",
+                i + 1,
+                device.name(),
+                device.num_blocks(),
+                device.block_size(),
+                size / (1024 * 1024)
+            );
+        }
+    });
+}
@@ -0,0 +1,463 @@
+//! Partition tables.
+//!
+//! Each partition becomes a [`BlockDevice`] of its own, named after the whole device and the
+//! partition number, e.g. `sd0p1`. Block numbers are relative to the start of the partition, and
+//! transfers can not leave it.
+//!
+//! - MBR: The four primary partitions are numbered 1 to 4. Logical partitions in an extended
+//!   partition follow from 5 on.
+//! - GPT: Partitions are numbered by their slot in the partition entry array, counting from 1.
+//!   Both the header and the entry array must pass their CRC-32 check. If the primary header is
+//!   damaged, the backup header at the end of the device is used instead.
+//!
+//! # Resources
+//!
+//! - <https://uefi.org/specs/UEFI/2.10/05_GUID_Partition_Table_Format.html>
+//! - <https://en.wikipedia.org/wiki/Master_boot_record>
+
+use super::{check_range, register_device, BlockDevice};
+use crate::{common::crc32, info, warn};
+use alloc::{boxed::Box, format, string::String, vec, vec::Vec};
+use core::fmt;
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Partition tables are defined in terms of at least 512 byte blocks.
+const MIN_BLOCK_SIZE: usize = 512;
+
+const MBR_SIGNATURE_OFFSET: usize = 510;
+const MBR_SIGNATURE: [u8; 2] = [0x55, 0xAA];
+const MBR_ENTRIES_OFFSET: usize = 446;
+const MBR_ENTRY_SIZE: usize = 16;
+const MBR_NUM_ENTRIES: usize = 4;
+
+/// Boot indicators of an entry. Anything else means that the block is not an MBR, e.g. the boot
+/// sector of a file system without a partition table.
+const MBR_STATUS_VALID: [u8; 2] = [0x00, 0x80];
+
+/// Partition types of the MBR.
+const MBR_TYPE_EMPTY: u8 = 0x00;
+const MBR_TYPE_GPT_PROTECTIVE: u8 = 0xEE;
+const MBR_TYPES_EXTENDED: [u8; 3] = [0x05, 0x0F, 0x85];
+
+/// Number of the first logical partition.
+const FIRST_LOGICAL_NUMBER: usize = 5;
+
+/// Stop following a chain of extended boot records after this many, it might be a loop.
+const MAX_LOGICAL_PARTITIONS: usize = 64;
+
+const GPT_SIGNATURE: &[u8; 8] = b"EFI PART";
+const GPT_MIN_HEADER_SIZE: usize = 92;
+const GPT_MIN_ENTRY_SIZE: usize = 128;
+
+/// Upper bound for the partition entry array. The specification asks for at least 16 KiB.
+const GPT_MAX_ENTRIES_SIZE: usize = 64 * 1024;
+
+/// A partition as found in a table, before it is checked against the device.
+struct Entry {
+    number: usize,
+    kind: Kind,
+    first_block: u64,
+    num_blocks: u64,
+}
+
+/// The parts of a GPT header that are needed to find the partitions.
+struct GptHeader {
+    first_usable: u64,
+    last_usable: u64,
+    entries_lba: u64,
+    num_entries: usize,
+    entry_size: usize,
+    entries_crc: u32,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// A GUID in the mixed-endian layout of GPT.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct Guid(pub [u8; 16]);
+
+/// The type of a partition, as the table states it.
+#[derive(Copy, Clone)]
+pub enum Kind {
+    Mbr(u8),
+    Gpt(Guid),
+}
+
+/// A partition of a block device.
+pub struct Partition {
+    device: &'static (dyn BlockDevice + Sync),
+    name: String,
+    kind: Kind,
+    first_block: u64,
+    num_blocks: u64,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn u64_at(data: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+/// Read `count` blocks starting at `first_block`.
+fn read(
+    device: &(dyn BlockDevice + Sync),
+    first_block: u64,
+    count: usize,
+) -> Result<Vec<u8>, &'static str> {
+    let mut buf = vec![0; count * device.block_size()];
+    device.read_blocks(first_block, &mut buf)?;
+
+    Ok(buf)
+}
+
+/// The four entries of an MBR or an extended boot record, with their start relative to the record.
+fn mbr_entries(record: &[u8]) -> Result<[(u8, u64, u64); MBR_NUM_ENTRIES], &'static str> {
+    if record[MBR_SIGNATURE_OFFSET..MBR_SIGNATURE_OFFSET + 2] != MBR_SIGNATURE {
+        return Err("No partition table");
+    }
+
+    let entry = |i: usize| &record[MBR_ENTRIES_OFFSET + i * MBR_ENTRY_SIZE..][..MBR_ENTRY_SIZE];
+    if (0..MBR_NUM_ENTRIES).any(|i| !MBR_STATUS_VALID.contains(&entry(i)[0])) {
+        return Err("No partition table");
+    }
+
+    Ok([0, 1, 2, 3].map(|i| {
+        let entry = entry(i);
+
+        (
+            entry[4],
+            u64::from(u32_at(entry, 8)),
+            u64::from(u32_at(entry, 12)),
+        )
+    }))
+}
+
+/// Follow the chain of extended boot records, starting at the extended partition at `start`.
+fn parse_logical(
+    device: &(dyn BlockDevice + Sync),
+    start: u64,
+    entries: &mut Vec<Entry>,
+) -> Result<(), &'static str> {
+    let mut record_block = start;
+
+    for number in FIRST_LOGICAL_NUMBER..FIRST_LOGICAL_NUMBER + MAX_LOGICAL_PARTITIONS {
+        let record = read(device, record_block, 1)?;
+        let [(kind, first, count), (next_kind, next, _), ..] = mbr_entries(&record)?;
+
+        // Data partitions are relative to their record, the next record to the extended partition.
+        if kind != MBR_TYPE_EMPTY && count != 0 {
+            entries.push(Entry {
+                number,
+                kind: Kind::Mbr(kind),
+                first_block: record_block + first,
+                num_blocks: count,
+            });
+        }
+
+        if next_kind == MBR_TYPE_EMPTY || next == 0 {
+            return Ok(());
+        }
+        record_block = start + next;
+    }
+
+    Err("Too many logical partitions")
+}
+
+/// Partitions of an MBR. `Ok(None)` if the MBR only protects a GPT.
+fn parse_mbr(
+    device: &(dyn BlockDevice + Sync),
+    mbr: &[u8],
+) -> Result<Option<Vec<Entry>>, &'static str> {
+    let primary = mbr_entries(mbr)?;
+    if primary
+        .iter()
+        .any(|(kind, ..)| *kind == MBR_TYPE_GPT_PROTECTIVE)
+    {
+        return Ok(None);
+    }
+
+    let mut entries = Vec::new();
+    for (i, (kind, first_block, num_blocks)) in primary.into_iter().enumerate() {
+        if kind == MBR_TYPE_EMPTY || num_blocks == 0 {
+            continue;
+        }
+
+        if MBR_TYPES_EXTENDED.contains(&kind) {
+            if let Err(x) = parse_logical(device, first_block, &mut entries) {
+                warn!("{}: Extended partition: {}", device.name(), x);
+            }
+            continue;
+        }
+
+        entries.push(Entry {
+            number: i + 1,
+            kind: Kind::Mbr(kind),
+            first_block,
+            num_blocks,
+        });
+    }
+
+    Ok(Some(entries))
+}
+
+/// Read and check the GPT header at `lba`.
+fn read_gpt_header(device: &(dyn BlockDevice + Sync), lba: u64) -> Result<GptHeader, &'static str> {
+    let mut block = read(device, lba, 1)?;
+
+    if &block[0..8] != GPT_SIGNATURE {
+        return Err("No GPT header");
+    }
+
+    let header_size = u32_at(&block, 12) as usize;
+    if !(GPT_MIN_HEADER_SIZE..=block.len()).contains(&header_size) {
+        return Err("Malformed GPT header");
+    }
+
+    // The CRC covers the header with its own CRC field zeroed.
+    let header_crc = u32_at(&block, 16);
+    block[16..20].fill(0);
+    if crc32::crc32(&block[..header_size]) != header_crc {
+        return Err("GPT header CRC mismatch");
+    }
+
+    if u64_at(&block, 24) != lba {
+        return Err("GPT header is not where it says");
+    }
+
+    let header = GptHeader {
+        first_usable: u64_at(&block, 40),
+        last_usable: u64_at(&block, 48),
+        entries_lba: u64_at(&block, 72),
+        num_entries: u32_at(&block, 80) as usize,
+        entry_size: u32_at(&block, 84) as usize,
+        entries_crc: u32_at(&block, 88),
+    };
+
+    // 128 * 2^n bytes per entry.
+    if header.entry_size < GPT_MIN_ENTRY_SIZE
+        || !header.entry_size.is_power_of_two()
+        || header.num_entries * header.entry_size > GPT_MAX_ENTRIES_SIZE
+    {
+        return Err("Unsupported GPT entry array");
+    }
+
+    Ok(header)
+}
+
+/// Partitions of the GPT described by `header`.
+fn parse_gpt(
+    device: &(dyn BlockDevice + Sync),
+    header: &GptHeader,
+) -> Result<Vec<Entry>, &'static str> {
+    let size = header.num_entries * header.entry_size;
+    let array = read(
+        device,
+        header.entries_lba,
+        size.div_ceil(device.block_size()),
+    )?;
+
+    if crc32::crc32(&array[..size]) != header.entries_crc {
+        return Err("GPT entry array CRC mismatch");
+    }
+
+    let mut entries = Vec::new();
+    for (i, entry) in array[..size].chunks_exact(header.entry_size).enumerate() {
+        let type_guid = Guid(entry[0..16].try_into().unwrap());
+        if type_guid == Guid::UNUSED {
+            continue;
+        }
+
+        let (first_lba, last_lba) = (u64_at(entry, 32), u64_at(entry, 40));
+        if first_lba < header.first_usable || last_lba > header.last_usable || last_lba < first_lba
+        {
+            warn!(
+                "{}: GPT partition {} is outside of the usable blocks",
+                device.name(),
+                i + 1
+            );
+            continue;
+        }
+
+        entries.push(Entry {
+            number: i + 1,
+            kind: Kind::Gpt(type_guid),
+            first_block: first_lba,
+            num_blocks: last_lba - first_lba + 1,
+        });
+    }
+
+    Ok(entries)
+}
+
+/// Find the partitions in the primary GPT, or in the backup GPT if the primary one is damaged.
+fn find_gpt(device: &(dyn BlockDevice + Sync)) -> Result<Vec<Entry>, &'static str> {
+    let primary = read_gpt_header(device, 1).and_then(|header| parse_gpt(device, &header));
+
+    primary.or_else(|x| {
+        warn!("{}: Primary GPT: {}, trying the backup", device.name(), x);
+
+        let backup_lba = device.num_blocks().saturating_sub(1);
+        read_gpt_header(device, backup_lba).and_then(|header| parse_gpt(device, &header))
+    })
+}
+
+impl Guid {
+    /// The type of unused GPT entries.
+    const UNUSED: Self = Self([0; 16]);
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Read the partition table of `device`.
+///
+/// Partitions that don't fit on the device, or overlap one listed before them, are left out with a
+/// warning.
+pub fn scan(device: &'static (dyn BlockDevice + Sync)) -> Result<Vec<Partition>, &'static str> {
+    if device.block_size() < MIN_BLOCK_SIZE {
+        return Err("Block size too small for a partition table");
+    }
+
+    let mbr = read(device, 0, 1)?;
+    let entries = match parse_mbr(device, &mbr)? {
+        Some(entries) => entries,
+        None => find_gpt(device)?,
+    };
+
+    let mut partitions: Vec<Partition> = Vec::new();
+    for entry in entries {
+        let fits = entry
+            .first_block
+            .checked_add(entry.num_blocks)
+            .map_or(false, |end| end <= device.num_blocks());
+        if !fits {
+            warn!(
+                "{}: Partition {} does not fit on the device",
+                device.name(),
+                entry.number
+            );
+            continue;
+        }
+
+        // Writes to one partition would corrupt the other. The first one in the table wins.
+        let end = entry.first_block + entry.num_blocks;
+        let overlapped = partitions.iter().find(|other| {
+            entry.first_block < other.first_block + other.num_blocks && other.first_block < end
+        });
+        if let Some(other) = overlapped {
+            warn!(
+                "{}: Partition {} overlaps {}",
+                device.name(),
+                entry.number,
+                other.name
+            );
+            continue;
+        }
+
+        partitions.push(Partition {
+            device,
+            name: format!("{}p{}", device.name(), entry.number),
+            kind: entry.kind,
+            first_block: entry.first_block,
+            num_blocks: entry.num_blocks,
+        });
+    }
+
+    Ok(partitions)
+}
+
+/// Scan `device` and register its partitions as block devices. Returns how many there are.
+///
+/// Only during kernel init.
+pub fn register_partitions(
+    device: &'static (dyn BlockDevice + Sync),
+) -> Result<usize, &'static str> {
+    let partitions = scan(device)?;
+    let count = partitions.len();
+
+    for partition in partitions {
+        info!(
+            "{}: {} blocks from block {}, {}",
+            partition.name, partition.num_blocks, partition.first_block, partition.kind
+        );
+
+        register_device(Box::leak(Box::new(partition)));
+    }
+
+    Ok(count)
+}
+
+impl fmt::Display for Guid {
+    /// The usual text form. The first three fields are little endian.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let g = &self.0;
+
+        write!(
+            f,
+            "{:08X}-{:04X}-{:04X}-{:02X}{:02X}-",
+            u32::from_le_bytes([g[0], g[1], g[2], g[3]]),
+            u16::from_le_bytes([g[4], g[5]]),
+            u16::from_le_bytes([g[6], g[7]]),
+            g[8],
+            g[9]
+        )?;
+
+        for byte in &g[10..] {
+            write!(f, "{:02X}", byte)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Kind::Mbr(kind) => write!(f, "MBR type {:#04x}", kind),
+            Kind::Gpt(guid) => write!(f, "GPT type {}", guid),
+        }
+    }
+}
+
+//------------------------------------------------------------------------------
+// OS Interface Code
+//------------------------------------------------------------------------------
+
+impl BlockDevice for Partition {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn block_size(&self) -> usize {
+        self.device.block_size()
+    }
+
+    fn num_blocks(&self) -> u64 {
+        self.num_blocks
+    }
+
+    fn read_blocks(&self, first_block: u64, buf: &mut [u8]) -> Result<(), &'static str> {
+        check_range(self, first_block, buf.len())?;
+
+        self.device.read_blocks(self.first_block + first_block, buf)
+    }
+
+    fn write_blocks(&self, first_block: u64, buf: &[u8]) -> Result<(), &'static str> {
+        check_range(self, first_block, buf.len())?;
+
+        self.device
+            .write_blocks(self.first_block + first_block, buf)
+    }
+}
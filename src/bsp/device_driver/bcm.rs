@@ -4,12 +4,30 @@
 
 //! BCM driver top level.
 
+#[cfg(not(feature = "chainloader"))]
+mod bcm2xxx_emmc;
 mod bcm2xxx_gpio;
+#[cfg(feature = "bsp_rpi3")]
+mod bcm2xxx_interrupt_controller;
 mod bcm2xxx_mailbox;
 mod bcm2xxx_pl011_uart;
+#[cfg(not(feature = "chainloader"))]
+mod bcm2xxx_power_management;
+#[cfg(not(feature = "chainloader"))]
+mod bcm2xxx_system_timer;
+#[cfg(not(feature = "chainloader"))]
 mod bcm2xxx_video;
 
+#[cfg(not(feature = "chainloader"))]
+pub use bcm2xxx_emmc::*;
 pub use bcm2xxx_gpio::*;
+#[cfg(feature = "bsp_rpi3")]
+pub use bcm2xxx_interrupt_controller::*;
 pub use bcm2xxx_mailbox::*;
 pub use bcm2xxx_pl011_uart::*;
+#[cfg(not(feature = "chainloader"))]
+pub use bcm2xxx_power_management::*;
+#[cfg(not(feature = "chainloader"))]
+pub use bcm2xxx_system_timer::*;
+#[cfg(not(feature = "chainloader"))]
 pub use bcm2xxx_video::*;
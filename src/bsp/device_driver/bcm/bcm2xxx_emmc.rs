@@ -0,0 +1,800 @@
+//! BCM EMMC driver.
+//!
+//! The SD host controller of the Raspberry Pi 3 is an Arasan SDHCI, the Raspberry Pi 4 brings the
+//! card slot to EMMC2. Both follow the SD Host Controller Simplified Specification 3.0 closely
+//! enough to share the driver. Data moves by PIO through the `DATA` register, 512 byte blocks at a
+//! time. Interrupts are not used, the driver polls the status bits.
+//!
+//! Cards are brought up once during driver init: SDSC cards of version 1 and 2, and SDHC/SDXC cards
+//! with block addressing. Cards that are swapped afterwards are not noticed.
+//!
+//! # Resources
+//!
+//! - <https://www.sdcard.org/downloads/pls/> (Physical Layer and Host Controller Simplified
+//!   Specifications)
+//! - <https://github.com/raspberrypi/documentation/files/1888662/BCM2837-ARM-Peripherals.-.Revised.-.V2-1.pdf>
+//! - <https://github.com/raspberrypi/linux/blob/rpi-5.15.y/drivers/mmc/host/sdhci-iproc.c>
+
+use crate::{
+    block,
+    bsp::{device_driver::common::MMIODerefWrapper, driver::MAILBOX},
+    driver,
+    exception::asynchronous::IRQNumber,
+    synchronization,
+    synchronization::SleepLock,
+    time,
+};
+use core::time::Duration;
+use tock_registers::{
+    fields::{Field, FieldValue},
+    interfaces::{ReadWriteable, Readable, Writeable},
+    register_bitfields, register_structs,
+    registers::{ReadOnly, ReadWrite},
+};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+register_bitfields! {
+    u32,
+
+    /// Block Size and Count
+    BLKSIZECNT [
+        BLKSIZE OFFSET(0) NUMBITS(10) [],
+        BLKCNT OFFSET(16) NUMBITS(16) []
+    ],
+
+    /// Command and Transfer Mode
+    CMDTM [
+        /// Count down the blocks in BLKCNT.
+        TM_BLKCNT_EN OFFSET(1) NUMBITS(1) [],
+
+        TM_AUTO_CMD_EN OFFSET(2) NUMBITS(2) [
+            None = 0b00,
+            Cmd12 = 0b01
+        ],
+
+        TM_DAT_DIR OFFSET(4) NUMBITS(1) [
+            HostToCard = 0,
+            CardToHost = 1
+        ],
+
+        TM_MULTI_BLOCK OFFSET(5) NUMBITS(1) [],
+
+        CMD_RSPNS_TYPE OFFSET(16) NUMBITS(2) [
+            None = 0b00,
+            Bits136 = 0b01,
+            Bits48 = 0b10,
+            Bits48Busy = 0b11
+        ],
+
+        CMD_CRCCHK_EN OFFSET(19) NUMBITS(1) [],
+        CMD_IXCHK_EN OFFSET(20) NUMBITS(1) [],
+        CMD_ISDATA OFFSET(21) NUMBITS(1) [],
+        CMD_INDEX OFFSET(24) NUMBITS(6) []
+    ],
+
+    /// Status
+    STATUS [
+        CMD_INHIBIT OFFSET(0) NUMBITS(1) [],
+        DAT_INHIBIT OFFSET(1) NUMBITS(1) []
+    ],
+
+    /// Host Configuration 0
+    CONTROL0 [
+        /// Use 4 data lines.
+        HCTL_DWIDTH OFFSET(1) NUMBITS(1) [],
+
+        /// SD bus power, the power control register of the specification.
+        BUS_POWER OFFSET(8) NUMBITS(1) [],
+        BUS_VOLTAGE OFFSET(9) NUMBITS(3) [
+            V3_3 = 0b111
+        ]
+    ],
+
+    /// Host Configuration 1
+    CONTROL1 [
+        CLK_INTLEN OFFSET(0) NUMBITS(1) [],
+        CLK_STABLE OFFSET(1) NUMBITS(1) [],
+        CLK_EN OFFSET(2) NUMBITS(1) [],
+
+        /// Upper and lower bits of the 10 bit clock divisor.
+        CLK_FREQ_MS2 OFFSET(6) NUMBITS(2) [],
+        CLK_FREQ8 OFFSET(8) NUMBITS(8) [],
+
+        /// Data timeout as a power of two of base clock cycles, minus 13.
+        DATA_TOUNIT OFFSET(16) NUMBITS(4) [
+            Max = 0b1110
+        ],
+
+        SRST_HC OFFSET(24) NUMBITS(1) [],
+        SRST_CMD OFFSET(25) NUMBITS(1) [],
+        SRST_DATA OFFSET(26) NUMBITS(1) []
+    ],
+
+    /// Interrupt Flags. Write one to clear.
+    INTERRUPT [
+        CMD_DONE OFFSET(0) NUMBITS(1) [],
+        DATA_DONE OFFSET(1) NUMBITS(1) [],
+        WRITE_RDY OFFSET(4) NUMBITS(1) [],
+        READ_RDY OFFSET(5) NUMBITS(1) [],
+
+        /// Set together with any of the error flags.
+        ERR OFFSET(15) NUMBITS(1) [],
+        CTO_ERR OFFSET(16) NUMBITS(1) []
+    ],
+
+    /// Slot Interrupt Status and Version
+    SLOTISR_VER [
+        SDVERSION OFFSET(16) NUMBITS(8) [
+            V3 = 2
+        ]
+    ]
+}
+
+register_structs! {
+    #[allow(non_snake_case)]
+    RegisterBlock {
+        (0x00 => _reserved1),
+        (0x04 => BLKSIZECNT: ReadWrite<u32, BLKSIZECNT::Register>),
+        (0x08 => ARG1: ReadWrite<u32>),
+        (0x0C => CMDTM: ReadWrite<u32, CMDTM::Register>),
+        (0x10 => RESP: [ReadOnly<u32>; 4]),
+        (0x20 => DATA: ReadWrite<u32>),
+        (0x24 => STATUS: ReadOnly<u32, STATUS::Register>),
+        (0x28 => CONTROL0: ReadWrite<u32, CONTROL0::Register>),
+        (0x2C => CONTROL1: ReadWrite<u32, CONTROL1::Register>),
+        (0x30 => INTERRUPT: ReadWrite<u32, INTERRUPT::Register>),
+        (0x34 => IRPT_MASK: ReadWrite<u32>),
+        (0x38 => IRPT_EN: ReadWrite<u32>),
+        (0x3C => _reserved2),
+        (0xFC => SLOTISR_VER: ReadOnly<u32, SLOTISR_VER::Register>),
+        (0x100 => @END),
+    }
+}
+
+/// Abstraction for the associated MMIO registers.
+type Registers = MMIODerefWrapper<RegisterBlock>;
+
+/// The firmware's clock ID of the controller's base clock.
+#[cfg(feature = "bsp_rpi3")]
+const BASE_CLOCK_ID: u32 = 1;
+
+/// The firmware's clock ID of the controller's base clock.
+#[cfg(feature = "bsp_rpi4")]
+const BASE_CLOCK_ID: u32 = 12;
+
+/// Card clock while the card is identified.
+const IDENTIFICATION_CLOCK_HZ: u32 = 400_000;
+
+/// Card clock in Default Speed mode.
+const TRANSFER_CLOCK_HZ: u32 = 25_000_000;
+
+const BLOCK_SIZE: usize = 512;
+
+/// Largest number of blocks in one command, limited by BLKCNT.
+const MAX_BLOCKS_PER_COMMAND: u64 = 0xFFFF;
+
+/// How long to wait for the controller itself, e.g. for a reset or a stable clock.
+const HOST_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// How long to wait for a command to complete.
+const COMMAND_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// How long to wait for a block of data, or for a card that is busy after a write.
+const DATA_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// How long a card may take to power up in ACMD41.
+const POWER_UP_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The card status bits of an R1 response that report an error.
+const R1_ERRORS: u32 = 0xFDF9_8008;
+
+/// CMD8 argument: 2.7-3.6 V and a check pattern that the card echoes.
+const IF_COND: u32 = 0x1AA;
+
+/// ACMD41 argument: 3.2-3.4 V, and high capacity cards are welcome.
+const OCR_VOLTAGE_WINDOW: u32 = 0x0030_0000;
+const OCR_HCS: u32 = 1 << 30;
+const OCR_BUSY: u32 = 1 << 31;
+
+/// The error of `send_command()` for a command that got no response.
+const NO_RESPONSE: &str = "Card did not respond";
+
+/// SD commands by index.
+mod cmd {
+    pub const GO_IDLE_STATE: u32 = 0;
+    pub const ALL_SEND_CID: u32 = 2;
+    pub const SEND_RELATIVE_ADDR: u32 = 3;
+    pub const SELECT_CARD: u32 = 7;
+    pub const SEND_IF_COND: u32 = 8;
+    pub const SEND_CSD: u32 = 9;
+    pub const SET_BLOCKLEN: u32 = 16;
+    pub const READ_SINGLE_BLOCK: u32 = 17;
+    pub const READ_MULTIPLE_BLOCK: u32 = 18;
+    pub const WRITE_BLOCK: u32 = 24;
+    pub const WRITE_MULTIPLE_BLOCK: u32 = 25;
+    pub const APP_CMD: u32 = 55;
+
+    pub const SET_BUS_WIDTH: u32 = 6;
+    pub const SD_SEND_OP_COND: u32 = 41;
+}
+
+/// Response types of the SD commands.
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Response {
+    None,
+    R1,
+    R1b,
+    R2,
+    R3,
+    R6,
+    R7,
+}
+
+/// Direction of a data transfer.
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Direction {
+    Read,
+    Write,
+}
+
+/// The buffer of a transfer, by direction.
+enum ChunkBuf<'a> {
+    Read(&'a mut [u8]),
+    Write(&'a [u8]),
+}
+
+/// An initialized card.
+struct Card {
+    /// SDHC and SDXC cards are addressed in blocks, SDSC cards in bytes.
+    high_capacity: bool,
+
+    num_blocks: u64,
+}
+
+struct EMMCInner {
+    registers: Registers,
+
+    /// Base clock of the controller in Hz.
+    base_clock: u32,
+
+    /// Current card clock in Hz.
+    clock: u32,
+
+    /// The card, or why there is none.
+    card: Result<Card, &'static str>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Representation of the EMMC controller and the card in its slot.
+///
+/// Transfers are long and never happen in IRQ context, so the controller is behind a lock that
+/// sleeps instead of masking IRQs.
+pub struct EMMC {
+    inner: SleepLock<EMMCInner>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+/// Wait until `condition` holds, for at most `timeout`.
+fn poll_until(timeout: Duration, mut condition: impl FnMut() -> bool) -> bool {
+    let deadline = time::time_manager().uptime() + timeout;
+
+    while !condition() {
+        if time::time_manager().uptime() >= deadline {
+            return condition();
+        }
+    }
+
+    true
+}
+
+/// Extract bits `high..=low` of a CSD from an R2 response.
+///
+/// The controller drops the CRC byte, so the response holds bits 127..8 of the register, shifted
+/// down by 8.
+fn csd_bits(response: &[u32; 4], high: u32, low: u32) -> u64 {
+    let csd = response
+        .iter()
+        .rev()
+        .fold(0u128, |csd, word| (csd << 32) | u128::from(*word));
+
+    ((csd >> (low - 8)) & ((1 << (high - low + 1)) - 1)) as u64
+}
+
+/// The card's size in blocks, from its CSD.
+fn capacity_from_csd(csd: &[u32; 4]) -> Result<u64, &'static str> {
+    let num_blocks = match csd_bits(csd, 127, 126) {
+        // SDSC: (C_SIZE + 1) * 2^(C_SIZE_MULT + 2) blocks of 2^READ_BL_LEN bytes.
+        0 => {
+            let c_size = csd_bits(csd, 73, 62);
+            let c_size_mult = csd_bits(csd, 49, 47);
+            let read_bl_len = csd_bits(csd, 83, 80);
+
+            ((c_size + 1) << (c_size_mult + 2 + read_bl_len)) / BLOCK_SIZE as u64
+        }
+
+        // SDHC and SDXC: (C_SIZE + 1) * 512 KiB.
+        1 => (csd_bits(csd, 69, 48) + 1) * 1024,
+
+        _ => return Err("Unknown CSD structure"),
+    };
+
+    if num_blocks == 0 {
+        return Err("Card reports no capacity");
+    }
+
+    Ok(num_blocks)
+}
+
+impl Response {
+    fn cmdtm(self) -> FieldValue<u32, CMDTM::Register> {
+        let checked = CMDTM::CMD_CRCCHK_EN::SET + CMDTM::CMD_IXCHK_EN::SET;
+
+        match self {
+            Response::None => CMDTM::CMD_RSPNS_TYPE::None,
+            Response::R1 | Response::R6 | Response::R7 => CMDTM::CMD_RSPNS_TYPE::Bits48 + checked,
+            Response::R1b => CMDTM::CMD_RSPNS_TYPE::Bits48Busy + checked,
+            Response::R2 => CMDTM::CMD_RSPNS_TYPE::Bits136 + CMDTM::CMD_CRCCHK_EN::SET,
+            // The OCR comes without a valid CRC and command index.
+            Response::R3 => CMDTM::CMD_RSPNS_TYPE::Bits48,
+        }
+    }
+}
+
+impl ChunkBuf<'_> {
+    fn len(&self) -> usize {
+        match self {
+            ChunkBuf::Read(buf) => buf.len(),
+            ChunkBuf::Write(buf) => buf.len(),
+        }
+    }
+
+    fn direction(&self) -> Direction {
+        match self {
+            ChunkBuf::Read(_) => Direction::Read,
+            ChunkBuf::Write(_) => Direction::Write,
+        }
+    }
+}
+
+impl EMMCInner {
+    /// Create an instance.
+    ///
+    /// # Safety
+    ///
+    /// - The user must ensure to provide a correct MMIO start address.
+    pub const unsafe fn new(mmio_start_addr: usize) -> Self {
+        Self {
+            registers: Registers::new(mmio_start_addr),
+            base_clock: 0,
+            clock: 0,
+            card: Err("Not initialized"),
+        }
+    }
+
+    /// The Arasan controller may lose a register write that follows another one within two card
+    /// clock cycles. That only matters while the card clock is slow.
+    fn settle(&self) {
+        if self.clock <= IDENTIFICATION_CLOCK_HZ {
+            time::time_manager().spin_for(Duration::from_micros(10));
+        }
+    }
+
+    /// Reset the command and data circuits after an error.
+    fn reset_lines(&mut self) {
+        self.registers
+            .CONTROL1
+            .modify(CONTROL1::SRST_CMD::SET + CONTROL1::SRST_DATA::SET);
+
+        poll_until(HOST_TIMEOUT, || {
+            !self.registers.CONTROL1.is_set(CONTROL1::SRST_CMD)
+                && !self.registers.CONTROL1.is_set(CONTROL1::SRST_DATA)
+        });
+
+        self.registers.INTERRUPT.set(u32::MAX);
+    }
+
+    /// Wait for a `flag` in INTERRUPT and clear it.
+    fn wait_for(
+        &mut self,
+        flag: Field<u32, INTERRUPT::Register>,
+        timeout: Duration,
+    ) -> Result<(), &'static str> {
+        let mut interrupt = self.registers.INTERRUPT.extract();
+        let arrived = poll_until(timeout, || {
+            interrupt = self.registers.INTERRUPT.extract();
+            interrupt.is_set(flag) || interrupt.is_set(INTERRUPT::ERR)
+        });
+
+        if interrupt.is_set(INTERRUPT::ERR) {
+            let no_response = interrupt.is_set(INTERRUPT::CTO_ERR);
+            self.reset_lines();
+
+            return Err(if no_response {
+                NO_RESPONSE
+            } else {
+                "Transfer error"
+            });
+        }
+
+        if !arrived {
+            self.reset_lines();
+            return Err("Timeout");
+        }
+
+        self.registers.INTERRUPT.write(flag.val(1));
+        Ok(())
+    }
+
+    /// Send a command and return its response. Data transfers announce their number of blocks and
+    /// their direction in `data`, the data itself is up to the caller.
+    fn send_command(
+        &mut self,
+        index: u32,
+        response: Response,
+        arg: u32,
+        data: Option<(u64, Direction)>,
+    ) -> Result<[u32; 4], &'static str> {
+        let uses_data_line = data.is_some() || response == Response::R1b;
+        let idle = poll_until(COMMAND_TIMEOUT, || {
+            let status = self.registers.STATUS.extract();
+            let data_busy = uses_data_line && status.is_set(STATUS::DAT_INHIBIT);
+
+            !status.is_set(STATUS::CMD_INHIBIT) && !data_busy
+        });
+        if !idle {
+            return Err("Controller busy");
+        }
+
+        self.registers.INTERRUPT.set(u32::MAX);
+
+        let mut cmdtm = CMDTM::CMD_INDEX.val(index) + response.cmdtm();
+        if let Some((count, direction)) = data {
+            self.registers.BLKSIZECNT.write(
+                BLKSIZECNT::BLKSIZE.val(BLOCK_SIZE as u32) + BLKSIZECNT::BLKCNT.val(count as u32),
+            );
+            self.settle();
+
+            cmdtm += CMDTM::CMD_ISDATA::SET;
+            cmdtm += match direction {
+                Direction::Read => CMDTM::TM_DAT_DIR::CardToHost,
+                Direction::Write => CMDTM::TM_DAT_DIR::HostToCard,
+            };
+            if count > 1 {
+                cmdtm += CMDTM::TM_MULTI_BLOCK::SET
+                    + CMDTM::TM_BLKCNT_EN::SET
+                    + CMDTM::TM_AUTO_CMD_EN::Cmd12;
+            }
+        }
+
+        self.registers.ARG1.set(arg);
+        self.settle();
+        self.registers.CMDTM.write(cmdtm);
+        self.settle();
+
+        self.wait_for(INTERRUPT::CMD_DONE, COMMAND_TIMEOUT)?;
+
+        let resp = [0, 1, 2, 3].map(|i| self.registers.RESP[i].get());
+
+        // The end of the busy signal counts as the end of a transfer.
+        if response == Response::R1b {
+            self.wait_for(INTERRUPT::DATA_DONE, DATA_TIMEOUT)?;
+        }
+
+        Ok(resp)
+    }
+
+    /// Send an application specific command, prefixed by APP_CMD.
+    fn send_app_command(
+        &mut self,
+        index: u32,
+        response: Response,
+        arg: u32,
+        rca: u32,
+    ) -> Result<[u32; 4], &'static str> {
+        self.send_command(cmd::APP_CMD, Response::R1, rca << 16, None)?;
+        self.send_command(index, response, arg, None)
+    }
+
+    /// Set the card clock to at most `hz`.
+    fn set_clock(&mut self, hz: u32) -> Result<(), &'static str> {
+        let idle = poll_until(HOST_TIMEOUT, || {
+            let status = self.registers.STATUS.extract();
+            !status.is_set(STATUS::CMD_INHIBIT) && !status.is_set(STATUS::DAT_INHIBIT)
+        });
+        if !idle {
+            return Err("Controller busy");
+        }
+
+        self.registers.CONTROL1.modify(CONTROL1::CLK_EN::CLEAR);
+        self.settle();
+
+        // 10 bit divided clock mode: base / (2 * divisor), or the base clock for a divisor of 0.
+        let divisor = if self.base_clock <= hz {
+            0
+        } else {
+            self.base_clock.div_ceil(2 * hz).min(0x3FF)
+        };
+
+        self.registers.CONTROL1.modify(
+            CONTROL1::CLK_FREQ8.val(divisor & 0xFF)
+                + CONTROL1::CLK_FREQ_MS2.val(divisor >> 8)
+                + CONTROL1::DATA_TOUNIT::Max
+                + CONTROL1::CLK_INTLEN::SET,
+        );
+        self.settle();
+
+        if !poll_until(HOST_TIMEOUT, || {
+            self.registers.CONTROL1.is_set(CONTROL1::CLK_STABLE)
+        }) {
+            return Err("Clock did not become stable");
+        }
+
+        self.registers.CONTROL1.modify(CONTROL1::CLK_EN::SET);
+        self.clock = match divisor {
+            0 => self.base_clock,
+            _ => self.base_clock / (2 * divisor),
+        };
+        self.settle();
+
+        Ok(())
+    }
+
+    /// Reset the controller and power the card bus.
+    fn init_host(&mut self) -> Result<(), &'static str> {
+        if !self
+            .registers
+            .SLOTISR_VER
+            .matches_all(SLOTISR_VER::SDVERSION::V3)
+        {
+            return Err("Unsupported host controller version");
+        }
+
+        self.base_clock = MAILBOX.clock_rate(BASE_CLOCK_ID)?;
+        if self.base_clock == 0 {
+            return Err("Unknown base clock");
+        }
+
+        self.registers.CONTROL0.set(0);
+        self.registers.CONTROL1.write(CONTROL1::SRST_HC::SET);
+        if !poll_until(HOST_TIMEOUT, || {
+            !self.registers.CONTROL1.is_set(CONTROL1::SRST_HC)
+        }) {
+            return Err("Controller did not come out of reset");
+        }
+
+        self.registers
+            .CONTROL0
+            .write(CONTROL0::BUS_POWER::SET + CONTROL0::BUS_VOLTAGE::V3_3);
+
+        // Status flags only, no IRQs.
+        self.registers.IRPT_EN.set(0);
+        self.registers.IRPT_MASK.set(u32::MAX);
+        self.registers.INTERRUPT.set(u32::MAX);
+
+        self.clock = 0;
+        self.set_clock(IDENTIFICATION_CLOCK_HZ)
+    }
+
+    /// Identify the card and bring it to the transfer state.
+    fn init_card(&mut self) -> Result<Card, &'static str> {
+        self.send_command(cmd::GO_IDLE_STATE, Response::None, 0, None)?;
+
+        // Version 1 cards don't know CMD8.
+        let version_2 = match self.send_command(cmd::SEND_IF_COND, Response::R7, IF_COND, None) {
+            Ok(resp) if resp[0] & 0xFFF == IF_COND => true,
+            Ok(_) => return Err("Card does not support the voltage"),
+            Err(NO_RESPONSE) => false,
+            Err(x) => return Err(x),
+        };
+
+        let arg = OCR_VOLTAGE_WINDOW | if version_2 { OCR_HCS } else { 0 };
+        let deadline = time::time_manager().uptime() + POWER_UP_TIMEOUT;
+        let ocr = loop {
+            let ocr = match self.send_app_command(cmd::SD_SEND_OP_COND, Response::R3, arg, 0) {
+                Ok(resp) => resp[0],
+                Err(NO_RESPONSE) => return Err("No card"),
+                Err(x) => return Err(x),
+            };
+
+            if ocr & OCR_BUSY != 0 {
+                break ocr;
+            }
+
+            if time::time_manager().uptime() >= deadline {
+                return Err("Card did not power up");
+            }
+            time::time_manager().spin_for(Duration::from_millis(10));
+        };
+
+        self.send_command(cmd::ALL_SEND_CID, Response::R2, 0, None)?;
+        let rca = self.send_command(cmd::SEND_RELATIVE_ADDR, Response::R6, 0, None)?[0] >> 16;
+        let csd = self.send_command(cmd::SEND_CSD, Response::R2, rca << 16, None)?;
+        let num_blocks = capacity_from_csd(&csd)?;
+
+        self.send_command(cmd::SELECT_CARD, Response::R1b, rca << 16, None)?;
+
+        // All SD cards support 4 data lines.
+        self.send_app_command(cmd::SET_BUS_WIDTH, Response::R1, 0b10, rca)?;
+        self.registers.CONTROL0.modify(CONTROL0::HCTL_DWIDTH::SET);
+
+        self.set_clock(TRANSFER_CLOCK_HZ)?;
+
+        let high_capacity = ocr & OCR_HCS != 0;
+        if !high_capacity {
+            self.send_command(cmd::SET_BLOCKLEN, Response::R1, BLOCK_SIZE as u32, None)?;
+        }
+
+        Ok(Card {
+            high_capacity,
+            num_blocks,
+        })
+    }
+
+    // A broken controller or a missing card is no reason to fail the boot. They only leave the
+    // device without a card.
+    fn init(&mut self) {
+        self.card = self.init_host().and_then(|_| self.init_card());
+    }
+
+    /// Transfer up to `MAX_BLOCKS_PER_COMMAND` blocks between `buf` and the card.
+    fn transfer_chunk(
+        &mut self,
+        first_block: u64,
+        mut buf: ChunkBuf<'_>,
+    ) -> Result<(), &'static str> {
+        let direction = buf.direction();
+        let (high_capacity, count) = match &self.card {
+            Ok(card) => (card.high_capacity, (buf.len() / BLOCK_SIZE) as u64),
+            Err(_) => return Err("No card"),
+        };
+
+        let address = if high_capacity {
+            first_block
+        } else {
+            first_block * BLOCK_SIZE as u64
+        };
+        let index = match (direction, count) {
+            (Direction::Read, 1) => cmd::READ_SINGLE_BLOCK,
+            (Direction::Read, _) => cmd::READ_MULTIPLE_BLOCK,
+            (Direction::Write, 1) => cmd::WRITE_BLOCK,
+            (Direction::Write, _) => cmd::WRITE_MULTIPLE_BLOCK,
+        };
+
+        let resp = self.send_command(
+            index,
+            Response::R1,
+            address as u32,
+            Some((count, direction)),
+        )?;
+        if resp[0] & R1_ERRORS != 0 {
+            self.reset_lines();
+            return Err("Card rejected the transfer");
+        }
+
+        for block in 0..count as usize {
+            let range = block * BLOCK_SIZE..(block + 1) * BLOCK_SIZE;
+
+            match &mut buf {
+                ChunkBuf::Read(buf) => {
+                    self.wait_for(INTERRUPT::READ_RDY, DATA_TIMEOUT)?;
+                    for word in buf[range].chunks_exact_mut(4) {
+                        word.copy_from_slice(&self.registers.DATA.get().to_le_bytes());
+                    }
+                }
+                ChunkBuf::Write(buf) => {
+                    self.wait_for(INTERRUPT::WRITE_RDY, DATA_TIMEOUT)?;
+                    for word in buf[range].chunks_exact(4) {
+                        self.registers
+                            .DATA
+                            .set(u32::from_le_bytes(word.try_into().unwrap()));
+                    }
+                }
+            }
+        }
+
+        // Includes the card's busy time after a write.
+        self.wait_for(INTERRUPT::DATA_DONE, DATA_TIMEOUT)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl EMMC {
+    #[cfg(feature = "bsp_rpi3")]
+    pub const COMPATIBLE: &'static str = "BCM Arasan EMMC";
+
+    #[cfg(feature = "bsp_rpi4")]
+    pub const COMPATIBLE: &'static str = "BCM EMMC2";
+
+    /// Create an instance.
+    ///
+    /// # Safety
+    ///
+    /// - The user must ensure to provide a correct MMIO start address.
+    pub const unsafe fn new(mmio_start_addr: usize) -> Self {
+        Self {
+            inner: SleepLock::new(EMMCInner::new(mmio_start_addr)),
+        }
+    }
+
+    /// Whether a card was brought up during init, and if not, why.
+    pub fn card_status(&self) -> Result<(), &'static str> {
+        self.inner
+            .lock(|inner| inner.card.as_ref().map(|_| ()).map_err(|x| *x))
+    }
+}
+
+//------------------------------------------------------------------------------
+// OS Interface Code
+//------------------------------------------------------------------------------
+use synchronization::interface::Mutex;
+
+impl driver::interface::DeviceDriver for EMMC {
+    type IRQNumberType = IRQNumber;
+
+    fn compatible(&self) -> &'static str {
+        Self::COMPATIBLE
+    }
+
+    unsafe fn init(&self) -> Result<(), &'static str> {
+        self.inner.lock(|inner| inner.init());
+
+        Ok(())
+    }
+}
+
+impl block::BlockDevice for EMMC {
+    fn name(&self) -> &str {
+        "sd0"
+    }
+
+    fn block_size(&self) -> usize {
+        BLOCK_SIZE
+    }
+
+    fn num_blocks(&self) -> u64 {
+        self.inner
+            .lock(|inner| inner.card.as_ref().map_or(0, |card| card.num_blocks))
+    }
+
+    fn read_blocks(&self, first_block: u64, buf: &mut [u8]) -> Result<(), &'static str> {
+        block::check_range(self, first_block, buf.len())?;
+
+        self.inner.lock(|inner| {
+            let chunk_size = MAX_BLOCKS_PER_COMMAND as usize * BLOCK_SIZE;
+
+            for (i, chunk) in buf.chunks_mut(chunk_size).enumerate() {
+                let block = first_block + i as u64 * MAX_BLOCKS_PER_COMMAND;
+                inner.transfer_chunk(block, ChunkBuf::Read(chunk))?;
+            }
+
+            Ok(())
+        })
+    }
+
+    fn write_blocks(&self, first_block: u64, buf: &[u8]) -> Result<(), &'static str> {
+        block::check_range(self, first_block, buf.len())?;
+
+        self.inner.lock(|inner| {
+            let chunk_size = MAX_BLOCKS_PER_COMMAND as usize * BLOCK_SIZE;
+
+            for (i, chunk) in buf.chunks(chunk_size).enumerate() {
+                let block = first_block + i as u64 * MAX_BLOCKS_PER_COMMAND;
+                inner.transfer_chunk(block, ChunkBuf::Write(chunk))?;
+            }
+
+            Ok(())
+        })
+    }
+}
@@ -5,13 +5,19 @@
 //! GPIO Driver.
 
 use crate::{
-    bsp::device_driver::common::MMIODerefWrapper, driver, synchronization,
-    synchronization::NullLock,
+    bsp::device_driver::common::MMIODerefWrapper,
+    driver, exception,
+    exception::asynchronous::{IRQHandlerDescriptor, IRQNumber},
+    synchronization,
+    synchronization::IRQSafeLock,
+    time,
 };
+use alloc::boxed::Box;
+use core::time::Duration;
 use tock_registers::{
-    interfaces::{ReadWriteable, Writeable},
+    interfaces::{Readable, Writeable},
     register_bitfields, register_structs,
-    registers::ReadWrite,
+    registers::{ReadOnly, ReadWrite, WriteOnly},
 };
 
 //--------------------------------------------------------------------------------------------------
@@ -23,27 +29,12 @@ use tock_registers::{
 // Descriptions taken from
 // - https://github.com/raspberrypi/documentation/files/1888662/BCM2837-ARM-Peripherals.-.Revised.-.V2-1.pdf
 // - https://datasheets.raspberrypi.org/bcm2711/bcm2711-peripherals.pdf
+//
+// Most registers hold one bit (or field) per pin and are split into banks. They are therefore
+// modeled as plain arrays and accessed through the per-pin helpers below.
 register_bitfields! {
     u32,
 
-    /// GPIO Function Select 1
-    GPFSEL1 [
-        /// Pin 15
-        FSEL15 OFFSET(15) NUMBITS(3) [
-            Input = 0b000,
-            Output = 0b001,
-            AltFunc0 = 0b100  // PL011 UART RX
-
-        ],
-
-        /// Pin 14
-        FSEL14 OFFSET(12) NUMBITS(3) [
-            Input = 0b000,
-            Output = 0b001,
-            AltFunc0 = 0b100  // PL011 UART TX
-        ]
-    ],
-
     /// GPIO Pull-up/down Register
     ///
     /// BCM2837 only.
@@ -54,77 +45,174 @@ register_bitfields! {
             PullDown = 0b01,
             PullUp = 0b10
         ]
-    ],
-
-    /// GPIO Pull-up/down Clock Register 0
-    ///
-    /// BCM2837 only.
-    GPPUDCLK0 [
-        /// Pin 15
-        PUDCLK15 OFFSET(15) NUMBITS(1) [
-            NoEffect = 0,
-            AssertClock = 1
-        ],
-
-        /// Pin 14
-        PUDCLK14 OFFSET(14) NUMBITS(1) [
-            NoEffect = 0,
-            AssertClock = 1
-        ]
-    ],
-
-    /// GPIO Pull-up / Pull-down Register 0
-    ///
-    /// BCM2711 only.
-    GPIO_PUP_PDN_CNTRL_REG0 [
-        /// Pin 15
-        GPIO_PUP_PDN_CNTRL15 OFFSET(30) NUMBITS(2) [
-            NoResistor = 0b00,
-            PullUp = 0b01
-        ],
-
-        /// Pin 14
-        GPIO_PUP_PDN_CNTRL14 OFFSET(28) NUMBITS(2) [
-            NoResistor = 0b00,
-            PullUp = 0b01
-        ]
     ]
 }
 
 register_structs! {
     #[allow(non_snake_case)]
     RegisterBlock {
-        (0x00 => _reserved1),
-        (0x04 => GPFSEL1: ReadWrite<u32, GPFSEL1::Register>),
-        (0x08 => _reserved2),
+        (0x00 => GPFSEL: [ReadWrite<u32>; 6]),
+        (0x18 => _reserved1),
+        (0x1C => GPSET: [WriteOnly<u32>; 2]),
+        (0x24 => _reserved2),
+        (0x28 => GPCLR: [WriteOnly<u32>; 2]),
+        (0x30 => _reserved3),
+        (0x34 => GPLEV: [ReadOnly<u32>; 2]),
+        (0x3C => _reserved4),
+        (0x40 => GPEDS: [ReadWrite<u32>; 2]),
+        (0x48 => _reserved5),
+        (0x4C => GPREN: [ReadWrite<u32>; 2]),
+        (0x54 => _reserved6),
+        (0x58 => GPFEN: [ReadWrite<u32>; 2]),
+        (0x60 => _reserved7),
+        (0x64 => GPHEN: [ReadWrite<u32>; 2]),
+        (0x6C => _reserved8),
+        (0x70 => GPLEN: [ReadWrite<u32>; 2]),
+        (0x78 => _reserved9),
         (0x94 => GPPUD: ReadWrite<u32, GPPUD::Register>),
-        (0x98 => GPPUDCLK0: ReadWrite<u32, GPPUDCLK0::Register>),
-        (0x9C => _reserved3),
-        (0xE4 => GPIO_PUP_PDN_CNTRL_REG0: ReadWrite<u32, GPIO_PUP_PDN_CNTRL_REG0::Register>),
-        (0xE8 => @END),
+        (0x98 => GPPUDCLK: [ReadWrite<u32>; 2]),
+        (0xA0 => _reserved10),
+        (0xE4 => GPIO_PUP_PDN_CNTRL: [ReadWrite<u32>; 4]),
+        (0xF4 => @END),
     }
 }
 
 /// Abstraction for the associated MMIO registers.
 type Registers = MMIODerefWrapper<RegisterBlock>;
 
+/// Width of a pin's field in the function select registers.
+const FSEL_BITS_PER_PIN: usize = 3;
+
+/// Number of pins covered by one function select register.
+const FSEL_PINS_PER_REGISTER: usize = 10;
+
+/// Width of a pin's field in the BCM2711 pull-up/down registers.
+#[cfg(feature = "bsp_rpi4")]
+const PUP_PDN_BITS_PER_PIN: usize = 2;
+
+/// Number of pins covered by one BCM2711 pull-up/down register.
+#[cfg(feature = "bsp_rpi4")]
+const PUP_PDN_PINS_PER_REGISTER: usize = 16;
+
 struct GPIOInner {
     registers: Registers,
+    claimed: u64,
 }
 
+/// A registered per-pin event callback.
+struct PinEventHandler {
+    callback: PinCallback,
+    debounce: Duration,
+    last_event: Option<Duration>,
+}
+
+type PinEventHandlerTable = [Option<PinEventHandler>; NUM_PINS];
+
 //--------------------------------------------------------------------------------------------------
 // Public Definitions
 //--------------------------------------------------------------------------------------------------
 
+/// Number of GPIO pins.
+#[cfg(feature = "bsp_rpi3")]
+pub const NUM_PINS: usize = 54;
+
+/// Number of GPIO pins.
+#[cfg(feature = "bsp_rpi4")]
+pub const NUM_PINS: usize = 58;
+
+/// Pin function as encoded in the function select registers. The chainloader only muxes the UART.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Function {
+    #[cfg(not(feature = "chainloader"))]
+    Input = 0b000,
+    #[cfg(not(feature = "chainloader"))]
+    Output = 0b001,
+    AltFunc0 = 0b100,
+    #[cfg(not(feature = "chainloader"))]
+    AltFunc1 = 0b101,
+    #[cfg(not(feature = "chainloader"))]
+    AltFunc2 = 0b110,
+    #[cfg(not(feature = "chainloader"))]
+    AltFunc3 = 0b111,
+    #[cfg(not(feature = "chainloader"))]
+    AltFunc4 = 0b011,
+    #[cfg(not(feature = "chainloader"))]
+    AltFunc5 = 0b010,
+}
+
+/// Logic level of a pin.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Level {
+    Low,
+    High,
+}
+
+/// Internal pull resistor configuration.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Pull {
+    Off,
+    #[cfg(not(feature = "chainloader"))]
+    Down,
+    Up,
+}
+
+/// Conditions that set a pin's bit in the event detect status registers.
+///
+/// Level events are one-shot. A level stays asserted after its IRQ, so detection of both levels is
+/// disabled on the pin when the event is taken. Enable it again once the level was dealt with.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Event {
+    RisingEdge,
+    FallingEdge,
+    HighLevel,
+    LowLevel,
+}
+
+/// Callback invoked from IRQ context when an enabled event was detected on a pin.
+///
+/// Receives the pin number and the pin's level at the time the IRQ was handled.
+pub type PinCallback = Box<dyn Fn(usize, Level) + Send>;
+
 /// Representation of the GPIO HW.
 pub struct GPIO {
-    inner: NullLock<GPIOInner>,
+    inner: IRQSafeLock<GPIOInner>,
+    event_handlers: IRQSafeLock<PinEventHandlerTable>,
+}
+
+/// An exclusively owned GPIO pin.
+///
+/// Obtained through [`GPIO::claim`]. The pin is released again when the object is dropped.
+pub struct Pin {
+    gpio: &'static GPIO,
+    number: usize,
 }
 
 //--------------------------------------------------------------------------------------------------
 // Private Code
 //--------------------------------------------------------------------------------------------------
 
+/// Return the register index and the bit mask of a pin in one-bit-per-pin register banks.
+#[inline(always)]
+fn bank_and_mask(pin: usize) -> (usize, u32) {
+    (pin / 32, 1 << (pin % 32))
+}
+
+#[cfg(not(feature = "chainloader"))]
+impl Function {
+    fn from_fsel(value: u32) -> Self {
+        match value & 0b111 {
+            0b000 => Self::Input,
+            0b001 => Self::Output,
+            0b100 => Self::AltFunc0,
+            0b101 => Self::AltFunc1,
+            0b110 => Self::AltFunc2,
+            0b111 => Self::AltFunc3,
+            0b011 => Self::AltFunc4,
+            _ => Self::AltFunc5,
+        }
+    }
+}
+
 impl GPIOInner {
     /// Create an instance.
     ///
@@ -134,55 +222,175 @@ impl GPIOInner {
     pub const unsafe fn new(mmio_start_addr: usize) -> Self {
         Self {
             registers: Registers::new(mmio_start_addr),
+            claimed: 0,
         }
     }
 
-    /// Disable pull-up/down on pins 14 and 15.
-    #[cfg(feature = "bsp_rpi3")]
-    fn disable_pud_14_15_bcm2837(&mut self) {
-        use crate::time;
-        use core::time::Duration;
+    /// Mark a pin as owned.
+    fn claim(&mut self, pin: usize) -> Result<(), &'static str> {
+        if pin >= NUM_PINS {
+            return Err("GPIO pin number out of range");
+        }
 
+        if self.claimed & (1 << pin) != 0 {
+            return Err("GPIO pin already claimed");
+        }
+
+        self.claimed |= 1 << pin;
+        Ok(())
+    }
+
+    /// Mark a pin as free again.
+    fn release(&mut self, pin: usize) {
+        self.claimed &= !(1 << pin);
+    }
+
+    /// Set the function of a pin.
+    fn set_function(&mut self, pin: usize, function: Function) {
+        let reg = &self.registers.GPFSEL[pin / FSEL_PINS_PER_REGISTER];
+        let shift = (pin % FSEL_PINS_PER_REGISTER) * FSEL_BITS_PER_PIN;
+
+        let val = reg.get() & !(0b111 << shift);
+        reg.set(val | ((function as u32) << shift));
+    }
+
+    /// Return the function of a pin.
+    #[cfg(not(feature = "chainloader"))]
+    fn function(&self, pin: usize) -> Function {
+        let reg = &self.registers.GPFSEL[pin / FSEL_PINS_PER_REGISTER];
+        let shift = (pin % FSEL_PINS_PER_REGISTER) * FSEL_BITS_PER_PIN;
+
+        Function::from_fsel(reg.get() >> shift)
+    }
+
+    /// Drive an output pin.
+    #[cfg(not(feature = "chainloader"))]
+    fn write(&mut self, pin: usize, level: Level) {
+        let (bank, mask) = bank_and_mask(pin);
+
+        match level {
+            Level::High => self.registers.GPSET[bank].set(mask),
+            Level::Low => self.registers.GPCLR[bank].set(mask),
+        }
+    }
+
+    /// Read the actual level of a pin.
+    fn read(&self, pin: usize) -> Level {
+        let (bank, mask) = bank_and_mask(pin);
+
+        if self.registers.GPLEV[bank].get() & mask != 0 {
+            Level::High
+        } else {
+            Level::Low
+        }
+    }
+
+    /// Configure the pull resistor of a pin.
+    ///
+    /// The BCM2837 needs a clocked sequence, see page 101 of the peripherals datasheet.
+    #[cfg(feature = "bsp_rpi3")]
+    fn set_pull(&mut self, pin: usize, pull: Pull) {
         // The Linux 2837 GPIO driver waits 1 µs between the steps.
         const DELAY: Duration = Duration::from_micros(1);
 
-        self.registers.GPPUD.write(GPPUD::PUD::Off);
+        let (bank, mask) = bank_and_mask(pin);
+        let pud = match pull {
+            Pull::Off => GPPUD::PUD::Off,
+            #[cfg(not(feature = "chainloader"))]
+            Pull::Down => GPPUD::PUD::PullDown,
+            Pull::Up => GPPUD::PUD::PullUp,
+        };
+
+        self.registers.GPPUD.write(pud);
         time::time_manager().spin_for(DELAY);
 
-        self.registers
-            .GPPUDCLK0
-            .write(GPPUDCLK0::PUDCLK15::AssertClock + GPPUDCLK0::PUDCLK14::AssertClock);
+        self.registers.GPPUDCLK[bank].set(mask);
         time::time_manager().spin_for(DELAY);
 
         self.registers.GPPUD.write(GPPUD::PUD::Off);
-        self.registers.GPPUDCLK0.set(0);
+        self.registers.GPPUDCLK[bank].set(0);
     }
 
-    /// Disable pull-up/down on pins 14 and 15.
+    /// Configure the pull resistor of a pin.
     #[cfg(feature = "bsp_rpi4")]
-    fn disable_pud_14_15_bcm2711(&mut self) {
-        self.registers.GPIO_PUP_PDN_CNTRL_REG0.write(
-            GPIO_PUP_PDN_CNTRL_REG0::GPIO_PUP_PDN_CNTRL15::PullUp
-                + GPIO_PUP_PDN_CNTRL_REG0::GPIO_PUP_PDN_CNTRL14::PullUp,
-        );
+    fn set_pull(&mut self, pin: usize, pull: Pull) {
+        let reg = &self.registers.GPIO_PUP_PDN_CNTRL[pin / PUP_PDN_PINS_PER_REGISTER];
+        let shift = (pin % PUP_PDN_PINS_PER_REGISTER) * PUP_PDN_BITS_PER_PIN;
+        let pup_pdn: u32 = match pull {
+            Pull::Off => 0b00,
+            Pull::Up => 0b01,
+            #[cfg(not(feature = "chainloader"))]
+            Pull::Down => 0b10,
+        };
+
+        let val = reg.get() & !(0b11 << shift);
+        reg.set(val | (pup_pdn << shift));
     }
 
-    /// Map PL011 UART as standard output.
+    /// Return the detect enable register bank for an event type.
+    fn event_registers(&self, event: Event) -> &[ReadWrite<u32>; 2] {
+        match event {
+            Event::RisingEdge => &self.registers.GPREN,
+            Event::FallingEdge => &self.registers.GPFEN,
+            Event::HighLevel => &self.registers.GPHEN,
+            Event::LowLevel => &self.registers.GPLEN,
+        }
+    }
+
+    /// Enable or disable detection of an event on a pin.
+    fn set_event_detect(&mut self, pin: usize, event: Event, enable: bool) {
+        let (bank, mask) = bank_and_mask(pin);
+        let reg = &self.event_registers(event)[bank];
+
+        if enable {
+            reg.set(reg.get() | mask);
+        } else {
+            reg.set(reg.get() & !mask);
+        }
+    }
+
+    /// Clear the event detect status of a pin.
+    fn clear_event(&mut self, pin: usize) {
+        let (bank, mask) = bank_and_mask(pin);
+
+        // Write-one-to-clear.
+        self.registers.GPEDS[bank].set(mask);
+    }
+
+    /// Return a bitmask of all pins with a detected event and clear their status.
     ///
-    /// TX to pin 14
-    /// RX to pin 15
-    pub fn map_pl011_uart(&mut self) {
-        // Select the UART on pins 14 and 15.
-        self.registers
-            .GPFSEL1
-            .modify(GPFSEL1::FSEL15::AltFunc0 + GPFSEL1::FSEL14::AltFunc0);
+    /// Disables level detection on these pins, otherwise a level that is still asserted sets the
+    /// status again right away.
+    fn take_pending_events(&mut self) -> u64 {
+        let bank0 = self.registers.GPEDS[0].get();
+        let bank1 = self.registers.GPEDS[1].get();
+
+        for (bank, pending) in [bank0, bank1].into_iter().enumerate() {
+            for reg in [&self.registers.GPHEN[bank], &self.registers.GPLEN[bank]] {
+                reg.set(reg.get() & !pending);
+            }
+        }
 
-        // Disable pull-up/down on pins 14 and 15.
-        #[cfg(feature = "bsp_rpi3")]
-        self.disable_pud_14_15_bcm2837();
+        // Only clear what was read, so that events arriving in between are not lost.
+        self.registers.GPEDS[0].set(bank0);
+        self.registers.GPEDS[1].set(bank1);
 
-        #[cfg(feature = "bsp_rpi4")]
-        self.disable_pud_14_15_bcm2711();
+        (u64::from(bank1) << 32) | u64::from(bank0)
+    }
+}
+
+impl PinEventHandler {
+    /// Returns true if the event should be forwarded to the callback, i.e. it is not within the
+    /// debounce interval of the previously accepted event.
+    fn accept(&mut self, now: Duration) -> bool {
+        if let Some(last) = self.last_event {
+            if now.saturating_sub(last) < self.debounce {
+                return false;
+            }
+        }
+
+        self.last_event = Some(now);
+        true
     }
 }
 
@@ -199,14 +407,191 @@ impl GPIO {
     ///
     /// - The user must ensure to provide a correct MMIO start address.
     pub const unsafe fn new(mmio_start_addr: usize) -> Self {
+        const NO_HANDLER: Option<PinEventHandler> = None;
+
         Self {
-            inner: NullLock::new(GPIOInner::new(mmio_start_addr)),
+            inner: IRQSafeLock::new(GPIOInner::new(mmio_start_addr)),
+            event_handlers: IRQSafeLock::new([NO_HANDLER; NUM_PINS]),
+        }
+    }
+
+    /// Take exclusive ownership of a pin.
+    pub fn claim(&'static self, number: usize) -> Result<Pin, &'static str> {
+        self.inner.lock(|inner| inner.claim(number))?;
+
+        Ok(Pin { gpio: self, number })
+    }
+
+    /// Map PL011 UART as standard output.
+    ///
+    /// TX to pin 14
+    /// RX to pin 15
+    ///
+    /// The pins stay claimed for the lifetime of the kernel.
+    pub fn map_pl011_uart(&'static self) -> Result<(), &'static str> {
+        // Pull-ups on the BCM2711 keep RX from floating. The BCM2837 has always run without.
+        let pull = if cfg!(feature = "bsp_rpi4") {
+            Pull::Up
+        } else {
+            Pull::Off
+        };
+
+        for number in [14, 15] {
+            let pin = self.claim(number)?;
+
+            pin.set_function(Function::AltFunc0);
+            pin.set_pull(pull);
+
+            core::mem::forget(pin);
+        }
+
+        Ok(())
+    }
+
+    /// Route the SD card slot to the Arasan EMMC controller.
+    ///
+    /// CLK to pin 48
+    /// CMD and DAT0-3 to pins 49-53
+    ///
+    /// The firmware leaves the slot with the SDHOST controller. The pins stay claimed for the
+    /// lifetime of the kernel.
+    #[cfg(all(feature = "bsp_rpi3", not(feature = "chainloader")))]
+    pub fn map_emmc(&'static self) -> Result<(), &'static str> {
+        for number in 48..=53 {
+            let pin = self.claim(number)?;
+
+            pin.set_function(Function::AltFunc3);
+            pin.set_pull(if number == 48 { Pull::Off } else { Pull::Up });
+
+            core::mem::forget(pin);
+        }
+
+        Ok(())
+    }
+}
+
+impl Pin {
+    /// The BCM number of the pin.
+    #[cfg(not(feature = "chainloader"))]
+    pub fn number(&self) -> usize {
+        self.number
+    }
+
+    /// Select the pin's function.
+    pub fn set_function(&self, function: Function) {
+        self.gpio
+            .inner
+            .lock(|inner| inner.set_function(self.number, function))
+    }
+
+    /// The pin's currently selected function.
+    #[cfg(not(feature = "chainloader"))]
+    pub fn function(&self) -> Function {
+        self.gpio.inner.lock(|inner| inner.function(self.number))
+    }
+
+    /// Configure the pin's pull resistor.
+    pub fn set_pull(&self, pull: Pull) {
+        self.gpio
+            .inner
+            .lock(|inner| inner.set_pull(self.number, pull))
+    }
+
+    /// Drive the pin to the given level.
+    ///
+    /// Only has an effect on the pin if it is configured as an output.
+    #[cfg(not(feature = "chainloader"))]
+    pub fn write(&self, level: Level) {
+        self.gpio
+            .inner
+            .lock(|inner| inner.write(self.number, level))
+    }
+
+    /// Read the pin's actual level.
+    #[cfg(not(feature = "chainloader"))]
+    pub fn read(&self) -> Level {
+        self.gpio.inner.lock(|inner| inner.read(self.number))
+    }
+
+    /// Invert the pin's level.
+    #[cfg(not(feature = "chainloader"))]
+    pub fn toggle(&self) {
+        match self.read() {
+            Level::Low => self.write(Level::High),
+            Level::High => self.write(Level::Low),
         }
     }
 
-    /// Concurrency safe version of `GPIOInner.map_pl011_uart()`
-    pub fn map_pl011_uart(&self) {
-        self.inner.lock(|inner| inner.map_pl011_uart())
+    /// Enable detection of the given event.
+    ///
+    /// Level events must be enabled again after each one, see [`Event`].
+    #[cfg(not(feature = "chainloader"))]
+    pub fn enable_event(&self, event: Event) {
+        self.gpio
+            .inner
+            .lock(|inner| inner.set_event_detect(self.number, event, true))
+    }
+
+    /// Disable detection of the given event.
+    #[cfg(not(feature = "chainloader"))]
+    pub fn disable_event(&self, event: Event) {
+        self.gpio
+            .inner
+            .lock(|inner| inner.set_event_detect(self.number, event, false))
+    }
+
+    /// Register a callback that is invoked from IRQ context for every detected event.
+    ///
+    /// Events that follow an accepted event within `debounce` are dropped. Pass
+    /// `Duration::ZERO` to disable debouncing. A previously registered callback is replaced.
+    ///
+    /// Events still need to be enabled with [`Pin::enable_event`]. The callback must not register
+    /// or unregister callbacks itself.
+    #[cfg(not(feature = "chainloader"))]
+    pub fn register_callback(
+        &self,
+        debounce: Duration,
+        callback: impl Fn(usize, Level) + Send + 'static,
+    ) {
+        let handler = PinEventHandler {
+            callback: Box::new(callback),
+            debounce,
+            last_event: None,
+        };
+
+        self.gpio
+            .event_handlers
+            .lock(|handlers| handlers[self.number] = Some(handler));
+    }
+
+    /// Remove the pin's callback, if any.
+    pub fn unregister_callback(&self) {
+        // Drop the callback outside of the lock.
+        let old = self
+            .gpio
+            .event_handlers
+            .lock(|handlers| handlers[self.number].take());
+
+        drop(old);
+    }
+}
+
+impl Drop for Pin {
+    fn drop(&mut self) {
+        self.unregister_callback();
+
+        self.gpio.inner.lock(|inner| {
+            for event in [
+                Event::RisingEdge,
+                Event::FallingEdge,
+                Event::HighLevel,
+                Event::LowLevel,
+            ] {
+                inner.set_event_detect(self.number, event, false);
+            }
+            inner.clear_event(self.number);
+            inner.release(self.number);
+        })
     }
 }
 
@@ -216,7 +601,49 @@ impl GPIO {
 use synchronization::interface::Mutex;
 
 impl driver::interface::DeviceDriver for GPIO {
+    type IRQNumberType = IRQNumber;
+
     fn compatible(&self) -> &'static str {
         Self::COMPATIBLE
     }
+
+    fn register_and_enable_irq_handler(
+        &'static self,
+        irq_number: &Self::IRQNumberType,
+    ) -> Result<(), &'static str> {
+        use exception::asynchronous::irq_manager;
+
+        let descriptor = IRQHandlerDescriptor::new(*irq_number, Self::COMPATIBLE, self);
+
+        irq_manager().register_handler(descriptor)?;
+        irq_manager().enable(irq_number);
+
+        Ok(())
+    }
+}
+
+impl exception::asynchronous::interface::IRQHandler for GPIO {
+    fn handle(&self) -> Result<(), &'static str> {
+        // The bank IRQs all end up here. Both status registers are checked regardless of which
+        // bank fired, which is cheap and keeps the handler independent of the IRQ number.
+        let mut pending = self.inner.lock(|inner| inner.take_pending_events());
+        let now = time::time_manager().uptime();
+
+        while pending != 0 {
+            let pin = pending.trailing_zeros() as usize;
+            pending &= pending - 1;
+
+            let level = self.inner.lock(|inner| inner.read(pin));
+
+            self.event_handlers.lock(|handlers| {
+                if let Some(handler) = &mut handlers[pin] {
+                    if handler.accept(now) {
+                        (handler.callback)(pin, level);
+                    }
+                }
+            });
+        }
+
+        Ok(())
+    }
 }
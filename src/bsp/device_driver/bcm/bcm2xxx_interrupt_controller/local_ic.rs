@@ -0,0 +1,217 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2022 Andre Richter <andre.o.richter@gmail.com>
+
+//! Local Interrupt Controller Driver.
+//!
+//! # Resources
+//!
+//! - <https://datasheets.raspberrypi.com/bcm2836/bcm2836-peripherals.pdf>
+
+use super::{LocalIRQ, PendingIRQs};
+use crate::{
+    bsp::device_driver::common::MMIODerefWrapper,
+    cpu, exception, info, synchronization,
+    synchronization::{IRQSafeLock, InitStateLock},
+};
+use tock_registers::{
+    interfaces::{Readable, Writeable},
+    register_bitfields, register_structs,
+    registers::{ReadOnly, ReadWrite, WriteOnly},
+};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+register_bitfields! {
+    u32,
+
+    /// Core interrupt sources
+    CORE_IRQ_SOURCE [
+        /// GPU interrupt. Set if one of the interrupts of the peripheral interrupt controller is
+        /// pending and routed to this core.
+        GPU OFFSET(8) NUMBITS(1) [],
+
+        /// Mailbox 0 interrupt. Used for IPIs.
+        Mailbox0 OFFSET(4) NUMBITS(1) [],
+
+        /// The four architectural timer interrupts. Bit numbers correspond to [`LocalIRQ`].
+        Timers OFFSET(0) NUMBITS(4) []
+    ]
+}
+
+register_structs! {
+    #[allow(non_snake_case)]
+    RegisterBlock {
+        (0x00 => _reserved1),
+        (0x40 => CORE_TIMER_INTERRUPT_CONTROL: [ReadWrite<u32>; 4]),
+        (0x50 => CORE_MAILBOX_INTERRUPT_CONTROL: [ReadWrite<u32>; 4]),
+        (0x60 => CORE_IRQ_SOURCE: [ReadOnly<u32, CORE_IRQ_SOURCE::Register>; 4]),
+        (0x70 => _reserved2),
+        (0x80 => CORE_MAILBOX_WRITE_SET: [WriteOnly<u32>; 16]),
+        (0xC0 => CORE_MAILBOX_READ_CLEAR: [ReadWrite<u32>; 16]),
+        (0x100 => @END),
+    }
+}
+
+/// Abstraction for the associated MMIO registers.
+type Registers = MMIODerefWrapper<RegisterBlock>;
+
+/// Each core has four mailboxes. Mailbox 0 is used for IPIs.
+const MAILBOXES_PER_CORE: usize = 4;
+
+type HandlerTable =
+    [Option<exception::asynchronous::IRQHandlerDescriptor<LocalIRQ>>; LocalIRQ::MAX_INCLUSIVE + 1];
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Representation of the local interrupt controller.
+pub struct LocalIC {
+    /// Access to the timer and mailbox registers is guarded with a lock.
+    registers: IRQSafeLock<Registers>,
+
+    /// Stores registered IRQ handlers. Writable only during kernel init. RO afterwards.
+    handler_table: InitStateLock<HandlerTable>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+use synchronization::interface::{Mutex, ReadWriteEx};
+
+impl LocalIC {
+    /// Create an instance.
+    ///
+    /// # Safety
+    ///
+    /// - The user must ensure to provide a correct MMIO start address.
+    pub const unsafe fn new(mmio_start_addr: usize) -> Self {
+        Self {
+            registers: IRQSafeLock::new(Registers::new(mmio_start_addr)),
+            handler_table: InitStateLock::new([None; LocalIRQ::MAX_INCLUSIVE + 1]),
+        }
+    }
+
+    /// Query the executing core's interrupt source register.
+    ///
+    /// Returns the pending local IRQs, whether a peripheral (GPU) IRQ is pending, and whether an IPI
+    /// is pending.
+    pub(super) fn pending_irqs(&self) -> (PendingIRQs, bool, bool) {
+        let core: usize = cpu::smp::core_id();
+
+        self.registers.lock(|regs| {
+            let source = &regs.CORE_IRQ_SOURCE[core];
+
+            (
+                PendingIRQs::new(u64::from(source.read(CORE_IRQ_SOURCE::Timers))),
+                source.is_set(CORE_IRQ_SOURCE::GPU),
+                source.is_set(CORE_IRQ_SOURCE::Mailbox0),
+            )
+        })
+    }
+
+    /// Enable the mailbox 0 IRQ of the executing core.
+    pub(super) fn enable_ipi(&self) {
+        let core: usize = cpu::smp::core_id();
+
+        self.registers.lock(|regs| {
+            let reg = &regs.CORE_MAILBOX_INTERRUPT_CONTROL[core];
+            reg.set(reg.get() | 1);
+        });
+    }
+
+    /// Raise an IPI by setting a bit in the target core's mailbox 0.
+    pub(super) fn send_ipi(&self, core_id: usize) {
+        self.registers.lock(|regs| {
+            regs.CORE_MAILBOX_WRITE_SET[core_id * MAILBOXES_PER_CORE].set(1);
+        });
+    }
+
+    /// Acknowledge a pending IPI of the executing core by clearing its mailbox 0.
+    pub(super) fn clear_ipi(&self) {
+        let core: usize = cpu::smp::core_id();
+
+        self.registers.lock(|regs| {
+            regs.CORE_MAILBOX_READ_CLEAR[core * MAILBOXES_PER_CORE].set(u32::MAX);
+        });
+    }
+}
+
+//------------------------------------------------------------------------------
+// OS Interface Code
+//------------------------------------------------------------------------------
+
+impl exception::asynchronous::interface::IRQManager for LocalIC {
+    type IRQNumberType = LocalIRQ;
+
+    fn register_handler(
+        &self,
+        irq_handler_descriptor: exception::asynchronous::IRQHandlerDescriptor<Self::IRQNumberType>,
+    ) -> Result<(), &'static str> {
+        self.handler_table.write(|table| {
+            let irq_number = irq_handler_descriptor.number().get();
+
+            if table[irq_number].is_some() {
+                return Err("IRQ handler already registered");
+            }
+
+            table[irq_number] = Some(irq_handler_descriptor);
+
+            Ok(())
+        })
+    }
+
+    fn enable(&self, irq: &Self::IRQNumberType) {
+        let core: usize = cpu::smp::core_id();
+
+        self.registers.lock(|regs| {
+            // The timer interrupt control bits are IRQ enables for the correspondingly numbered
+            // timer interrupt. Setting the FIQ bits instead is not supported.
+            let reg = &regs.CORE_TIMER_INTERRUPT_CONTROL[core];
+            reg.set(reg.get() | (1 << irq.get()));
+        });
+    }
+
+    fn disable_all(&self) {
+        self.registers.lock(|regs| {
+            for core in 0..regs.CORE_TIMER_INTERRUPT_CONTROL.len() {
+                regs.CORE_TIMER_INTERRUPT_CONTROL[core].set(0);
+                regs.CORE_MAILBOX_INTERRUPT_CONTROL[core].set(0);
+            }
+        });
+    }
+
+    fn handle_pending_irqs<'irq_context>(
+        &'irq_context self,
+        _ic: &exception::asynchronous::IRQContext<'irq_context>,
+    ) {
+        let (pending, _, _) = self.pending_irqs();
+
+        self.handler_table.read(|table| {
+            for irq_number in pending {
+                match table[irq_number] {
+                    None => panic!("No handler registered for local IRQ {}", irq_number),
+                    Some(descriptor) => {
+                        // Call the IRQ handler. Panics on failure.
+                        descriptor.handler().handle().expect("Error handling IRQ");
+                    }
+                }
+            }
+        })
+    }
+
+    fn print_handler(&self) {
+        info!("      Local handler:");
+
+        self.handler_table.read(|table| {
+            for (i, opt) in table.iter().enumerate() {
+                if let Some(handler) = opt {
+                    info!("            {: >3}. {}", i, handler.name());
+                }
+            }
+        });
+    }
+}
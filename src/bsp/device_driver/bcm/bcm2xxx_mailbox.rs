@@ -1,15 +1,33 @@
-use core::{mem::size_of, ptr};
+use core::{
+    hint,
+    mem::size_of,
+    ptr,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+#[cfg(not(feature = "chainloader"))]
+use alloc::vec::Vec;
+#[cfg(not(feature = "chainloader"))]
+use core::{
+    future, mem,
+    task::{Poll, Waker},
+};
+
+use crate::{
+    bsp::device_driver::common::MMIODerefWrapper, cpu, debug, driver,
+    exception::asynchronous::IRQNumber, memory, synchronization, synchronization::SpinLock,
+};
 
+#[cfg(not(feature = "chainloader"))]
 use crate::{
-    bsp::device_driver::common::MMIODerefWrapper, cpu, debug, driver, gpu::*, synchronization,
-    synchronization::NullLock,
+    exception, exception::asynchronous::IRQHandlerDescriptor, executor::WakerSlot, gpu::*,
 };
 
 use tock_registers::{
     fields::FieldValue,
     interfaces::{Readable, Writeable},
     register_bitfields, register_structs,
-    registers::{ReadOnly, WriteOnly},
+    registers::{ReadOnly, ReadWrite, WriteOnly},
 };
 
 register_bitfields! {
@@ -25,6 +43,11 @@ register_bitfields! {
         FULL OFFSET(31) NUMBITS(1) [],
     ],
 
+    CONFIG [
+        /// Raise the ARM Mailbox IRQ while there is mail to read.
+        DATA_IRQ OFFSET(0) NUMBITS(1) [],
+    ],
+
     WRITE [
         CHANNEL OFFSET(0) NUMBITS(4) [
             MAIL_POWER    = 0x0, // Mailbox Channel 0: Power Management Interface
@@ -47,7 +70,7 @@ register_structs! {
         (0x00 => READ: ReadOnly<u32, READ::Register>),
         (0x04 => _reserved1),
         (0x18 => STATUS: ReadOnly<u32, STATUS::Register>),
-        (0x1C => _reserved2), // CONFIG
+        (0x1C => CONFIG: ReadWrite<u32, CONFIG::Register>),
         (0x20 => WRITE: WriteOnly<u32, WRITE::Register>),
         (0x24 => @END),
     }
@@ -58,23 +81,75 @@ type Registers = MMIODerefWrapper<RegisterBlock>;
 
 // Buffer for recv and sending mailbox messages
 const BUFFER_LENGTH: usize = 32;
+const BUFFER_SIZE: usize = BUFFER_LENGTH * size_of::<u32>();
 
-#[repr(C, align(16))]
+// response code of a successfully processed property message
+const RESPONSE_SUCCESS: u32 = 0x8000_0000;
+
+// property tags
+#[cfg(not(feature = "chainloader"))]
+const TAG_GET_BOARD_REVISION: u32 = 0x0001_0002;
+const TAG_GET_ARM_MEMORY: u32 = 0x0001_0005;
+const TAG_GET_VC_MEMORY: u32 = 0x0001_0006;
+#[cfg(not(feature = "chainloader"))]
+const TAG_GET_CLOCK_RATE: u32 = 0x0003_0002;
+
+// message length in words for `property_tag()`, a multiple of 16 bytes
+#[cfg(not(feature = "chainloader"))]
+const TAG_MSG_LENGTH: usize = (2 + 3 + MAX_TAG_VALUES + 1 + 3) & !3;
+
+// aligned to the cache line size so that cache maintenance on the buffer never touches other data
+#[repr(C, align(64))]
 struct BufferAligned([u32; BUFFER_LENGTH]);
 
-#[repr(C, align(16))]
+#[repr(C, align(64))]
 struct MailBoxInner {
     registers: Registers,
     buffer: BufferAligned,
 }
 
+/// Releases the mailbox when a request is done.
+struct BusyGuard<'a>(&'a MailBox);
+
+/// A property message that the firmware has not answered yet.
+///
+/// Keeps the mailbox claimed until the answer is in, also if the request is dropped early, so that
+/// the next request does not take the answer for its own or reuse the buffer too soon.
+#[cfg(not(feature = "chainloader"))]
+struct PendingAnswer<'a> {
+    mailbox: &'a MailBox,
+    answered: bool,
+    _busy: BusyGuard<'a>,
+}
+
 //--------------------------------------------------------------------------------------------------
 // Public Definitions
 //--------------------------------------------------------------------------------------------------
 
+/// Most request or response values of a single tag in `MailBox::property_tag()`.
+#[cfg(not(feature = "chainloader"))]
+pub const MAX_TAG_VALUES: usize = 16;
+
 /// Representation of the Mailbox.
 pub struct MailBox {
-    inner: NullLock<MailBoxInner>,
+    inner: SpinLock<MailBoxInner>,
+
+    /// Set while a request is in flight. All requests share the buffer, so only one at a time is
+    /// allowed. Async requests keep it across polls, so the spinlock alone is not enough.
+    busy: AtomicBool,
+
+    /// Tasks that wait for `busy` to be released.
+    #[cfg(not(feature = "chainloader"))]
+    claim_wakers: SpinLock<Vec<Waker>>,
+
+    /// The task that waits for the answer to its property message.
+    #[cfg(not(feature = "chainloader"))]
+    answer_waker: WakerSlot,
+
+    /// Access to `CONFIG` from the IRQ handler, which must not take `inner`. Needs no lock, the
+    /// register is only written as a whole.
+    #[cfg(not(feature = "chainloader"))]
+    irq_registers: Registers,
 }
 
 impl MailBoxInner {
@@ -87,6 +162,7 @@ impl MailBoxInner {
 
     // TODO
     // check return code
+    #[cfg(not(feature = "chainloader"))]
     pub fn request_framebuffer(&mut self) -> Option<Display> {
         {
             #[rustfmt::skip]
@@ -137,6 +213,47 @@ impl MailBoxInner {
         Some(result)
     }
 
+    // query a memory split of the firmware, returns (base, size)
+    fn request_memory(&mut self, tag: u32) -> Result<(usize, usize), &'static str> {
+        #[rustfmt::skip]
+        let msg: [u32; 8] = [
+            32,
+            0,
+            tag, 8, 0, 0, 0,
+            0,
+        ];
+
+        self.send_mail(&msg, WRITE::CHANNEL::MAIL_TAGS);
+        while self.recv_mail(WRITE::CHANNEL::MAIL_TAGS).is_err() {}
+
+        if self.read_buffer(1) != RESPONSE_SUCCESS {
+            return Err("Firmware did not answer the memory request");
+        }
+
+        Ok((self.read_buffer(5) as usize, self.read_buffer(6) as usize))
+    }
+
+    // query the rate of a clock in Hz, 0 if the firmware doesn't know the clock
+    #[cfg(not(feature = "chainloader"))]
+    fn request_clock_rate(&mut self, clock_id: u32) -> Result<u32, &'static str> {
+        #[rustfmt::skip]
+        let msg: [u32; 8] = [
+            32,
+            0,
+            TAG_GET_CLOCK_RATE, 8, 0, clock_id, 0,
+            0,
+        ];
+
+        self.send_mail(&msg, WRITE::CHANNEL::MAIL_TAGS);
+        while self.recv_mail(WRITE::CHANNEL::MAIL_TAGS).is_err() {}
+
+        if self.read_buffer(1) != RESPONSE_SUCCESS {
+            return Err("Firmware did not answer the clock rate request");
+        }
+
+        Ok(self.read_buffer(6))
+    }
+
     // calc padding (nr. of zeros as u32[4bytes]) so the size is  16 byte aligned
     fn calc_padding<T>(&self, len: usize) -> usize {
         // https://en.wikipedia.org/wiki/Data_structure_alignment
@@ -172,6 +289,9 @@ impl MailBoxInner {
             debug!("{:#010x}", self.read_buffer(n));
         }
 
+        // the GPU does not snoop the CPU caches, so the message has to be in memory before the mail is sent
+        memory::cache::clean_dcache_range(self.buffer_addr(), BUFFER_SIZE);
+
         // write data
         // channel + address bit shif by length of the channel
         // c c c c | a a ... a
@@ -186,25 +306,54 @@ impl MailBoxInner {
     fn recv_mail(&self, channel: FieldValue<u32, WRITE::Register>) -> Result<u32, u32> {
         // wait for data
         debug!("Waiting Mail on channel {}", channel.value);
-        while self.registers.STATUS.matches_all(STATUS::EMPTY::SET) {
+        loop {
+            if let Some(result) = self.try_recv_mail(channel) {
+                return result;
+            }
+
             cpu::nop();
         }
+    }
+
+    // like recv_mail, but returns None right away if no message is there yet
+    fn try_recv_mail(&self, channel: FieldValue<u32, WRITE::Register>) -> Option<Result<u32, u32>> {
+        if self.registers.STATUS.matches_all(STATUS::EMPTY::SET) {
+            return None;
+        }
 
         // The callee is not allowed to return a different buffer address, this allows the caller to make independent asynchronous requests.
         // Thats why we dont need to check the response data since its the BUFFER Addr
         let recv_channel = self.registers.READ.read(READ::CHANNEL);
         debug!("Received Mail on channel {}", recv_channel);
         if recv_channel != channel.value {
-            return Err(recv_channel);
+            return Some(Err(recv_channel));
         }
 
+        // drop stale cache lines so that the response written by the GPU is visible
+        memory::cache::clean_and_invalidate_dcache_range(self.buffer_addr(), BUFFER_SIZE);
+
         // debug print
         let recv_length = self.read_buffer(0) as usize / size_of::<u32>();
         for n in 0..recv_length {
             debug!("{:#010x}", self.read_buffer(n));
         }
 
-        Ok(recv_channel)
+        Some(Ok(recv_channel))
+    }
+
+    fn buffer_addr(&self) -> usize {
+        self.buffer.0.as_ptr() as usize
+    }
+
+    // copy of the whole buffer, e.g. to hand a response out
+    #[cfg(not(feature = "chainloader"))]
+    fn buffer_copy(&self) -> [u32; BUFFER_LENGTH] {
+        let mut copy = [0; BUFFER_LENGTH];
+        for (idx, word) in copy.iter_mut().enumerate() {
+            *word = self.read_buffer(idx);
+        }
+
+        copy
     }
 
     /// read buffer
@@ -257,17 +406,206 @@ impl MailBox {
     /// - The user must ensure to provide a correct MMIO start address.
     pub const unsafe fn new(mmio_start_addr: usize) -> Self {
         Self {
-            inner: NullLock::new(MailBoxInner::new(mmio_start_addr)),
+            inner: SpinLock::new(MailBoxInner::new(mmio_start_addr)),
+            busy: AtomicBool::new(false),
+            #[cfg(not(feature = "chainloader"))]
+            claim_wakers: SpinLock::new(Vec::new()),
+            #[cfg(not(feature = "chainloader"))]
+            answer_waker: WakerSlot::new(),
+            #[cfg(not(feature = "chainloader"))]
+            irq_registers: Registers::new(mmio_start_addr),
         }
     }
 
+    fn try_claim(&self) -> Option<BusyGuard<'_>> {
+        self.busy
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| BusyGuard(self))
+    }
+
+    // like try_claim, but waits without blocking the thread
+    #[cfg(not(feature = "chainloader"))]
+    async fn claim(&self) -> BusyGuard<'_> {
+        future::poll_fn(|cx| {
+            if let Some(guard) = self.try_claim() {
+                return Poll::Ready(guard);
+            }
+
+            self.claim_wakers.lock(|wakers| {
+                if !wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+                    wakers.push(cx.waker().clone());
+                }
+            });
+
+            // The mailbox might have been released before the waker was in place.
+            match self.try_claim() {
+                Some(guard) => Poll::Ready(guard),
+                None => Poll::Pending,
+            }
+        })
+        .await
+    }
+
+    // runs a blocking request
+    fn exclusive<R>(&self, f: impl FnOnce(&mut MailBoxInner) -> R) -> R {
+        let _guard = loop {
+            if let Some(guard) = self.try_claim() {
+                break guard;
+            }
+
+            hint::spin_loop();
+        };
+
+        self.inner.lock(f)
+    }
+
+    #[cfg(not(feature = "chainloader"))]
     pub fn request_framebuffer(&self) -> Option<Display> {
-        self.inner.lock(|inner| inner.request_framebuffer())
+        self.exclusive(|inner| inner.request_framebuffer())
+    }
+
+    /// Base and size of the RAM that the firmware assigned to the ARM cores.
+    pub fn arm_memory(&self) -> Result<(usize, usize), &'static str> {
+        self.exclusive(|inner| inner.request_memory(TAG_GET_ARM_MEMORY))
+    }
+
+    /// Base and size of the RAM that the firmware reserved for the VideoCore.
+    pub fn vc_memory(&self) -> Result<(usize, usize), &'static str> {
+        self.exclusive(|inner| inner.request_memory(TAG_GET_VC_MEMORY))
+    }
+
+    /// Rate of one of the firmware's clocks in Hz, 0 if the clock is unknown.
+    #[cfg(not(feature = "chainloader"))]
+    pub fn clock_rate(&self, clock_id: u32) -> Result<u32, &'static str> {
+        self.exclusive(|inner| inner.request_clock_rate(clock_id))
+    }
+
+    /// Send a property message and wait for the answer without blocking the thread. Returns the
+    /// buffer holding the answer.
+    ///
+    /// The task sleeps until the mailbox IRQ signals the answer. Dropping the future before the
+    /// answer is in blocks until the firmware has answered.
+    #[cfg(not(feature = "chainloader"))]
+    pub async fn property_call(&self, msg: &[u32]) -> Result<[u32; BUFFER_LENGTH], &'static str> {
+        let busy = self.claim().await;
+
+        self.inner
+            .lock(|inner| inner.send_mail(msg, WRITE::CHANNEL::MAIL_TAGS));
+        let mut pending = PendingAnswer {
+            mailbox: self,
+            answered: false,
+            _busy: busy,
+        };
+
+        // Mails on other channels are dropped, like in the blocking requests.
+        let response = future::poll_fn(|cx| {
+            self.answer_waker.register(cx.waker());
+
+            match self
+                .inner
+                .lock(|inner| inner.try_recv_mail(WRITE::CHANNEL::MAIL_TAGS))
+            {
+                Some(Ok(_)) => {
+                    pending.answered = true;
+                    self.irq_registers.CONFIG.write(CONFIG::DATA_IRQ::CLEAR);
+
+                    Poll::Ready(self.inner.lock(|inner| inner.buffer_copy()))
+                }
+                _ => {
+                    // Mail that came in since the check raises the IRQ right away.
+                    self.irq_registers.CONFIG.write(CONFIG::DATA_IRQ::SET);
+
+                    Poll::Pending
+                }
+            }
+        })
+        .await;
+        drop(pending);
+
+        if response[1] != RESPONSE_SUCCESS {
+            return Err("Firmware did not answer the request");
+        }
+
+        Ok(response)
+    }
+
+    /// Send a single property tag with the given request values. Returns the values of the
+    /// response.
+    ///
+    /// Requests and responses are limited to `MAX_TAG_VALUES` words.
+    #[cfg(not(feature = "chainloader"))]
+    pub async fn property_tag(&self, tag: u32, values: &[u32]) -> Result<Vec<u32>, &'static str> {
+        if values.len() > MAX_TAG_VALUES {
+            return Err("Too many values for a property tag");
+        }
+
+        // Header, tag header, value buffer and end tag, padded to 16 bytes.
+        let mut msg = [0; TAG_MSG_LENGTH];
+        msg[0] = (TAG_MSG_LENGTH * size_of::<u32>()) as u32;
+        msg[2] = tag;
+        msg[3] = (MAX_TAG_VALUES * size_of::<u32>()) as u32;
+        msg[5..5 + values.len()].copy_from_slice(values);
+
+        let response = self.property_call(&msg).await?;
+
+        // Bit 31 marks a response, the rest is the length in bytes.
+        if response[4] & RESPONSE_SUCCESS == 0 {
+            return Err("Firmware did not answer the tag");
+        }
+        let len = (response[4] & !RESPONSE_SUCCESS) as usize / size_of::<u32>();
+
+        Ok(response[5..5 + len.min(MAX_TAG_VALUES)].to_vec())
+    }
+
+    /// The board revision code, see the firmware's mailbox property interface.
+    #[cfg(not(feature = "chainloader"))]
+    pub async fn board_revision(&self) -> Result<u32, &'static str> {
+        #[rustfmt::skip]
+        let msg: [u32; 8] = [
+            32,
+            0,
+            TAG_GET_BOARD_REVISION, 4, 0, 0,
+            0, 0,
+        ];
+
+        let response = self.property_call(&msg).await?;
+
+        Ok(response[5])
     }
 
     // DEBUG
     pub fn _test(&self) {
-        self.inner.lock(|inner| inner._test())
+        self.exclusive(|inner| inner._test())
+    }
+}
+
+impl Drop for BusyGuard<'_> {
+    fn drop(&mut self) {
+        self.0.busy.store(false, Ordering::Release);
+
+        #[cfg(not(feature = "chainloader"))]
+        for waker in self.0.claim_wakers.lock(mem::take) {
+            waker.wake();
+        }
+    }
+}
+
+#[cfg(not(feature = "chainloader"))]
+impl Drop for PendingAnswer<'_> {
+    fn drop(&mut self) {
+        if self.answered {
+            return;
+        }
+
+        // The firmware answers within microseconds, so waiting for it here is fine.
+        self.mailbox
+            .irq_registers
+            .CONFIG
+            .write(CONFIG::DATA_IRQ::CLEAR);
+        self.mailbox
+            .inner
+            .lock(|inner| while inner.recv_mail(WRITE::CHANNEL::MAIL_TAGS).is_err() {});
     }
 }
 
@@ -277,7 +615,35 @@ impl MailBox {
 use synchronization::interface::Mutex;
 
 impl driver::interface::DeviceDriver for MailBox {
+    type IRQNumberType = IRQNumber;
+
     fn compatible(&self) -> &'static str {
         Self::COMPATIBLE
     }
+
+    #[cfg(not(feature = "chainloader"))]
+    fn register_and_enable_irq_handler(
+        &'static self,
+        irq_number: &Self::IRQNumberType,
+    ) -> Result<(), &'static str> {
+        use exception::asynchronous::irq_manager;
+
+        let descriptor = IRQHandlerDescriptor::new(*irq_number, Self::COMPATIBLE, self);
+
+        irq_manager().register_handler(descriptor)?;
+        irq_manager().enable(irq_number);
+
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "chainloader"))]
+impl exception::asynchronous::interface::IRQHandler for MailBox {
+    fn handle(&self) -> Result<(), &'static str> {
+        // The IRQ stays raised until the mail is read, which is up to the woken task.
+        self.irq_registers.CONFIG.write(CONFIG::DATA_IRQ::CLEAR);
+        self.answer_waker.wake();
+
+        Ok(())
+    }
 }
@@ -10,10 +10,18 @@
 //! - <https://developer.arm.com/documentation/ddi0183/latest>
 
 use crate::{
-    bsp::device_driver::common::MMIODerefWrapper, console, cpu, driver, synchronization,
-    synchronization::NullLock,
+    bsp::device_driver::common::MMIODerefWrapper,
+    console, cpu, driver, exception,
+    exception::asynchronous::{IRQHandlerDescriptor, IRQNumber},
+    executor::WakerSlot,
+    synchronization,
+    synchronization::{IRQSafeLock, WaitQueue},
+};
+use core::{
+    fmt,
+    sync::atomic::{AtomicBool, Ordering},
+    task::{Context, Poll},
 };
-use core::fmt;
 use tock_registers::{
     interfaces::{Readable, Writeable},
     register_bitfields, register_structs,
@@ -134,6 +142,52 @@ register_bitfields! {
         ]
     ],
 
+    /// Interrupt FIFO Level Select Register.
+    IFLS [
+        /// Receive interrupt FIFO level select. The trigger points for the receive interrupt are as
+        /// follows.
+        RXIFLSEL OFFSET(3) NUMBITS(3) [
+            OneEigth = 0b000,
+            OneQuarter = 0b001,
+            OneHalf = 0b010,
+            ThreeQuarters = 0b011,
+            SevenEights = 0b100
+        ]
+    ],
+
+    /// Interrupt Mask Set/Clear Register.
+    IMSC [
+        /// Receive timeout interrupt mask. A read returns the current mask for the UARTRTINTR
+        /// interrupt.
+        ///
+        /// - On a write of 1, the mask of the UARTRTINTR interrupt is set.
+        /// - A write of 0 clears the mask.
+        RTIM OFFSET(6) NUMBITS(1) [
+            Disabled = 0,
+            Enabled = 1
+        ],
+
+        /// Receive interrupt mask. A read returns the current mask for the UARTRXINTR interrupt.
+        ///
+        /// - On a write of 1, the mask of the UARTRXINTR interrupt is set.
+        /// - A write of 0 clears the mask.
+        RXIM OFFSET(4) NUMBITS(1) [
+            Disabled = 0,
+            Enabled = 1
+        ]
+    ],
+
+    /// Masked Interrupt Status Register.
+    MIS [
+        /// Receive timeout masked interrupt status. Returns the masked interrupt state of the
+        /// UARTRTINTR interrupt.
+        RTMIS OFFSET(6) NUMBITS(1) [],
+
+        /// Receive masked interrupt status. Returns the masked interrupt state of the UARTRXINTR
+        /// interrupt.
+        RXMIS OFFSET(4) NUMBITS(1) []
+    ],
+
     /// Interrupt Clear Register.
     ICR [
         /// Meta field for all pending interrupts.
@@ -152,7 +206,10 @@ register_structs! {
         (0x28 => FBRD: WriteOnly<u32, FBRD::Register>),
         (0x2c => LCR_H: WriteOnly<u32, LCR_H::Register>),
         (0x30 => CR: WriteOnly<u32, CR::Register>),
-        (0x34 => _reserved3),
+        (0x34 => IFLS: ReadWrite<u32, IFLS::Register>),
+        (0x38 => IMSC: ReadWrite<u32, IMSC::Register>),
+        (0x3C => _reserved3),
+        (0x40 => MIS: ReadOnly<u32, MIS::Register>),
         (0x44 => ICR: WriteOnly<u32, ICR::Register>),
         (0x48 => @END),
     }
@@ -161,14 +218,26 @@ register_structs! {
 /// Abstraction for the associated MMIO registers.
 type Registers = MMIODerefWrapper<RegisterBlock>;
 
+/// About 40 ms worth of input at 921_600 baud, so that a reader that is only switched in on the next
+/// time slice doesn't lose any.
+const RX_BUFFER_SIZE: usize = 4096;
+
 #[derive(PartialEq)]
 enum BlockingMode {
     Blocking,
     NonBlocking,
 }
 
-struct PL011UartInner {
+/// Bytes received by the IRQ handler that were not read yet.
+struct RxBuffer {
+    bytes: [u8; RX_BUFFER_SIZE],
+    head: usize,
+    len: usize,
+}
+
+pub struct PL011UartInner {
     registers: Registers,
+    rx_buffer: RxBuffer,
     chars_written: usize,
     chars_read: usize,
 }
@@ -177,15 +246,72 @@ struct PL011UartInner {
 // Public Definitions
 //--------------------------------------------------------------------------------------------------
 
+// Export the inner struct so that BSPs can use it for the panic handler.
+pub use PL011UartInner as PanicUart;
+
 /// Representation of the UART.
 pub struct PL011Uart {
-    inner: NullLock<PL011UartInner>,
+    inner: IRQSafeLock<PL011UartInner>,
+
+    /// Set once the IRQ handler is registered. From then on, received characters are taken from
+    /// the RX buffer instead of the FIFO.
+    rx_irq_enabled: AtomicBool,
+
+    /// Readers waiting for the RX buffer to fill.
+    rx_waiters: WaitQueue,
+
+    /// The task waiting for the RX buffer to fill.
+    rx_waker: WakerSlot,
 }
 
 //--------------------------------------------------------------------------------------------------
 // Private Code
 //--------------------------------------------------------------------------------------------------
 
+/// Turn a received byte into a character. Carriage returns become newlines.
+fn convert_received(byte: u8) -> char {
+    match byte {
+        b'\r' => '\n',
+        _ => byte as char,
+    }
+}
+
+impl RxBuffer {
+    const fn new() -> Self {
+        Self {
+            bytes: [0; RX_BUFFER_SIZE],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    /// Drops the byte if the buffer is full.
+    fn push(&mut self, byte: u8) {
+        if self.len == RX_BUFFER_SIZE {
+            return;
+        }
+
+        self.bytes[(self.head + self.len) % RX_BUFFER_SIZE] = byte;
+        self.len += 1;
+    }
+
+    fn pop(&mut self) -> Option<u8> {
+        if self.len == 0 {
+            return None;
+        }
+
+        let c = self.bytes[self.head];
+        self.head = (self.head + 1) % RX_BUFFER_SIZE;
+        self.len -= 1;
+
+        Some(c)
+    }
+
+    fn clear(&mut self) {
+        self.len = 0;
+    }
+}
+
 impl PL011UartInner {
     /// Create an instance.
     ///
@@ -195,6 +321,7 @@ impl PL011UartInner {
     pub const unsafe fn new(mmio_start_addr: usize) -> Self {
         Self {
             registers: Registers::new(mmio_start_addr),
+            rx_buffer: RxBuffer::new(),
             chars_written: 0,
             chars_read: 0,
         }
@@ -247,6 +374,14 @@ impl PL011UartInner {
             .LCR_H
             .write(LCR_H::WLEN::EightBit + LCR_H::FEN::FifosEnabled);
 
+        // Set RX FIFO fill level at 1/8.
+        self.registers.IFLS.write(IFLS::RXIFLSEL::OneEigth);
+
+        // Enable RX IRQ + RX timeout IRQ.
+        self.registers
+            .IMSC
+            .write(IMSC::RXIM::Enabled + IMSC::RTIM::Enabled);
+
         // Turn the UART on.
         self.registers
             .CR
@@ -274,8 +409,8 @@ impl PL011UartInner {
         }
     }
 
-    /// Retrieve a character.
-    fn read_char_converting(&mut self, blocking_mode: BlockingMode) -> Option<char> {
+    /// Retrieve a byte as received.
+    fn read_byte(&mut self, blocking_mode: BlockingMode) -> Option<u8> {
         // If RX FIFO is empty,
         if self.registers.FR.matches_all(FR::RXFE::SET) {
             // immediately return in non-blocking mode.
@@ -289,19 +424,28 @@ impl PL011UartInner {
             }
         }
 
-        // Read one character.
-        let mut ret = self.registers.DR.get() as u8 as char;
-
-        // Convert carrige return to newline.
-        if ret == '\r' {
-            ret = '\n'
-        }
+        // Read one byte.
+        let ret = self.registers.DR.get() as u8;
 
         // Update statistics.
         self.chars_read += 1;
 
         Some(ret)
     }
+
+    /// Move everything from the RX FIFO to the RX buffer. Returns whether anything was received.
+    ///
+    /// Bytes that don't fit into the buffer anymore are dropped.
+    fn drain_rx_fifo(&mut self) -> bool {
+        let mut received = false;
+
+        while let Some(byte) = self.read_byte(BlockingMode::NonBlocking) {
+            self.rx_buffer.push(byte);
+            received = true;
+        }
+
+        received
+    }
 }
 
 /// Implementing `core::fmt::Write` enables usage of the `format_args!` macros, which in turn are
@@ -337,9 +481,23 @@ impl PL011Uart {
     /// - The user must ensure to provide a correct MMIO start address.
     pub const unsafe fn new(mmio_start_addr: usize) -> Self {
         Self {
-            inner: NullLock::new(PL011UartInner::new(mmio_start_addr)),
+            inner: IRQSafeLock::new(PL011UartInner::new(mmio_start_addr)),
+            rx_irq_enabled: AtomicBool::new(false),
+            rx_waiters: WaitQueue::new(),
+            rx_waker: WakerSlot::new(),
         }
     }
+
+    /// Mask all UART interrupts and clear the pending ones. Reads fall back to polling.
+    #[cfg(feature = "chainloader")]
+    pub fn disable_irqs(&self) {
+        self.rx_irq_enabled.store(false, Ordering::Release);
+
+        self.inner.lock(|inner| {
+            inner.registers.IMSC.set(0);
+            inner.registers.ICR.write(ICR::ALL::CLEAR);
+        });
+    }
 }
 
 //------------------------------------------------------------------------------
@@ -348,6 +506,8 @@ impl PL011Uart {
 use synchronization::interface::Mutex;
 
 impl driver::interface::DeviceDriver for PL011Uart {
+    type IRQNumberType = IRQNumber;
+
     fn compatible(&self) -> &'static str {
         Self::COMPATIBLE
     }
@@ -357,6 +517,21 @@ impl driver::interface::DeviceDriver for PL011Uart {
 
         Ok(())
     }
+
+    fn register_and_enable_irq_handler(
+        &'static self,
+        irq_number: &Self::IRQNumberType,
+    ) -> Result<(), &'static str> {
+        use exception::asynchronous::irq_manager;
+
+        let descriptor = IRQHandlerDescriptor::new(*irq_number, Self::COMPATIBLE, self);
+
+        irq_manager().register_handler(descriptor)?;
+        irq_manager().enable(irq_number);
+        self.rx_irq_enabled.store(true, Ordering::Release);
+
+        Ok(())
+    }
 }
 
 impl console::interface::Write for PL011Uart {
@@ -366,6 +541,11 @@ impl console::interface::Write for PL011Uart {
         self.inner.lock(|inner| inner.write_char(c));
     }
 
+    fn write_byte(&self, byte: u8) {
+        // Characters up to U+00FF go out as the byte of the same value.
+        self.inner.lock(|inner| inner.write_char(char::from(byte)));
+    }
+
     fn write_fmt(&self, args: core::fmt::Arguments) -> fmt::Result {
         // Fully qualified syntax for the call to `core::fmt::Write::write_fmt()` to increase
         // readability.
@@ -380,15 +560,44 @@ impl console::interface::Write for PL011Uart {
 
 impl console::interface::Read for PL011Uart {
     fn read_char(&self) -> char {
-        self.inner
-            .lock(|inner| inner.read_char_converting(BlockingMode::Blocking).unwrap())
+        convert_received(self.read_byte())
+    }
+
+    fn read_byte(&self) -> u8 {
+        if !self.rx_irq_enabled.load(Ordering::Acquire) {
+            return self
+                .inner
+                .lock(|inner| inner.read_byte(BlockingMode::Blocking).unwrap());
+        }
+
+        // The IRQ handler fills the buffer and wakes up the reader.
+        let mut byte = None;
+        self.rx_waiters.wait_until(|| {
+            byte = self.inner.lock(|inner| inner.rx_buffer.pop());
+            byte.is_some()
+        });
+
+        // Cannot fail, the wait only ends once a byte was taken.
+        byte.unwrap()
+    }
+
+    fn try_read_byte(&self) -> Option<u8> {
+        self.inner.lock(|inner| {
+            if self.rx_irq_enabled.load(Ordering::Acquire) {
+                inner.rx_buffer.pop()
+            } else {
+                inner.read_byte(BlockingMode::NonBlocking)
+            }
+        })
     }
 
     fn clear_rx(&self) {
+        self.inner.lock(|inner| inner.rx_buffer.clear());
+
         // Read from the RX FIFO until it is indicating empty.
         while self
             .inner
-            .lock(|inner| inner.read_char_converting(BlockingMode::NonBlocking))
+            .lock(|inner| inner.read_byte(BlockingMode::NonBlocking))
             .is_some()
         {}
     }
@@ -404,4 +613,73 @@ impl console::interface::Statistics for PL011Uart {
     }
 }
 
+impl console::interface::AsyncWrite for PL011Uart {
+    /// There is no TX IRQ. While the TX FIFO is full, the task yields to the others and tries
+    /// again on the next poll.
+    fn poll_write_char(&self, cx: &mut Context<'_>, c: char) -> Poll<()> {
+        let written = self.inner.lock(|inner| {
+            if inner.registers.FR.matches_all(FR::TXFF::SET) {
+                return false;
+            }
+
+            inner.write_char(c);
+            true
+        });
+
+        if !written {
+            cx.waker().wake_by_ref();
+            return Poll::Pending;
+        }
+
+        Poll::Ready(())
+    }
+}
+
+impl console::interface::AsyncRead for PL011Uart {
+    fn poll_read_char(&self, cx: &mut Context<'_>) -> Poll<char> {
+        if !self.rx_irq_enabled.load(Ordering::Acquire) {
+            // Nobody would wake the task up, so keep polling the FIFO.
+            return match self
+                .inner
+                .lock(|inner| inner.read_byte(BlockingMode::NonBlocking))
+            {
+                Some(byte) => Poll::Ready(convert_received(byte)),
+                None => {
+                    cx.waker().wake_by_ref();
+                    Poll::Pending
+                }
+            };
+        }
+
+        // Register before checking, so that a character arriving in between is not missed.
+        self.rx_waker.register(cx.waker());
+
+        match self.inner.lock(|inner| inner.rx_buffer.pop()) {
+            Some(byte) => Poll::Ready(convert_received(byte)),
+            None => Poll::Pending,
+        }
+    }
+}
+
 impl console::interface::All for PL011Uart {}
+
+impl exception::asynchronous::interface::IRQHandler for PL011Uart {
+    fn handle(&self) -> Result<(), &'static str> {
+        let received = self.inner.lock(|inner| {
+            let pending = inner.registers.MIS.extract();
+
+            // Clear all pending IRQs.
+            inner.registers.ICR.write(ICR::ALL::CLEAR);
+
+            // Check for any kind of RX interrupt.
+            (pending.is_set(MIS::RXMIS) || pending.is_set(MIS::RTMIS)) && inner.drain_rx_fifo()
+        });
+
+        if received {
+            self.rx_waiters.notify_all();
+            self.rx_waker.wake();
+        }
+
+        Ok(())
+    }
+}
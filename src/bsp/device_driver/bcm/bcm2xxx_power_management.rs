@@ -0,0 +1,150 @@
+//! BCM Power Management driver.
+//!
+//! Only the watchdog is used, to reset the board. Every write to the block must carry a password
+//! in the upper byte, otherwise it is ignored.
+//!
+//! # Resources
+//!
+//! - <https://github.com/raspberrypi/linux/blob/rpi-5.15.y/drivers/watchdog/bcm2835_wdt.c>
+
+use crate::{
+    bsp::device_driver::common::MMIODerefWrapper, console, cpu, driver,
+    exception::asynchronous::IRQNumber, synchronization, synchronization::IRQSafeLock,
+};
+use tock_registers::{
+    interfaces::{ReadWriteable, Writeable},
+    register_bitfields, register_structs,
+    registers::ReadWrite,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+register_bitfields! {
+    u32,
+
+    /// Reset Control
+    RSTC [
+        /// What happens when the watchdog expires.
+        WRCFG OFFSET(4) NUMBITS(2) [
+            FullReset = 0b10
+        ],
+
+        PASSWD OFFSET(24) NUMBITS(8) [
+            Password = 0x5A
+        ]
+    ],
+
+    /// Watchdog
+    WDOG [
+        /// Ticks of about 16 µs until the watchdog expires.
+        TIME OFFSET(0) NUMBITS(20) [],
+
+        PASSWD OFFSET(24) NUMBITS(8) [
+            Password = 0x5A
+        ]
+    ]
+}
+
+register_structs! {
+    #[allow(non_snake_case)]
+    RegisterBlock {
+        (0x00 => _reserved1),
+        (0x1C => RSTC: ReadWrite<u32, RSTC::Register>),
+        (0x20 => _reserved2),
+        (0x24 => WDOG: ReadWrite<u32, WDOG::Register>),
+        (0x28 => @END),
+    }
+}
+
+/// Abstraction for the associated MMIO registers.
+type Registers = MMIODerefWrapper<RegisterBlock>;
+
+/// Watchdog ticks until the reset. Short, but long enough for the write to RSTC to land first.
+const RESET_TICKS: u32 = 10;
+
+struct PowerManagementInner {
+    registers: Registers,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Representation of the Power Management block.
+pub struct PowerManagement {
+    inner: IRQSafeLock<PowerManagementInner>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+impl PowerManagementInner {
+    /// Create an instance.
+    ///
+    /// # Safety
+    ///
+    /// - The user must ensure to provide a correct MMIO start address.
+    pub const unsafe fn new(mmio_start_addr: usize) -> Self {
+        Self {
+            registers: Registers::new(mmio_start_addr),
+        }
+    }
+
+    fn start_reset(&mut self) {
+        self.registers
+            .WDOG
+            .write(WDOG::PASSWD::Password + WDOG::TIME.val(RESET_TICKS));
+
+        // Keeps the other reset configuration bits.
+        self.registers
+            .RSTC
+            .modify(RSTC::PASSWD::Password + RSTC::WRCFG::FullReset);
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl PowerManagement {
+    pub const COMPATIBLE: &'static str = "BCM Power Management";
+
+    /// Create an instance.
+    ///
+    /// # Safety
+    ///
+    /// - The user must ensure to provide a correct MMIO start address.
+    pub const unsafe fn new(mmio_start_addr: usize) -> Self {
+        Self {
+            inner: IRQSafeLock::new(PowerManagementInner::new(mmio_start_addr)),
+        }
+    }
+
+    /// Reset the whole board through the watchdog.
+    ///
+    /// Waits for the console to send out everything that was printed before.
+    pub fn reset(&self) -> ! {
+        use console::interface::Write;
+
+        console::console_manger().flush();
+        self.inner.lock(|inner| inner.start_reset());
+
+        cpu::wait_forever()
+    }
+}
+
+//------------------------------------------------------------------------------
+// OS Interface Code
+//------------------------------------------------------------------------------
+use synchronization::interface::Mutex;
+
+impl driver::interface::DeviceDriver for PowerManagement {
+    type IRQNumberType = IRQNumber;
+
+    fn compatible(&self) -> &'static str {
+        Self::COMPATIBLE
+    }
+}
@@ -0,0 +1,198 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2018-2022 Andre Richter <andre.o.richter@gmail.com>
+
+//! BCM System Timer driver.
+//!
+//! A free-running 64 bit counter ticking at 1 MHz, plus four 32 bit compare channels that assert
+//! a peripheral IRQ when they match the lower half of the counter. Channels 0 and 2 are used by the
+//! VideoCore firmware, so only 1 and 3 are available to the ARM.
+//!
+//! # Resources
+//!
+//! - <https://github.com/raspberrypi/documentation/files/1888662/BCM2837-ARM-Peripherals.-.Revised.-.V2-1.pdf>
+//! - <https://datasheets.raspberrypi.com/bcm2711/bcm2711-peripherals.pdf>
+
+use crate::{
+    bsp::device_driver::common::MMIODerefWrapper,
+    driver, exception,
+    exception::asynchronous::{IRQHandlerDescriptor, IRQNumber},
+    synchronization,
+    synchronization::IRQSafeLock,
+    time,
+};
+use core::time::Duration;
+use tock_registers::{
+    interfaces::{Readable, Writeable},
+    register_structs,
+    registers::{ReadOnly, ReadWrite},
+};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+register_structs! {
+    #[allow(non_snake_case)]
+    RegisterBlock {
+        /// Control/Status. Bit n is set on a match of channel n. Write one to clear.
+        (0x00 => CS: ReadWrite<u32>),
+        (0x04 => CLO: ReadOnly<u32>),
+        (0x08 => CHI: ReadOnly<u32>),
+        (0x0C => C: [ReadWrite<u32>; 4]),
+        (0x1C => @END),
+    }
+}
+
+/// Abstraction for the associated MMIO registers.
+type Registers = MMIODerefWrapper<RegisterBlock>;
+
+/// The compare channel used for the alarm.
+const ALARM_CHANNEL: usize = 1;
+
+/// Matches that are closer than this might already be missed when the compare register is written.
+const MIN_ALARM_DELAY_US: u64 = 10;
+
+/// The compare registers only cover 32 bits of the counter. Stay well below to not wrap around.
+const MAX_ALARM_DELAY_US: u64 = i32::MAX as u64;
+
+struct SystemTimerInner {
+    registers: Registers,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Representation of the System Timer.
+pub struct SystemTimer {
+    inner: IRQSafeLock<SystemTimerInner>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+impl SystemTimerInner {
+    /// Create an instance.
+    ///
+    /// # Safety
+    ///
+    /// - The user must ensure to provide a correct MMIO start address.
+    pub const unsafe fn new(mmio_start_addr: usize) -> Self {
+        Self {
+            registers: Registers::new(mmio_start_addr),
+        }
+    }
+
+    /// Read the full 64 bit counter.
+    ///
+    /// The two halves can not be read atomically, so read the upper half again to detect a carry.
+    fn counter(&self) -> u64 {
+        loop {
+            let hi = self.registers.CHI.get();
+            let lo = self.registers.CLO.get();
+
+            if hi == self.registers.CHI.get() {
+                return (u64::from(hi) << 32) | u64::from(lo);
+            }
+        }
+    }
+
+    fn set_alarm(&mut self, delay: Duration) {
+        let delay_us = (delay.as_micros() as u64).clamp(MIN_ALARM_DELAY_US, MAX_ALARM_DELAY_US);
+        let target = self.registers.CLO.get().wrapping_add(delay_us as u32);
+
+        self.clear_match();
+        self.registers.C[ALARM_CHANNEL].set(target);
+    }
+
+    fn clear_match(&mut self) {
+        // Write-one-to-clear.
+        self.registers.CS.set(1 << ALARM_CHANNEL);
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl SystemTimer {
+    pub const COMPATIBLE: &'static str = "BCM System Timer";
+
+    /// Create an instance.
+    ///
+    /// # Safety
+    ///
+    /// - The user must ensure to provide a correct MMIO start address.
+    pub const unsafe fn new(mmio_start_addr: usize) -> Self {
+        Self {
+            inner: IRQSafeLock::new(SystemTimerInner::new(mmio_start_addr)),
+        }
+    }
+}
+
+//------------------------------------------------------------------------------
+// OS Interface Code
+//------------------------------------------------------------------------------
+use synchronization::interface::Mutex;
+
+impl driver::interface::DeviceDriver for SystemTimer {
+    type IRQNumberType = IRQNumber;
+
+    fn compatible(&self) -> &'static str {
+        Self::COMPATIBLE
+    }
+
+    unsafe fn init(&self) -> Result<(), &'static str> {
+        self.inner.lock(|inner| inner.clear_match());
+
+        Ok(())
+    }
+
+    fn register_and_enable_irq_handler(
+        &'static self,
+        irq_number: &Self::IRQNumberType,
+    ) -> Result<(), &'static str> {
+        use exception::asynchronous::irq_manager;
+
+        let descriptor = IRQHandlerDescriptor::new(*irq_number, Self::COMPATIBLE, self);
+
+        irq_manager().register_handler(descriptor)?;
+        irq_manager().enable(irq_number);
+
+        Ok(())
+    }
+}
+
+impl time::interface::ClockSource for SystemTimer {
+    fn name(&self) -> &'static str {
+        Self::COMPATIBLE
+    }
+
+    fn resolution(&self) -> Duration {
+        Duration::from_micros(1)
+    }
+
+    fn uptime(&self) -> Duration {
+        Duration::from_micros(self.inner.lock(|inner| inner.counter()))
+    }
+}
+
+impl time::interface::Alarm for SystemTimer {
+    fn set_alarm(&self, delay: Duration) {
+        self.inner.lock(|inner| inner.set_alarm(delay))
+    }
+
+    fn conclude_alarm(&self) {
+        self.inner.lock(|inner| inner.clear_match())
+    }
+}
+
+impl exception::asynchronous::interface::IRQHandler for SystemTimer {
+    fn handle(&self) -> Result<(), &'static str> {
+        time::time_manager().handle_alarm();
+
+        Ok(())
+    }
+}
@@ -1,8 +1,8 @@
 use core::{fmt, mem::size_of, ptr};
 
 use crate::{
-    bsp::driver::MAILBOX, console, debug, driver, gpu::Display, info, synchronization,
-    synchronization::NullLock, warn,
+    bsp::driver::MAILBOX, console, debug, driver, exception::asynchronous::IRQNumber, gpu::Display,
+    info, memory, synchronization, synchronization::IRQSafeLock, warn,
 };
 use embedded_graphics::{
     mono_font::{ascii::FONT_6X10, MonoFont, MonoTextStyle},
@@ -31,7 +31,7 @@ struct VideoInner {
 
 /// Representation of the VideoCore.
 pub struct Video {
-    inner: NullLock<VideoInner>,
+    inner: IRQSafeLock<VideoInner>,
 }
 
 impl VideoInner {
@@ -59,7 +59,19 @@ impl VideoInner {
                 debug!(
                     "Framebuffer at {:?} with length {} bytes",
                     ptr, display.fp_len
-                )
+                );
+
+                // the GPU scans the framebuffer out of memory, so bypass the caches for it
+                let result = unsafe {
+                    memory::mmu::kernel_map_shared_buffer(
+                        "Framebuffer",
+                        ptr as usize,
+                        display.fp_len,
+                    )
+                };
+                if let Err(x) = result {
+                    warn!("Framebuffer could not be mapped non-cacheable: {}", x);
+                }
             }
         }
     }
@@ -245,7 +257,7 @@ impl Video {
     /// Create an instance.
     pub const unsafe fn new() -> Self {
         Self {
-            inner: NullLock::new(VideoInner::new()),
+            inner: IRQSafeLock::new(VideoInner::new()),
         }
     }
 
@@ -271,6 +283,8 @@ impl Video {
 use synchronization::interface::Mutex;
 
 impl driver::interface::DeviceDriver for Video {
+    type IRQNumberType = IRQNumber;
+
     fn compatible(&self) -> &'static str {
         Self::COMPATIBLE
     }
@@ -312,4 +326,7 @@ impl console::interface::Statistics for Video {
     }
 }
 
+impl console::interface::AsyncWrite for Video {}
+impl console::interface::AsyncRead for Video {}
+
 impl console::interface::All for Video {}
@@ -4,7 +4,7 @@
 
 //! Common device driver code.
 
-use core::{marker::PhantomData, ops};
+use core::{fmt, marker::PhantomData, ops};
 
 //--------------------------------------------------------------------------------------------------
 // Public Definitions
@@ -15,6 +15,10 @@ pub struct MMIODerefWrapper<T> {
     phantom: PhantomData<fn() -> T>,
 }
 
+/// A wrapper type for usize with integrated range bound check.
+#[derive(Copy, Clone)]
+pub struct BoundedUsize<const MAX_INCLUSIVE: usize>(usize);
+
 //--------------------------------------------------------------------------------------------------
 // Public Code
 //--------------------------------------------------------------------------------------------------
@@ -36,3 +40,25 @@ impl<T> ops::Deref for MMIODerefWrapper<T> {
         unsafe { &*(self.start_addr as *const _) }
     }
 }
+
+impl<const MAX_INCLUSIVE: usize> BoundedUsize<{ MAX_INCLUSIVE }> {
+    pub const MAX_INCLUSIVE: usize = MAX_INCLUSIVE;
+
+    /// Creates a new instance if number <= MAX_INCLUSIVE.
+    pub const fn new(number: usize) -> Self {
+        assert!(number <= MAX_INCLUSIVE);
+
+        Self(number)
+    }
+
+    /// Return the wrapped number.
+    pub const fn get(self) -> usize {
+        self.0
+    }
+}
+
+impl<const MAX_INCLUSIVE: usize> fmt::Display for BoundedUsize<{ MAX_INCLUSIVE }> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
@@ -6,6 +6,7 @@
 
 pub mod cpu;
 pub mod driver;
+pub mod exception;
 pub mod memory;
 
 //--------------------------------------------------------------------------------------------------
@@ -24,3 +25,9 @@ pub fn board_name() -> &'static str {
         "Raspberry Pi 4"
     }
 }
+
+/// Reset the board.
+#[cfg(not(feature = "chainloader"))]
+pub fn reset() -> ! {
+    driver::POWER_MANAGEMENT.reset()
+}
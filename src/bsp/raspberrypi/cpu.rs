@@ -12,3 +12,13 @@
 #[no_mangle]
 #[link_section = ".text._start_arguments"]
 pub static BOOT_CORE_ID: u64 = 0;
+
+/// Number of CPU cores.
+pub const NUM_CORES: usize = 4;
+
+/// Spin-table release addresses of the cores.
+///
+/// The firmware's armstub parks the secondary cores until their release address holds a non-zero
+/// value, then jumps there.
+#[cfg(not(feature = "chainloader"))]
+pub const SPIN_TABLE_RELEASE_ADDR: [usize; NUM_CORES] = [0xd8, 0xe0, 0xe8, 0xf0];
@@ -4,9 +4,24 @@
 
 //! BSP driver support.
 
-use super::memory::map::mmio;
-use crate::{bsp::device_driver, console::copy_console, driver as generic_driver};
-use core::sync::atomic::{AtomicBool, Ordering};
+use super::{exception, memory::map::mmio};
+#[cfg(not(feature = "chainloader"))]
+use crate::{block, warn};
+use crate::{
+    bsp::device_driver, console::copy_console, driver as generic_driver,
+    exception as generic_exception, time,
+};
+use core::{
+    fmt,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Pin settings for users of `GPIO`.
+pub use device_driver::{Event, Function, Level, Pin, Pull};
 
 //--------------------------------------------------------------------------------------------------
 // Global instances
@@ -14,10 +29,28 @@ use core::sync::atomic::{AtomicBool, Ordering};
 
 static PL011_UART: device_driver::PL011Uart =
     unsafe { device_driver::PL011Uart::new(mmio::PL011_UART_START) };
-static GPIO: device_driver::GPIO = unsafe { device_driver::GPIO::new(mmio::GPIO_START) };
+pub static GPIO: device_driver::GPIO = unsafe { device_driver::GPIO::new(mmio::GPIO_START) };
 pub static MAILBOX: device_driver::MailBox =
     unsafe { device_driver::MailBox::new(mmio::MAIL_START) };
+#[cfg(not(feature = "chainloader"))]
+pub static POWER_MANAGEMENT: device_driver::PowerManagement =
+    unsafe { device_driver::PowerManagement::new(mmio::PM_START) };
+#[cfg(not(feature = "chainloader"))]
 pub static VIDEOCORE: device_driver::Video = unsafe { device_driver::Video::new() };
+#[cfg(not(feature = "chainloader"))]
+static EMMC: device_driver::EMMC = unsafe { device_driver::EMMC::new(mmio::EMMC_START) };
+#[cfg(not(feature = "chainloader"))]
+static SYSTEM_TIMER: device_driver::SystemTimer =
+    unsafe { device_driver::SystemTimer::new(mmio::SYSTEM_TIMER_START) };
+
+#[cfg(feature = "bsp_rpi3")]
+static INTERRUPT_CONTROLLER: device_driver::InterruptController = unsafe {
+    device_driver::InterruptController::new(mmio::LOCAL_IC_START, mmio::PERIPHERAL_IC_START)
+};
+
+#[cfg(feature = "bsp_rpi4")]
+static INTERRUPT_CONTROLLER: device_driver::GICv2 =
+    unsafe { device_driver::GICv2::new(mmio::GICD_START, mmio::GICC_START) };
 
 //--------------------------------------------------------------------------------------------------
 // Private Code
@@ -33,7 +66,11 @@ fn post_init_uart() -> Result<(), &'static str> {
 
 /// This must be called only after successful init of the GPIO driver.
 fn post_init_gpio() -> Result<(), &'static str> {
-    GPIO.map_pl011_uart();
+    GPIO.map_pl011_uart()?;
+
+    #[cfg(all(feature = "bsp_rpi3", not(feature = "chainloader")))]
+    GPIO.map_emmc()?;
+
     Ok(())
 }
 
@@ -42,7 +79,34 @@ fn post_init_mailbox() -> Result<(), &'static str> {
     Ok(())
 }
 
+/// This must be called only after successful init of the interrupt controller driver.
+fn post_init_interrupt_controller() -> Result<(), &'static str> {
+    generic_exception::asynchronous::register_irq_manager(&INTERRUPT_CONTROLLER);
+
+    Ok(())
+}
+
+/// This must be called only after successful init of the System Timer driver.
+#[cfg(not(feature = "chainloader"))]
+fn post_init_system_timer() -> Result<(), &'static str> {
+    // Only advisory. The selected clock source is used anyway, it might be the only one that works.
+    if let Err(x) = time::time_manager().cross_check(&SYSTEM_TIMER) {
+        warn!("{}: {}", device_driver::SystemTimer::COMPATIBLE, x);
+    }
+
+    if cfg!(feature = "sys_timer_clocksource") {
+        time::time_manager().register_clock_source(&SYSTEM_TIMER)?;
+    }
+
+    if cfg!(feature = "sys_timer_alarm") {
+        time::time_manager().register_alarm(&SYSTEM_TIMER);
+    }
+
+    Ok(())
+}
+
 /// This must be called only after successful init of the Video driver.
+#[cfg(not(feature = "chainloader"))]
 fn post_init_video() -> Result<(), &'static str> {
     VIDEOCORE.reset_console();
 
@@ -52,33 +116,122 @@ fn post_init_video() -> Result<(), &'static str> {
     Ok(())
 }
 
+/// This must be called only after successful init of the EMMC driver.
+#[cfg(not(feature = "chainloader"))]
+fn post_init_emmc() -> Result<(), &'static str> {
+    if let Err(x) = EMMC.card_status() {
+        warn!("{}: {}", device_driver::EMMC::COMPATIBLE, x);
+        return Ok(());
+    }
+
+    block::register_device(&EMMC);
+    if let Err(x) = block::partition::register_partitions(&EMMC) {
+        warn!("{}: {}", block::BlockDevice::name(&EMMC), x);
+    }
+
+    Ok(())
+}
+
 fn driver_uart() -> Result<(), &'static str> {
-    let uart_descriptor =
-        generic_driver::DeviceDriverDescriptor::new(&PL011_UART, Some(post_init_uart));
-    generic_driver::driver_manager().register_driver(uart_descriptor);
+    let uart_descriptor = generic_driver::DeviceDriverDescriptor::new(
+        &PL011_UART,
+        Some(post_init_uart),
+        exception::asynchronous::irq_map::PL011_UART,
+    );
+    generic_driver::driver_manager().register_driver(uart_descriptor)?;
 
     Ok(())
 }
 
 fn driver_gpio() -> Result<(), &'static str> {
-    let gpio_descriptor = generic_driver::DeviceDriverDescriptor::new(&GPIO, Some(post_init_gpio));
-    generic_driver::driver_manager().register_driver(gpio_descriptor);
+    let gpio_descriptor = generic_driver::DeviceDriverDescriptor::new(
+        &GPIO,
+        Some(post_init_gpio),
+        exception::asynchronous::irq_map::GPIO,
+    );
+    generic_driver::driver_manager().register_driver(gpio_descriptor)?;
 
     Ok(())
 }
 
 fn driver_mailbox() -> Result<(), &'static str> {
+    // The chainloader only makes blocking requests.
+    #[cfg(not(feature = "chainloader"))]
+    let irq_numbers = exception::asynchronous::irq_map::MAILBOX;
+    #[cfg(feature = "chainloader")]
+    let irq_numbers = &[];
+
     let mailbox_descriptor =
-        generic_driver::DeviceDriverDescriptor::new(&MAILBOX, Some(post_init_mailbox));
-    generic_driver::driver_manager().register_driver(mailbox_descriptor);
+        generic_driver::DeviceDriverDescriptor::new(&MAILBOX, Some(post_init_mailbox), irq_numbers);
+    generic_driver::driver_manager().register_driver(mailbox_descriptor)?;
+
+    Ok(())
+}
+
+fn driver_interrupt_controller() -> Result<(), &'static str> {
+    let interrupt_controller_descriptor = generic_driver::DeviceDriverDescriptor::new(
+        &INTERRUPT_CONTROLLER,
+        Some(post_init_interrupt_controller),
+        &[],
+    );
+    generic_driver::driver_manager().register_driver(interrupt_controller_descriptor)?;
+
+    Ok(())
+}
+
+fn driver_timer() -> Result<(), &'static str> {
+    let timer_descriptor = generic_driver::DeviceDriverDescriptor::new(
+        time::time_manager(),
+        None,
+        exception::asynchronous::irq_map::ARCH_TIMER,
+    );
+    generic_driver::driver_manager().register_driver(timer_descriptor)?;
+
+    Ok(())
+}
+
+#[cfg(not(feature = "chainloader"))]
+fn driver_system_timer() -> Result<(), &'static str> {
+    // Only take the compare channel's IRQ if the System Timer is the alarm backend.
+    let irq_numbers = if cfg!(feature = "sys_timer_alarm") {
+        exception::asynchronous::irq_map::SYSTEM_TIMER
+    } else {
+        &[]
+    };
+
+    let system_timer_descriptor = generic_driver::DeviceDriverDescriptor::new(
+        &SYSTEM_TIMER,
+        Some(post_init_system_timer),
+        irq_numbers,
+    );
+    generic_driver::driver_manager().register_driver(system_timer_descriptor)?;
+
+    Ok(())
+}
+
+#[cfg(not(feature = "chainloader"))]
+fn driver_power_management() -> Result<(), &'static str> {
+    let power_management_descriptor =
+        generic_driver::DeviceDriverDescriptor::new(&POWER_MANAGEMENT, None, &[]);
+    generic_driver::driver_manager().register_driver(power_management_descriptor)?;
 
     Ok(())
 }
 
+#[cfg(not(feature = "chainloader"))]
 fn driver_video() -> Result<(), &'static str> {
     let video_descriptor =
-        generic_driver::DeviceDriverDescriptor::new(&VIDEOCORE, Some(post_init_video));
-    generic_driver::driver_manager().register_driver(video_descriptor);
+        generic_driver::DeviceDriverDescriptor::new(&VIDEOCORE, Some(post_init_video), &[]);
+    generic_driver::driver_manager().register_driver(video_descriptor)?;
+
+    Ok(())
+}
+
+#[cfg(not(feature = "chainloader"))]
+fn driver_emmc() -> Result<(), &'static str> {
+    let emmc_descriptor =
+        generic_driver::DeviceDriverDescriptor::new(&EMMC, Some(post_init_emmc), &[]);
+    generic_driver::driver_manager().register_driver(emmc_descriptor)?;
 
     Ok(())
 }
@@ -111,9 +264,45 @@ pub unsafe fn init() -> Result<(), &'static str> {
 
     driver_uart()?;
     driver_gpio()?;
+    driver_interrupt_controller()?;
+    driver_timer()?;
+    #[cfg(not(feature = "chainloader"))]
+    driver_system_timer()?;
     driver_mailbox()?;
-    driver_video()?;
+
+    // The chainloader does without the rest.
+    #[cfg(not(feature = "chainloader"))]
+    {
+        driver_power_management()?;
+        driver_video()?;
+        // Needs the GPIO pins and the Mailbox.
+        driver_emmc()?;
+    }
 
     INIT_DONE.store(true, Ordering::Relaxed);
     Ok(())
 }
+
+/// Silence all interrupt sources the chainloader enabled, and the interrupt controller itself.
+///
+/// The kernel that is booted next expects the interrupts off, like the firmware leaves them.
+#[cfg(feature = "chainloader")]
+pub fn disable_irqs() {
+    use generic_exception::asynchronous::interface::IRQManager;
+
+    PL011_UART.disable_irqs();
+    INTERRUPT_CONTROLLER.disable_all();
+}
+
+/// In case of a panic, the panic handler uses this function to take a last shot at printing
+/// something before the system is halted.
+///
+/// Writes to the UART registers directly, so that a lock held by the panicking or a stopped core
+/// can not block the output. The UART is used as the driver left it.
+///
+/// # Safety
+///
+/// - Use only for printing during a panic.
+pub unsafe fn panic_console_out() -> impl fmt::Write {
+    device_driver::PanicUart::new(mmio::PL011_UART_START)
+}
@@ -0,0 +1,68 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020-2022 Andre Richter <andre.o.richter@gmail.com>
+
+//! BSP asynchronous exception handling.
+
+use crate::bsp;
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Export for reuse in generic asynchronous.rs.
+pub use bsp::device_driver::IRQNumber;
+
+/// The IRQ map.
+#[cfg(feature = "bsp_rpi3")]
+pub(in crate::bsp) mod irq_map {
+    use super::bsp::device_driver::{IRQNumber, LocalIRQ, PeripheralIRQ};
+
+    /// Non-secure physical timer (`CNTPNSIRQ`) of the executing core.
+    pub const ARCH_TIMER: &[IRQNumber] = &[IRQNumber::Local(LocalIRQ::new(1))];
+
+    /// System Timer compare channel 1.
+    #[cfg(not(feature = "chainloader"))]
+    pub const SYSTEM_TIMER: &[IRQNumber] = &[IRQNumber::Peripheral(PeripheralIRQ::new(1))];
+
+    /// PL011 UART (`uart_int`).
+    pub const PL011_UART: &[IRQNumber] = &[IRQNumber::Peripheral(PeripheralIRQ::new(57))];
+
+    /// ARM Mailbox, bit 1 of the basic pending register.
+    #[cfg(not(feature = "chainloader"))]
+    pub const MAILBOX: &[IRQNumber] = &[IRQNumber::Peripheral(PeripheralIRQ::new(65))];
+
+    /// GPIO bank 0, 1 and 2 (`gpio_int[0..2]`).
+    pub const GPIO: &[IRQNumber] = &[
+        IRQNumber::Peripheral(PeripheralIRQ::new(49)),
+        IRQNumber::Peripheral(PeripheralIRQ::new(50)),
+        IRQNumber::Peripheral(PeripheralIRQ::new(51)),
+    ];
+}
+
+/// The IRQ map.
+#[cfg(feature = "bsp_rpi4")]
+pub(in crate::bsp) mod irq_map {
+    use super::bsp::device_driver::IRQNumber;
+
+    /// Non-secure physical timer (`CNTPNSIRQ`), PPI 14.
+    pub const ARCH_TIMER: &[IRQNumber] = &[IRQNumber::new(30)];
+
+    /// System Timer compare channel 1, i.e. VideoCore IRQ 1 routed to SPI 97.
+    #[cfg(not(feature = "chainloader"))]
+    pub const SYSTEM_TIMER: &[IRQNumber] = &[IRQNumber::new(97)];
+
+    /// PL011 UART, i.e. VideoCore IRQ 57 routed to SPI 153.
+    pub const PL011_UART: &[IRQNumber] = &[IRQNumber::new(153)];
+
+    /// ARM Mailbox, i.e. ARMC IRQ 1 routed to SPI 33.
+    #[cfg(not(feature = "chainloader"))]
+    pub const MAILBOX: &[IRQNumber] = &[IRQNumber::new(65)];
+
+    /// GPIO bank 0, 1 and 2 (`gpio_int[0..2]`), i.e. VideoCore IRQs 49-51 routed to SPIs 145-147.
+    pub const GPIO: &[IRQNumber] = &[
+        IRQNumber::new(145),
+        IRQNumber::new(146),
+        IRQNumber::new(147),
+    ];
+}
@@ -3,6 +3,54 @@
 // Copyright (c) 2018-2022 Andre Richter <andre.o.richter@gmail.com>
 
 //! BSP Memory Management.
+//!
+//! The physical memory layout.
+//!
+//! The Raspberry's firmware copies the kernel binary to 0x8_0000. The preceding region will be used
+//! as the boot core's stack.
+//!
+//! +---------------------------------------+
+//! |                                       | 0x0
+//! | Firmware armstub and spin tables      |
+//! |                                       |
+//! +---------------------------------------+
+//! |                                       | boot_core_stack_guard_page_start @ 0x1_0000
+//! | Boot-core Stack Guard Page (unmapped) |
+//! |                                       |
+//! +---------------------------------------+
+//! |                                       |                                ^
+//! | Boot-core Stack                       |                                | stack
+//! |                                       |                                | growth
+//! |                                       |                                | direction
+//! +---------------------------------------+
+//! |                                       | code_start @ 0x8_0000
+//! | .text                                 |
+//! |                                       |
+//! +---------------------------------------+
+//! |                                       | code_end_exclusive, rodata_start
+//! | .rodata                               |
+//! |                                       |
+//! +---------------------------------------+
+//! |                                       | rodata_end_exclusive
+//! | .data                                 |
+//! | .bss                                  |
+//! |                                       |
+//! +---------------------------------------+
+//! |                                       | kernel_end_exclusive
+//! | Usable RAM, e.g. the kernel heap      |
+//! |                                       |
+//! +---------------------------------------+
+//! |                                       | ARM memory end
+//! | VideoCore memory                      |
+//! |                                       |
+//!
+//! The chainloader is laid out the same way, but 32 MiB further up, see `chainloader.ld`. Its
+//! `_start()` moves it there from 0x8_0000, which leaves the load address to the kernel it receives.
+
+pub mod mmu;
+
+use super::driver::MAILBOX;
+use crate::memory::phys_map::{phys_memory_map, RegionType};
 use core::cell::UnsafeCell;
 
 //--------------------------------------------------------------------------------------------------
@@ -11,8 +59,19 @@ use core::cell::UnsafeCell;
 
 // Symbols from the linker script.
 extern "Rust" {
-    static __heap_start: UnsafeCell<()>;
-    static __heap_end_exclusive: UnsafeCell<()>;
+    static __code_start: UnsafeCell<()>;
+    static __code_end_exclusive: UnsafeCell<()>;
+
+    static __rodata_start: UnsafeCell<()>;
+    static __rodata_end_exclusive: UnsafeCell<()>;
+
+    static __boot_core_stack_guard_page_start: UnsafeCell<()>;
+    static __boot_core_stack_guard_page_end_exclusive: UnsafeCell<()>;
+
+    static __kernel_start: UnsafeCell<()>;
+    static __kernel_end_exclusive: UnsafeCell<()>;
+
+    static __rpi_phys_binary_load_addr: UnsafeCell<()>;
 }
 
 //--------------------------------------------------------------------------------------------------
@@ -23,6 +82,18 @@ extern "Rust" {
 #[rustfmt::skip]
 pub(super) mod map {
 
+    /// The inclusive end address of the memory map.
+    ///
+    /// End address + 1 must be power of two.
+    pub const END_INCLUSIVE:       usize = 0xFFFF_FFFF;
+
+    /// The window that user address spaces live in. It is above the RAM that the firmware reports
+    /// and below the MMIO ranges, so the kernel never needs its identity mapping.
+    pub const USER_START:          usize = 0x8000_0000;
+    pub const USER_END_INCLUSIVE:  usize = 0x9FFF_FFFF;
+
+    #[cfg(not(feature = "chainloader"))]
+    pub const PM_OFFSET:           usize = 0x0010_0000;
     pub const GPIO_OFFSET:         usize = 0x0020_0000;
     pub const UART_OFFSET:         usize = 0x0020_1000;
 
@@ -31,10 +102,19 @@ pub(super) mod map {
     pub mod mmio {
         use super::*;
 
-        pub const START:            usize =         0x3F00_0000;
-        pub const GPIO_START:       usize = START + GPIO_OFFSET;
-        pub const PL011_UART_START: usize = START + UART_OFFSET;
-        pub const MAIL_START:       usize = START + 0xB880;
+        pub const START:               usize =         0x3F00_0000;
+        #[cfg(not(feature = "chainloader"))]
+        pub const SYSTEM_TIMER_START:  usize = START + 0x0000_3000;
+        pub const PERIPHERAL_IC_START: usize = START + 0x0000_B200;
+        pub const GPIO_START:          usize = START + GPIO_OFFSET;
+        pub const PL011_UART_START:    usize = START + UART_OFFSET;
+        pub const MAIL_START:          usize = START + 0xB880;
+        #[cfg(not(feature = "chainloader"))]
+        pub const PM_START:            usize = START + PM_OFFSET;
+        #[cfg(not(feature = "chainloader"))]
+        pub const EMMC_START:          usize = START + 0x0030_0000;
+        pub const LOCAL_IC_START:      usize =         0x4000_0000;
+        pub const END_INCLUSIVE:       usize =         0x4000_FFFF;
     }
 
     /// Physical devices.
@@ -42,24 +122,143 @@ pub(super) mod map {
     pub mod mmio {
         use super::*;
 
-        pub const START:            usize =         0xFE00_0000;
-        pub const GPIO_START:       usize = START + GPIO_OFFSET;
-        pub const PL011_UART_START: usize = START + UART_OFFSET;
+        pub const START:              usize =         0xFE00_0000;
+        #[cfg(not(feature = "chainloader"))]
+        pub const SYSTEM_TIMER_START: usize = START + 0x0000_3000;
+        pub const GPIO_START:         usize = START + GPIO_OFFSET;
+        pub const PL011_UART_START:   usize = START + UART_OFFSET;
+        pub const MAIL_START:         usize = START + 0xB880;
+        #[cfg(not(feature = "chainloader"))]
+        pub const PM_START:           usize = START + PM_OFFSET;
+        #[cfg(not(feature = "chainloader"))]
+        pub const EMMC_START:         usize = START + 0x0034_0000;
+        pub const GICD_START:         usize =         0xFF84_1000;
+        pub const GICC_START:         usize =         0xFF84_2000;
+        pub const END_INCLUSIVE:      usize =         0xFF84_FFFF;
     }
 }
 
-/// Start page address of the heap segment.
+/// Start page address of the code segment.
+///
+/// # Safety
+///
+/// - Value is provided by the linker script and must be trusted as-is.
+#[inline(always)]
+fn code_start() -> usize {
+    unsafe { __code_start.get() as usize }
+}
+
+/// Exclusive end page address of the code segment.
+///
+/// # Safety
+///
+/// - Value is provided by the linker script and must be trusted as-is.
+#[inline(always)]
+fn code_end_exclusive() -> usize {
+    unsafe { __code_end_exclusive.get() as usize }
+}
+
+/// Start page address of the read-only data segment.
+///
+/// # Safety
+///
+/// - Value is provided by the linker script and must be trusted as-is.
 #[inline(always)]
-pub fn virt_heap_start() -> *mut u8 {
-    unsafe { __heap_start.get() as *mut u8 }
+fn rodata_start() -> usize {
+    unsafe { __rodata_start.get() as usize }
 }
 
-/// Size of the heap segment.
+/// Exclusive end page address of the read-only data segment.
 ///
 /// # Safety
 ///
 /// - Value is provided by the linker script and must be trusted as-is.
 #[inline(always)]
-pub fn heap_size() -> usize {
-    unsafe { (__heap_end_exclusive.get() as usize) - (__heap_start.get() as usize) }
+fn rodata_end_exclusive() -> usize {
+    unsafe { __rodata_end_exclusive.get() as usize }
+}
+
+/// Start page address of the boot core's stack guard page.
+///
+/// # Safety
+///
+/// - Value is provided by the linker script and must be trusted as-is.
+#[inline(always)]
+fn boot_core_stack_guard_page_start() -> usize {
+    unsafe { __boot_core_stack_guard_page_start.get() as usize }
+}
+
+/// Exclusive end page address of the boot core's stack guard page.
+///
+/// # Safety
+///
+/// - Value is provided by the linker script and must be trusted as-is.
+#[inline(always)]
+fn boot_core_stack_guard_page_end_exclusive() -> usize {
+    unsafe { __boot_core_stack_guard_page_end_exclusive.get() as usize }
+}
+
+/// Start page address of the kernel image, including the boot core's stack.
+///
+/// # Safety
+///
+/// - Value is provided by the linker script and must be trusted as-is.
+#[cfg(feature = "chainloader")]
+#[inline(always)]
+fn kernel_start() -> usize {
+    unsafe { __kernel_start.get() as usize }
+}
+
+/// The address that the firmware loads the kernel binary to.
+///
+/// # Safety
+///
+/// - Value is provided by the linker script and must be trusted as-is.
+#[cfg(feature = "chainloader")]
+#[inline(always)]
+fn phys_binary_load_addr() -> usize {
+    unsafe { __rpi_phys_binary_load_addr.get() as usize }
+}
+
+/// Exclusive end page address of the kernel image, including the boot core's stack.
+///
+/// # Safety
+///
+/// - Value is provided by the linker script and must be trusted as-is.
+#[inline(always)]
+fn kernel_end_exclusive() -> usize {
+    unsafe { __kernel_end_exclusive.get() as usize }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Fill the physical memory map with the RAM split that the firmware reports.
+///
+/// # Note
+///
+/// On the RPi4, the firmware only reports the RAM below 1 GiB. Anything above is left out.
+pub fn init_phys_memory_map() -> Result<(), &'static str> {
+    let map = phys_memory_map();
+
+    let (arm_start, arm_size) = MAILBOX.arm_memory()?;
+    map.add(arm_start, arm_size, RegionType::Usable)?;
+
+    let (vc_start, vc_size) = MAILBOX.vc_memory()?;
+    map.add(vc_start, vc_size, RegionType::Reserved("VideoCore"))?;
+
+    // The firmware's armstub, the boot core's stack and the kernel binary itself.
+    map.reserve(0, kernel_end_exclusive(), "Kernel image")
+}
+
+/// Where the chainloader puts the kernel it receives, as start and maximum size.
+///
+/// The kernel goes to the address that the firmware would have loaded it to, and must end below
+/// the chainloader's own image.
+#[cfg(feature = "chainloader")]
+pub fn chainloader_payload_region() -> (usize, usize) {
+    let start = phys_binary_load_addr();
+
+    (start, kernel_start() - start)
 }
@@ -0,0 +1,168 @@
+//! Serial chainloader.
+//!
+//! Built with the `chainloader` feature, e.g. `make CHAINLOADER=1`. Instead of running
+//! `kernel_main()`, the kernel then asks the host for a kernel image, see `console::transfer`, and
+//! boots it in its place. On the host, `make chainboot` pushes the image, see
+//! `common/serial/minipush.rb`.
+//!
+//! The chainloader is linked 32 MiB above the load address, and `_start()` relocates it there. The
+//! received image goes to the load address and starts the same way as after the firmware: On the
+//! boot core, in EL2, with the secondary cores still waiting in the armstub. The host can ask for
+//! another load address and entry point, as long as the image stays below the chainloader.
+//!
+//! # Signed images
+//!
+//! Only images with a valid Ed25519 signature are booted. The public key is built in from the file
+//! that `CHAINLOADER_PUBLIC_KEY` names at build time. `common/serial/sign.rb` creates the key pair
+//! and signs images, `make chainboot SIGNING_KEY=...` signs and pushes in one go.
+//!
+//! The signature covers the image only. Signed images therefore always go to the load address, a
+//! load address or entry point from the host is refused.
+//!
+//! The `chainloader_unsigned` feature (`make CHAINLOADER_UNSIGNED=1`) is for development. It boots
+//! unsigned images as well, and does without a key.
+
+#[cfg(target_arch = "aarch64")]
+#[path = "_arch/aarch64/chainloader.rs"]
+mod arch_chainloader;
+
+use crate::{bsp, common, console, console::transfer::Payload, info, time, warn};
+use core::time::Duration;
+use ed25519_compact::{PublicKey, Signature};
+
+//--------------------------------------------------------------------------------------------------
+// Architectural Public Reexports
+//--------------------------------------------------------------------------------------------------
+pub use arch_chainloader::init_el2_handover;
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// The key that images must be signed with. Only development builds may go without one.
+const PUBLIC_KEY: Option<[u8; PublicKey::BYTES]> = {
+    const HEX: &str = env!("RPOS_CHAINLOADER_PUBLIC_KEY");
+
+    if HEX.is_empty() {
+        None
+    } else {
+        Some(common::parse_hex(HEX))
+    }
+};
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+/// Check the signature of the image with `PUBLIC_KEY`.
+fn verify(payload: &Payload) -> Result<(), &'static str> {
+    match (payload.signature, PUBLIC_KEY) {
+        (Some(signature), Some(key)) => {
+            PublicKey::new(key)
+                .verify(&payload.data, &Signature::new(signature))
+                .map_err(|_| "Invalid signature")?;
+
+            // Not covered by the signature.
+            if payload.placement.is_some() {
+                return Err("Signed images cannot choose their load address");
+            }
+
+            Ok(())
+        }
+
+        // Only development builds have no key, see build.rs.
+        (Some(_), None) if cfg!(feature = "chainloader_unsigned") => {
+            warn!("No public key built in, cannot check the signature");
+            Ok(())
+        }
+        (Some(_), None) => Err("No public key built in"),
+        (None, _) if cfg!(feature = "chainloader_unsigned") => {
+            warn!("Booting an unsigned image");
+            Ok(())
+        }
+        (None, _) => Err("Image is not signed"),
+    }
+}
+
+/// Decide where the payload goes and where it starts. Both default to the start of the `region`.
+fn place(
+    payload: &Payload,
+    (region_start, region_size): (usize, usize),
+) -> Result<(usize, usize), &'static str> {
+    let (load_addr, entry) = match payload.placement {
+        Some(placement) => (placement.load_addr, placement.entry),
+        None => (region_start, region_start),
+    };
+
+    // The handover copies u64 chunks.
+    if load_addr % 8 != 0 || entry % 4 != 0 {
+        return Err("Load address or entry point misaligned");
+    }
+
+    let fits = load_addr >= region_start
+        && load_addr
+            .checked_add(payload.data.len())
+            .map_or(false, |end| end <= region_start + region_size);
+    if !fits {
+        return Err("Image does not fit below the chainloader");
+    }
+
+    if !(load_addr..load_addr + payload.data.len()).contains(&entry) {
+        return Err("Entry point outside of the image");
+    }
+
+    Ok((load_addr, entry))
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Receive a kernel image over the console and boot it.
+///
+/// Must be called from the main thread. Only the boot core may be online.
+pub fn chainloader_main() -> ! {
+    use console::interface::Write;
+
+    let region = bsp::memory::chainloader_payload_region();
+    info!(
+        "Chainloader on {}, loading to {:#x}",
+        bsp::board_name(),
+        region.0
+    );
+
+    // Waits for the host as long as it takes.
+    let (payload, load_addr, entry) = loop {
+        info!("Requesting binary");
+        let result = console::transfer::receive(console::console_manger(), Duration::MAX, region.1)
+            .and_then(|payload| {
+                verify(&payload)?;
+
+                let (load_addr, entry) = place(&payload, region)?;
+                Ok((payload, load_addr, entry))
+            });
+
+        match result {
+            Ok(placed) => break placed,
+            Err(x) => warn!("Receiving the kernel failed: {}", x),
+        }
+    };
+
+    info!(
+        "Loaded {} bytes to {:#x}, executing the payload at {:#x} now",
+        payload.data.len(),
+        load_addr,
+        entry
+    );
+    console::console_manger().flush();
+    bsp::driver::disable_irqs();
+
+    // The image is on the heap, which lies above the chainloader. See
+    // `bsp::memory::init_phys_memory_map()`.
+    // The host's time, see `console::transfer`.
+    let wall_clock_offset = time::wall_clock()
+        .unix_time()
+        .map(|unix_time| unix_time.saturating_sub(time::time_manager().uptime()));
+
+    unsafe { arch_chainloader::boot_payload(&payload.data, load_addr, entry, wall_clock_offset) }
+}
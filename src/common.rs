@@ -4,6 +4,10 @@
 
 //! General purpose code.
 
+pub mod crc32;
+#[cfg(any(feature = "chainloader", feature = "program_loader"))]
+pub mod sha256;
+
 /// Convert a size into human readable format.
 pub const fn size_human_readable_ceil(size: usize) -> (usize, &'static str) {
     const KIB: usize = 1024;
@@ -20,3 +24,44 @@ pub const fn size_human_readable_ceil(size: usize) -> (usize, &'static str) {
         (size, "Byte")
     }
 }
+
+/// Parse a decimal number at compile time, e.g. from `env!()`.
+pub const fn parse_usize(s: &str) -> usize {
+    let bytes = s.as_bytes();
+    let mut value = 0;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        assert!(bytes[i].is_ascii_digit());
+
+        value = value * 10 + (bytes[i] - b'0') as usize;
+        i += 1;
+    }
+
+    value
+}
+
+/// Parse exactly `N` bytes in hex at compile time, e.g. from `env!()`.
+#[cfg(feature = "chainloader")]
+pub const fn parse_hex<const N: usize>(s: &str) -> [u8; N] {
+    const fn nibble(c: u8) -> u8 {
+        match c {
+            b'0'..=b'9' => c - b'0',
+            b'a'..=b'f' => c - b'a' + 10,
+            b'A'..=b'F' => c - b'A' + 10,
+            _ => panic!("Not a hex digit"),
+        }
+    }
+
+    let hex = s.as_bytes();
+    assert!(hex.len() == 2 * N);
+
+    let mut bytes = [0; N];
+    let mut i = 0;
+    while i < N {
+        bytes[i] = nibble(hex[2 * i]) << 4 | nibble(hex[2 * i + 1]);
+        i += 1;
+    }
+
+    bytes
+}
@@ -0,0 +1,60 @@
+//! CRC-32 as used by zlib and Ethernet.
+//!
+//! Reflected, with the polynomial 0x04C11DB7 and an initial value and final XOR of all ones. The
+//! host computes the same with Ruby's `Zlib.crc32`.
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// The reflected polynomial.
+const POLYNOMIAL: u32 = 0xEDB8_8320;
+
+/// The CRC of every byte value.
+const TABLE: [u32; 256] = make_table();
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+const fn make_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+
+    while i < table.len() {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ POLYNOMIAL
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+
+        table[i] = crc;
+        i += 1;
+    }
+
+    table
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Continue a CRC with more data. Start with 0, i.e. `update(update(0, a), b)` is the CRC of `a`
+/// followed by `b`.
+pub fn update(crc: u32, data: &[u8]) -> u32 {
+    let crc = data.iter().fold(!crc, |crc, byte| {
+        TABLE[((crc ^ *byte as u32) & 0xFF) as usize] ^ (crc >> 8)
+    });
+
+    !crc
+}
+
+/// The CRC of `data`.
+pub fn crc32(data: &[u8]) -> u32 {
+    update(0, data)
+}
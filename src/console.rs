@@ -5,16 +5,24 @@
 //! System console.
 
 pub mod copy_console;
+#[cfg(any(feature = "chainloader", feature = "program_loader"))]
+pub mod transfer;
 
 pub use copy_console::*;
 
+#[cfg(not(feature = "chainloader"))]
+use core::future;
+
 //--------------------------------------------------------------------------------------------------
 // Public Definitions
 //--------------------------------------------------------------------------------------------------
 
 /// Console interfaces.
 pub mod interface {
-    use core::fmt;
+    use core::{
+        fmt,
+        task::{Context, Poll},
+    };
 
     /// Console write functions.
     pub trait Write {
@@ -24,6 +32,11 @@ pub mod interface {
         /// Write a Rust format string.
         fn write_fmt(&self, args: fmt::Arguments) -> fmt::Result;
 
+        /// Write a single byte as it is, without any conversion. For binary transfers.
+        ///
+        /// Consoles that only display text drop it.
+        fn write_byte(&self, _byte: u8) {}
+
         /// Block until the last buffered character has been physically put on the TX wire.
         fn flush(&self);
     }
@@ -35,6 +48,16 @@ pub mod interface {
             ' '
         }
 
+        /// Read a single byte as it was received, without any conversion. For binary transfers.
+        fn read_byte(&self) -> u8 {
+            0
+        }
+
+        /// Like `read_byte()`, but return `None` right away if nothing was received.
+        fn try_read_byte(&self) -> Option<u8> {
+            None
+        }
+
         /// Clear RX buffers, if any.
         fn clear_rx(&self);
     }
@@ -52,6 +75,46 @@ pub mod interface {
         }
     }
 
+    /// Console write functions for `async` code.
+    pub trait AsyncWrite: Write {
+        /// Write a single character if the console can take it right now. Otherwise, arrange for
+        /// the task to be woken up and return `Pending`.
+        fn poll_write_char(&self, _cx: &mut Context<'_>, c: char) -> Poll<()> {
+            self.write_char(c);
+
+            Poll::Ready(())
+        }
+    }
+
+    /// Console read functions for `async` code.
+    pub trait AsyncRead: Read {
+        /// Take a received character if there is one. Otherwise, arrange for the task to be woken
+        /// up and return `Pending`.
+        ///
+        /// Consoles without input never complete.
+        fn poll_read_char(&self, _cx: &mut Context<'_>) -> Poll<char> {
+            Poll::Pending
+        }
+    }
+
     /// Trait alias for a full-fledged console.
-    pub trait All: Write + Read + Statistics {}
+    pub trait All: Write + Read + Statistics + AsyncWrite + AsyncRead {}
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Read a single character without blocking the thread.
+#[cfg(not(feature = "chainloader"))]
+pub async fn read_char(console: &(dyn interface::AsyncRead + Sync)) -> char {
+    future::poll_fn(|cx| console.poll_read_char(cx)).await
+}
+
+/// Write a string without blocking the thread.
+#[cfg(not(feature = "chainloader"))]
+pub async fn write_str(console: &(dyn interface::AsyncWrite + Sync), s: &str) {
+    for c in s.chars() {
+        future::poll_fn(|cx| console.poll_write_char(cx, c)).await;
+    }
 }
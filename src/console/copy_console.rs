@@ -1,9 +1,12 @@
 //! Copy console.
 
-use crate::synchronization::{interface::Mutex, NullLock};
+use crate::synchronization::{interface::ReadWriteEx, RwLock};
 
 use super::interface;
-use core::fmt;
+use core::{
+    fmt,
+    task::{Context, Poll},
+};
 
 //--------------------------------------------------------------------------------------------------
 // Private Definitions
@@ -26,7 +29,7 @@ pub struct Console {
 }
 
 pub struct ConsoleManger {
-    inner: NullLock<ConsoleInner>,
+    inner: RwLock<ConsoleInner>,
 }
 
 //--------------------------------------------------------------------------------------------------
@@ -68,22 +71,28 @@ impl ConsoleManger {
     /// Create an instance.
     pub const fn new() -> Self {
         Self {
-            inner: NullLock::new(ConsoleInner::new()),
+            inner: RwLock::new(ConsoleInner::new()),
         }
     }
 
     /// Register a console
     pub fn register_console(&self, console: Console) {
-        self.inner.lock(|inner| {
+        self.inner.write(|inner| {
             inner.list[inner.next_index] = Some(console);
             inner.next_index += 1;
         })
     }
 
+    /// The console that input is taken from.
+    fn first_console(&self) -> Option<Console> {
+        // Copied out, so that the lock is not held while blocking.
+        self.inner.read(|inner| inner.list[0])
+    }
+
     /// Helper for iterating over registered consoles.
     fn for_each_console<'a>(&'a self, f: impl FnMut(&'a Console)) {
         self.inner
-            .lock(|inner| inner.list.iter().filter_map(|x| x.as_ref()).for_each(f))
+            .read(|inner| inner.list.iter().filter_map(|x| x.as_ref()).for_each(f))
     }
 }
 
@@ -99,16 +108,72 @@ impl interface::Write for ConsoleManger {
         Ok(())
     }
 
+    fn write_byte(&self, byte: u8) {
+        self.for_each_console(|console| console.console.write_byte(byte))
+    }
+
     fn flush(&self) {
         self.for_each_console(|console| console.console.flush())
     }
 }
 
 impl interface::Read for ConsoleManger {
+    /// Input is only taken from the first registered console.
+    fn read_char(&self) -> char {
+        self.first_console()
+            .map_or(' ', |console| console.console.read_char())
+    }
+
+    fn read_byte(&self) -> u8 {
+        self.first_console()
+            .map_or(0, |console| console.console.read_byte())
+    }
+
+    fn try_read_byte(&self) -> Option<u8> {
+        self.first_console()
+            .and_then(|console| console.console.try_read_byte())
+    }
+
     fn clear_rx(&self) {
         self.for_each_console(|console| console.console.clear_rx())
     }
 }
 
+/// The first console decides when a character is written. The copies to the other consoles are
+/// written right after.
+impl interface::AsyncWrite for ConsoleManger {
+    fn poll_write_char(&self, cx: &mut Context<'_>, c: char) -> Poll<()> {
+        let first = match self.first_console() {
+            Some(console) => console,
+            None => return Poll::Ready(()),
+        };
+
+        if first.console.poll_write_char(cx, c).is_pending() {
+            return Poll::Pending;
+        }
+
+        self.inner.read(|inner| {
+            inner
+                .list
+                .iter()
+                .skip(1)
+                .filter_map(|x| x.as_ref())
+                .for_each(|console| console.console.write_char(c))
+        });
+
+        Poll::Ready(())
+    }
+}
+
+/// Input is only taken from the first registered console.
+impl interface::AsyncRead for ConsoleManger {
+    fn poll_read_char(&self, cx: &mut Context<'_>) -> Poll<char> {
+        match self.first_console() {
+            Some(console) => console.console.poll_read_char(cx),
+            None => Poll::Pending,
+        }
+    }
+}
+
 impl interface::Statistics for ConsoleManger {}
 impl interface::All for ConsoleManger {}
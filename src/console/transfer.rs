@@ -0,0 +1,367 @@
+//! Framed transfers from the host, the target side of `common/serial/minipush.rb`.
+//!
+//! The target asks for a payload with three `\x03`. The host then sends the payload in frames and
+//! waits for an answer to each one before it sends the next. All numbers are little endian.
+//!
+//! | Offset     | Size   | Content                                                  |
+//! |------------|--------|----------------------------------------------------------|
+//! | 0          | 1      | SOH (0x01)                                               |
+//! | 1          | 1      | Kind: `H` for the header, `D` for data, `E` for the end  |
+//! | 2          | 4      | Block number, counting from 0 for the header             |
+//! | 6          | 2      | Length of the data, at most 1024                         |
+//! | 8          | length | Data                                                     |
+//! | 8 + length | 4      | CRC-32 of the kind, block number, length and data        |
+//!
+//! The target answers with ACK (0x06) and the number of the block that it took, or with NAK (0x15)
+//! and the number of the block that it expects next. The host continues with the block from the
+//! answer, and sends a block again if no answer arrives in time. That way, a damaged frame costs
+//! one block instead of the whole transfer. Frames with a bad CRC or an unexpected block number get
+//! a NAK, and so do frames that stop halfway. A block that arrives twice because an ACK got lost is
+//! acknowledged again.
+//!
+//! - The header holds the image size as `u32` and flags as `u32`, followed by the address to load
+//!   the image to and its entry point as `u64` each. The addresses only count if bit 0 of the flags
+//!   is set. Last comes the host's Unix time in nanoseconds as `u64`, 0 if unknown.
+//! - The data blocks follow in order.
+//! - The end block holds the SHA-256 hash of the whole image, optionally followed by an Ed25519
+//!   signature of the image. Checking the signature is up to the user of the payload.
+//!
+//! The target gives up with CAN (0x18) if the hash doesn't match, after too many errors in a row, or
+//! if the host sends something that doesn't fit the protocol at all. After a successful transfer, it
+//! sets its wall clock from the host's time.
+
+use super::interface;
+use crate::{
+    common::{crc32, sha256},
+    thread, time, warn,
+};
+use alloc::{vec, vec::Vec};
+use core::time::Duration;
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Sent to ask the host for a payload.
+const REQUEST: [u8; 3] = [0x03; 3];
+
+const SOH: u8 = 0x01;
+const ACK: u8 = 0x06;
+const NAK: u8 = 0x15;
+const CAN: u8 = 0x18;
+
+const KIND_HEADER: u8 = b'H';
+const KIND_DATA: u8 = b'D';
+const KIND_END: u8 = b'E';
+
+/// Kind, block number and length.
+const FRAME_HEADER_SIZE: usize = 7;
+
+/// The largest amount of data in a frame.
+const MAX_BLOCK_SIZE: usize = 1024;
+
+/// Size of the data in the header block.
+const HEADER_SIZE: usize = 32;
+
+/// Size of an Ed25519 signature.
+const SIGNATURE_SIZE: usize = 64;
+
+/// Header flag for a valid load address and entry point.
+const FLAG_PLACEMENT: u32 = 1 << 0;
+
+/// How long the host may take for the next frame once the transfer runs.
+const HOST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long the host may pause within a frame.
+const BYTE_TIMEOUT: Duration = Duration::from_millis(50);
+
+/// How often to look for received bytes while waiting for them.
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Errors in a row after which the transfer is given up.
+const MAX_ERRORS: usize = 16;
+
+struct Frame {
+    kind: u8,
+    block: u32,
+    data: Vec<u8>,
+}
+
+/// State of a running transfer.
+struct Transfer<'a> {
+    console: &'a (dyn interface::All + Sync),
+
+    /// The block that is expected next.
+    next_block: u32,
+
+    /// Damaged or unexpected frames since the last good one.
+    errors: usize,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Where the host wants the payload to go.
+#[derive(Copy, Clone)]
+pub struct Placement {
+    pub load_addr: usize,
+    pub entry: usize,
+}
+
+/// A payload that passed all checks.
+pub struct Payload {
+    pub data: Vec<u8>,
+
+    /// Only if the host sent it.
+    pub placement: Option<Placement>,
+
+    /// Ed25519 signature of `data`, if the host sent one. Not checked here.
+    pub signature: Option<[u8; SIGNATURE_SIZE]>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn u64_at(data: &[u8], offset: usize) -> usize {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap()) as usize
+}
+
+/// The uptime at which `timeout` is over. `None` if it never is.
+fn deadline(timeout: Duration) -> Option<Duration> {
+    time::time_manager().uptime().checked_add(timeout)
+}
+
+impl<'a> Transfer<'a> {
+    /// Wait for a byte until the `deadline`.
+    fn read_byte(&self, deadline: Option<Duration>) -> Option<u8> {
+        loop {
+            if let Some(byte) = self.console.try_read_byte() {
+                return Some(byte);
+            }
+
+            if deadline.map_or(false, |deadline| time::time_manager().uptime() >= deadline) {
+                return None;
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    /// Fill `buf`. Returns `false` if the host pauses for longer than `BYTE_TIMEOUT`.
+    fn read_exact(&self, buf: &mut [u8]) -> bool {
+        for byte in buf {
+            match self.read_byte(deadline(BYTE_TIMEOUT)) {
+                Some(received) => *byte = received,
+                None => return false,
+            }
+        }
+
+        true
+    }
+
+    /// Wait for the start of a frame for at most `timeout` and read the frame. Returns `None` if it
+    /// is damaged.
+    fn read_frame(&self, timeout: Duration) -> Result<Option<Frame>, &'static str> {
+        let deadline = deadline(timeout);
+
+        // Anything before the start of the frame is line noise.
+        loop {
+            match self.read_byte(deadline) {
+                Some(SOH) => break,
+                Some(_) => (),
+                None => return Err("No answer from the host"),
+            }
+        }
+
+        let mut header = [0; FRAME_HEADER_SIZE];
+        if !self.read_exact(&mut header) {
+            return Ok(None);
+        }
+
+        let length = u16::from_le_bytes([header[5], header[6]]) as usize;
+        if length > MAX_BLOCK_SIZE {
+            return Ok(None);
+        }
+
+        let mut data = vec![0; length];
+        let mut crc = [0; 4];
+        if !self.read_exact(&mut data) || !self.read_exact(&mut crc) {
+            return Ok(None);
+        }
+
+        if u32::from_le_bytes(crc) != crc32::update(crc32::crc32(&header), &data) {
+            return Ok(None);
+        }
+
+        Ok(Some(Frame {
+            kind: header[0],
+            block: u32_at(&header, 1),
+            data,
+        }))
+    }
+
+    fn answer(&self, code: u8, block: u32) {
+        self.console.write_byte(code);
+        for byte in block.to_le_bytes() {
+            self.console.write_byte(byte);
+        }
+        self.console.flush();
+    }
+
+    /// Ask the host to send the expected block again.
+    fn reject(&mut self) -> Result<(), &'static str> {
+        self.errors += 1;
+        if self.errors > MAX_ERRORS {
+            return Err(self.fail("Too many transmission errors"));
+        }
+
+        // Let the rest of the frame pass, so that the next one is read from its start.
+        while self.read_byte(deadline(BYTE_TIMEOUT)).is_some() {}
+
+        self.answer(NAK, self.next_block);
+        Ok(())
+    }
+
+    /// Tell the host that the transfer is over, and why.
+    fn fail(&self, reason: &'static str) -> &'static str {
+        self.console.write_byte(CAN);
+        self.console.flush();
+
+        reason
+    }
+
+    /// Receive frames until the expected block arrives. It must be of the given `kind`.
+    ///
+    /// The block is not acknowledged yet, see `accept()`.
+    fn receive_block(&mut self, kind: u8, timeout: Duration) -> Result<Vec<u8>, &'static str> {
+        loop {
+            let frame = match self.read_frame(timeout)? {
+                Some(frame) => frame,
+                None => {
+                    self.reject()?;
+                    continue;
+                }
+            };
+
+            if frame.block < self.next_block {
+                self.answer(ACK, frame.block);
+                continue;
+            }
+
+            if frame.block > self.next_block {
+                self.reject()?;
+                continue;
+            }
+
+            if frame.kind != kind {
+                return Err(self.fail("Unexpected frame"));
+            }
+
+            self.errors = 0;
+            return Ok(frame.data);
+        }
+    }
+
+    /// Acknowledge the expected block and move on to the next one.
+    fn accept(&mut self) {
+        self.answer(ACK, self.next_block);
+        self.next_block += 1;
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Ask the host for a payload and receive it.
+///
+/// Gives up if the host doesn't start within `timeout`, if it stops sending halfway, or if the size
+/// is 0 or larger than `max_size`.
+///
+/// Must be called from a thread. Nothing else may read from the console in the meantime.
+pub fn receive(
+    console: &(dyn interface::All + Sync),
+    timeout: Duration,
+    max_size: usize,
+) -> Result<Payload, &'static str> {
+    console.clear_rx();
+    for byte in REQUEST {
+        console.write_byte(byte);
+    }
+    console.flush();
+
+    let mut transfer = Transfer {
+        console,
+        next_block: 0,
+        errors: 0,
+    };
+
+    let header = transfer.receive_block(KIND_HEADER, timeout)?;
+    if header.len() != HEADER_SIZE {
+        return Err(transfer.fail("Malformed header"));
+    }
+
+    let size = u32_at(&header, 0) as usize;
+    if size == 0 || size > max_size {
+        return Err(transfer.fail("Payload size out of range"));
+    }
+
+    let placement = (u32_at(&header, 4) & FLAG_PLACEMENT != 0).then(|| Placement {
+        load_addr: u64_at(&header, 8),
+        entry: u64_at(&header, 16),
+    });
+    let host_time = match u64_at(&header, 24) {
+        0 => None,
+        nanos => Some((
+            Duration::from_nanos(nanos as u64),
+            time::time_manager().uptime(),
+        )),
+    };
+    transfer.accept();
+
+    let mut data = Vec::with_capacity(size);
+    while data.len() < size {
+        let block = transfer.receive_block(KIND_DATA, HOST_TIMEOUT)?;
+        if block.is_empty() || data.len() + block.len() > size {
+            return Err(transfer.fail("Data block does not fit the payload size"));
+        }
+
+        data.extend_from_slice(&block);
+        transfer.accept();
+    }
+
+    let end = transfer.receive_block(KIND_END, HOST_TIMEOUT)?;
+    let (hash, signature) = match end.len() {
+        sha256::HASH_SIZE => (&end[..], None),
+        n if n == sha256::HASH_SIZE + SIGNATURE_SIZE => {
+            let (hash, signature) = end.split_at(sha256::HASH_SIZE);
+            (hash, Some(signature.try_into().unwrap()))
+        }
+        _ => return Err(transfer.fail("Malformed end block")),
+    };
+
+    if hash != sha256::sha256(&data) {
+        return Err(transfer.fail("Payload hash mismatch"));
+    }
+    transfer.accept();
+
+    // Not before the host is done, since setting the clock prints.
+    if let Some((unix_time, received_at)) = host_time {
+        let unix_time = unix_time + time::time_manager().uptime().saturating_sub(received_at);
+
+        if let Err(x) = time::wall_clock().set(unix_time, time::TimeSource::Host) {
+            warn!("Host time: {}", x);
+        }
+    }
+
+    Ok(Payload {
+        data,
+        placement,
+        signature,
+    })
+}
@@ -7,3 +7,11 @@
 #[cfg(target_arch = "aarch64")]
 #[path = "../_arch/aarch64/cpu/boot.rs"]
 mod arch_boot;
+
+//--------------------------------------------------------------------------------------------------
+// Architectural Public Reexports
+//--------------------------------------------------------------------------------------------------
+pub use arch_boot::handover_time;
+
+#[cfg(feature = "chainloader")]
+pub use arch_boot::HANDOVER_TIME_MAGIC;
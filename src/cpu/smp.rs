@@ -0,0 +1,306 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2018-2022 Andre Richter <andre.o.richter@gmail.com>
+
+//! Symmetric multiprocessing.
+
+#[cfg(target_arch = "aarch64")]
+#[path = "../_arch/aarch64/cpu/smp.rs"]
+mod arch_smp;
+
+use crate::{
+    bsp, cpu,
+    exception::{self, asynchronous::IRQContext},
+    synchronization::{interface::Mutex, IRQSafeLock},
+};
+use core::{
+    hint, mem,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+// Starting the secondary cores is left to the kernel that the chainloader loads.
+#[cfg(not(feature = "chainloader"))]
+use crate::{
+    memory::{
+        self,
+        frame::{frame_allocator, FRAME_SIZE},
+    },
+    time, warn,
+};
+#[cfg(not(feature = "chainloader"))]
+use core::time::Duration;
+
+//--------------------------------------------------------------------------------------------------
+// Architectural Public Reexports
+//--------------------------------------------------------------------------------------------------
+pub use arch_smp::core_id;
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Secondary cores get 2^4 frames, aka 64 KiB, of stack.
+#[cfg(not(feature = "chainloader"))]
+const SECONDARY_CORE_STACK_ORDER: usize = 4;
+
+/// Time a secondary core gets to come online after it was released.
+#[cfg(not(feature = "chainloader"))]
+const CORE_START_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// A function that another core asked the executing core to call.
+///
+/// The lifetimes are erased. The requesting core waits until `done` is set, so the references stay
+/// valid for as long as the request is pending.
+#[derive(Copy, Clone)]
+struct CallRequest {
+    func: &'static (dyn Fn() + Sync),
+    done: &'static AtomicBool,
+}
+
+/// Pending requests of a core, indexed by the requesting core.
+///
+/// A requester waits for completion before it sends the next request, so a single slot per
+/// requester suffices.
+type CallQueue = [Option<CallRequest>; NUM_CORES];
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Number of cores.
+pub const NUM_CORES: usize = bsp::cpu::NUM_CORES;
+
+/// A variable with one instance per core.
+///
+/// Any core may look at the other cores' instances, so the type must be `Sync`, e.g. an atomic or
+/// a lock.
+pub struct PerCore<T> {
+    data: [T; NUM_CORES],
+}
+
+//--------------------------------------------------------------------------------------------------
+// Global instances
+//--------------------------------------------------------------------------------------------------
+
+#[allow(clippy::declare_interior_mutable_const)]
+const OFFLINE: AtomicBool = AtomicBool::new(false);
+
+static CORE_ONLINE: PerCore<AtomicBool> = PerCore::new([OFFLINE; NUM_CORES]);
+
+#[allow(clippy::declare_interior_mutable_const)]
+const EMPTY_CALL_QUEUE: IRQSafeLock<CallQueue> = IRQSafeLock::new([None; NUM_CORES]);
+
+static CALL_QUEUES: PerCore<IRQSafeLock<CallQueue>> = PerCore::new([EMPTY_CALL_QUEUE; NUM_CORES]);
+
+/// Set when a core panicked and the others shall stop.
+static STOP_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(not(feature = "chainloader"))]
+fn boot_core_id() -> usize {
+    bsp::cpu::BOOT_CORE_ID as usize
+}
+
+/// Release a secondary core and wait for it to finish its early init.
+#[cfg(not(feature = "chainloader"))]
+fn start_core(core_id: usize) -> Result<(), &'static str> {
+    let stack_size = FRAME_SIZE << SECONDARY_CORE_STACK_ORDER;
+    let stack_start = frame_allocator().alloc_contiguous(SECONDARY_CORE_STACK_ORDER)?;
+
+    // The core starts with caching off. Make sure that no stale lines of the stack are left that
+    // could be written back on top of it later.
+    memory::cache::clean_and_invalidate_dcache_range(stack_start, stack_size);
+
+    unsafe {
+        arch_smp::start_core(
+            core_id,
+            bsp::cpu::SPIN_TABLE_RELEASE_ADDR[core_id],
+            stack_start + stack_size,
+        );
+    }
+
+    let deadline = time::time_manager().uptime() + CORE_START_TIMEOUT;
+    while !is_core_online(core_id) {
+        if time::time_manager().uptime() > deadline {
+            // The stack is not freed. The core might still wake up late and use it.
+            return Err("Did not come online");
+        }
+
+        hint::spin_loop();
+    }
+
+    Ok(())
+}
+
+/// Park the executing core for good, on request of a panicking core.
+fn stop_local_core() -> ! {
+    exception::asynchronous::local_irq_mask();
+    CORE_ONLINE.local().store(false, Ordering::Release);
+
+    cpu::wait_forever()
+}
+
+/// Make the calls that other cores requested from the executing core.
+fn handle_pending_calls() {
+    if STOP_REQUESTED.load(Ordering::Acquire) {
+        stop_local_core();
+    }
+
+    // Take the requests out first, so that the functions run without the lock held.
+    let requests = CALL_QUEUES
+        .local()
+        .lock(|queue| mem::replace(queue, [None; NUM_CORES]));
+
+    for request in requests.iter().flatten() {
+        (request.func)();
+        request.done.store(true, Ordering::Release);
+    }
+}
+
+/// Queue `func` on another core and raise an IPI there.
+///
+/// # Safety
+///
+/// - `func` and `done` must stay valid until `done` is set.
+unsafe fn post_call(core_id: usize, func: &(dyn Fn() + Sync), done: &AtomicBool) {
+    let request = CallRequest {
+        func: mem::transmute::<&(dyn Fn() + Sync), &'static (dyn Fn() + Sync)>(func),
+        done: mem::transmute::<&AtomicBool, &'static AtomicBool>(done),
+    };
+
+    CALL_QUEUES
+        .get(core_id)
+        .lock(|queue| queue[self::core_id::<usize>()] = Some(request));
+
+    exception::asynchronous::irq_manager().send_ipi(core_id);
+}
+
+/// Wait until a posted call is done.
+///
+/// Meanwhile, calls that other cores requested from the executing core are served. Otherwise, two
+/// cores that wait for each other with IRQs masked would deadlock.
+fn wait_for_call(done: &AtomicBool) {
+    while !done.load(Ordering::Acquire) {
+        handle_pending_calls();
+        hint::spin_loop();
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl<T> PerCore<T> {
+    /// Create an instance.
+    pub const fn new(data: [T; NUM_CORES]) -> Self {
+        Self { data }
+    }
+
+    /// The executing core's instance.
+    pub fn local(&self) -> &T {
+        &self.data[core_id::<usize>()]
+    }
+
+    /// The instance of the given core.
+    pub fn get(&self, core_id: usize) -> &T {
+        &self.data[core_id]
+    }
+}
+
+/// Mark the executing core as online. Called by every core once its early init is done.
+pub fn set_core_online() {
+    CORE_ONLINE.local().store(true, Ordering::Release);
+}
+
+/// Return true if the core finished its early init.
+pub fn is_core_online(core_id: usize) -> bool {
+    CORE_ONLINE.get(core_id).load(Ordering::Acquire)
+}
+
+/// Return the number of cores that are online.
+#[cfg(not(feature = "chainloader"))]
+pub fn num_cores_online() -> usize {
+    (0..NUM_CORES).filter(|&core| is_core_online(core)).count()
+}
+
+/// Wake up all secondary cores, one after the other.
+///
+/// Cores that fail to come online are reported and left alone.
+#[cfg(not(feature = "chainloader"))]
+pub fn start_secondary_cores() {
+    for core_id in (0..NUM_CORES).filter(|&core| core != boot_core_id()) {
+        if let Err(x) = start_core(core_id) {
+            warn!("Core {}: {}", core_id, x);
+        }
+    }
+}
+
+/// Call `func` on the given core and wait until it returned.
+///
+/// On another core, `func` runs in IRQ context.
+///
+/// # Note
+///
+/// The target core must be able to take IPIs. Do not hold a lock that the target core might be
+/// spinning on with IRQs masked.
+pub fn call_on(core_id: usize, func: &(dyn Fn() + Sync)) -> Result<(), &'static str> {
+    if core_id >= NUM_CORES || !is_core_online(core_id) {
+        return Err("Core is not online");
+    }
+
+    if core_id == self::core_id::<usize>() {
+        func();
+
+        return Ok(());
+    }
+
+    let done = AtomicBool::new(false);
+    unsafe { post_call(core_id, func, &done) };
+    wait_for_call(&done);
+
+    Ok(())
+}
+
+/// Call `func` on all online cores, including the executing one, and wait until all of them
+/// returned.
+///
+/// See [`call_on()`] for the restrictions.
+pub fn call_on_all(func: &(dyn Fn() + Sync)) {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const NOT_DONE: AtomicBool = AtomicBool::new(false);
+    let done = [NOT_DONE; NUM_CORES];
+
+    let local_core_id = core_id::<usize>();
+    let mut posted = [false; NUM_CORES];
+
+    for core in (0..NUM_CORES).filter(|&core| core != local_core_id && is_core_online(core)) {
+        unsafe { post_call(core, func, &done[core]) };
+        posted[core] = true;
+    }
+
+    func();
+
+    for core in (0..NUM_CORES).filter(|&core| posted[core]) {
+        wait_for_call(&done[core]);
+    }
+}
+
+/// Handle an IPI. Called by the interrupt controller driver.
+pub fn handle_ipi(_ic: &IRQContext) {
+    handle_pending_calls();
+}
+
+/// Stop all other cores. Used when the executing core panics.
+///
+/// Does not wait for the other cores, so that it works even if they are stuck with IRQs masked.
+pub fn stop_other_cores() {
+    STOP_REQUESTED.store(true, Ordering::Release);
+
+    let local_core_id = core_id::<usize>();
+    for core in (0..NUM_CORES).filter(|&core| core != local_core_id && is_core_online(core)) {
+        exception::asynchronous::irq_manager().send_ipi(core);
+    }
+}
@@ -5,19 +5,23 @@
 //! Driver support.
 
 use crate::{
-    info,
-    synchronization::{interface::Mutex, NullLock},
+    exception,
+    synchronization::{interface::ReadWriteEx, InitStateLock},
 };
+use core::fmt;
 
 //--------------------------------------------------------------------------------------------------
 // Private Definitions
 //--------------------------------------------------------------------------------------------------
 
-const NUM_DRIVERS: usize = 5;
+const NUM_DRIVERS: usize = 9;
 
-struct DriverManagerInner {
+struct DriverManagerInner<T>
+where
+    T: 'static,
+{
     next_index: usize,
-    descriptors: [Option<DeviceDriverDescriptor>; NUM_DRIVERS],
+    descriptors: [Option<DeviceDriverDescriptor<T>>; NUM_DRIVERS],
 }
 
 //--------------------------------------------------------------------------------------------------
@@ -28,6 +32,9 @@ struct DriverManagerInner {
 pub mod interface {
     /// Device Driver functions.
     pub trait DeviceDriver {
+        /// Different interrupt controllers might use different types for IRQ number.
+        type IRQNumberType: super::fmt::Display;
+
         /// Return a compatibility string for identifying the driver.
         fn compatible(&self) -> &'static str;
 
@@ -39,6 +46,21 @@ pub mod interface {
         unsafe fn init(&self) -> Result<(), &'static str> {
             Ok(())
         }
+
+        /// Called by the kernel to register and enable the device's IRQ handler.
+        ///
+        /// Rust's type system will prevent a call to this function unless the calling instance
+        /// itself has static lifetime.
+        fn register_and_enable_irq_handler(
+            &'static self,
+            irq_number: &Self::IRQNumberType,
+        ) -> Result<(), &'static str> {
+            panic!(
+                "Attempt to enable IRQ {} for device {}, but driver does not support this",
+                irq_number,
+                self.compatible()
+            )
+        }
     }
 }
 
@@ -47,27 +69,37 @@ pub type DeviceDriverPostInitCallback = unsafe fn() -> Result<(), &'static str>;
 
 /// A descriptor for device drivers.
 #[derive(Copy, Clone)]
-pub struct DeviceDriverDescriptor {
-    device_driver: &'static (dyn interface::DeviceDriver + Sync),
+pub struct DeviceDriverDescriptor<T>
+where
+    T: 'static,
+{
+    device_driver: &'static (dyn interface::DeviceDriver<IRQNumberType = T> + Sync),
     post_init_callback: Option<DeviceDriverPostInitCallback>,
+    irq_numbers: &'static [T],
 }
 
 /// Provides device driver management functions.
-pub struct DriverManager {
-    inner: NullLock<DriverManagerInner>,
+pub struct DriverManager<T>
+where
+    T: 'static,
+{
+    inner: InitStateLock<DriverManagerInner<T>>,
 }
 
 //--------------------------------------------------------------------------------------------------
 // Global instances
 //--------------------------------------------------------------------------------------------------
 
-static DRIVER_MANAGER: DriverManager = DriverManager::new();
+static DRIVER_MANAGER: DriverManager<exception::asynchronous::IRQNumber> = DriverManager::new();
 
 //--------------------------------------------------------------------------------------------------
 // Private Code
 //--------------------------------------------------------------------------------------------------
 
-impl DriverManagerInner {
+impl<T> DriverManagerInner<T>
+where
+    T: 'static + Copy,
+{
     /// Create an instance.
     pub const fn new() -> Self {
         Self {
@@ -81,43 +113,63 @@ impl DriverManagerInner {
 // Public Code
 //--------------------------------------------------------------------------------------------------
 
-impl DeviceDriverDescriptor {
+impl<T> DeviceDriverDescriptor<T> {
     /// Create an instance.
+    ///
+    /// A driver that drives several interrupt lines, e.g. one per bank, lists all of them in
+    /// `irq_numbers`. Pass an empty slice for drivers without interrupt support.
     pub fn new(
-        device_driver: &'static (dyn interface::DeviceDriver + Sync),
+        device_driver: &'static (dyn interface::DeviceDriver<IRQNumberType = T> + Sync),
         post_init_callback: Option<DeviceDriverPostInitCallback>,
+        irq_numbers: &'static [T],
     ) -> Self {
         Self {
             device_driver,
             post_init_callback,
+            irq_numbers,
         }
     }
 }
 
 /// Return a reference to the global DriverManager.
-pub fn driver_manager() -> &'static DriverManager {
+pub fn driver_manager() -> &'static DriverManager<exception::asynchronous::IRQNumber> {
     &DRIVER_MANAGER
 }
 
-impl DriverManager {
+impl<T> DriverManager<T>
+where
+    T: fmt::Display + Copy,
+{
     /// Create an instance.
     pub const fn new() -> Self {
         Self {
-            inner: NullLock::new(DriverManagerInner::new()),
+            inner: InitStateLock::new(DriverManagerInner::new()),
         }
     }
 
     /// Register a device driver with the kernel.
-    pub fn register_driver(&self, descriptor: DeviceDriverDescriptor) {
-        self.inner.lock(|inner| {
-            inner.descriptors[inner.next_index] = Some(descriptor);
+    ///
+    /// Fails if all `NUM_DRIVERS` slots are taken.
+    pub fn register_driver(
+        &self,
+        descriptor: DeviceDriverDescriptor<T>,
+    ) -> Result<(), &'static str> {
+        self.inner.write(|inner| {
+            let slot = inner
+                .descriptors
+                .get_mut(inner.next_index)
+                .ok_or("Driver table full")?;
+
+            *slot = Some(descriptor);
             inner.next_index += 1;
+
+            Ok(())
         })
     }
 
     /// Helper for iterating over registered drivers.
-    fn for_each_descriptor<'a>(&'a self, f: impl FnMut(&'a DeviceDriverDescriptor)) {
-        self.inner.lock(|inner| {
+    fn for_each_descriptor<'a>(&'a self, f: impl FnMut(&'a DeviceDriverDescriptor<T>)) {
+        self.inner.read(|inner| {
             inner
                 .descriptors
                 .iter()
@@ -126,12 +178,12 @@ impl DriverManager {
         })
     }
 
-    /// Fully initialize all drivers.
+    /// Fully initialize all drivers and their interrupts handlers.
     ///
     /// # Safety
     ///
     /// - During init, drivers might do stuff with system-wide impact.
-    pub unsafe fn init_drivers(&self) {
+    pub unsafe fn init_drivers_and_irqs(&self) {
         self.for_each_descriptor(|descriptor| {
             // 1. Initialize driver.
             if let Err(x) = descriptor.device_driver.init() {
@@ -153,10 +205,30 @@ impl DriverManager {
                 }
             }
         });
+
+        // 3. After all post-init callbacks were done, the interrupt controller should be
+        //    registered and functional. So let drivers register with it now.
+        self.for_each_descriptor(|descriptor| {
+            for irq_number in descriptor.irq_numbers {
+                if let Err(x) = descriptor
+                    .device_driver
+                    .register_and_enable_irq_handler(irq_number)
+                {
+                    panic!(
+                        "Error during driver interrupt handler registration: {}: {}",
+                        descriptor.device_driver.compatible(),
+                        x
+                    );
+                }
+            }
+        });
     }
 
     /// Enumerate all registered device drivers.
+    #[cfg(not(feature = "chainloader"))]
     pub fn enumerate(&self) {
+        use crate::info;
+
         let mut i: usize = 1;
         self.for_each_descriptor(|descriptor| {
             info!("      {}. {}", i, descriptor.device_driver.compatible());
@@ -0,0 +1,223 @@
+//! A small executor for `async` code.
+//!
+//! Tasks are polled by a dedicated kernel thread, `executor`, which sleeps while no task is ready.
+//! Waking a task queues it and wakes up the executor thread. Wakers can be called from any core and
+//! from IRQ context, so an IRQ handler only needs to keep the waker of the task that waits for it,
+//! see [`WakerSlot`].
+//!
+//! Tasks are polled one after the other. A task that never returns from `poll()` stalls all others,
+//! so long-running work belongs into a thread. Threads can wait for a future with `block_on()`.
+//!
+//! The chainloader does not run the executor thread and only takes `WakerSlot` for its drivers.
+
+use crate::synchronization::{interface::Mutex, IRQSafeLock};
+use core::task::Waker;
+
+#[cfg(not(feature = "chainloader"))]
+use crate::{
+    synchronization::WaitQueue,
+    thread::{self, ThreadId},
+};
+#[cfg(not(feature = "chainloader"))]
+use alloc::{boxed::Box, collections::BTreeMap, sync::Arc, task::Wake, vec::Vec};
+#[cfg(not(feature = "chainloader"))]
+use core::{
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicUsize, Ordering},
+    task::{Context, Poll},
+};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(not(feature = "chainloader"))]
+struct Task {
+    future: Pin<Box<dyn Future<Output = ()> + Send>>,
+    waker: Waker,
+}
+
+#[cfg(not(feature = "chainloader"))]
+struct TaskWaker {
+    id: TaskId,
+}
+
+/// Wakes up a thread that waits in `block_on()`.
+#[cfg(not(feature = "chainloader"))]
+struct ThreadWaker {
+    thread: ThreadId,
+}
+
+#[cfg(not(feature = "chainloader"))]
+struct Executor {
+    /// Tasks that are not being polled right now.
+    tasks: IRQSafeLock<BTreeMap<TaskId, Task>>,
+
+    /// Woken tasks, in wake order. May contain duplicates and tasks that have completed already.
+    ready: IRQSafeLock<Vec<TaskId>>,
+
+    /// The executor thread, while nothing is ready.
+    idle: WaitQueue,
+
+    next_task_id: AtomicUsize,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Identifies a task.
+#[cfg(not(feature = "chainloader"))]
+#[derive(Copy, Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub struct TaskId(usize);
+
+/// Keeps the waker of a task that waits for an event, e.g. an IRQ.
+///
+/// The waiting future registers its waker on every poll. The event source calls `wake()`.
+pub struct WakerSlot {
+    waker: IRQSafeLock<Option<Waker>>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Global instances
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(not(feature = "chainloader"))]
+static EXECUTOR: Executor = Executor::new();
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(not(feature = "chainloader"))]
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref()
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        EXECUTOR.ready.lock(|ready| ready.push(self.id));
+        EXECUTOR.idle.notify_one();
+    }
+}
+
+#[cfg(not(feature = "chainloader"))]
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        thread::unpark(self.thread)
+    }
+}
+
+#[cfg(not(feature = "chainloader"))]
+impl Executor {
+    const fn new() -> Self {
+        Self {
+            tasks: IRQSafeLock::new(BTreeMap::new()),
+            ready: IRQSafeLock::new(Vec::new()),
+            idle: WaitQueue::new(),
+            next_task_id: AtomicUsize::new(0),
+        }
+    }
+
+    fn poll_task(&self, id: TaskId) {
+        // Completed tasks are gone from the map.
+        let mut task = match self.tasks.lock(|tasks| tasks.remove(&id)) {
+            Some(task) => task,
+            None => return,
+        };
+
+        // Polled without any lock held, so the task can spawn others or wake itself.
+        let mut cx = Context::from_waker(&task.waker);
+        if task.future.as_mut().poll(&mut cx).is_pending() {
+            self.tasks.lock(|tasks| tasks.insert(id, task));
+        }
+    }
+
+    fn run(&self) -> ! {
+        loop {
+            let mut ready = Vec::new();
+            self.idle.wait_until(|| {
+                ready = self.ready.lock(core::mem::take);
+                !ready.is_empty()
+            });
+
+            for id in ready {
+                self.poll_task(id);
+            }
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Start the executor thread.
+///
+/// # Safety
+///
+/// - Must be called once, after the scheduler is started.
+#[cfg(not(feature = "chainloader"))]
+pub unsafe fn start() {
+    thread::spawn("executor", || EXECUTOR.run());
+}
+
+/// Run `future` as a new task. It is polled for the first time once the executor gets to it.
+#[cfg(not(feature = "chainloader"))]
+pub fn spawn(future: impl Future<Output = ()> + Send + 'static) -> TaskId {
+    let id = TaskId(EXECUTOR.next_task_id.fetch_add(1, Ordering::Relaxed));
+    let waker = Arc::new(TaskWaker { id });
+
+    let task = Task {
+        future: Box::pin(future),
+        waker: Waker::from(waker.clone()),
+    };
+    EXECUTOR.tasks.lock(|tasks| tasks.insert(id, task));
+
+    waker.wake();
+    id
+}
+
+/// Run `future` to completion on the calling thread instead of the executor. The thread parks while
+/// the future is pending.
+#[cfg(not(feature = "chainloader"))]
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let waker = Waker::from(Arc::new(ThreadWaker {
+        thread: thread::current_id(),
+    }));
+    let mut cx = Context::from_waker(&waker);
+    let mut future = Box::pin(future);
+
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+
+        thread::park();
+    }
+}
+
+impl WakerSlot {
+    /// Create an instance.
+    pub const fn new() -> Self {
+        Self {
+            waker: IRQSafeLock::new(None),
+        }
+    }
+
+    /// Register the waker to call on the next `wake()`. Replaces the previous one.
+    pub fn register(&self, waker: &Waker) {
+        self.waker.lock(|slot| match slot {
+            Some(w) if w.will_wake(waker) => (),
+            _ => *slot = Some(waker.clone()),
+        })
+    }
+
+    /// Wake the registered task, if any. Can be called from any core and from IRQ context.
+    pub fn wake(&self) {
+        if let Some(waker) = self.waker.lock(Option::take) {
+            waker.wake();
+        }
+    }
+}
@@ -105,12 +105,21 @@
 //! 1. The kernel's entry point is the function `cpu::boot::arch_boot::_start()`.
 //!     - It is implemented in `src/_arch/__arch_name__/cpu/boot.s`.
 //! 2. Once finished with architectural setup, the arch code calls `kernel_init()`.
+//! 3. At the end of `kernel_init()`, the boot core wakes the secondary cores. They enter at
+//!    `cpu::boot::arch_boot::_start_secondary()` and end up in `kernel_init_secondary()`.
+//! 4. `kernel_main()` runs as the `main` thread on the boot core. Further threads are started with
+//!    `thread::spawn()`.
+//!
+//! With the `chainloader` feature, `_start()` first relocates the binary, the secondary cores stay
+//! parked and `kernel_main()` hands over to `chainloader::chainloader_main()`. Only the UART, GPIO,
+//! interrupt controller, timer and mailbox drivers are brought up, and no threads are started.
 
 #![allow(clippy::upper_case_acronyms)]
 #![feature(alloc_error_handler)]
 #![feature(int_roundings)]
 #![feature(asm_const)]
 #![feature(const_option)]
+#![feature(core_intrinsics)]
 #![feature(format_args_nl)]
 #![feature(nonzero_min_max)]
 #![feature(panic_info_message)]
@@ -119,20 +128,34 @@
 #![no_main]
 #![no_std]
 
-use crate::{bsp::memory::virt_heap_start, memory::heap_alloc::kernel_heap_allocator};
+#[cfg(not(feature = "chainloader"))]
+use crate::memory::heap_alloc::kernel_heap_allocator;
 
 extern crate alloc;
 
+#[cfg(not(feature = "chainloader"))]
+mod block;
 mod bsp;
+#[cfg(feature = "chainloader")]
+mod chainloader;
 mod common;
 mod console;
 mod cpu;
 mod driver;
+mod exception;
+mod executor;
+#[cfg(not(feature = "chainloader"))]
 mod gpu;
 mod memory;
 mod panic_wait;
 mod print;
+#[cfg(not(feature = "chainloader"))]
+mod process;
+#[cfg(not(feature = "chainloader"))]
+mod shell;
+mod state;
 mod synchronization;
+mod thread;
 mod time;
 
 /// Early init code.
@@ -142,8 +165,18 @@ mod time;
 /// - Only a single core must be active and running this function.
 /// - The init calls in this function must appear in the correct order.
 unsafe fn kernel_init() -> ! {
+    use memory::mmu::interface::MMU;
+
+    exception::handling_init();
+
+    if let Err(string) = memory::mmu::mmu().enable_mmu_and_caching() {
+        panic!("MMU: {}", string);
+    }
+
     // init heap first to enable drivers to use the heap
-    memory::init();
+    if let Err(x) = memory::init() {
+        panic!("Error initializing memory: {}", x);
+    }
 
     // Initialize the BSP driver subsystem.
     if let Err(x) = bsp::driver::init() {
@@ -151,14 +184,246 @@ unsafe fn kernel_init() -> ! {
     }
 
     // Initialize all device drivers.
-    driver::driver_manager().init_drivers();
+    driver::driver_manager().init_drivers_and_irqs();
     // println! is usable from here on.
 
+    // Unmask interrupts on the boot CPU core.
+    exception::asynchronous::local_irq_unmask();
+
+    // A chainloader passes on the time it got from the host.
+    if let Some(offset) = cpu::boot::handover_time() {
+        let unix_time = offset + time::time_manager().uptime();
+
+        if let Err(x) = time::wall_clock().set(unix_time, time::TimeSource::Host) {
+            warn!("Wall clock: {}", x);
+        }
+    }
+
+    // The chainloader only needs the console and leaves everything else to the kernel it loads.
+    #[cfg(not(feature = "chainloader"))]
+    {
+        // No RTC on the board. Start with the build timestamp until a better source sets the time.
+        if let Err(x) = time::wall_clock().init_from_build_timestamp() {
+            warn!("Wall clock: {}", x);
+        }
+
+        // From here on, kernel_init() continues as the main thread.
+        thread::start_scheduler();
+        executor::start();
+    }
+
+    // Announce conclusion of the kernel_init() phase.
+    state::state_manager().transition_to_single_core_main();
+
+    cpu::smp::set_core_online();
+
+    #[cfg(not(feature = "chainloader"))]
+    {
+        cpu::smp::start_secondary_cores();
+        state::state_manager().transition_to_multi_core_main();
+    }
+
     // Transition from unsafe to safe.
     kernel_main()
 }
 
+/// Early init code of the secondary cores.
+///
+/// # Safety
+///
+/// - Must only be entered from the secondary cores' boot code.
+/// - The boot core must have finished `kernel_init()` up to starting the secondary cores.
+unsafe fn kernel_init_secondary() -> ! {
+    use memory::mmu::interface::MMU;
+
+    exception::handling_init();
+
+    if let Err(string) = memory::mmu::mmu().enable_mmu_and_caching_secondary() {
+        panic!("MMU: {}", string);
+    }
+
+    // Take IPIs from here on.
+    exception::asynchronous::irq_manager().init_secondary_core();
+    exception::asynchronous::local_irq_unmask();
+
+    cpu::smp::set_core_online();
+
+    kernel_main_secondary()
+}
+
+#[cfg(feature = "demos")]
+fn test_frame_allocator() {
+    use memory::frame::frame_allocator;
+
+    let result = frame_allocator().alloc().and_then(|frame| {
+        let run = frame_allocator().alloc_contiguous(4)?;
+        info!("Allocated frame {:#x} and run {:#x}", frame, run);
+
+        frame_allocator().free_contiguous(run, 4)?;
+        frame_allocator().free(frame)
+    });
+
+    if let Err(x) = result {
+        warn!("Frame allocator: {}", x);
+    }
+}
+
+#[cfg(feature = "demos")]
+fn test_threads() {
+    use core::time::Duration;
+    use synchronization::{channel, interface::Mutex, SleepLock};
+
+    static ROUNDS_DONE: SleepLock<usize> = SleepLock::new(0);
+
+    let (sender, receiver) = channel::channel(4);
+
+    for (name, period) in [("worker-a", 300), ("worker-b", 500)] {
+        let sender = sender.clone();
+
+        thread::spawn(name, move || {
+            for round in 1..=3 {
+                thread::sleep(Duration::from_millis(period));
+                ROUNDS_DONE.lock(|rounds| *rounds += 1);
+
+                if sender.send((name, round)).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    // IRQ handlers can only use `try_send()`.
+    let timer_sender = sender.clone();
+    time::time_manager().set_timeout(Duration::from_millis(100), move || {
+        let _ = timer_sender.try_send(("timer", 1));
+    });
+
+    // Only the workers and the timeout keep the channel open, so the collector ends once they are
+    // done.
+    drop(sender);
+
+    thread::spawn("collector", move || {
+        while let Some((name, round)) = receiver.recv() {
+            info!("{}: Round {} done", name, round);
+        }
+
+        info!(
+            "Workers done after {} rounds",
+            ROUNDS_DONE.lock(|rounds| *rounds)
+        );
+    });
+}
+
+/// Ask the host for a user program with `console::transfer` and run it. Start the shell afterwards.
+#[cfg(feature = "program_loader")]
+fn spawn_program_loader() {
+    use core::time::Duration;
+
+    const MAX_PROGRAM_SIZE: usize = 1024 * 1024;
+
+    thread::spawn("loader", || {
+        let result = console::transfer::receive(
+            console::console_manger(),
+            Duration::from_secs(1),
+            MAX_PROGRAM_SIZE,
+        )
+        .and_then(|payload| {
+            // ELF files bring their own addresses.
+            if payload.placement.is_some() {
+                warn!("Ignoring the load address of the pushed program");
+            }
+
+            process::spawn_elf("pushed", &payload.data, &["pushed"], &[])
+        })
+        .and_then(process::wait);
+
+        match result {
+            Ok(status) => info!("Pushed program {}", status),
+            Err(x) => info!("No pushed program: {}", x),
+        }
+
+        thread::spawn("shell", || shell::run());
+    });
+}
+
+#[cfg(feature = "demos")]
+fn test_executor() {
+    use core::time::Duration;
+
+    executor::spawn(async {
+        match bsp::driver::MAILBOX.board_revision().await {
+            Ok(revision) => info!("Board revision (async): {:#x}", revision),
+            Err(x) => warn!("Mailbox: {}", x),
+        }
+    });
+
+    executor::spawn(async {
+        time::delay(Duration::from_millis(200)).await;
+        console::write_str(
+            console::console_manger(),
+            "Async task woke up after 200 ms\n",
+        )
+        .await;
+    });
+}
+
+/// Run the demo programs and the embedded ELF programs in EL0. A thread collects their exit status.
+#[cfg(feature = "demos")]
+fn test_processes() {
+    use alloc::vec::Vec;
+
+    let mut pids = Vec::new();
+    for (name, image) in process::demo_programs() {
+        match process::spawn(name, image) {
+            Ok(pid) => pids.push((pid, name)),
+            Err(x) => warn!("Process {}: {}", name, x),
+        }
+    }
+
+    for (name, elf) in process::embedded_programs() {
+        match process::spawn_elf(name, elf, &[name, "--verbose"], &["BOARD=raspberrypi"]) {
+            Ok(pid) => pids.push((pid, name)),
+            Err(x) => warn!("Process {}: {}", name, x),
+        }
+    }
+
+    thread::spawn("reaper", move || {
+        for (pid, name) in pids {
+            match process::wait(pid) {
+                Ok(status) => info!("Process {} ({}) {}", pid, name, status),
+                Err(x) => warn!("Process {} ({}): {}", pid, name, x),
+            }
+        }
+    });
+}
+
+#[cfg(feature = "demos")]
+fn test_cross_core_calls() {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    let count = AtomicUsize::new(0);
+    cpu::smp::call_on_all(&|| {
+        count.fetch_add(1, Ordering::Relaxed);
+    });
+
+    info!(
+        "Cross-core call reached {} of {} cores",
+        count.load(Ordering::Relaxed),
+        cpu::smp::num_cores_online()
+    );
+
+    let last_core = cpu::smp::NUM_CORES - 1;
+    let result = cpu::smp::call_on(last_core, &|| {
+        info!("Pinned call runs on core {}", cpu::smp::core_id::<usize>())
+    });
+
+    if let Err(x) = result {
+        warn!("Cross-core call to core {}: {}", last_core, x);
+    }
+}
+
 /// The main function running after the early init.
+#[cfg(not(feature = "chainloader"))]
 fn kernel_main() -> ! {
     use core::time::Duration;
 
@@ -169,25 +434,92 @@ fn kernel_main() -> ! {
     );
     info!("Booting on: {}", bsp::board_name());
 
+    info!("MMU online. Special regions:");
+    bsp::memory::mmu::virt_mem_layout().print_layout();
+
     info!(
-        "Architectural timer resolution: {} ns",
+        "Clock source: {}, resolution: {} ns",
+        time::time_manager().clock_source_name(),
         time::time_manager().resolution().as_nanos()
     );
 
+    let (_, privilege_level) = exception::current_privilege_level();
+    info!("Current privilege level: {}", privilege_level);
+
+    info!("Exception handling state:");
+    exception::asynchronous::print_state();
+
+    if let (Some(now), Some(source)) = (time::wall_clock().now(), time::wall_clock().source()) {
+        info!("Wall clock: {:?}, {} (from {})", now.weekday(), now, source);
+    }
+
     info!("Drivers loaded:");
     driver::driver_manager().enumerate();
 
+    info!("Block devices:");
+    block::print_devices();
+
+    info!("Cores online: {}", cpu::smp::num_cores_online());
+    #[cfg(feature = "demos")]
+    test_cross_core_calls();
+
+    info!("Registered IRQ handlers:");
+    exception::asynchronous::irq_manager().print_handler();
+
+    info!("Physical memory map:");
+    memory::phys_map::phys_memory_map().print();
+
     info!("Heap initialized");
-    info!("      Addr: {:?}", virt_heap_start());
     kernel_heap_allocator().print_usage();
+    kernel_heap_allocator().print_statistics();
+
+    info!("Page frames:");
+    memory::frame::frame_allocator().print_usage();
+
+    // Test the frame allocator with a single frame and a 64 KiB run.
+    #[cfg(feature = "demos")]
+    test_frame_allocator();
 
     // Test a failing timer case.
     time::time_manager().spin_for(Duration::from_nanos(1));
 
+    time::time_manager().set_timeout(Duration::from_secs(3), || info!("Timeout after 3 seconds"));
+
+    // The workers keep running while the main thread sleeps below.
+    #[cfg(feature = "demos")]
+    test_threads();
+    #[cfg(feature = "demos")]
+    test_executor();
+    #[cfg(feature = "demos")]
+    test_processes();
+    #[cfg(feature = "program_loader")]
+    spawn_program_loader();
+    #[cfg(not(feature = "program_loader"))]
+    thread::spawn("shell", || shell::run());
+    info!("Threads:");
+    thread::print_threads();
+    info!("Processes:");
+    process::print_processes();
+
+    // The boot test waits for this line.
+    info!("Sleeping for 1 second");
+    thread::sleep(Duration::from_secs(1));
+
+    // From here on, the shell and the other threads do the work.
     loop {
-        info!("Spinning for 1 second");
-        time::time_manager().spin_for(Duration::from_secs(1));
+        thread::park();
     }
+}
+
+/// The main function of the chainloader.
+#[cfg(feature = "chainloader")]
+fn kernel_main() -> ! {
+    chainloader::chainloader_main()
+}
+
+/// The main function of the secondary cores.
+fn kernel_main_secondary() -> ! {
+    info!("Core {} online", cpu::smp::core_id::<usize>());
 
-    //loop {}
+    cpu::wait_forever()
 }
@@ -1,5 +1,21 @@
+pub mod cache;
+// The chainloader hands out no frames, it only needs the heap.
+#[cfg(not(feature = "chainloader"))]
+pub mod frame;
 pub mod heap_alloc;
+pub mod mmu;
+pub mod phys_map;
 
-pub fn init() {
-    heap_alloc::kernel_init_heap_allocator();
+use crate::bsp;
+
+/// Build the physical memory map, set up the heap inside it and hand the rest to the frame
+/// allocator.
+pub fn init() -> Result<(), &'static str> {
+    bsp::memory::init_phys_memory_map()?;
+    heap_alloc::kernel_init_heap_allocator()?;
+
+    #[cfg(not(feature = "chainloader"))]
+    frame::kernel_init_frame_allocator()?;
+
+    Ok(())
 }
@@ -0,0 +1,20 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2022 Andre Richter <andre.o.richter@gmail.com>
+
+//! Data cache maintenance.
+//!
+//! Needed whenever memory that is mapped cacheable is shared with a bus master that does not snoop
+//! the CPU caches, e.g. the VideoCore GPU reading and writing mailbox buffers. Code that the kernel
+//! writes for later execution, e.g. a user program, needs it as well.
+
+#[cfg(target_arch = "aarch64")]
+#[path = "../_arch/aarch64/memory/cache.rs"]
+mod arch_cache;
+
+//--------------------------------------------------------------------------------------------------
+// Architectural Public Reexports
+//--------------------------------------------------------------------------------------------------
+#[cfg(not(feature = "chainloader"))]
+pub use arch_cache::sync_icache_range;
+pub use arch_cache::{clean_and_invalidate_dcache_range, clean_dcache_range};
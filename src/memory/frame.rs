@@ -0,0 +1,251 @@
+//! Physical page frame allocator.
+//!
+//! Hands out page-aligned, physically contiguous memory, e.g. for DMA buffers or translation
+//! tables. It manages the regions that the physical memory map still lists as usable once the
+//! kernel image, the VideoCore and the heap have been reserved.
+//!
+//! Frames are tracked in a bitmap with one bit per 4 KiB frame. Runs of 2^order frames are aligned
+//! to their own size.
+//!
+//! # Note
+//!
+//! The MMU maps memory in 64 KiB pages. Buffers that will be remapped, e.g. with
+//! `memory::mmu::kernel_map_shared_buffer()`, must therefore be at least order 4.
+
+use crate::{
+    memory::phys_map::phys_memory_map,
+    synchronization::{interface::Mutex, IRQSafeLock},
+};
+use alloc::{vec, vec::Vec};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+const BITS_PER_WORD: usize = u64::BITS as usize;
+
+struct FrameAllocatorInner {
+    /// One bit per frame, set if the frame is in use or not RAM at all.
+    bitmap: Vec<u64>,
+    num_frames: usize,
+    num_free: usize,
+    num_usable: usize,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Size of a frame in bytes.
+pub const FRAME_SIZE: usize = 4096;
+
+/// Frame runs up to 2^MAX_ORDER frames can be allocated.
+pub const MAX_ORDER: usize = 10;
+
+/// The physical page frame allocator.
+pub struct FrameAllocator {
+    inner: IRQSafeLock<FrameAllocatorInner>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Global instances
+//--------------------------------------------------------------------------------------------------
+
+static FRAME_ALLOCATOR: FrameAllocator = FrameAllocator::new();
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+impl FrameAllocatorInner {
+    const fn new() -> Self {
+        Self {
+            bitmap: Vec::new(),
+            num_frames: 0,
+            num_free: 0,
+            num_usable: 0,
+        }
+    }
+
+    fn is_used(&self, frame: usize) -> bool {
+        self.bitmap[frame / BITS_PER_WORD] & (1 << (frame % BITS_PER_WORD)) != 0
+    }
+
+    fn set_range(&mut self, first: usize, count: usize, used: bool) {
+        for frame in first..(first + count) {
+            let mask = 1 << (frame % BITS_PER_WORD);
+            let word = &mut self.bitmap[frame / BITS_PER_WORD];
+
+            if used {
+                *word |= mask;
+            } else {
+                *word &= !mask;
+            }
+        }
+    }
+
+    fn is_free_range(&self, first: usize, count: usize) -> bool {
+        (first..(first + count)).all(|frame| !self.is_used(frame))
+    }
+
+    fn alloc(&mut self, order: usize) -> Option<usize> {
+        let count = 1 << order;
+        if count > self.num_free {
+            return None;
+        }
+
+        let mut first = 0;
+        while first + count <= self.num_frames {
+            // Skip fully used words quickly.
+            if count < BITS_PER_WORD
+                && first % BITS_PER_WORD == 0
+                && self.bitmap[first / BITS_PER_WORD] == u64::MAX
+            {
+                first += BITS_PER_WORD;
+                continue;
+            }
+
+            if self.is_free_range(first, count) {
+                self.set_range(first, count, true);
+                self.num_free -= count;
+
+                return Some(first);
+            }
+
+            first += count;
+        }
+
+        None
+    }
+}
+
+impl FrameAllocator {
+    const fn new() -> Self {
+        Self {
+            inner: IRQSafeLock::new(FrameAllocatorInner::new()),
+        }
+    }
+}
+
+fn check_order(order: usize) -> Result<(), &'static str> {
+    if order > MAX_ORDER {
+        return Err("Order too large");
+    }
+
+    Ok(())
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Return a reference to the kernel's frame allocator.
+pub fn frame_allocator() -> &'static FrameAllocator {
+    &FRAME_ALLOCATOR
+}
+
+impl FrameAllocator {
+    /// Allocate a single frame. Returns its physical address.
+    pub fn alloc(&self) -> Result<usize, &'static str> {
+        self.alloc_contiguous(0)
+    }
+
+    /// Allocate 2^order physically contiguous frames, aligned to their total size. Returns the
+    /// physical start address.
+    pub fn alloc_contiguous(&self, order: usize) -> Result<usize, &'static str> {
+        check_order(order)?;
+
+        self.inner
+            .lock(|inner| inner.alloc(order))
+            .map(|frame| frame * FRAME_SIZE)
+            .ok_or("Out of physical frames")
+    }
+
+    /// Free a frame that was returned by `alloc()`.
+    pub fn free(&self, addr: usize) -> Result<(), &'static str> {
+        self.free_contiguous(addr, 0)
+    }
+
+    /// Free a run that was returned by `alloc_contiguous()` with the same order.
+    pub fn free_contiguous(&self, addr: usize, order: usize) -> Result<(), &'static str> {
+        check_order(order)?;
+
+        let count = 1 << order;
+        if addr % (count * FRAME_SIZE) != 0 {
+            return Err("Address is not aligned to the run size");
+        }
+
+        let first = addr / FRAME_SIZE;
+        self.inner.lock(|inner| {
+            if first + count > inner.num_frames {
+                return Err("Address is outside of the managed RAM");
+            }
+
+            if (first..(first + count)).any(|frame| !inner.is_used(frame)) {
+                return Err("Double free");
+            }
+
+            inner.set_range(first, count, false);
+            inner.num_free += count;
+
+            Ok(())
+        })
+    }
+
+    /// Print the number of managed and free frames.
+    #[cfg(not(feature = "chainloader"))]
+    pub fn print_usage(&self) {
+        use crate::{common, info};
+
+        let (usable, free) = self.inner.lock(|inner| (inner.num_usable, inner.num_free));
+
+        let (usable_h, usable_unit) = common::size_human_readable_ceil(usable * FRAME_SIZE);
+        let (free_h, free_unit) = common::size_human_readable_ceil(free * FRAME_SIZE);
+
+        info!(
+            "      Managed: {} frames ({} {})",
+            usable, usable_h, usable_unit
+        );
+        info!("      Free:    {} frames ({} {})", free, free_h, free_unit);
+    }
+}
+
+/// Take over all RAM that is still usable in the physical memory map.
+///
+/// Must be called after the heap is initialized, because the bitmap lives on the heap.
+pub fn kernel_init_frame_allocator() -> Result<(), &'static str> {
+    static INIT_DONE: AtomicBool = AtomicBool::new(false);
+    if INIT_DONE.load(Ordering::Relaxed) {
+        return Err("Frame allocator already initialized");
+    }
+
+    let mut ram_end_exclusive = 0;
+    phys_memory_map().for_each_usable(|r| {
+        ram_end_exclusive = ram_end_exclusive.max(r.start + r.size);
+    });
+
+    let num_frames = ram_end_exclusive / FRAME_SIZE;
+
+    FRAME_ALLOCATOR.inner.lock(|inner| {
+        // Everything starts out as used. Only the usable regions are freed below.
+        inner.bitmap = vec![u64::MAX; num_frames.div_ceil(BITS_PER_WORD)];
+        inner.num_frames = num_frames;
+
+        phys_memory_map().for_each_usable(|r| {
+            // Only whole frames can be handed out.
+            let first = r.start.div_ceil(FRAME_SIZE);
+            let end_exclusive = (r.start + r.size) / FRAME_SIZE;
+
+            if end_exclusive > first {
+                inner.set_range(first, end_exclusive - first, false);
+                inner.num_free += end_exclusive - first;
+            }
+        });
+
+        inner.num_usable = inner.num_free;
+    });
+
+    INIT_DONE.store(true, Ordering::Relaxed);
+    Ok(())
+}
@@ -1,6 +1,11 @@
+#[cfg(feature = "heap_debug")]
+mod redzone;
+mod size_class;
+
 use crate::{
-    bsp, common, debug, info,
-    synchronization::{self, NullLock},
+    common, debug,
+    memory::phys_map::phys_memory_map,
+    synchronization::{self, IRQSafeLock},
 };
 
 use core::{
@@ -8,6 +13,54 @@ use core::{
     sync::atomic::{AtomicBool, Ordering},
 };
 use linked_list_allocator::Heap as LinkedListHeap;
+use size_class::SizeClasses;
+
+#[cfg(feature = "heap_debug")]
+use redzone::BadFree;
+
+/// Without the checks, every free succeeds.
+#[cfg(not(feature = "heap_debug"))]
+type BadFree = core::convert::Infallible;
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Upper bound for the heap in percent of RAM. Configured at build time, see `build.rs`.
+const MAX_HEAP_RAM_PERCENT: usize = common::parse_usize(env!("RPOS_HEAP_RAM_PERCENT"));
+
+/// The heap starts out with this size and grows on demand.
+const INITIAL_HEAP_SIZE: usize = 16 * 1024 * 1024;
+
+/// Minimum amount by which the heap grows.
+const HEAP_GROWTH_STEP: usize = 1024 * 1024;
+
+/// Heap memory is claimed and grown in multiples of this.
+const HEAP_GRANULE: usize = 64 * 1024;
+
+/// Number of bins of the allocation size histogram. Bin `n` counts requests of up to 2^(n + 4)
+/// bytes, the last bin also everything larger.
+const NUM_HISTOGRAM_BINS: usize = 16;
+
+struct HeapStatistics {
+    num_allocs: usize,
+    num_frees: usize,
+    num_failed: usize,
+    /// Bytes requested by live allocations.
+    requested_bytes: usize,
+    peak_requested_bytes: usize,
+    histogram: [usize; NUM_HISTOGRAM_BINS],
+}
+
+struct HeapAllocatorInner {
+    heap: LinkedListHeap,
+    /// The heap may grow up to this size. The whole window is reserved in the physical memory map.
+    max_size: usize,
+    size_classes: SizeClasses,
+    stats: HeapStatistics,
+    #[cfg(feature = "heap_debug")]
+    live_allocations: redzone::LiveAllocations,
+}
 
 //--------------------------------------------------------------------------------------------------
 // Public Definitions
@@ -15,7 +68,7 @@ use linked_list_allocator::Heap as LinkedListHeap;
 
 /// A heap allocator that can be lazyily initialized.
 pub struct HeapAllocator {
-    inner: NullLock<LinkedListHeap>,
+    inner: IRQSafeLock<HeapAllocatorInner>,
 }
 
 //--------------------------------------------------------------------------------------------------
@@ -48,6 +101,131 @@ fn debug_print_alloc_dealloc(operation: &'static str, ptr: *mut u8, layout: Layo
     );
 }
 
+fn histogram_bin(size: usize) -> usize {
+    let bin = size.max(16).next_power_of_two().trailing_zeros() as usize - 4;
+
+    bin.min(NUM_HISTOGRAM_BINS - 1)
+}
+
+impl HeapStatistics {
+    const fn new() -> Self {
+        Self {
+            num_allocs: 0,
+            num_frees: 0,
+            num_failed: 0,
+            requested_bytes: 0,
+            peak_requested_bytes: 0,
+            histogram: [0; NUM_HISTOGRAM_BINS],
+        }
+    }
+
+    fn record_alloc(&mut self, layout: Layout, success: bool) {
+        if !success {
+            self.num_failed += 1;
+            return;
+        }
+
+        self.num_allocs += 1;
+        self.requested_bytes += layout.size();
+        self.peak_requested_bytes = self.peak_requested_bytes.max(self.requested_bytes);
+        self.histogram[histogram_bin(layout.size())] += 1;
+    }
+
+    fn record_dealloc(&mut self, layout: Layout) {
+        self.num_frees += 1;
+        self.requested_bytes -= layout.size();
+    }
+}
+
+impl HeapAllocatorInner {
+    const fn new() -> Self {
+        Self {
+            heap: LinkedListHeap::empty(),
+            max_size: 0,
+            size_classes: SizeClasses::new(),
+            stats: HeapStatistics::new(),
+            #[cfg(feature = "heap_debug")]
+            live_allocations: redzone::LiveAllocations::new(),
+        }
+    }
+
+    fn alloc(&mut self, layout: Layout) -> Option<*mut u8> {
+        #[cfg(feature = "heap_debug")]
+        let result = self.alloc_with_redzones(layout);
+
+        #[cfg(not(feature = "heap_debug"))]
+        let result = self.alloc_raw(layout);
+
+        self.stats.record_alloc(layout, result.is_some());
+
+        result
+    }
+
+    unsafe fn dealloc(&mut self, ptr: *mut u8, layout: Layout) -> Result<(), BadFree> {
+        #[cfg(feature = "heap_debug")]
+        self.dealloc_with_redzones(ptr, layout)?;
+
+        #[cfg(not(feature = "heap_debug"))]
+        self.dealloc_raw(ptr, layout);
+
+        self.stats.record_dealloc(layout);
+
+        Ok(())
+    }
+
+    fn alloc_raw(&mut self, layout: Layout) -> Option<*mut u8> {
+        match SizeClasses::class_of(layout) {
+            Some(class) => self.alloc_small(class),
+            None => self.alloc_backing(layout),
+        }
+    }
+
+    /// Serve a request from a size class, refilling it from the backing heap if it is empty.
+    fn alloc_small(&mut self, class: usize) -> Option<*mut u8> {
+        if let Some(ptr) = self.size_classes.pop(class) {
+            return Some(ptr);
+        }
+
+        let chunk = self.alloc_backing(SizeClasses::chunk_layout(class))?;
+        unsafe { self.size_classes.refill(class, chunk) };
+
+        self.size_classes.pop(class)
+    }
+
+    unsafe fn dealloc_raw(&mut self, ptr: *mut u8, layout: Layout) {
+        match SizeClasses::class_of(layout) {
+            Some(class) => self.size_classes.push(class, ptr),
+            None => self
+                .heap
+                .deallocate(core::ptr::NonNull::new_unchecked(ptr), layout),
+        }
+    }
+
+    /// Allocate from the backing heap, growing it first if the request does not fit.
+    fn alloc_backing(&mut self, layout: Layout) -> Option<*mut u8> {
+        if let Ok(allocation) = self.heap.allocate_first_fit(layout) {
+            return Some(allocation.as_ptr());
+        }
+
+        // Worst case, the allocation needs padding for its alignment on top of its size.
+        let needed = layout.size().checked_add(layout.align())?;
+        let growth =
+            needed.max(HEAP_GROWTH_STEP).checked_add(HEAP_GRANULE - 1)? & !(HEAP_GRANULE - 1);
+        let growth = growth.min(self.max_size - self.heap.size());
+        if growth < needed {
+            return None;
+        }
+
+        unsafe { self.heap.extend(growth) };
+        debug!("Kernel Heap: Grown to {:#x}", self.heap.size());
+
+        self.heap
+            .allocate_first_fit(layout)
+            .ok()
+            .map(|allocation| allocation.as_ptr())
+    }
+}
+
 //--------------------------------------------------------------------------------------------------
 // Public Code
 //--------------------------------------------------------------------------------------------------
@@ -59,6 +237,7 @@ fn alloc_error_handler(layout: Layout) -> ! {
 }
 
 /// Return a reference to the kernel's heap allocator.
+#[cfg(not(feature = "chainloader"))]
 pub fn kernel_heap_allocator() -> &'static HeapAllocator {
     &KERNEL_HEAP_ALLOCATOR
 }
@@ -67,29 +246,126 @@ impl HeapAllocator {
     /// Create an instance.
     pub const fn new() -> Self {
         Self {
-            inner: NullLock::new(LinkedListHeap::empty()),
+            inner: IRQSafeLock::new(HeapAllocatorInner::new()),
         }
     }
 
     /// Print the current heap usage.
+    #[cfg(not(feature = "chainloader"))]
     pub fn print_usage(&self) {
-        let (used, free) = KERNEL_HEAP_ALLOCATOR
+        use crate::info;
+
+        let (start, size, max_size, used, free) = KERNEL_HEAP_ALLOCATOR.inner.lock(|inner| {
+            (
+                inner.heap.bottom(),
+                inner.heap.size(),
+                inner.max_size,
+                inner.heap.used(),
+                inner.heap.free(),
+            )
+        });
+
+        info!("      Addr: {:?}", start);
+        print_size("Size", size);
+        print_size("Max ", max_size);
+        print_size("Used", used);
+        print_size("Free", free);
+    }
+
+    /// Check the redzones and headers of all live allocations and report corrupted ones.
+    ///
+    /// Returns an error if any allocation is corrupted.
+    #[cfg(feature = "heap_debug")]
+    pub fn check_integrity(&self) -> Result<(), &'static str> {
+        use crate::info;
+
+        let (live, corrupted) = KERNEL_HEAP_ALLOCATOR
             .inner
-            .lock(|inner| (inner.used(), inner.free()));
+            .lock(|inner| inner.check_integrity());
 
-        if used >= 1024 {
-            let (used_h, used_unit) = common::size_human_readable_ceil(used);
-            info!("      Used: {} Byte ({} {})", used, used_h, used_unit);
-        } else {
-            info!("      Used: {} Byte", used);
-        }
+        info!(
+            "Heap integrity: {} live allocations, {} corrupted",
+            live, corrupted
+        );
 
-        if free >= 1024 {
-            let (free_h, free_unit) = common::size_human_readable_ceil(free);
-            info!("      Free: {} Byte ({} {})", free, free_h, free_unit);
-        } else {
-            info!("      Free: {} Byte", free);
+        if corrupted > 0 {
+            return Err("Heap corruption detected");
         }
+
+        Ok(())
+    }
+
+    /// Print allocation counters, the size classes and a histogram of request sizes.
+    #[cfg(not(feature = "chainloader"))]
+    pub fn print_statistics(&self) {
+        use crate::info;
+
+        KERNEL_HEAP_ALLOCATOR.inner.lock(|inner| {
+            let stats = &inner.stats;
+
+            info!(
+                "      Allocations: {}, frees: {}, failed: {}",
+                stats.num_allocs, stats.num_frees, stats.num_failed
+            );
+            print_size("In use", stats.requested_bytes);
+            print_size("Peak  ", stats.peak_requested_bytes);
+
+            // Share of the memory taken from the backing heap that no live allocation asked for:
+            // rounding to size classes, blocks cached in free lists and alignment padding.
+            let used = inner.heap.used();
+            if used > 0 {
+                let overhead = used.saturating_sub(stats.requested_bytes);
+                info!("      Fragmentation: {}%", overhead * 100 / used);
+            }
+
+            info!("      Size classes:");
+            for class in 0..size_class::NUM_CLASSES {
+                let (class_used, cached) = (
+                    inner.size_classes.used_bytes(class),
+                    inner.size_classes.cached_bytes(class),
+                );
+                if class_used + cached == 0 {
+                    continue;
+                }
+
+                info!(
+                    "            {: >4} Byte: {: >8} Byte used, {: >8} Byte cached",
+                    SizeClasses::block_size(class),
+                    class_used,
+                    cached
+                );
+            }
+
+            info!("      Request sizes:");
+            for (bin, count) in stats.histogram.iter().enumerate() {
+                if *count == 0 {
+                    continue;
+                }
+
+                let (limit, unit) = common::size_human_readable_ceil(1 << (bin + 4));
+                let relation = if bin == NUM_HISTOGRAM_BINS - 1 {
+                    ">"
+                } else {
+                    "<="
+                };
+                info!(
+                    "            {: >2} {: >3} {: <4}: {}",
+                    relation, limit, unit, count
+                );
+            }
+        });
+    }
+}
+
+#[cfg(not(feature = "chainloader"))]
+fn print_size(label: &str, size: usize) {
+    use crate::info;
+
+    if size >= 1024 {
+        let (size_h, size_unit) = common::size_human_readable_ceil(size);
+        info!("      {}: {} Byte ({} {})", label, size, size_h, size_unit);
+    } else {
+        info!("      {}: {} Byte", label, size);
     }
 }
 
@@ -97,13 +373,11 @@ unsafe impl GlobalAlloc for HeapAllocator {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
         let result = KERNEL_HEAP_ALLOCATOR
             .inner
-            .lock(|inner| inner.allocate_first_fit(layout).ok());
+            .lock(|inner| inner.alloc(layout));
 
         match result {
             None => core::ptr::null_mut(),
-            Some(allocation) => {
-                let ptr = allocation.as_ptr();
-
+            Some(ptr) => {
                 debug_print_alloc_dealloc("Allocation", ptr, layout);
 
                 ptr
@@ -112,23 +386,39 @@ unsafe impl GlobalAlloc for HeapAllocator {
     }
 
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
-        KERNEL_HEAP_ALLOCATOR
+        let result = KERNEL_HEAP_ALLOCATOR
             .inner
-            .lock(|inner| inner.deallocate(core::ptr::NonNull::new_unchecked(ptr), layout));
+            .lock(|inner| inner.dealloc(ptr, layout));
+
+        // Panic outside of the lock, the panic handler may want to inspect the heap.
+        if let Err(x) = result {
+            panic!("Heap: {}", x);
+        }
 
         debug_print_alloc_dealloc("Free", ptr, layout);
     }
 }
 
-pub fn kernel_init_heap_allocator() {
+/// Reserve the heap's window in the physical memory map and hand the initial part to the allocator.
+///
+/// The window is a fraction of RAM, but never more than the largest usable region.
+pub fn kernel_init_heap_allocator() -> Result<(), &'static str> {
     static INIT_DONE: AtomicBool = AtomicBool::new(false);
     if INIT_DONE.load(Ordering::Relaxed) {
-        return;
+        return Err("Heap already initialized");
     }
 
+    let max_size =
+        (phys_memory_map().ram_size() / 100 * MAX_HEAP_RAM_PERCENT) & !(HEAP_GRANULE - 1);
+    let region = phys_memory_map().claim(max_size, HEAP_GRANULE, "Kernel heap")?;
+
     KERNEL_HEAP_ALLOCATOR.inner.lock(|inner| unsafe {
-        inner.init(bsp::memory::virt_heap_start(), bsp::memory::heap_size())
+        inner.max_size = region.size;
+        inner
+            .heap
+            .init(region.start as *mut u8, INITIAL_HEAP_SIZE.min(region.size))
     });
 
     INIT_DONE.store(true, Ordering::Relaxed);
+    Ok(())
 }
@@ -0,0 +1,293 @@
+//! Heap corruption detection, enabled with the `heap_debug` feature.
+//!
+//! Every allocation is wrapped as follows:
+//!
+//! ```text
+//! | padding | Header | front redzone | user data | back redzone |
+//!                                    ^ pointer handed out
+//! ```
+//!
+//! The redzones are filled with a canary pattern that is checked on free and during integrity
+//! walks. Freed user data is poisoned. The header records the requested layout, which catches
+//! layout mismatches, and a magic value that catches double frees of blocks that have not been
+//! reused yet. All live allocations are linked together, so the whole heap can be checked at once.
+
+use super::HeapAllocatorInner;
+use crate::warn;
+use core::{alloc::Layout, fmt, mem::size_of, ptr};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+const REDZONE_SIZE: usize = 16;
+const CANARY: u8 = 0xCA;
+const FREE_POISON: u8 = 0xDF;
+
+const MAGIC_ALLOCATED: usize = 0xA110_CA7E_D000_0000;
+const MAGIC_FREED: usize = 0xF4EE_D000_0000_0000;
+
+/// Bookkeeping in front of every allocation.
+///
+/// The backing allocators put their free-list links at the start of freed blocks, so the magic
+/// goes last to survive a free.
+#[repr(C, align(16))]
+struct Header {
+    prev: *mut Header,
+    next: *mut Header,
+    size: usize,
+    align: usize,
+    magic: usize,
+}
+
+const HEADER_SIZE: usize = size_of::<Header>();
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// A free that failed the checks.
+///
+/// Returned instead of panicking right away, so that the allocator's lock is released before the
+/// panic handler walks the heap.
+pub enum BadFree {
+    DoubleFree(*mut u8),
+    NotLive(*mut u8),
+    LayoutMismatch {
+        ptr: *mut u8,
+        size: usize,
+        align: usize,
+        layout: Layout,
+    },
+    Redzone {
+        ptr: *mut u8,
+        size: usize,
+        reason: &'static str,
+    },
+}
+
+/// List of all live allocations.
+pub struct LiveAllocations {
+    head: *mut Header,
+}
+
+/// # Safety
+///
+/// - The list only links headers inside heap memory owned by the allocator. All access is
+///   serialized by the allocator's lock.
+unsafe impl Send for LiveAllocations {}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+/// Offset of the user data from the start of the wrapped allocation.
+fn prefix_size(align: usize) -> usize {
+    (HEADER_SIZE + REDZONE_SIZE + align - 1) & !(align - 1)
+}
+
+fn outer_layout(layout: Layout) -> Option<Layout> {
+    let align = layout.align().max(16);
+    let size = prefix_size(align)
+        .checked_add(layout.size())?
+        .checked_add(REDZONE_SIZE)?;
+
+    Layout::from_size_align(size, align).ok()
+}
+
+unsafe fn header_of(user_ptr: *mut u8) -> *mut Header {
+    user_ptr.sub(REDZONE_SIZE + HEADER_SIZE) as *mut Header
+}
+
+unsafe fn user_ptr_of(header: *mut Header) -> *mut u8 {
+    (header as *mut u8).add(HEADER_SIZE + REDZONE_SIZE)
+}
+
+fn is_canary(start: *const u8, len: usize) -> bool {
+    (0..len).all(|i| unsafe { ptr::read_volatile(start.add(i)) } == CANARY)
+}
+
+/// Check the redzones of a live allocation.
+unsafe fn check_redzones(header: *mut Header) -> Result<(), &'static str> {
+    let user_ptr = user_ptr_of(header);
+
+    if !is_canary(user_ptr.sub(REDZONE_SIZE), REDZONE_SIZE) {
+        return Err("Buffer underflow, front redzone overwritten");
+    }
+
+    if !is_canary(user_ptr.add((*header).size), REDZONE_SIZE) {
+        return Err("Buffer overflow, back redzone overwritten");
+    }
+
+    Ok(())
+}
+
+impl fmt::Display for BadFree {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DoubleFree(ptr) => write!(f, "Double free of {:?}", ptr),
+            Self::NotLive(ptr) => write!(f, "Free of {:?}, which is not a live allocation", ptr),
+            Self::LayoutMismatch {
+                ptr,
+                size,
+                align,
+                layout,
+            } => write!(
+                f,
+                "Layout mismatch on free of {:?}: allocated with size {} align {}, freed with \
+                size {} align {}",
+                ptr,
+                size,
+                align,
+                layout.size(),
+                layout.align()
+            ),
+            Self::Redzone { ptr, size, reason } => {
+                write!(f, "{} at {:?} (size {})", reason, ptr, size)
+            }
+        }
+    }
+}
+
+impl LiveAllocations {
+    pub const fn new() -> Self {
+        Self {
+            head: ptr::null_mut(),
+        }
+    }
+
+    unsafe fn insert(&mut self, header: *mut Header) {
+        (*header).prev = ptr::null_mut();
+        (*header).next = self.head;
+
+        if !self.head.is_null() {
+            (*self.head).prev = header;
+        }
+        self.head = header;
+    }
+
+    unsafe fn remove(&mut self, header: *mut Header) {
+        let (prev, next) = ((*header).prev, (*header).next);
+
+        if prev.is_null() {
+            self.head = next;
+        } else {
+            (*prev).next = next;
+        }
+
+        if !next.is_null() {
+            (*next).prev = prev;
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl HeapAllocatorInner {
+    /// Allocate with redzones around the user data.
+    pub(super) fn alloc_with_redzones(&mut self, layout: Layout) -> Option<*mut u8> {
+        let outer = outer_layout(layout)?;
+        let outer_ptr = self.alloc_raw(outer)?;
+
+        unsafe {
+            let user_ptr = outer_ptr.add(prefix_size(outer.align()));
+            let header = header_of(user_ptr);
+
+            header.write(Header {
+                prev: ptr::null_mut(),
+                next: ptr::null_mut(),
+                size: layout.size(),
+                align: layout.align(),
+                magic: MAGIC_ALLOCATED,
+            });
+            ptr::write_bytes(user_ptr.sub(REDZONE_SIZE), CANARY, REDZONE_SIZE);
+            ptr::write_bytes(user_ptr.add(layout.size()), CANARY, REDZONE_SIZE);
+
+            self.live_allocations.insert(header);
+
+            Some(user_ptr)
+        }
+    }
+
+    /// Check an allocation before it is freed, then poison and free it.
+    ///
+    /// # Safety
+    ///
+    /// - `user_ptr` must point into the heap.
+    pub(super) unsafe fn dealloc_with_redzones(
+        &mut self,
+        user_ptr: *mut u8,
+        layout: Layout,
+    ) -> Result<(), BadFree> {
+        let header = header_of(user_ptr);
+
+        match (*header).magic {
+            MAGIC_ALLOCATED => (),
+            MAGIC_FREED => return Err(BadFree::DoubleFree(user_ptr)),
+            _ => return Err(BadFree::NotLive(user_ptr)),
+        }
+
+        if (*header).size != layout.size() || (*header).align != layout.align() {
+            return Err(BadFree::LayoutMismatch {
+                ptr: user_ptr,
+                size: (*header).size,
+                align: (*header).align,
+                layout,
+            });
+        }
+
+        if let Err(reason) = check_redzones(header) {
+            return Err(BadFree::Redzone {
+                ptr: user_ptr,
+                size: layout.size(),
+                reason,
+            });
+        }
+
+        self.live_allocations.remove(header);
+        (*header).magic = MAGIC_FREED;
+        ptr::write_bytes(user_ptr, FREE_POISON, layout.size());
+
+        // Cannot fail, the layout was validated when it was allocated.
+        let outer = outer_layout(layout).unwrap();
+        self.dealloc_raw(user_ptr.sub(prefix_size(outer.align())), outer);
+
+        Ok(())
+    }
+
+    /// Walk all live allocations and report the corrupted ones. Returns the number of live and
+    /// corrupted allocations.
+    pub(super) fn check_integrity(&self) -> (usize, usize) {
+        let (mut live, mut corrupted) = (0, 0);
+        let mut header = self.live_allocations.head;
+
+        while !header.is_null() {
+            unsafe {
+                let user_ptr = user_ptr_of(header);
+                let result = if (*header).magic != MAGIC_ALLOCATED {
+                    Err("Header overwritten")
+                } else {
+                    check_redzones(header)
+                };
+
+                if let Err(x) = result {
+                    warn!("Heap: {} at {:?} (size {})", x, user_ptr, (*header).size);
+                    corrupted += 1;
+
+                    // The links may be garbage as well.
+                    if (*header).magic != MAGIC_ALLOCATED {
+                        warn!("Heap: Integrity walk aborted");
+                        break;
+                    }
+                }
+
+                live += 1;
+                header = (*header).next;
+            }
+        }
+
+        (live, corrupted)
+    }
+}
@@ -0,0 +1,141 @@
+//! Size-class front-end for small heap allocations.
+//!
+//! Small requests are rounded up to a power-of-two block size. Each class keeps a free list of
+//! blocks that are carved out of larger chunks taken from the backing heap. Freed blocks go back
+//! onto their class' free list and are never returned to the backing heap, so allocation and free
+//! are O(1) once a class is warmed up.
+
+use core::{alloc::Layout, ptr::NonNull};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+const MIN_BLOCK_SHIFT: usize = 4;
+
+/// A free block. The link is stored inside the block itself.
+struct FreeBlock {
+    next: Option<NonNull<FreeBlock>>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Number of size classes, from 16 to 2048 bytes.
+pub const NUM_CLASSES: usize = 8;
+
+/// Size of the chunks that classes refill from the backing heap.
+pub const CHUNK_SIZE: usize = 16 * 1024;
+
+/// Free lists of all size classes.
+pub struct SizeClasses {
+    free_lists: [Option<NonNull<FreeBlock>>; NUM_CLASSES],
+    /// Bytes of each class that sit in a free list.
+    cached_bytes: [usize; NUM_CLASSES],
+    /// Bytes of each class that are handed out.
+    used_bytes: [usize; NUM_CLASSES],
+}
+
+/// # Safety
+///
+/// - The free lists only point into heap memory that is owned by the allocator. All access is
+///   serialized by the allocator's lock.
+unsafe impl Send for SizeClasses {}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl SizeClasses {
+    /// Create an instance.
+    pub const fn new() -> Self {
+        Self {
+            free_lists: [None; NUM_CLASSES],
+            cached_bytes: [0; NUM_CLASSES],
+            used_bytes: [0; NUM_CLASSES],
+        }
+    }
+
+    /// The class serving `layout`, or `None` if it is too large for the front-end.
+    pub fn class_of(layout: Layout) -> Option<usize> {
+        let size = layout
+            .size()
+            .max(layout.align())
+            .max(1 << MIN_BLOCK_SHIFT)
+            .next_power_of_two();
+        let class = size.trailing_zeros() as usize - MIN_BLOCK_SHIFT;
+
+        (class < NUM_CLASSES).then_some(class)
+    }
+
+    /// Block size of a class in bytes.
+    pub const fn block_size(class: usize) -> usize {
+        1 << (class + MIN_BLOCK_SHIFT)
+    }
+
+    /// Layout of the chunks that a class refills from. Chunks are aligned to the block size, so
+    /// every block is naturally aligned.
+    pub fn chunk_layout(class: usize) -> Layout {
+        Layout::from_size_align(CHUNK_SIZE, Self::block_size(class)).unwrap()
+    }
+
+    /// Take a block from the class' free list.
+    pub fn pop(&mut self, class: usize) -> Option<*mut u8> {
+        let block = self.free_lists[class]?;
+
+        self.free_lists[class] = unsafe { block.as_ref().next };
+        self.cached_bytes[class] -= Self::block_size(class);
+        self.used_bytes[class] += Self::block_size(class);
+
+        Some(block.as_ptr() as *mut u8)
+    }
+
+    /// Put a block back onto the class' free list.
+    ///
+    /// # Safety
+    ///
+    /// - `ptr` must have been returned by `pop()` for the same class.
+    pub unsafe fn push(&mut self, class: usize, ptr: *mut u8) {
+        let block = ptr as *mut FreeBlock;
+        block.write(FreeBlock {
+            next: self.free_lists[class],
+        });
+
+        self.free_lists[class] = NonNull::new(block);
+        self.cached_bytes[class] += Self::block_size(class);
+        self.used_bytes[class] -= Self::block_size(class);
+    }
+
+    /// Carve a fresh chunk into blocks of the class.
+    ///
+    /// # Safety
+    ///
+    /// - `chunk` must have been allocated with `chunk_layout(class)` and is owned by the class from
+    ///   now on.
+    pub unsafe fn refill(&mut self, class: usize, chunk: *mut u8) {
+        let block_size = Self::block_size(class);
+
+        for offset in (0..CHUNK_SIZE).step_by(block_size).rev() {
+            let block = chunk.add(offset) as *mut FreeBlock;
+            block.write(FreeBlock {
+                next: self.free_lists[class],
+            });
+            self.free_lists[class] = NonNull::new(block);
+        }
+
+        self.cached_bytes[class] += CHUNK_SIZE;
+    }
+
+    /// Bytes handed out by a class.
+    #[cfg(not(feature = "chainloader"))]
+    pub fn used_bytes(&self, class: usize) -> usize {
+        self.used_bytes[class]
+    }
+
+    /// Bytes a class holds in its free list.
+    #[cfg(not(feature = "chainloader"))]
+    pub fn cached_bytes(&self, class: usize) -> usize {
+        self.cached_bytes[class]
+    }
+}
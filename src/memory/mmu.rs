@@ -0,0 +1,380 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020-2022 Andre Richter <andre.o.richter@gmail.com>
+
+//! Memory Management Unit.
+//!
+//! In order to decouple `BSP` and `arch` parts of the MMU code (to keep them pluggable), this file
+//! provides types for composing an architecture-agnostic description of the kernel's virtual memory
+//! layout.
+//!
+//! The `BSP` provides such a description through the `bsp::memory::mmu::virt_mem_layout()`
+//! function.
+//!
+//! The `MMU` driver of the `arch` code uses `bsp::memory::mmu::virt_mem_layout()` to compile and
+//! install respective translation tables.
+
+#[cfg(target_arch = "aarch64")]
+#[path = "../_arch/aarch64/memory/mmu.rs"]
+mod arch_mmu;
+
+mod translation_table;
+// The chainloader runs no processes.
+#[cfg(not(feature = "chainloader"))]
+mod user_space;
+
+use crate::{common, cpu};
+use core::{fmt, ops::RangeInclusive};
+
+//--------------------------------------------------------------------------------------------------
+// Architectural Public Reexports
+//--------------------------------------------------------------------------------------------------
+pub use arch_mmu::{activate_user_space, mmu};
+#[cfg(not(feature = "chainloader"))]
+pub use user_space::{UserAddressSpace, PAGE_SIZE};
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// MMU enable errors variants.
+#[allow(missing_docs)]
+#[derive(Debug)]
+pub enum MMUEnableError {
+    AlreadyEnabled,
+    Other(&'static str),
+}
+
+/// Memory Management interfaces.
+pub mod interface {
+    use super::*;
+
+    /// MMU functions.
+    pub trait MMU {
+        /// Called by the kernel during early init. Supposed to take the translation tables from the
+        /// `BSP`-supplied `virt_mem_layout()` and install/activate them for the respective MMU.
+        ///
+        /// # Safety
+        ///
+        /// - Changes the HW's global state.
+        unsafe fn enable_mmu_and_caching(&self) -> Result<(), MMUEnableError>;
+
+        /// Called by the secondary cores during their early init. Activates the translation tables
+        /// that the boot core installed with `enable_mmu_and_caching()`.
+        ///
+        /// # Safety
+        ///
+        /// - Changes the HW's global state.
+        /// - The boot core must have enabled its MMU before.
+        unsafe fn enable_mmu_and_caching_secondary(&self) -> Result<(), MMUEnableError>;
+
+        /// Returns true if the MMU is enabled, false otherwise.
+        fn is_enabled(&self) -> bool;
+
+        /// Change the attributes of an already mapped, page-aligned range.
+        ///
+        /// # Safety
+        ///
+        /// - Changes the HW's global state.
+        /// - The range must not be in use by the kernel while it is remapped.
+        /// - All other online cores must be able to take IPIs, see `tlb_shootdown()`.
+        unsafe fn remap(
+            &self,
+            virt_range: RangeInclusive<usize>,
+            attribute_fields: &AttributeFields,
+        ) -> Result<(), &'static str>;
+    }
+}
+
+/// Refers to the translation tables of a user address space, see
+/// `UserAddressSpace::token()`.
+#[derive(Copy, Clone)]
+pub struct UserSpaceToken {
+    phys_base: u64,
+    asid: u16,
+}
+
+/// Describes the characteristics of a translation granule.
+pub struct TranslationGranule<const GRANULE_SIZE: usize>;
+
+/// Describes properties of an address space.
+pub struct AddressSpace<const AS_SIZE: usize>;
+
+/// Architecture agnostic translation types.
+#[allow(missing_docs)]
+#[derive(Copy, Clone)]
+pub enum Translation {
+    Identity,
+    /// No valid mapping. Any access faults, e.g. for guard pages.
+    Unmapped,
+}
+
+/// Architecture agnostic memory attributes.
+#[allow(missing_docs)]
+#[derive(Copy, Clone)]
+pub enum MemAttributes {
+    CacheableDRAM,
+    /// Normal memory that bypasses the caches. Writes may be gathered, which makes it suitable for
+    /// buffers shared with other bus masters, e.g. a framebuffer.
+    NonCacheableDRAM,
+    Device,
+}
+
+/// Architecture agnostic access permissions.
+#[allow(missing_docs)]
+#[derive(Copy, Clone)]
+pub enum AccessPermissions {
+    ReadOnly,
+    ReadWrite,
+}
+
+/// Collection of memory attributes.
+#[allow(missing_docs)]
+#[derive(Copy, Clone)]
+pub struct AttributeFields {
+    pub mem_attributes: MemAttributes,
+    pub acc_perms: AccessPermissions,
+    pub execute_never: bool,
+}
+
+/// Architecture agnostic descriptor for a memory range.
+#[allow(missing_docs)]
+pub struct TranslationDescriptor {
+    pub name: &'static str,
+    pub virtual_range: fn() -> RangeInclusive<usize>,
+    pub physical_range_translation: Translation,
+    pub attribute_fields: AttributeFields,
+}
+
+/// Type for expressing the kernel's virtual memory layout.
+pub struct KernelVirtualLayout<const NUM_SPECIAL_RANGES: usize> {
+    /// The last (inclusive) address of the address space.
+    max_virt_addr_inclusive: usize,
+
+    /// Array of descriptors for non-standard (normal cacheable DRAM) memory regions.
+    inner: [TranslationDescriptor; NUM_SPECIAL_RANGES],
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl fmt::Display for MMUEnableError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MMUEnableError::AlreadyEnabled => write!(f, "MMU is already enabled"),
+            MMUEnableError::Other(x) => write!(f, "{}", x),
+        }
+    }
+}
+
+impl<const GRANULE_SIZE: usize> TranslationGranule<GRANULE_SIZE> {
+    /// The granule's size.
+    pub const SIZE: usize = Self::size_checked();
+
+    /// The granule's shift, aka log2(size).
+    pub const SHIFT: usize = Self::SIZE.trailing_zeros() as usize;
+
+    const fn size_checked() -> usize {
+        assert!(GRANULE_SIZE.is_power_of_two());
+
+        GRANULE_SIZE
+    }
+}
+
+impl<const AS_SIZE: usize> AddressSpace<AS_SIZE> {
+    /// The address space size.
+    pub const SIZE: usize = Self::size_checked();
+
+    /// The address space shift, aka log2(size).
+    pub const SIZE_SHIFT: usize = Self::SIZE.trailing_zeros() as usize;
+
+    const fn size_checked() -> usize {
+        assert!(AS_SIZE.is_power_of_two());
+
+        // Check for architectural restrictions as well.
+        Self::arch_address_space_size_sanity_checks();
+
+        AS_SIZE
+    }
+}
+
+impl Default for AttributeFields {
+    fn default() -> AttributeFields {
+        AttributeFields {
+            mem_attributes: MemAttributes::CacheableDRAM,
+            acc_perms: AccessPermissions::ReadWrite,
+            execute_never: true,
+        }
+    }
+}
+
+/// Human-readable output of AttributeFields.
+impl fmt::Display for AttributeFields {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let attr = match self.mem_attributes {
+            MemAttributes::CacheableDRAM => "C",
+            MemAttributes::NonCacheableDRAM => "NC",
+            MemAttributes::Device => "Dev",
+        };
+
+        let acc_p = match self.acc_perms {
+            AccessPermissions::ReadOnly => "RO",
+            AccessPermissions::ReadWrite => "RW",
+        };
+
+        let xn = if self.execute_never { "PXN" } else { "PX" };
+
+        write!(f, "{: <3} {} {: <3}", attr, acc_p, xn)
+    }
+}
+
+/// Human-readable output of a TranslationDescriptor.
+impl fmt::Display for TranslationDescriptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Call the function to which self.range points, and dereference the result, which causes
+        // Rust to copy the value.
+        let start = *(self.virtual_range)().start();
+        let end = *(self.virtual_range)().end() + 1;
+
+        match self.physical_range_translation {
+            Translation::Unmapped => write_range(f, start, end, &"unmapped", self.name),
+            _ => write_range(f, start, end, &self.attribute_fields, self.name),
+        }
+    }
+}
+
+fn write_range(
+    f: &mut fmt::Formatter<'_>,
+    start: usize,
+    end_exclusive: usize,
+    attributes: &dyn fmt::Display,
+    name: &str,
+) -> fmt::Result {
+    let (size, unit) = common::size_human_readable_ceil(end_exclusive - start);
+
+    write!(
+        f,
+        "      {:#010x} - {:#010x} | {: >3} {} | {: <10} | {}",
+        start, end_exclusive, size, unit, attributes, name
+    )
+}
+
+impl<const NUM_SPECIAL_RANGES: usize> KernelVirtualLayout<{ NUM_SPECIAL_RANGES }> {
+    /// Create a new instance.
+    pub const fn new(max: usize, layout: [TranslationDescriptor; NUM_SPECIAL_RANGES]) -> Self {
+        Self {
+            max_virt_addr_inclusive: max,
+            inner: layout,
+        }
+    }
+
+    /// For a virtual address, find and return the physical output address and corresponding
+    /// attributes.
+    ///
+    /// If the address is not found in `inner`, return an identity mapped default with normal
+    /// cacheable DRAM attributes. `None` is returned for addresses that must stay unmapped.
+    pub fn virt_addr_properties(
+        &self,
+        virt_addr: usize,
+    ) -> Result<Option<(usize, AttributeFields)>, &'static str> {
+        if virt_addr > self.max_virt_addr_inclusive {
+            return Err("Address out of range");
+        }
+
+        for i in self.inner.iter() {
+            if (i.virtual_range)().contains(&virt_addr) {
+                let output_addr = match i.physical_range_translation {
+                    Translation::Identity => virt_addr,
+                    Translation::Unmapped => return Ok(None),
+                };
+
+                return Ok(Some((output_addr, i.attribute_fields)));
+            }
+        }
+
+        Ok(Some((virt_addr, AttributeFields::default())))
+    }
+
+    /// Return the name of the special range containing the virtual address, if any.
+    pub fn region_name(&self, virt_addr: usize) -> Option<&'static str> {
+        self.inner
+            .iter()
+            .find(|i| (i.virtual_range)().contains(&virt_addr))
+            .map(|i| i.name)
+    }
+
+    /// Print the memory layout.
+    #[cfg(not(feature = "chainloader"))]
+    pub fn print_layout(&self) {
+        use crate::info;
+
+        for i in self.inner.iter() {
+            info!("{}", i);
+        }
+    }
+}
+
+/// Map a buffer that is shared with another bus master, e.g. the GPU's framebuffer, as
+/// non-cacheable normal memory.
+///
+/// The range is widened to full pages. It is cleaned from the data cache before the attributes
+/// change, so that nothing written through the cacheable mapping gets lost.
+///
+/// # Safety
+///
+/// - The buffer must not overlap memory that the kernel uses otherwise.
+#[cfg(not(feature = "chainloader"))]
+pub unsafe fn kernel_map_shared_buffer(
+    name: &'static str,
+    phys_start_addr: usize,
+    size: usize,
+) -> Result<(), &'static str> {
+    use crate::info;
+
+    use interface::MMU;
+
+    if size == 0 {
+        return Err("Empty buffer");
+    }
+
+    let page_size = arch_mmu::KernelGranule::SIZE;
+    let start = phys_start_addr & !(page_size - 1);
+    let end_exclusive = phys_start_addr
+        .checked_add(size + page_size - 1)
+        .ok_or("Buffer exceeds the address space")?
+        & !(page_size - 1);
+
+    let attribute_fields = AttributeFields {
+        mem_attributes: MemAttributes::NonCacheableDRAM,
+        acc_perms: AccessPermissions::ReadWrite,
+        execute_never: true,
+    };
+
+    super::cache::clean_and_invalidate_dcache_range(start, end_exclusive - start);
+    mmu().remap(start..=(end_exclusive - 1), &attribute_fields)?;
+
+    info!(
+        "{}",
+        DisplayRange(start, end_exclusive, &attribute_fields, name)
+    );
+
+    Ok(())
+}
+
+/// Invalidate the TLB entries of a range on all online cores.
+///
+/// Every core is asked to invalidate its own TLB with a cross-core call, and to synchronize its
+/// instruction stream afterwards. Returns once all cores are done.
+pub fn tlb_shootdown(virt_range: &RangeInclusive<usize>) {
+    cpu::smp::call_on_all(&|| arch_mmu::local_tlb_invalidate_range(virt_range));
+}
+
+/// Helper for printing a range that is not part of the static layout.
+struct DisplayRange<'a>(usize, usize, &'a AttributeFields, &'a str);
+
+impl fmt::Display for DisplayRange<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_range(f, self.0, self.1, self.2, self.3)
+    }
+}
@@ -0,0 +1,229 @@
+//! User address spaces.
+//!
+//! A user address space adds pages in the BSP's user window to the kernel's mappings. Each one
+//! gets its own ASID, so switching between them needs no TLB maintenance. Pages are backed by frames
+//! from the frame allocator, which are returned together with the address space.
+//!
+//! The kernel accesses user memory through the identity mapping of the backing frames, so reads
+//! and writes work no matter which address space is active and can't fault.
+
+use super::{arch_mmu, translation_table::UserTranslationTable, AttributeFields, UserSpaceToken};
+use crate::{
+    memory::{
+        cache,
+        frame::{self, frame_allocator},
+        mmu::AccessPermissions,
+    },
+    synchronization::{interface::Mutex, IRQSafeLock},
+};
+use alloc::{collections::BTreeMap, vec::Vec};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Order of the frame run that backs a page.
+const PAGE_ORDER: usize =
+    arch_mmu::KernelGranule::SHIFT - frame::FRAME_SIZE.trailing_zeros() as usize;
+
+const NUM_ASIDS: usize = arch_mmu::MAX_ASID as usize + 1;
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Size of a user page in bytes.
+pub const PAGE_SIZE: usize = arch_mmu::KernelGranule::SIZE;
+
+/// The pages of one user process.
+pub struct UserAddressSpace {
+    asid: u16,
+    tables: UserTranslationTable,
+
+    /// Mapped pages by virtual address, with their backing frames and attributes.
+    pages: BTreeMap<usize, (usize, AttributeFields)>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Global instances
+//--------------------------------------------------------------------------------------------------
+
+/// ASIDs in use. The kernel's ASID 0 is taken from the start.
+static ASIDS_IN_USE: IRQSafeLock<[bool; NUM_ASIDS]> = IRQSafeLock::new({
+    let mut in_use = [false; NUM_ASIDS];
+    in_use[0] = true;
+    in_use
+});
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+fn alloc_asid() -> Result<u16, &'static str> {
+    ASIDS_IN_USE.lock(|in_use| {
+        let asid = in_use.iter().position(|used| !used).ok_or("Out of ASIDs")?;
+        in_use[asid] = true;
+
+        Ok(asid as u16)
+    })
+}
+
+impl UserAddressSpace {
+    /// Split `len` bytes at `virt_addr` into chunks of the backing frames. Returns physical start
+    /// addresses and lengths.
+    fn phys_chunks(
+        &self,
+        virt_addr: usize,
+        len: usize,
+        need_user_write: bool,
+    ) -> Result<Vec<(usize, usize)>, &'static str> {
+        let end_exclusive = virt_addr.checked_add(len).ok_or("Range too large")?;
+
+        let mut chunks = Vec::new();
+        let mut addr = virt_addr;
+        while addr < end_exclusive {
+            let page = addr & !(PAGE_SIZE - 1);
+            let (frame, attribute_fields) = self.pages.get(&page).ok_or("Address is not mapped")?;
+
+            if need_user_write
+                && !matches!(attribute_fields.acc_perms, AccessPermissions::ReadWrite)
+            {
+                return Err("Page is read-only");
+            }
+
+            let chunk_len = (page + PAGE_SIZE).min(end_exclusive) - addr;
+            chunks.push((frame + (addr - page), chunk_len));
+            addr += chunk_len;
+        }
+
+        Ok(chunks)
+    }
+
+    fn copy_in(
+        &self,
+        virt_addr: usize,
+        data: &[u8],
+        need_user_write: bool,
+    ) -> Result<(), &'static str> {
+        let mut offset = 0;
+        for (phys, len) in self.phys_chunks(virt_addr, data.len(), need_user_write)? {
+            // The frames are identity mapped.
+            unsafe {
+                core::ptr::copy_nonoverlapping(data[offset..].as_ptr(), phys as *mut u8, len);
+            }
+            offset += len;
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for UserAddressSpace {
+    fn drop(&mut self) {
+        // The ASID could still have TLB entries that point to the frames freed below.
+        arch_mmu::tlb_invalidate_asid(self.asid);
+
+        for (frame, _) in self.pages.values() {
+            // Handed out by the frame allocator, freeing can't fail.
+            let _ = frame_allocator().free_contiguous(*frame, PAGE_ORDER);
+        }
+
+        ASIDS_IN_USE.lock(|in_use| in_use[self.asid as usize] = false);
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl UserAddressSpace {
+    /// Create an empty address space.
+    pub fn new() -> Result<Self, &'static str> {
+        let tables = arch_mmu::new_user_tables()?;
+        let asid = alloc_asid()?;
+
+        Ok(Self {
+            asid,
+            tables,
+            pages: BTreeMap::new(),
+        })
+    }
+
+    /// The token to activate this address space with, see `activate_user_space()`.
+    pub fn token(&self) -> UserSpaceToken {
+        UserSpaceToken {
+            phys_base: self.tables.phys_base_address(),
+            asid: self.asid,
+        }
+    }
+
+    /// Map zeroed pages for `size` bytes at `virt_addr`, which must be page aligned.
+    ///
+    /// The execute-never attribute applies to EL0. The kernel never executes user pages. On error,
+    /// the pages mapped so far stay mapped.
+    pub fn map_zeroed(
+        &mut self,
+        virt_addr: usize,
+        size: usize,
+        attribute_fields: &AttributeFields,
+    ) -> Result<(), &'static str> {
+        if virt_addr % PAGE_SIZE != 0 {
+            return Err("Address is not page aligned");
+        }
+
+        let end_exclusive = virt_addr
+            .checked_add(size)
+            .and_then(|end| end.checked_next_multiple_of(PAGE_SIZE))
+            .ok_or("Range too large")?;
+
+        for page in (virt_addr..end_exclusive).step_by(PAGE_SIZE) {
+            let frame = frame_allocator().alloc_contiguous(PAGE_ORDER)?;
+            unsafe { core::ptr::write_bytes(frame as *mut u8, 0, PAGE_SIZE) };
+
+            if let Err(x) = self.tables.map_page(page, frame, attribute_fields) {
+                let _ = frame_allocator().free_contiguous(frame, PAGE_ORDER);
+                return Err(x);
+            }
+
+            self.pages.insert(page, (frame, *attribute_fields));
+        }
+
+        Ok(())
+    }
+
+    /// Copy user memory at `virt_addr` into `buf`. All of it must be mapped.
+    pub fn read(&self, virt_addr: usize, buf: &mut [u8]) -> Result<(), &'static str> {
+        let mut offset = 0;
+        for (phys, len) in self.phys_chunks(virt_addr, buf.len(), false)? {
+            unsafe {
+                core::ptr::copy_nonoverlapping(phys as *const u8, buf[offset..].as_mut_ptr(), len);
+            }
+            offset += len;
+        }
+
+        Ok(())
+    }
+
+    /// Check that `len` bytes at `virt_addr` are mapped writable.
+    pub fn check_writable(&self, virt_addr: usize, len: usize) -> Result<(), &'static str> {
+        self.phys_chunks(virt_addr, len, true).map(|_| ())
+    }
+
+    /// Copy `data` to user memory at `virt_addr`. All of it must be mapped writable.
+    pub fn write(&self, virt_addr: usize, data: &[u8]) -> Result<(), &'static str> {
+        self.copy_in(virt_addr, data, true)
+    }
+
+    /// Copy code or data of a program to `virt_addr`, regardless of the pages' permissions.
+    ///
+    /// Instruction fetches see the new contents afterwards.
+    pub fn load(&self, virt_addr: usize, data: &[u8]) -> Result<(), &'static str> {
+        self.copy_in(virt_addr, data, false)?;
+
+        for (phys, len) in self.phys_chunks(virt_addr, data.len(), false)? {
+            cache::sync_icache_range(phys, len);
+        }
+
+        Ok(())
+    }
+}
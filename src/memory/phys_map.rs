@@ -0,0 +1,329 @@
+//! Physical memory map.
+//!
+//! Records which parts of physical RAM are free for the kernel to hand out and which are taken,
+//! e.g. by the kernel image, the VideoCore or the heap. The BSP fills in what the firmware
+//! reports during early boot. Consumers then claim their memory from the usable regions.
+
+use crate::{
+    common,
+    synchronization::{interface::ReadWriteEx, InitStateLock},
+};
+use core::fmt;
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+const MAX_REGIONS: usize = 16;
+
+struct PhysMemoryMapInner {
+    /// Sorted by start address, non-overlapping.
+    regions: [PhysRegion; MAX_REGIONS],
+    num_regions: usize,
+    ram_size: usize,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// What a physical memory region is used for.
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub enum RegionType {
+    Usable,
+    Reserved(&'static str),
+}
+
+/// A contiguous range of physical memory.
+#[derive(Copy, Clone)]
+pub struct PhysRegion {
+    pub start: usize,
+    pub size: usize,
+    pub region_type: RegionType,
+}
+
+/// The kernel's map of physical RAM.
+pub struct PhysMemoryMap {
+    inner: InitStateLock<PhysMemoryMapInner>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Global instances
+//--------------------------------------------------------------------------------------------------
+
+static PHYS_MEMORY_MAP: PhysMemoryMap = PhysMemoryMap::new();
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+impl PhysRegion {
+    const EMPTY: Self = Self {
+        start: 0,
+        size: 0,
+        region_type: RegionType::Usable,
+    };
+
+    fn end_exclusive(&self) -> usize {
+        self.start + self.size
+    }
+}
+
+impl PhysMemoryMapInner {
+    const fn new() -> Self {
+        Self {
+            regions: [PhysRegion::EMPTY; MAX_REGIONS],
+            num_regions: 0,
+            ram_size: 0,
+        }
+    }
+
+    fn regions(&self) -> &[PhysRegion] {
+        &self.regions[..self.num_regions]
+    }
+
+    fn insert_at(&mut self, index: usize, region: PhysRegion) -> Result<(), &'static str> {
+        if self.num_regions == MAX_REGIONS {
+            return Err("Physical memory map is full");
+        }
+
+        self.regions.copy_within(index..self.num_regions, index + 1);
+        self.regions[index] = region;
+        self.num_regions += 1;
+
+        Ok(())
+    }
+
+    fn remove_at(&mut self, index: usize) {
+        self.regions.copy_within(index + 1..self.num_regions, index);
+        self.num_regions -= 1;
+    }
+
+    fn add(&mut self, region: PhysRegion) -> Result<(), &'static str> {
+        if region.size == 0 {
+            return Err("Empty region");
+        }
+
+        let end_exclusive = region
+            .start
+            .checked_add(region.size)
+            .ok_or("Region exceeds the address space")?;
+
+        if self
+            .regions()
+            .iter()
+            .any(|r| region.start < r.end_exclusive() && r.start < end_exclusive)
+        {
+            return Err("Region overlaps an existing one");
+        }
+
+        let index = self.regions().partition_point(|r| r.start < region.start);
+        self.insert_at(index, region)?;
+        self.ram_size += region.size;
+
+        Ok(())
+    }
+
+    /// Carve `[start, start + size)` out of the usable region that fully contains it.
+    fn reserve(
+        &mut self,
+        start: usize,
+        size: usize,
+        name: &'static str,
+    ) -> Result<(), &'static str> {
+        let end_exclusive = start
+            .checked_add(size)
+            .ok_or("Region exceeds the address space")?;
+
+        let index = self
+            .regions()
+            .iter()
+            .position(|r| {
+                r.region_type == RegionType::Usable
+                    && r.start <= start
+                    && end_exclusive <= r.end_exclusive()
+            })
+            .ok_or("Range is not inside a single usable region")?;
+
+        let usable = self.regions[index];
+        let head = PhysRegion {
+            start: usable.start,
+            size: start - usable.start,
+            region_type: RegionType::Usable,
+        };
+        let tail = PhysRegion {
+            start: end_exclusive,
+            size: usable.end_exclusive() - end_exclusive,
+            region_type: RegionType::Usable,
+        };
+
+        // Make sure that the split cannot fail halfway through.
+        let new_regions = [head, tail].iter().filter(|r| r.size > 0).count();
+        if self.num_regions + new_regions > MAX_REGIONS {
+            return Err("Physical memory map is full");
+        }
+
+        self.remove_at(index);
+        let mut index = index;
+        for region in [
+            head,
+            PhysRegion {
+                start,
+                size,
+                region_type: RegionType::Reserved(name),
+            },
+            tail,
+        ] {
+            if region.size > 0 {
+                self.insert_at(index, region)?;
+                index += 1;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn claim(
+        &mut self,
+        max_size: usize,
+        align: usize,
+        name: &'static str,
+    ) -> Result<PhysRegion, &'static str> {
+        let candidate = self
+            .regions()
+            .iter()
+            .filter(|r| r.region_type == RegionType::Usable)
+            .filter_map(|r| {
+                let start = r.start.checked_add(align - 1)? & !(align - 1);
+                let available = r.end_exclusive().checked_sub(start)? & !(align - 1);
+
+                (available > 0).then_some((start, available))
+            })
+            .max_by_key(|(_, available)| *available);
+
+        let (start, available) = candidate.ok_or("No usable memory left")?;
+
+        // Keep the size a multiple of the alignment, like the start.
+        let size = max_size.min(available) & !(align - 1);
+        if size == 0 {
+            return Err("Requested size is smaller than the alignment");
+        }
+
+        self.reserve(start, size, name)?;
+
+        Ok(PhysRegion {
+            start,
+            size,
+            region_type: RegionType::Reserved(name),
+        })
+    }
+}
+
+impl PhysMemoryMap {
+    const fn new() -> Self {
+        Self {
+            inner: InitStateLock::new(PhysMemoryMapInner::new()),
+        }
+    }
+}
+
+impl fmt::Display for PhysRegion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (size, unit) = common::size_human_readable_ceil(self.size);
+        let name = match self.region_type {
+            RegionType::Usable => "Usable",
+            RegionType::Reserved(name) => name,
+        };
+
+        write!(
+            f,
+            "      {:#010x} - {:#010x} | {: >4} {: <4} | {}",
+            self.start,
+            self.end_exclusive(),
+            size,
+            unit,
+            name
+        )
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Return a reference to the kernel's physical memory map.
+pub fn phys_memory_map() -> &'static PhysMemoryMap {
+    &PHYS_MEMORY_MAP
+}
+
+impl PhysMemoryMap {
+    /// Add a region of RAM. It must not overlap any region that is already known.
+    pub fn add(
+        &self,
+        start: usize,
+        size: usize,
+        region_type: RegionType,
+    ) -> Result<(), &'static str> {
+        self.inner.write(|inner| {
+            inner.add(PhysRegion {
+                start,
+                size,
+                region_type,
+            })
+        })
+    }
+
+    /// Mark a fixed range of usable RAM as taken.
+    pub fn reserve(
+        &self,
+        start: usize,
+        size: usize,
+        name: &'static str,
+    ) -> Result<(), &'static str> {
+        self.inner.write(|inner| inner.reserve(start, size, name))
+    }
+
+    /// Take up to `max_size` bytes from the largest usable region. `align` must be a power of two
+    /// and applies to both start and size.
+    pub fn claim(
+        &self,
+        max_size: usize,
+        align: usize,
+        name: &'static str,
+    ) -> Result<PhysRegion, &'static str> {
+        if !align.is_power_of_two() {
+            return Err("Alignment is not a power of two");
+        }
+
+        self.inner.write(|inner| inner.claim(max_size, align, name))
+    }
+
+    /// Call `f` for every region that is still usable.
+    #[cfg(not(feature = "chainloader"))]
+    pub fn for_each_usable(&self, mut f: impl FnMut(&PhysRegion)) {
+        self.inner.read(|inner| {
+            inner
+                .regions()
+                .iter()
+                .filter(|r| r.region_type == RegionType::Usable)
+                .for_each(&mut f)
+        })
+    }
+
+    /// Total size of all RAM known to the map, usable or not.
+    pub fn ram_size(&self) -> usize {
+        self.inner.read(|inner| inner.ram_size)
+    }
+
+    /// Print the map.
+    #[cfg(not(feature = "chainloader"))]
+    pub fn print(&self) {
+        use crate::info;
+
+        self.inner.read(|inner| {
+            for region in inner.regions() {
+                info!("{}", region);
+            }
+        });
+    }
+}
@@ -4,13 +4,29 @@
 
 //! A panic handler that infinitely waits.
 
-use crate::{cpu, println};
-use core::panic::PanicInfo;
+use crate::{bsp, cpu};
+use core::{fmt, panic::PanicInfo};
 
 //--------------------------------------------------------------------------------------------------
 // Private Code
 //--------------------------------------------------------------------------------------------------
 
+/// Print through the lock-free panic console.
+fn _panic_print(args: fmt::Arguments) {
+    use fmt::Write;
+
+    unsafe { bsp::driver::panic_console_out().write_fmt(args).unwrap() };
+}
+
+/// Prints with a newline - only use from the panic handler.
+///
+/// Carbon copy from <https://doc.rust-lang.org/src/std/macros.rs.html>
+macro_rules! panic_println {
+    ($($arg:tt)*) => ({
+        _panic_print(format_args_nl!($($arg)*));
+    })
+}
+
 /// Stop immediately if called a second time.
 ///
 /// # Note
@@ -45,13 +61,16 @@ fn panic(info: &PanicInfo) -> ! {
     // Protect against panic infinite loops if any of the following code panics itself.
     panic_prevent_reenter();
 
+    // Keep the other cores from running on in a possibly broken kernel.
+    cpu::smp::stop_other_cores();
+
     let timestamp = crate::time::time_manager().uptime();
     let (location, line, column) = match info.location() {
         Some(loc) => (loc.file(), loc.line(), loc.column()),
         _ => ("???", 0, 0),
     };
 
-    println!(
+    panic_println!(
         "[  {:>3}.{:06}] Kernel panic!\n\n\
         Panic location:\n      File '{}', line {}, column {}\n\n\
         {}",
@@ -63,5 +82,9 @@ fn panic(info: &PanicInfo) -> ! {
         info.message().unwrap_or(&format_args!("")),
     );
 
+    // Heap corruption is a common cause of panics. Report it while the evidence is fresh.
+    #[cfg(feature = "heap_debug")]
+    let _ = crate::memory::heap_alloc::kernel_heap_allocator().check_integrity();
+
     cpu::wait_forever()
 }
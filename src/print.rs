@@ -4,8 +4,26 @@
 
 //! Printing.
 
-use crate::console::{copy_console, interface::Write};
-use core::fmt;
+use crate::{
+    console::{copy_console, interface::Write},
+    time,
+    time::calendar::DateTime,
+};
+use core::{fmt, time::Duration};
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Timestamp prefix of log messages.
+///
+/// Shows the uptime, or the UTC date and time if the `utc_timestamps` feature is enabled and the
+/// wall clock has been set.
+#[doc(hidden)]
+pub struct Timestamp {
+    uptime: Duration,
+    utc: Option<DateTime>,
+}
 
 //--------------------------------------------------------------------------------------------------
 // Public Code
@@ -16,6 +34,44 @@ pub fn _print(args: fmt::Arguments) {
     copy_console::console_manger().write_fmt(args).unwrap();
 }
 
+#[doc(hidden)]
+pub fn _timestamp() -> Timestamp {
+    let utc = if cfg!(feature = "utc_timestamps") {
+        time::wall_clock().now()
+    } else {
+        None
+    };
+
+    Timestamp {
+        uptime: time::time_manager().uptime(),
+        utc,
+    }
+}
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.utc {
+            Some(dt) => write!(
+                f,
+                "{:04}-{:02}-{:02} {:02}:{:02}:{:02}.{:06}",
+                dt.year,
+                dt.month,
+                dt.day,
+                dt.hour,
+                dt.minute,
+                dt.second,
+                dt.nanosecond / 1000
+            ),
+            None => write!(
+                f,
+                "{:>3}.{:06}",
+                self.uptime.as_secs(),
+                self.uptime.subsec_micros()
+            ),
+        }
+    }
+}
+
 /// Prints without a newline.
 ///
 /// Carbon copy from <https://doc.rust-lang.org/src/std/macros.rs.html>
@@ -39,21 +95,15 @@ macro_rules! println {
 #[macro_export]
 macro_rules! info {
     ($string:expr) => ({
-        let timestamp = $crate::time::time_manager().uptime();
-
         $crate::print::_print(format_args_nl!(
-            concat!("[  {:>3}.{:06}] ", $string),
-            timestamp.as_secs(),
-            timestamp.subsec_micros(),
+            concat!("[  {}] ", $string),
+            $crate::print::_timestamp(),
         ));
     });
     ($format_string:expr, $($arg:tt)*) => ({
-        let timestamp = $crate::time::time_manager().uptime();
-
         $crate::print::_print(format_args_nl!(
-            concat!("[  {:>3}.{:06}] ", $format_string),
-            timestamp.as_secs(),
-            timestamp.subsec_micros(),
+            concat!("[  {}] ", $format_string),
+            $crate::print::_timestamp(),
             $($arg)*
         ));
     })
@@ -63,21 +113,15 @@ macro_rules! info {
 #[macro_export]
 macro_rules! warn {
     ($string:expr) => ({
-        let timestamp = $crate::time::time_manager().uptime();
-
         $crate::print::_print(format_args_nl!(
-            concat!("[W {:>3}.{:06}] ", $string),
-            timestamp.as_secs(),
-            timestamp.subsec_micros(),
+            concat!("[W {}] ", $string),
+            $crate::print::_timestamp(),
         ));
     });
     ($format_string:expr, $($arg:tt)*) => ({
-        let timestamp = $crate::time::time_manager().uptime();
-
         $crate::print::_print(format_args_nl!(
-            concat!("[W {:>3}.{:06}] ", $format_string),
-            timestamp.as_secs(),
-            timestamp.subsec_micros(),
+            concat!("[W {}] ", $format_string),
+            $crate::print::_timestamp(),
             $($arg)*
         ));
     })
@@ -88,23 +132,17 @@ macro_rules! warn {
 macro_rules! debug {
     ($string:expr) => ({
         if cfg!(feature = "debug_prints") {
-            let timestamp = $crate::time::time_manager().uptime();
-
             $crate::print::_print(format_args_nl!(
-                concat!("<[>D {:>3}.{:06}> ", $string),
-                timestamp.as_secs(),
-                timestamp.subsec_micros(),
+                concat!("<[>D {}> ", $string),
+                $crate::print::_timestamp(),
             ));
         }
     });
     ($format_string:expr, $($arg:tt)*) => ({
         if cfg!(feature = "debug_prints") {
-            let timestamp = $crate::time::time_manager().uptime();
-
             $crate::print::_print(format_args_nl!(
-                concat!("<D {:>3}.{:06}> ", $format_string),
-                timestamp.as_secs(),
-                timestamp.subsec_micros(),
+                concat!("<D {}> ", $format_string),
+                $crate::print::_timestamp(),
                 $($arg)*
             ));
         }
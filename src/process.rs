@@ -0,0 +1,592 @@
+//! User processes.
+//!
+//! A process runs a program in EL0, in a user address space of its own. Each process is backed by
+//! a kernel thread. The thread enters the program, and it handles the program's syscalls and
+//! exceptions on its kernel stack. It is scheduled like any other thread, so processes are
+//! preempted by the timer tick and block in syscalls.
+//!
+//! The process table keeps all processes until their exit status is collected with `wait()`. A
+//! process that faults is killed. The kernel and the other processes keep running.
+//!
+//! Programs are either raw, position independent machine code, or statically linked ELF
+//! executables. The latter can be embedded into the kernel, see `embedded_programs()`, or pushed
+//! over the console with `console::transfer::receive()`.
+//!
+//! # User memory layout
+//!
+//! Inside the BSP's user window, see `bsp::memory::mmu::user_range_inclusive()`:
+//!
+//! - The program in the lower half of the window. A raw image is put at the start of the window
+//!   and execution begins at its first byte. ELF segments are put at their own addresses, with the
+//!   permissions from their flags.
+//! - Pages from `mmap`, growing upwards from the middle of the window.
+//! - The stack, one page at the end of the window, with an unmapped page below.
+//!
+//! # Program start
+//!
+//! ELF executables start with argc, argv and envp in the first three argument registers. The stack
+//! is laid out like on other Unix systems: argc, the argv pointers, NULL, the envp pointers, NULL
+//! and an empty auxiliary vector, followed by the strings. Raw images start with all registers
+//! cleared and an empty stack.
+//!
+//! # Syscalls
+//!
+//! The syscall number and up to six arguments are passed in registers, see the arch code. Results
+//! are non-negative on success and a negated `SyscallError` otherwise.
+//!
+//! | Nr | Name       | Arguments | Result                                         |
+//! |----|------------|-----------|------------------------------------------------|
+//! | 0  | `exit`     | code      | Does not return.                               |
+//! | 1  | `write`    | buf, len  | Bytes written to the console.                  |
+//! | 2  | `read`     | buf, len  | Bytes read from the console, up to a newline.  |
+//! | 3  | `sleep`    | ms        | 0                                              |
+//! | 4  | `get_time` |           | Uptime in nanoseconds.                         |
+//! | 5  | `mmap`     | len       | Address of `len` bytes of zeroed memory.       |
+//!
+//! # Note
+//!
+//! Thread switches don't save the FP/SIMD registers. They stay disabled for EL0, so a process that
+//! uses them is killed.
+
+#[cfg(target_arch = "aarch64")]
+#[path = "_arch/aarch64/process.rs"]
+mod arch_process;
+
+mod elf;
+
+use crate::{
+    bsp, console,
+    memory::mmu::{AccessPermissions, AttributeFields, MemAttributes, UserAddressSpace, PAGE_SIZE},
+    print,
+    synchronization::{interface::Mutex, IRQSafeLock, WaitQueue},
+    thread::{self, ThreadId},
+    time, warn,
+};
+use alloc::{string::String, vec, vec::Vec};
+use core::{
+    fmt,
+    ops::Range,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Architectural Public Reexports
+//--------------------------------------------------------------------------------------------------
+#[cfg(feature = "demos")]
+pub use arch_process::demo_programs;
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Syscall numbers.
+mod syscall_nr {
+    pub const EXIT: u64 = 0;
+    pub const WRITE: u64 = 1;
+    pub const READ: u64 = 2;
+    pub const SLEEP: u64 = 3;
+    pub const GET_TIME: u64 = 4;
+    pub const MMAP: u64 = 5;
+}
+
+/// The most bytes that one `write` or `read` transfers.
+const MAX_IO_SIZE: usize = 4096;
+
+/// The most stack space that arguments and environment may take up at program start.
+const MAX_ARGS_SIZE: usize = PAGE_SIZE / 4;
+
+/// Why a syscall failed. The process gets the negated value.
+#[derive(Copy, Clone)]
+#[repr(i64)]
+enum SyscallError {
+    NoSuchSyscall = 1,
+    BadAddress = 2,
+    OutOfMemory = 3,
+    InvalidArgument = 4,
+}
+
+enum ProcessState {
+    Running,
+    Ended(ExitStatus),
+}
+
+/// Register state that a program starts with.
+#[derive(Copy, Clone)]
+struct StartState {
+    entry: usize,
+    stack_pointer: usize,
+    args: [usize; 3],
+}
+
+struct Process {
+    pid: Pid,
+    name: &'static str,
+    thread: ThreadId,
+    state: ProcessState,
+
+    /// `None` once the process has ended.
+    memory: Option<UserAddressSpace>,
+    start: StartState,
+
+    /// Where the next `mmap` goes.
+    mmap_next: usize,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Identifies a process.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Pid(usize);
+
+/// How a process ended.
+#[derive(Copy, Clone)]
+pub enum ExitStatus {
+    /// The process called `exit` with this code.
+    Exited(i64),
+
+    /// The process was killed because of a fault.
+    Killed,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Global instances
+//--------------------------------------------------------------------------------------------------
+
+static PROCESSES: IRQSafeLock<Vec<Process>> = IRQSafeLock::new(Vec::new());
+
+/// Notified whenever a process ends.
+static ENDED: WaitQueue = WaitQueue::new();
+
+static NEXT_PID: AtomicUsize = AtomicUsize::new(1);
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+fn user_start() -> usize {
+    *bsp::memory::mmu::user_range_inclusive().start()
+}
+
+fn mmap_start() -> usize {
+    let user_range = bsp::memory::mmu::user_range_inclusive();
+
+    user_start() + (user_range.end() - user_start() + 1) / 2
+}
+
+fn stack_end_exclusive() -> usize {
+    bsp::memory::mmu::user_range_inclusive().end() + 1
+}
+
+/// The first address above the `mmap` area. One page below the stack is left unmapped.
+fn mmap_end_exclusive() -> usize {
+    stack_end_exclusive() - 2 * PAGE_SIZE
+}
+
+/// Run `f` on the process that the running thread belongs to.
+fn with_current<R>(f: impl FnOnce(&mut Process) -> R) -> R {
+    let thread = thread::current_id();
+
+    PROCESSES.lock(|processes| {
+        let process = processes
+            .iter_mut()
+            .find(|p| p.thread == thread)
+            .expect("Not running in a process");
+
+        f(process)
+    })
+}
+
+/// Where the thread of a process begins.
+fn enter_current() {
+    let (token, start) = with_current(|p| {
+        // Cannot fail, the memory is only taken away when the process ends.
+        (p.memory.as_ref().unwrap().token(), p.start)
+    });
+
+    // The address space is dropped by the thread itself, after it switched back to the kernel's.
+    unsafe { thread::set_user_space(Some(token)) };
+
+    arch_process::enter_user(start.entry, start.stack_pointer, start.args)
+}
+
+/// End the process of the running thread. Releases its memory and wakes up `wait()`.
+fn end_current(status: ExitStatus) -> ! {
+    let memory = with_current(|p| {
+        p.state = ProcessState::Ended(status);
+        p.memory.take()
+    });
+
+    unsafe { thread::set_user_space(None) };
+    drop(memory);
+
+    ENDED.notify_all();
+    thread::exit()
+}
+
+fn sys_write(buf: usize, len: usize) -> Result<i64, SyscallError> {
+    let mut data = vec![0; len.min(MAX_IO_SIZE)];
+
+    with_current(|p| p.memory.as_ref().unwrap().read(buf, &mut data))
+        .map_err(|_| SyscallError::BadAddress)?;
+
+    print!("{}", String::from_utf8_lossy(&data));
+
+    Ok(data.len() as i64)
+}
+
+fn sys_read(buf: usize, len: usize) -> Result<i64, SyscallError> {
+    use console::interface::Read;
+
+    let len = len.min(MAX_IO_SIZE);
+
+    // Fail before any input is consumed.
+    with_current(|p| p.memory.as_ref().unwrap().check_writable(buf, len))
+        .map_err(|_| SyscallError::BadAddress)?;
+
+    let mut data = Vec::with_capacity(len);
+    while data.len() < len {
+        let c = console::console_manger().read_char();
+        data.push(if c.is_ascii() { c as u8 } else { b'?' });
+
+        if c == '\n' {
+            break;
+        }
+    }
+
+    // The process is blocked in here, so its memory can't have changed in the meantime.
+    with_current(|p| p.memory.as_ref().unwrap().write(buf, &data))
+        .map_err(|_| SyscallError::BadAddress)?;
+
+    Ok(data.len() as i64)
+}
+
+fn sys_mmap(len: usize) -> Result<i64, SyscallError> {
+    let size = len
+        .checked_next_multiple_of(PAGE_SIZE)
+        .filter(|size| *size > 0)
+        .ok_or(SyscallError::InvalidArgument)?;
+
+    let attribute_fields = AttributeFields {
+        mem_attributes: MemAttributes::CacheableDRAM,
+        acc_perms: AccessPermissions::ReadWrite,
+        execute_never: true,
+    };
+
+    with_current(|p| {
+        let addr = p.mmap_next;
+        if size > mmap_end_exclusive() - addr {
+            return Err(SyscallError::OutOfMemory);
+        }
+
+        // Advanced on error as well. Pages that did get mapped stay with the process until it ends.
+        p.mmap_next += size;
+        p.memory
+            .as_mut()
+            .unwrap()
+            .map_zeroed(addr, size, &attribute_fields)
+            .map_err(|_| SyscallError::OutOfMemory)?;
+
+        Ok(addr as i64)
+    })
+}
+
+fn map_stack(memory: &mut UserAddressSpace) -> Result<(), &'static str> {
+    let stack = AttributeFields {
+        mem_attributes: MemAttributes::CacheableDRAM,
+        acc_perms: AccessPermissions::ReadWrite,
+        execute_never: true,
+    };
+
+    memory.map_zeroed(stack_end_exclusive() - PAGE_SIZE, PAGE_SIZE, &stack)
+}
+
+/// Build the address space for a program image.
+fn load_image(image: &[u8]) -> Result<(UserAddressSpace, StartState), &'static str> {
+    if image.is_empty() || image.len() > mmap_start() - user_start() {
+        return Err("Program image is empty or too large");
+    }
+
+    let mut memory = UserAddressSpace::new()?;
+
+    let code = AttributeFields {
+        mem_attributes: MemAttributes::CacheableDRAM,
+        acc_perms: AccessPermissions::ReadOnly,
+        execute_never: false,
+    };
+    memory.map_zeroed(user_start(), image.len(), &code)?;
+    memory.load(user_start(), image)?;
+    map_stack(&mut memory)?;
+
+    let start = StartState {
+        entry: user_start(),
+        stack_pointer: stack_end_exclusive(),
+        args: [0; 3],
+    };
+
+    Ok((memory, start))
+}
+
+/// Build the address space for an ELF executable and put the arguments on its stack.
+fn load_elf(
+    data: &[u8],
+    argv: &[&str],
+    envp: &[&str],
+) -> Result<(UserAddressSpace, StartState), &'static str> {
+    let executable = elf::Executable::parse(data)?;
+    let mut memory = UserAddressSpace::new()?;
+
+    // Page ranges of the segments loaded so far.
+    let mut loaded: Vec<Range<usize>> = Vec::new();
+
+    for segment in &executable.segments {
+        let end_exclusive = segment
+            .virt_addr
+            .checked_add(segment.mem_size)
+            .filter(|end| segment.virt_addr >= user_start() && *end <= mmap_start())
+            .ok_or("Segment is outside of the program area")?;
+
+        let pages = (segment.virt_addr & !(PAGE_SIZE - 1))
+            ..end_exclusive
+                .checked_next_multiple_of(PAGE_SIZE)
+                .ok_or("Segment is outside of the program area")?;
+
+        // Pages have a single set of permissions.
+        if loaded
+            .iter()
+            .any(|other| other.start < pages.end && pages.start < other.end)
+        {
+            return Err("Segments share a page");
+        }
+
+        let attribute_fields = AttributeFields {
+            mem_attributes: MemAttributes::CacheableDRAM,
+            acc_perms: if segment.writable {
+                AccessPermissions::ReadWrite
+            } else {
+                AccessPermissions::ReadOnly
+            },
+            execute_never: !segment.executable,
+        };
+        memory.map_zeroed(pages.start, pages.len(), &attribute_fields)?;
+        memory.load(segment.virt_addr, segment.data)?;
+
+        loaded.push(pages);
+    }
+
+    let entry = executable.entry;
+    if !executable
+        .segments
+        .iter()
+        .any(|s| s.executable && (s.virt_addr..s.virt_addr + s.mem_size).contains(&entry))
+    {
+        return Err("Entry point is not in an executable segment");
+    }
+
+    map_stack(&mut memory)?;
+    let (stack_pointer, args) = push_args(&memory, argv, envp)?;
+
+    let start = StartState {
+        entry,
+        stack_pointer,
+        args,
+    };
+
+    Ok((memory, start))
+}
+
+/// Put arguments and environment on the stack of a new process, see the module documentation.
+/// Returns the stack pointer and argc, argv and envp.
+fn push_args(
+    memory: &UserAddressSpace,
+    argv: &[&str],
+    envp: &[&str],
+) -> Result<(usize, [usize; 3]), &'static str> {
+    const WORD_SIZE: usize = core::mem::size_of::<u64>();
+
+    let strings = || argv.iter().chain(envp.iter());
+    if strings().any(|s| s.contains('\0')) {
+        return Err("Argument contains a NUL byte");
+    }
+
+    // argc, both arrays with their NULL and an AT_NULL entry as the auxiliary vector.
+    let num_words = 1 + (argv.len() + 1) + (envp.len() + 1) + 2;
+    let strings_size: usize = strings().map(|s| s.len() + 1).sum();
+
+    let size = num_words * WORD_SIZE + strings_size;
+    if size > MAX_ARGS_SIZE {
+        return Err("Arguments are too large");
+    }
+
+    let stack_pointer = (stack_end_exclusive() - size) & !0xf;
+    let mut string_addr = stack_pointer + num_words * WORD_SIZE;
+
+    let mut words = Vec::with_capacity(num_words);
+    let mut string_data = Vec::with_capacity(strings_size);
+    words.push(argv.len());
+    for list in [argv, envp] {
+        for s in list {
+            words.push(string_addr);
+            string_data.extend_from_slice(s.as_bytes());
+            string_data.push(0);
+            string_addr += s.len() + 1;
+        }
+        words.push(0);
+    }
+    words.extend_from_slice(&[0, 0]);
+
+    let mut data: Vec<u8> = words
+        .iter()
+        .flat_map(|w| (*w as u64).to_le_bytes())
+        .collect();
+    data.extend_from_slice(&string_data);
+    memory.write(stack_pointer, &data)?;
+
+    let argv_addr = stack_pointer + WORD_SIZE;
+    let envp_addr = argv_addr + (argv.len() + 1) * WORD_SIZE;
+
+    Ok((stack_pointer, [argv.len(), argv_addr, envp_addr]))
+}
+
+/// Start a process for a loaded program.
+fn spawn_loaded(name: &'static str, memory: UserAddressSpace, start: StartState) -> Pid {
+    let pid = Pid(NEXT_PID.fetch_add(1, Ordering::Relaxed));
+
+    // The thread looks itself up in the table, so it must be in there before it runs. The lock
+    // keeps IRQs masked, so it can't be switched in before.
+    PROCESSES.lock(|processes| {
+        let thread = thread::spawn(name, enter_current);
+
+        processes.push(Process {
+            pid,
+            name,
+            thread,
+            state: ProcessState::Running,
+            memory: Some(memory),
+            start,
+            mmap_next: mmap_start(),
+        })
+    });
+
+    pid
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl fmt::Display for Pid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl fmt::Display for ExitStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExitStatus::Exited(code) => write!(f, "exited with {}", code),
+            ExitStatus::Killed => write!(f, "killed"),
+        }
+    }
+}
+
+/// Start a process that runs the position independent machine code in `image`.
+pub fn spawn(name: &'static str, image: &[u8]) -> Result<Pid, &'static str> {
+    let (memory, start) = load_image(image)?;
+
+    Ok(spawn_loaded(name, memory, start))
+}
+
+/// Start a process that runs the ELF executable in `data`, with the given arguments and
+/// environment.
+pub fn spawn_elf(
+    name: &'static str,
+    data: &[u8],
+    argv: &[&str],
+    envp: &[&str],
+) -> Result<Pid, &'static str> {
+    let (memory, start) = load_elf(data, argv, envp)?;
+
+    Ok(spawn_loaded(name, memory, start))
+}
+
+/// ELF executables that are built together with the kernel, from `user/`. Returns names and
+/// files.
+#[cfg(feature = "demos")]
+pub fn embedded_programs() -> [(&'static str, &'static [u8]); 1] {
+    [("args", include_bytes!(concat!(env!("OUT_DIR"), "/args")))]
+}
+
+/// Block until the process has ended, then remove it from the process table.
+pub fn wait(pid: Pid) -> Result<ExitStatus, &'static str> {
+    let mut status = Err("No such process");
+
+    ENDED.wait_until(|| {
+        PROCESSES.lock(|processes| {
+            let index = match processes.iter().position(|p| p.pid == pid) {
+                Some(index) => index,
+                None => return true,
+            };
+
+            match processes[index].state {
+                ProcessState::Running => false,
+                ProcessState::Ended(s) => {
+                    processes.swap_remove(index);
+                    status = Ok(s);
+                    true
+                }
+            }
+        })
+    });
+
+    status
+}
+
+/// Handle a syscall of the running process. Returns the value for the process.
+pub fn handle_syscall(number: u64, args: [u64; 6]) -> i64 {
+    let result = match number {
+        syscall_nr::EXIT => end_current(ExitStatus::Exited(args[0] as i64)),
+        syscall_nr::WRITE => sys_write(args[0] as usize, args[1] as usize),
+        syscall_nr::READ => sys_read(args[0] as usize, args[1] as usize),
+        syscall_nr::SLEEP => {
+            thread::sleep(Duration::from_millis(args[0]));
+            Ok(0)
+        }
+        syscall_nr::GET_TIME => Ok(time::time_manager().uptime().as_nanos() as i64),
+        syscall_nr::MMAP => sys_mmap(args[0] as usize),
+        _ => Err(SyscallError::NoSuchSyscall),
+    };
+
+    result.unwrap_or_else(|error| -(error as i64))
+}
+
+/// Kill the process of the running thread after it faulted.
+pub fn kill_current(reason: &dyn fmt::Display) -> ! {
+    let (pid, name) = with_current(|p| (p.pid, p.name));
+    warn!("Process {} ({}) killed: {}", pid, name, reason);
+
+    end_current(ExitStatus::Killed)
+}
+
+/// Print the process table.
+#[cfg(not(feature = "chainloader"))]
+pub fn print_processes() {
+    use crate::info;
+
+    PROCESSES.lock(|processes| {
+        for process in processes.iter() {
+            match process.state {
+                ProcessState::Running => info!(
+                    "      {: >3} | {: <10} | running",
+                    process.pid, process.name
+                ),
+                ProcessState::Ended(status) => info!(
+                    "      {: >3} | {: <10} | {}",
+                    process.pid, process.name, status
+                ),
+            }
+        }
+    })
+}
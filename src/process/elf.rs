@@ -0,0 +1,167 @@
+//! ELF64 executables.
+//!
+//! Only what's needed to load statically linked AArch64 executables is looked at: the file header
+//! and the `PT_LOAD` entries of the program header table. Sections are ignored.
+
+use alloc::vec::Vec;
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+const MAGIC: [u8; 4] = *b"\x7fELF";
+const CLASS_64: u8 = 2;
+const DATA_LITTLE_ENDIAN: u8 = 1;
+const VERSION_CURRENT: u8 = 1;
+const TYPE_EXEC: u16 = 2;
+const MACHINE_AARCH64: u16 = 183;
+
+const FILE_HEADER_SIZE: usize = 64;
+const PROGRAM_HEADER_SIZE: usize = 56;
+
+const PT_LOAD: u32 = 1;
+const PT_DYNAMIC: u32 = 2;
+const PT_INTERP: u32 = 3;
+
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// A part of the program that is loaded into memory.
+pub struct Segment<'a> {
+    pub virt_addr: usize,
+
+    /// At least as large as `data`. The rest is zeroed.
+    pub mem_size: usize,
+    pub data: &'a [u8],
+    pub writable: bool,
+    pub executable: bool,
+}
+
+/// A validated executable.
+pub struct Executable<'a> {
+    pub entry: usize,
+
+    /// Only non-empty segments, in file order.
+    pub segments: Vec<Segment<'a>>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+fn u16_at(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(data[offset..offset + 2].try_into().unwrap())
+}
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn usize_at(data: &[u8], offset: usize) -> usize {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap()) as usize
+}
+
+fn check_file_header(data: &[u8]) -> Result<(), &'static str> {
+    if data.len() < FILE_HEADER_SIZE || data[0..4] != MAGIC {
+        return Err("Not an ELF file");
+    }
+
+    if data[4] != CLASS_64 || data[5] != DATA_LITTLE_ENDIAN || data[6] != VERSION_CURRENT {
+        return Err("Not a little endian ELF64 file");
+    }
+
+    if u16_at(data, 16) != TYPE_EXEC {
+        return Err("Not an executable");
+    }
+
+    if u16_at(data, 18) != MACHINE_AARCH64 {
+        return Err("Not an AArch64 executable");
+    }
+
+    if u16_at(data, 54) as usize != PROGRAM_HEADER_SIZE {
+        return Err("Unexpected program header size");
+    }
+
+    Ok(())
+}
+
+/// Parse the program header at `offset`. Returns `None` for entries that don't need loading.
+fn parse_program_header(data: &[u8], offset: usize) -> Result<Option<Segment>, &'static str> {
+    let header = &data[offset..offset + PROGRAM_HEADER_SIZE];
+
+    match u32_at(header, 0) {
+        PT_LOAD => (),
+        PT_DYNAMIC | PT_INTERP => return Err("Dynamically linked executables are not supported"),
+        _ => return Ok(None),
+    }
+
+    let flags = u32_at(header, 4);
+    let file_offset = usize_at(header, 8);
+    let virt_addr = usize_at(header, 16);
+    let file_size = usize_at(header, 32);
+    let mem_size = usize_at(header, 40);
+
+    if file_size > mem_size {
+        return Err("Segment is larger in the file than in memory");
+    }
+
+    let data = file_offset
+        .checked_add(file_size)
+        .and_then(|end| data.get(file_offset..end))
+        .ok_or("Segment exceeds the file")?;
+
+    if mem_size == 0 {
+        return Ok(None);
+    }
+
+    Ok(Some(Segment {
+        virt_addr,
+        mem_size,
+        data,
+        writable: flags & PF_W != 0,
+        executable: flags & PF_X != 0,
+    }))
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl<'a> Executable<'a> {
+    /// Check the headers of the ELF file in `data` and collect its loadable segments.
+    ///
+    /// Where the segments go is not checked.
+    pub fn parse(data: &'a [u8]) -> Result<Self, &'static str> {
+        check_file_header(data)?;
+
+        let table_offset = usize_at(data, 32);
+        let table_len = u16_at(data, 56) as usize * PROGRAM_HEADER_SIZE;
+        if table_offset
+            .checked_add(table_len)
+            .filter(|end| *end <= data.len())
+            .is_none()
+        {
+            return Err("Program header table exceeds the file");
+        }
+
+        let mut segments = Vec::new();
+        for offset in (table_offset..table_offset + table_len).step_by(PROGRAM_HEADER_SIZE) {
+            if let Some(segment) = parse_program_header(data, offset)? {
+                segments.push(segment);
+            }
+        }
+
+        if segments.is_empty() {
+            return Err("Nothing to load");
+        }
+
+        Ok(Self {
+            entry: usize_at(data, 24),
+            segments,
+        })
+    }
+}
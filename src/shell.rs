@@ -0,0 +1,474 @@
+//! Interactive kernel shell.
+//!
+//! Runs in a thread of its own and reads commands from the console, see `line_editor` for the
+//! supported keys. Type `help` for the list of commands. Numbers can be given in decimal or, with a
+//! `0x` prefix, in hex.
+//!
+//! The commands poke at the hardware directly. `poke` and `gpio` in particular can disturb the
+//! drivers that use the same registers or pins.
+
+mod line_editor;
+
+use crate::{
+    block, bsp, console, driver, executor,
+    memory::{heap_alloc::kernel_heap_allocator, mmu::MemAttributes},
+    print, println, process, thread, time,
+    time::{calendar::DateTime, TimeSource},
+};
+use alloc::{sync::Arc, vec, vec::Vec};
+use core::{
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+use line_editor::LineEditor;
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+const PROMPT: &str = "rpos> ";
+
+struct Command {
+    name: &'static str,
+    args: &'static str,
+    help: &'static str,
+    run: fn(&[&str]) -> Result<(), &'static str>,
+}
+
+const COMMANDS: &[Command] = &[
+    Command {
+        name: "help",
+        args: "",
+        help: "List the commands",
+        run: cmd_help,
+    },
+    Command {
+        name: "drivers",
+        args: "",
+        help: "List the loaded drivers",
+        run: |_| {
+            driver::driver_manager().enumerate();
+            Ok(())
+        },
+    },
+    Command {
+        name: "heap",
+        args: "",
+        help: "Show the kernel heap usage",
+        run: |_| {
+            kernel_heap_allocator().print_usage();
+            Ok(())
+        },
+    },
+    #[cfg(feature = "heap_debug")]
+    Command {
+        name: "heapcheck",
+        args: "",
+        help: "Check the live heap allocations for corruption",
+        run: |_| kernel_heap_allocator().check_integrity(),
+    },
+    Command {
+        name: "threads",
+        args: "",
+        help: "List the threads",
+        run: |_| {
+            thread::print_threads();
+            Ok(())
+        },
+    },
+    Command {
+        name: "ps",
+        args: "",
+        help: "List the processes",
+        run: |_| {
+            process::print_processes();
+            Ok(())
+        },
+    },
+    Command {
+        name: "uptime",
+        args: "",
+        help: "Show the time since boot and the wall clock",
+        run: cmd_uptime,
+    },
+    Command {
+        name: "date",
+        args: "[YYYY-MM-DD HH:MM:SS | @unix]",
+        help: "Show or set the wall clock, in UTC",
+        run: cmd_date,
+    },
+    Command {
+        name: "after",
+        args: "<seconds> <text>",
+        help: "Print the text after a while, in the background",
+        run: cmd_after,
+    },
+    Command {
+        name: "revision",
+        args: "",
+        help: "Show the board revision code",
+        run: |_| {
+            let revision = executor::block_on(bsp::driver::MAILBOX.board_revision())?;
+            println!("{:#x}", revision);
+            Ok(())
+        },
+    },
+    Command {
+        name: "peek",
+        args: "<addr>",
+        help: "Read a 32 bit MMIO register",
+        run: cmd_peek,
+    },
+    Command {
+        name: "poke",
+        args: "<addr> <value>",
+        help: "Write a 32 bit MMIO register",
+        run: cmd_poke,
+    },
+    Command {
+        name: "gpio",
+        args: "<pin> read|high|low|toggle",
+        help: "Read a pin, or make it an output and drive it",
+        run: cmd_gpio,
+    },
+    Command {
+        name: "gpiopull",
+        args: "<pin> up|down|off",
+        help: "Set the pull resistor of a pin",
+        run: cmd_gpio_pull,
+    },
+    Command {
+        name: "gpiowatch",
+        args: "<pin> <event> [seconds]",
+        help: "Count rising, falling, high or low events on a pin",
+        run: cmd_gpio_watch,
+    },
+    Command {
+        name: "mbox",
+        args: "<tag> [value...]",
+        help: "Send a mailbox property tag and show the response",
+        run: cmd_mbox,
+    },
+    Command {
+        name: "blockread",
+        args: "<device> <block>",
+        help: "Dump a block of a block device, e.g. of sd0",
+        run: cmd_block_read,
+    },
+    Command {
+        name: "run",
+        args: "<device> [arg...]",
+        help: "Run the program on a block device, an ELF file or plain code",
+        run: cmd_run,
+    },
+    Command {
+        name: "reboot",
+        args: "",
+        help: "Reset the board",
+        run: |_| bsp::reset(),
+    },
+];
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+/// Parse a decimal or `0x` prefixed hex number.
+fn parse_number(arg: &str) -> Result<usize, &'static str> {
+    let result = match arg.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => arg.parse(),
+    };
+
+    result.map_err(|_| "Invalid number")
+}
+
+fn parse_u32(arg: &str) -> Result<u32, &'static str> {
+    u32::try_from(parse_number(arg)?).map_err(|_| "Number does not fit into 32 bits")
+}
+
+/// Parse an address and check that it is a register in the device MMIO range.
+fn parse_mmio_address(arg: &str) -> Result<usize, &'static str> {
+    let addr = parse_number(arg)?;
+    if addr % core::mem::size_of::<u32>() != 0 {
+        return Err("Address is not 4 byte aligned");
+    }
+
+    match bsp::memory::mmu::virt_mem_layout().virt_addr_properties(addr) {
+        Ok(Some((_, attribute_fields)))
+            if matches!(attribute_fields.mem_attributes, MemAttributes::Device) =>
+        {
+            Ok(addr)
+        }
+        _ => Err("Not a device MMIO address"),
+    }
+}
+
+fn cmd_help(_args: &[&str]) -> Result<(), &'static str> {
+    for command in COMMANDS {
+        let usage = [command.name, command.args].join(" ");
+        println!("  {: <36} {}", usage, command.help);
+    }
+
+    Ok(())
+}
+
+fn cmd_uptime(_args: &[&str]) -> Result<(), &'static str> {
+    let uptime = time::time_manager().uptime();
+    println!(
+        "Up for {}.{:03} s",
+        uptime.as_secs(),
+        uptime.subsec_millis()
+    );
+
+    if let (Some(now), Some(source)) = (time::wall_clock().now(), time::wall_clock().source()) {
+        println!("Wall clock: {:?}, {} (from {})", now.weekday(), now, source);
+    }
+
+    Ok(())
+}
+
+fn cmd_date(args: &[&str]) -> Result<(), &'static str> {
+    if !args.is_empty() {
+        let date_time: DateTime = args.join(" ").parse()?;
+        time::wall_clock().set_date_time(&date_time, TimeSource::Console)?;
+    }
+
+    match (time::wall_clock().now(), time::wall_clock().source()) {
+        (Some(now), Some(source)) => println!("{:?}, {} (from {})", now.weekday(), now, source),
+        _ => println!("Wall clock not set"),
+    }
+
+    Ok(())
+}
+
+fn cmd_after(args: &[&str]) -> Result<(), &'static str> {
+    let [seconds, text @ ..] = args else {
+        return Err("Expected the seconds and a text");
+    };
+    let seconds = parse_number(seconds)? as u64;
+    let mut text = text.join(" ");
+    text.push('\n');
+
+    // Runs as a task, so the shell takes the next command right away.
+    executor::spawn(async move {
+        time::delay(Duration::from_secs(seconds)).await;
+        console::write_str(console::console_manger(), &text).await;
+    });
+
+    Ok(())
+}
+
+fn cmd_peek(args: &[&str]) -> Result<(), &'static str> {
+    let [addr] = args else {
+        return Err("Expected an address");
+    };
+    let addr = parse_mmio_address(addr)?;
+
+    // The address is mapped as device memory.
+    let value = unsafe { core::ptr::read_volatile(addr as *const u32) };
+    println!("{:#010x}: {:#010x}", addr, value);
+
+    Ok(())
+}
+
+fn cmd_poke(args: &[&str]) -> Result<(), &'static str> {
+    let [addr, value] = args else {
+        return Err("Expected an address and a value");
+    };
+    let addr = parse_mmio_address(addr)?;
+    let value = parse_u32(value)?;
+
+    // The address is mapped as device memory.
+    unsafe { core::ptr::write_volatile(addr as *mut u32, value) };
+
+    Ok(())
+}
+
+fn print_pin(pin: &bsp::driver::Pin) {
+    println!(
+        "GPIO {}: {:?}, {:?}",
+        pin.number(),
+        pin.function(),
+        pin.read()
+    );
+}
+
+fn cmd_gpio(args: &[&str]) -> Result<(), &'static str> {
+    use bsp::driver::{Function, Level};
+
+    let [pin, action] = args else {
+        return Err("Expected a pin and an action");
+    };
+
+    // Pins that a driver holds can't be claimed.
+    let pin = bsp::driver::GPIO.claim(parse_number(pin)?)?;
+
+    // The pin keeps its function and level after it is released.
+    match *action {
+        "read" => (),
+        "high" | "low" | "toggle" => {
+            pin.set_function(Function::Output);
+
+            match *action {
+                "high" => pin.write(Level::High),
+                "low" => pin.write(Level::Low),
+                _ => pin.toggle(),
+            }
+        }
+        _ => return Err("Unknown action"),
+    }
+
+    print_pin(&pin);
+    Ok(())
+}
+
+fn cmd_gpio_pull(args: &[&str]) -> Result<(), &'static str> {
+    use bsp::driver::Pull;
+
+    let [pin, pull] = args else {
+        return Err("Expected a pin and a pull");
+    };
+    let pin = bsp::driver::GPIO.claim(parse_number(pin)?)?;
+
+    pin.set_pull(match *pull {
+        "up" => Pull::Up,
+        "down" => Pull::Down,
+        "off" => Pull::Off,
+        _ => return Err("Unknown pull"),
+    });
+
+    print_pin(&pin);
+    Ok(())
+}
+
+/// Count the events on a pin for a while, 10 seconds by default. Level events are one-shot, so they
+/// count at most once.
+fn cmd_gpio_watch(args: &[&str]) -> Result<(), &'static str> {
+    use bsp::driver::Event;
+
+    let (pin, event, seconds) = match args {
+        [pin, event] => (pin, event, 10),
+        [pin, event, seconds] => (pin, event, parse_number(seconds)?),
+        _ => return Err("Expected a pin, an event and optionally the seconds"),
+    };
+    let event = match *event {
+        "rising" => Event::RisingEdge,
+        "falling" => Event::FallingEdge,
+        "high" => Event::HighLevel,
+        "low" => Event::LowLevel,
+        _ => return Err("Unknown event"),
+    };
+    let pin = bsp::driver::GPIO.claim(parse_number(pin)?)?;
+
+    let count = Arc::new(AtomicUsize::new(0));
+    let counter = count.clone();
+    pin.register_callback(Duration::from_millis(10), move |_, _| {
+        counter.fetch_add(1, Ordering::Relaxed);
+    });
+    pin.enable_event(event);
+
+    thread::sleep(Duration::from_secs(seconds as u64));
+
+    pin.disable_event(event);
+    pin.unregister_callback();
+
+    println!(
+        "GPIO {}: {} events",
+        pin.number(),
+        count.load(Ordering::Relaxed)
+    );
+
+    Ok(())
+}
+
+fn cmd_mbox(args: &[&str]) -> Result<(), &'static str> {
+    let (tag, values) = args.split_first().ok_or("Expected a tag")?;
+    let tag = parse_u32(tag)?;
+    let values = values
+        .iter()
+        .map(|value| parse_u32(value))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let response = executor::block_on(bsp::driver::MAILBOX.property_tag(tag, &values))?;
+    for (i, value) in response.iter().enumerate() {
+        println!("  [{}] {:#010x}", i, value);
+    }
+
+    Ok(())
+}
+
+fn cmd_block_read(args: &[&str]) -> Result<(), &'static str> {
+    let [name, first_block] = args else {
+        return Err("Expected a device and a block number");
+    };
+    let device = block::device(name).ok_or("Unknown device")?;
+
+    let mut buf = vec![0; device.block_size()];
+    device.read_blocks(parse_number(first_block)? as u64, &mut buf)?;
+
+    for (i, line) in buf.chunks(16).enumerate() {
+        print!("{:#06x}:", i * 16);
+        for byte in line {
+            print!(" {:02x}", byte);
+        }
+        println!();
+    }
+
+    Ok(())
+}
+
+fn cmd_run(args: &[&str]) -> Result<(), &'static str> {
+    const MAX_PROGRAM_SIZE: usize = 1024 * 1024;
+
+    let name = args.first().ok_or("Expected a device")?;
+    let device = block::device(name).ok_or("Unknown device")?;
+
+    // Without a file system, the program is the start of the device.
+    let size = (device.num_blocks() * device.block_size() as u64).min(MAX_PROGRAM_SIZE as u64);
+    let mut data = vec![0; size as usize - size as usize % device.block_size()];
+    device.read_blocks(0, &mut data)?;
+
+    let pid = if data.starts_with(b"\x7fELF") {
+        process::spawn_elf("run", &data, args, &[])?
+    } else {
+        process::spawn("run", &data)?
+    };
+    println!("Process {} {}", pid, process::wait(pid)?);
+
+    Ok(())
+}
+
+fn execute(line: &str) {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let (name, args) = match words.split_first() {
+        Some(split) => split,
+        None => return,
+    };
+
+    match COMMANDS.iter().find(|command| command.name == *name) {
+        Some(command) => {
+            if let Err(x) = (command.run)(args) {
+                println!("{}: {}", name, x);
+            }
+        }
+        None => println!("{}: Unknown command, try help", name),
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Read and execute commands forever.
+///
+/// Must run in a thread. Nothing else may read from the console.
+pub fn run() -> ! {
+    let mut editor = LineEditor::new(PROMPT);
+
+    println!("Kernel shell ready, type help for a list of commands");
+    loop {
+        let line = editor.read_line();
+        execute(&line);
+    }
+}
@@ -0,0 +1,215 @@
+//! Line editing for the shell.
+//!
+//! Understands the keys that terminals like miniterm send: cursor movement with the arrow keys,
+//! Home/End and Ctrl-A/Ctrl-E, Backspace and Delete, Ctrl-U to clear the line, Ctrl-C to drop it,
+//! and Up/Down to step through the history. Only ASCII input is taken, anything else is ignored.
+//!
+//! The line is redrawn after every change, so the terminal only needs to understand carriage
+//! return, "erase to end of line" and "cursor left".
+
+use crate::{console, executor, print, println};
+use alloc::{collections::VecDeque, string::String};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Lines kept in the history.
+const HISTORY_SIZE: usize = 32;
+
+const CTRL_A: char = '\u{1}';
+const CTRL_C: char = '\u{3}';
+const CTRL_E: char = '\u{5}';
+const CTRL_U: char = '\u{15}';
+const BACKSPACE: char = '\u{8}';
+const DELETE: char = '\u{7f}';
+const ESCAPE: char = '\u{1b}';
+
+enum Key {
+    Char(char),
+    Enter,
+    Backspace,
+    Delete,
+    Left,
+    Right,
+    Home,
+    End,
+    Up,
+    Down,
+    ClearLine,
+    Cancel,
+    Ignored,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Reads lines from the console.
+pub struct LineEditor {
+    prompt: &'static str,
+    history: VecDeque<String>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+// Parks the shell thread until the console has a character for it.
+fn read_char() -> char {
+    executor::block_on(console::read_char(console::console_manger()))
+}
+
+/// Read one key press, including the rest of its escape sequence.
+fn read_key() -> Key {
+    match read_char() {
+        '\n' => Key::Enter,
+        BACKSPACE | DELETE => Key::Backspace,
+        CTRL_A => Key::Home,
+        CTRL_E => Key::End,
+        CTRL_U => Key::ClearLine,
+        CTRL_C => Key::Cancel,
+        ESCAPE => read_escape_sequence(),
+        c if c.is_ascii_graphic() || c == ' ' => Key::Char(c),
+        _ => Key::Ignored,
+    }
+}
+
+/// The part of a CSI sequence after the escape character, e.g. "[A" for Up or "[3~" for Delete.
+fn read_escape_sequence() -> Key {
+    if read_char() != '[' {
+        return Key::Ignored;
+    }
+
+    match read_char() {
+        'A' => Key::Up,
+        'B' => Key::Down,
+        'C' => Key::Right,
+        'D' => Key::Left,
+        'H' => Key::Home,
+        'F' => Key::End,
+        c @ '0'..='9' => {
+            // Parameters up to the final '~'.
+            let mut parameter = c;
+            let mut next = read_char();
+            while next.is_ascii_digit() {
+                parameter = next;
+                next = read_char();
+            }
+
+            match (parameter, next) {
+                ('1', '~') => Key::Home,
+                ('3', '~') => Key::Delete,
+                ('4', '~') => Key::End,
+                _ => Key::Ignored,
+            }
+        }
+        _ => Key::Ignored,
+    }
+}
+
+impl LineEditor {
+    /// Print the prompt and the line, then put the cursor where it belongs.
+    fn redraw(&self, line: &str, cursor: usize) {
+        print!("\r{}{}\x1b[K", self.prompt, line);
+
+        let back = line.len() - cursor;
+        if back > 0 {
+            print!("\x1b[{}D", back);
+        }
+    }
+
+    fn add_to_history(&mut self, line: &str) {
+        if line.is_empty() || self.history.back().map(String::as_str) == Some(line) {
+            return;
+        }
+
+        if self.history.len() == HISTORY_SIZE {
+            self.history.pop_front();
+        }
+        self.history.push_back(line.into());
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl LineEditor {
+    /// Create an instance.
+    pub fn new(prompt: &'static str) -> Self {
+        Self {
+            prompt,
+            history: VecDeque::new(),
+        }
+    }
+
+    /// Read a line, with the prompt in front. Returns it without the newline.
+    ///
+    /// Must be called from a thread, it blocks until the line is complete.
+    pub fn read_line(&mut self) -> String {
+        let mut line = String::new();
+        let mut cursor = 0;
+
+        // Index into the history while stepping through it, and the line that was being typed.
+        let mut history_index = self.history.len();
+        let mut typed = String::new();
+
+        self.redraw(&line, cursor);
+
+        loop {
+            match read_key() {
+                Key::Char(c) => {
+                    line.insert(cursor, c);
+                    cursor += 1;
+                }
+                Key::Enter => break,
+                Key::Backspace if cursor > 0 => {
+                    cursor -= 1;
+                    line.remove(cursor);
+                }
+                Key::Delete if cursor < line.len() => {
+                    line.remove(cursor);
+                }
+                Key::Left if cursor > 0 => cursor -= 1,
+                Key::Right if cursor < line.len() => cursor += 1,
+                Key::Home => cursor = 0,
+                Key::End => cursor = line.len(),
+                Key::Up if history_index > 0 => {
+                    if history_index == self.history.len() {
+                        typed = core::mem::take(&mut line);
+                    }
+
+                    history_index -= 1;
+                    line = self.history[history_index].clone();
+                    cursor = line.len();
+                }
+                Key::Down if history_index < self.history.len() => {
+                    history_index += 1;
+                    line = match self.history.get(history_index) {
+                        Some(entry) => entry.clone(),
+                        None => core::mem::take(&mut typed),
+                    };
+                    cursor = line.len();
+                }
+                Key::ClearLine => {
+                    line.clear();
+                    cursor = 0;
+                }
+                Key::Cancel => {
+                    println!("^C");
+                    line.clear();
+                    cursor = 0;
+                }
+                _ => continue,
+            }
+
+            self.redraw(&line, cursor);
+        }
+
+        println!();
+        self.add_to_history(&line);
+
+        line
+    }
+}
@@ -4,13 +4,39 @@
 
 //! Synchronization primitives.
 //!
+//! The locks in this file spin. The ones in the submodules block the calling thread instead and
+//! let the scheduler run others, see [`WaitQueue`].
+//!
 //! # Resources
 //!
 //!   - <https://doc.rust-lang.org/book/ch16-04-extensible-concurrency-sync-and-send.html>
 //!   - <https://stackoverflow.com/questions/59428096/understanding-the-send-trait>
 //!   - <https://doc.rust-lang.org/std/cell/index.html>
 
-use core::cell::UnsafeCell;
+// Only the demos pass values between threads so far.
+#[cfg(feature = "demos")]
+pub mod channel;
+#[cfg(not(feature = "chainloader"))]
+mod semaphore;
+mod wait_queue;
+
+#[cfg(not(feature = "chainloader"))]
+pub use semaphore::{Semaphore, SleepLock};
+pub use wait_queue::WaitQueue;
+
+use crate::{exception, state};
+use core::{
+    cell::UnsafeCell,
+    hint,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// [`RwLock`] state while a writer holds the lock.
+const RWLOCK_WRITER: u32 = u32::MAX;
 
 //--------------------------------------------------------------------------------------------------
 // Public Definitions
@@ -28,16 +54,72 @@ pub mod interface {
         /// Locks the mutex and grants the closure temporary mutable access to the wrapped data.
         fn lock<'a, R>(&'a self, f: impl FnOnce(&'a mut Self::Data) -> R) -> R;
     }
+
+    /// A reader-writer exclusion type.
+    ///
+    /// The implementing object allows either a number of readers or at most one writer at any point
+    /// in time.
+    pub trait ReadWriteEx {
+        /// The type of encapsulated data.
+        type Data;
+
+        /// Grants temporary mutable access to the encapsulated data.
+        fn write<'a, R>(&'a self, f: impl FnOnce(&'a mut Self::Data) -> R) -> R;
+
+        /// Grants temporary immutable access to the encapsulated data.
+        fn read<'a, R>(&'a self, f: impl FnOnce(&'a Self::Data) -> R) -> R;
+    }
+}
+
+/// A ticket spinlock.
+///
+/// Every locker draws a ticket and spins until it is served, so the lock is handed out in FIFO
+/// order and no core can starve.
+///
+/// The lock does not mask IRQs. It must not be taken by code that can run in IRQ context, else an
+/// IRQ handler spinning on a lock that is held by the interrupted code deadlocks the core. Use
+/// [`IRQSafeLock`] for such data.
+///
+/// Atomics need the MMU and caching to be enabled, so the lock must not be used before that.
+pub struct SpinLock<T>
+where
+    T: ?Sized,
+{
+    next_ticket: AtomicU32,
+    now_serving: AtomicU32,
+    data: UnsafeCell<T>,
 }
 
-/// A pseudo-lock for teaching purposes.
+/// A [`SpinLock`] that additionally masks IRQs on the executing core while the lock is held.
 ///
-/// In contrast to a real Mutex implementation, does not protect against concurrent access from
-/// other cores to the contained data. This part is preserved for later lessons.
+/// Safe to use for data that is shared with IRQ handlers.
+pub struct IRQSafeLock<T>
+where
+    T: ?Sized,
+{
+    inner: SpinLock<T>,
+}
+
+/// A reader-writer spinlock for read-mostly data.
+///
+/// Any number of readers may hold the lock at the same time. A writer waits until all readers are
+/// gone. IRQs are masked while the write lock is held, so readers in IRQ context can not deadlock
+/// against a writer on the same core.
+pub struct RwLock<T>
+where
+    T: ?Sized,
+{
+    /// Number of readers, or `RWLOCK_WRITER` if a writer holds the lock.
+    state: AtomicU32,
+    data: UnsafeCell<T>,
+}
+
+/// A pseudo-lock that is RW during the single-core kernel init phase and RO afterwards.
 ///
-/// The lock will only be used as long as it is safe to do so, i.e. as long as the kernel is
-/// executing single-threaded, aka only running on a single core with interrupts disabled.
-pub struct NullLock<T>
+/// Intended to encapsulate data that is populated during kernel init when no concurrency exists.
+/// Reads are free of any atomics, which makes this the cheapest choice for registries that never
+/// change once the kernel is up. Use [`RwLock`] if the data must be written later on.
+pub struct InitStateLock<T>
 where
     T: ?Sized,
 {
@@ -48,10 +130,102 @@ where
 // Public Code
 //--------------------------------------------------------------------------------------------------
 
-unsafe impl<T> Send for NullLock<T> where T: ?Sized + Send {}
-unsafe impl<T> Sync for NullLock<T> where T: ?Sized + Send {}
+unsafe impl<T> Send for SpinLock<T> where T: ?Sized + Send {}
+unsafe impl<T> Sync for SpinLock<T> where T: ?Sized + Send {}
+
+impl<T> SpinLock<T> {
+    /// Create an instance.
+    pub const fn new(data: T) -> Self {
+        Self {
+            next_ticket: AtomicU32::new(0),
+            now_serving: AtomicU32::new(0),
+            data: UnsafeCell::new(data),
+        }
+    }
+}
+
+impl<T: ?Sized> SpinLock<T> {
+    fn acquire(&self) {
+        let ticket = self.next_ticket.fetch_add(1, Ordering::Relaxed);
+
+        while self.now_serving.load(Ordering::Acquire) != ticket {
+            hint::spin_loop();
+        }
+    }
+
+    fn release(&self) {
+        // Only the lock holder writes `now_serving`, so no read-modify-write is needed.
+        let next = self.now_serving.load(Ordering::Relaxed).wrapping_add(1);
+        self.now_serving.store(next, Ordering::Release);
+    }
+}
+
+unsafe impl<T> Send for IRQSafeLock<T> where T: ?Sized + Send {}
+unsafe impl<T> Sync for IRQSafeLock<T> where T: ?Sized + Send {}
+
+impl<T> IRQSafeLock<T> {
+    /// Create an instance.
+    pub const fn new(data: T) -> Self {
+        Self {
+            inner: SpinLock::new(data),
+        }
+    }
+}
+
+unsafe impl<T> Send for RwLock<T> where T: ?Sized + Send {}
+unsafe impl<T> Sync for RwLock<T> where T: ?Sized + Send + Sync {}
+
+impl<T> RwLock<T> {
+    /// Create an instance.
+    pub const fn new(data: T) -> Self {
+        Self {
+            state: AtomicU32::new(0),
+            data: UnsafeCell::new(data),
+        }
+    }
+}
+
+impl<T: ?Sized> RwLock<T> {
+    fn acquire_read(&self) {
+        loop {
+            let state = self.state.load(Ordering::Relaxed);
+
+            if state < RWLOCK_WRITER - 1
+                && self
+                    .state
+                    .compare_exchange_weak(state, state + 1, Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+            {
+                return;
+            }
+
+            hint::spin_loop();
+        }
+    }
+
+    fn release_read(&self) {
+        self.state.fetch_sub(1, Ordering::Release);
+    }
+
+    fn acquire_write(&self) {
+        while self
+            .state
+            .compare_exchange_weak(0, RWLOCK_WRITER, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            hint::spin_loop();
+        }
+    }
+
+    fn release_write(&self) {
+        self.state.store(0, Ordering::Release);
+    }
+}
+
+unsafe impl<T> Send for InitStateLock<T> where T: ?Sized + Send {}
+unsafe impl<T> Sync for InitStateLock<T> where T: ?Sized + Send {}
 
-impl<T> NullLock<T> {
+impl<T> InitStateLock<T> {
     /// Create an instance.
     pub const fn new(data: T) -> Self {
         Self {
@@ -64,14 +238,85 @@ impl<T> NullLock<T> {
 // OS Interface Code
 //------------------------------------------------------------------------------
 
-impl<T> interface::Mutex for NullLock<T> {
+impl<T> interface::Mutex for SpinLock<T> {
+    type Data = T;
+
+    fn lock<'a, R>(&'a self, f: impl FnOnce(&'a mut Self::Data) -> R) -> R {
+        self.acquire();
+
+        // The ticket guarantees that this mutable reference is only given out once at a time.
+        let data = unsafe { &mut *self.data.get() };
+        let ret = f(data);
+
+        self.release();
+
+        ret
+    }
+}
+
+impl<T> interface::Mutex for IRQSafeLock<T> {
     type Data = T;
 
     fn lock<'a, R>(&'a self, f: impl FnOnce(&'a mut Self::Data) -> R) -> R {
-        // In a real lock, there would be code encapsulating this line that ensures that this
-        // mutable reference will ever only be given out once at a time.
+        // Mask IRQs before taking the lock, so that an IRQ handler on this core can not spin on
+        // the lock while it is held.
+        exception::asynchronous::exec_with_irq_masked(|| self.inner.lock(f))
+    }
+}
+
+impl<T> interface::ReadWriteEx for RwLock<T> {
+    type Data = T;
+
+    fn write<'a, R>(&'a self, f: impl FnOnce(&'a mut Self::Data) -> R) -> R {
+        exception::asynchronous::exec_with_irq_masked(|| {
+            self.acquire_write();
+
+            let data = unsafe { &mut *self.data.get() };
+            let ret = f(data);
+
+            self.release_write();
+
+            ret
+        })
+    }
+
+    fn read<'a, R>(&'a self, f: impl FnOnce(&'a Self::Data) -> R) -> R {
+        // Like for writers. A writer on another core spins until the read lock is dropped, so an
+        // IRQ handler on this core must not stretch the time it is held.
+        exception::asynchronous::exec_with_irq_masked(|| {
+            self.acquire_read();
+
+            let data = unsafe { &*self.data.get() };
+            let ret = f(data);
+
+            self.release_read();
+
+            ret
+        })
+    }
+}
+
+impl<T> interface::ReadWriteEx for InitStateLock<T> {
+    type Data = T;
+
+    fn write<'a, R>(&'a self, f: impl FnOnce(&'a mut Self::Data) -> R) -> R {
+        assert!(
+            state::state_manager().is_init(),
+            "InitStateLock::write called after kernel init phase"
+        );
+        assert!(
+            exception::asynchronous::is_local_irq_masked(),
+            "InitStateLock::write called with IRQs unmasked"
+        );
+
         let data = unsafe { &mut *self.data.get() };
 
         f(data)
     }
+
+    fn read<'a, R>(&'a self, f: impl FnOnce(&'a Self::Data) -> R) -> R {
+        let data = unsafe { &*self.data.get() };
+
+        f(data)
+    }
 }
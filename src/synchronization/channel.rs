@@ -0,0 +1,188 @@
+//! Bounded multi-producer, single-consumer channels.
+//!
+//! Senders block while the channel is full, the receiver blocks while it is empty. IRQ handlers
+//! must use `try_send()`, which never blocks.
+
+use super::{interface::Mutex, wait_queue::WaitQueue, IRQSafeLock};
+use alloc::{collections::VecDeque, sync::Arc};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+struct ChannelInner<T> {
+    queue: VecDeque<T>,
+    num_senders: usize,
+    receiver_alive: bool,
+}
+
+struct Channel<T> {
+    inner: IRQSafeLock<ChannelInner<T>>,
+    capacity: usize,
+    not_empty: WaitQueue,
+    not_full: WaitQueue,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// The sending half of a channel. Can be cloned to get more producers.
+pub struct Sender<T> {
+    channel: Arc<Channel<T>>,
+}
+
+/// The receiving half of a channel.
+pub struct Receiver<T> {
+    channel: Arc<Channel<T>>,
+}
+
+/// Why a value could not be sent. The value is handed back.
+pub enum SendError<T> {
+    /// The channel is full. Only returned by `try_send()`.
+    Full(T),
+
+    /// The receiver is gone.
+    Disconnected(T),
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+impl<T> Channel<T> {
+    fn try_send(&self, value: T) -> Result<(), SendError<T>> {
+        self.inner.lock(|inner| {
+            if !inner.receiver_alive {
+                return Err(SendError::Disconnected(value));
+            }
+
+            if inner.queue.len() == self.capacity {
+                return Err(SendError::Full(value));
+            }
+
+            inner.queue.push_back(value);
+            Ok(())
+        })?;
+
+        self.not_empty.notify_one();
+        Ok(())
+    }
+
+    /// Returns `None` if the channel is empty, `Some(None)` if it is also disconnected.
+    fn try_recv(&self) -> Option<Option<T>> {
+        let value = self.inner.lock(|inner| match inner.queue.pop_front() {
+            Some(value) => Some(Some(value)),
+            None if inner.num_senders == 0 => Some(None),
+            None => None,
+        });
+
+        if let Some(Some(_)) = value {
+            self.not_full.notify_one();
+        }
+
+        value
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Create a channel that buffers up to `capacity` values.
+pub fn channel<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(capacity > 0, "Channel capacity must not be zero");
+
+    let channel = Arc::new(Channel {
+        inner: IRQSafeLock::new(ChannelInner {
+            queue: VecDeque::with_capacity(capacity),
+            num_senders: 1,
+            receiver_alive: true,
+        }),
+        capacity,
+        not_empty: WaitQueue::new(),
+        not_full: WaitQueue::new(),
+    });
+
+    (
+        Sender {
+            channel: channel.clone(),
+        },
+        Receiver { channel },
+    )
+}
+
+impl<T> Sender<T> {
+    /// Send a value, blocking while the channel is full.
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        let mut value = Some(value);
+        let mut result = Ok(());
+
+        self.channel.not_full.wait_until(|| {
+            // Cannot fail, the value is put back whenever the condition returns false.
+            match self.channel.try_send(value.take().unwrap()) {
+                Err(SendError::Full(v)) => {
+                    value = Some(v);
+                    false
+                }
+                x => {
+                    result = x;
+                    true
+                }
+            }
+        });
+
+        result
+    }
+
+    /// Send a value if there is room. Never blocks, so it can be used in IRQ context.
+    pub fn try_send(&self, value: T) -> Result<(), SendError<T>> {
+        self.channel.try_send(value)
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.channel.inner.lock(|inner| inner.num_senders += 1);
+
+        Self {
+            channel: self.channel.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        self.channel.inner.lock(|inner| inner.num_senders -= 1);
+
+        // The receiver might wait for a value that never comes now.
+        self.channel.not_empty.notify_all();
+    }
+}
+
+impl<T> Receiver<T> {
+    /// Receive a value, blocking while the channel is empty.
+    ///
+    /// Returns `None` once the channel is empty and all senders are gone.
+    pub fn recv(&self) -> Option<T> {
+        let mut value = None;
+
+        self.channel.not_empty.wait_until(|| {
+            value = self.channel.try_recv();
+            value.is_some()
+        });
+
+        value.flatten()
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.channel
+            .inner
+            .lock(|inner| inner.receiver_alive = false);
+
+        // Blocked senders would wait forever otherwise.
+        self.channel.not_full.notify_all();
+    }
+}
@@ -0,0 +1,102 @@
+//! Counting semaphores and a mutex that sleeps instead of spinning.
+
+use super::{
+    interface::{self, Mutex},
+    wait_queue::WaitQueue,
+    IRQSafeLock,
+};
+use core::cell::UnsafeCell;
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// A counting semaphore.
+///
+/// `acquire()` blocks the thread while the count is zero. `release()` never blocks and may be
+/// called from IRQ context, e.g. to signal a thread that data has arrived.
+pub struct Semaphore {
+    count: IRQSafeLock<usize>,
+    waiters: WaitQueue,
+}
+
+/// A lock that blocks the thread while it is taken by another one.
+///
+/// Unlike [`super::SpinLock`], waiting does not burn the CPU and the lock may be held across
+/// `thread::sleep()` or other blocking calls. It must not be used in IRQ context.
+pub struct SleepLock<T>
+where
+    T: ?Sized,
+{
+    available: Semaphore,
+    data: UnsafeCell<T>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl Semaphore {
+    /// Create an instance with `count` permits.
+    pub const fn new(count: usize) -> Self {
+        Self {
+            count: IRQSafeLock::new(count),
+            waiters: WaitQueue::new(),
+        }
+    }
+
+    /// Take a permit if one is available. Never blocks.
+    pub fn try_acquire(&self) -> bool {
+        self.count.lock(|count| {
+            let available = *count > 0;
+            if available {
+                *count -= 1;
+            }
+
+            available
+        })
+    }
+
+    /// Take a permit, blocking until one is available.
+    pub fn acquire(&self) {
+        self.waiters.wait_until(|| self.try_acquire())
+    }
+
+    /// Return a permit and wake up a waiter.
+    pub fn release(&self) {
+        self.count.lock(|count| *count += 1);
+        self.waiters.notify_one();
+    }
+}
+
+unsafe impl<T> Send for SleepLock<T> where T: ?Sized + Send {}
+unsafe impl<T> Sync for SleepLock<T> where T: ?Sized + Send {}
+
+impl<T> SleepLock<T> {
+    /// Create an instance.
+    pub const fn new(data: T) -> Self {
+        Self {
+            available: Semaphore::new(1),
+            data: UnsafeCell::new(data),
+        }
+    }
+}
+
+//------------------------------------------------------------------------------
+// OS Interface Code
+//------------------------------------------------------------------------------
+
+impl<T> interface::Mutex for SleepLock<T> {
+    type Data = T;
+
+    fn lock<'a, R>(&'a self, f: impl FnOnce(&'a mut Self::Data) -> R) -> R {
+        self.available.acquire();
+
+        let data = unsafe { &mut *self.data.get() };
+        let ret = f(data);
+
+        self.available.release();
+
+        ret
+    }
+}
@@ -0,0 +1,98 @@
+//! Wait queues.
+//!
+//! A wait queue blocks threads until a condition becomes true. Whoever changes the state behind
+//! the condition notifies the queue afterwards. This is the building block of the other blocking
+//! primitives, and it serves as a condition variable when the condition checks lock-protected data.
+
+use crate::{
+    synchronization::{interface::Mutex, IRQSafeLock},
+    thread::{self, ThreadId},
+};
+use alloc::vec::Vec;
+use core::hint;
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Threads waiting for a condition, in FIFO order.
+pub struct WaitQueue {
+    waiters: IRQSafeLock<Vec<ThreadId>>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl WaitQueue {
+    /// Create an instance.
+    pub const fn new() -> Self {
+        Self {
+            waiters: IRQSafeLock::new(Vec::new()),
+        }
+    }
+
+    /// Block until `condition` returns true.
+    ///
+    /// The condition is checked again after every wakeup, so it is fine to notify too often. It
+    /// runs without the queue's lock held and may take other locks.
+    ///
+    /// Outside of threads, e.g. on the secondary cores, this spins on the condition instead. It
+    /// must not be called from IRQ context.
+    pub fn wait_until(&self, mut condition: impl FnMut() -> bool) {
+        if condition() {
+            return;
+        }
+
+        if !thread::in_thread() {
+            while !condition() {
+                hint::spin_loop();
+            }
+            return;
+        }
+
+        let id = thread::current_id();
+        loop {
+            // Enqueue before checking, so that a notification between the check and `park()` is
+            // not lost. It leaves an unpark token behind instead.
+            self.waiters.lock(|w| {
+                if !w.contains(&id) {
+                    w.push(id);
+                }
+            });
+
+            if condition() {
+                break;
+            }
+
+            thread::park();
+        }
+
+        self.waiters.lock(|w| w.retain(|waiter| *waiter != id));
+    }
+
+    /// Wake up the longest waiting thread. Returns whether there was one.
+    ///
+    /// Can be called from any core and from IRQ context.
+    #[cfg(not(feature = "chainloader"))]
+    pub fn notify_one(&self) -> bool {
+        let waiter = self.waiters.lock(|w| (!w.is_empty()).then(|| w.remove(0)));
+
+        if let Some(id) = waiter {
+            thread::unpark(id);
+        }
+
+        waiter.is_some()
+    }
+
+    /// Wake up all waiting threads.
+    ///
+    /// Can be called from any core and from IRQ context.
+    pub fn notify_all(&self) {
+        let waiters = self.waiters.lock(core::mem::take);
+
+        for id in waiters {
+            thread::unpark(id);
+        }
+    }
+}
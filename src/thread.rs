@@ -0,0 +1,458 @@
+//! Preemptive kernel threads.
+//!
+//! Threads are scheduled round-robin on the boot core. A periodic timer tick ends the running
+//! thread's time slice, and the switch happens on the way out of the IRQ handler. Threads that
+//! sleep or park are taken off the ready queue until they are woken up. If no thread is ready, the
+//! idle thread waits for the next IRQ.
+//!
+//! `kernel_main()` becomes the first thread, `main`, and keeps running on the boot stack. Stacks of
+//! spawned threads are allocated from the heap. They have no guard page, so an overflow corrupts
+//! the heap instead of faulting.
+//!
+//! A thread can run with a user address space, which is switched together with the thread. See
+//! `set_user_space()` and the `process` module.
+//!
+//! # Note
+//!
+//! Only the boot core runs threads. The secondary cores are left to cross-core calls, see
+//! `cpu::smp::call_on()`. On them, and before the scheduler is started, `sleep()` spins and
+//! `yield_now()` returns right away.
+
+#[cfg(target_arch = "aarch64")]
+#[path = "_arch/aarch64/thread.rs"]
+mod arch_thread;
+
+use crate::{
+    bsp, cpu, exception,
+    exception::asynchronous::IRQContext,
+    memory::mmu::{self, UserSpaceToken},
+    synchronization::{interface::Mutex, IRQSafeLock},
+    time,
+};
+use alloc::{boxed::Box, vec::Vec};
+use core::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+#[cfg(not(feature = "chainloader"))]
+use alloc::vec;
+#[cfg(not(feature = "chainloader"))]
+use core::sync::atomic::AtomicUsize;
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+// The chainloader never leaves the boot thread.
+#[cfg(not(feature = "chainloader"))]
+const STACK_SIZE: usize = 32 * 1024;
+
+/// How long a thread runs before it is preempted.
+#[cfg(not(feature = "chainloader"))]
+const TIME_SLICE: Duration = Duration::from_millis(10);
+
+const IDLE_THREAD_ID: ThreadId = ThreadId(0);
+#[cfg(not(feature = "chainloader"))]
+const MAIN_THREAD_ID: ThreadId = ThreadId(1);
+
+#[cfg(not(feature = "chainloader"))]
+type ThreadEntry = Box<dyn FnOnce() + Send>;
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+enum State {
+    Ready,
+    Running,
+    Parked,
+    #[cfg(not(feature = "chainloader"))]
+    Finished,
+}
+
+struct Thread {
+    id: ThreadId,
+    #[cfg(not(feature = "chainloader"))]
+    name: &'static str,
+    state: State,
+    context: arch_thread::Context,
+
+    /// Set by `unpark()` if the thread was not parked at the time. The next `park()` consumes it
+    /// and returns right away, so that a wakeup is never lost.
+    unpark_token: bool,
+
+    /// Taken by the thread when it starts.
+    #[cfg(not(feature = "chainloader"))]
+    entry: Option<ThreadEntry>,
+
+    /// The user address space that is active while the thread runs, if any.
+    user_space: Option<UserSpaceToken>,
+
+    /// `None` for the main thread, which runs on the boot stack.
+    _stack: Option<Vec<u8>>,
+}
+
+/// Threads are boxed, so that their contexts stay in place while they move between the queues.
+#[allow(clippy::vec_box)]
+struct SchedulerInner {
+    current: Option<Box<Thread>>,
+
+    /// Runs when nothing else is ready. Never queued.
+    idle: Option<Box<Thread>>,
+
+    /// Round-robin order, next to run first.
+    ready: Vec<Box<Thread>>,
+    parked: Vec<Box<Thread>>,
+
+    /// Threads that have returned. They can't free their own stack, so whoever runs next does.
+    finished: Vec<Box<Thread>>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Identifies a thread.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct ThreadId(usize);
+
+//--------------------------------------------------------------------------------------------------
+// Global instances
+//--------------------------------------------------------------------------------------------------
+
+static SCHEDULER: IRQSafeLock<SchedulerInner> = IRQSafeLock::new(SchedulerInner::new());
+
+static SCHEDULER_STARTED: AtomicBool = AtomicBool::new(false);
+
+/// Set by the timer tick or a wakeup. Checked on the way out of the IRQ handler.
+static NEED_RESCHED: AtomicBool = AtomicBool::new(false);
+
+#[cfg(not(feature = "chainloader"))]
+static NEXT_THREAD_ID: AtomicUsize = AtomicUsize::new(MAIN_THREAD_ID.0 + 1);
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(not(feature = "chainloader"))]
+impl Thread {
+    fn new(id: ThreadId, name: &'static str, entry: ThreadEntry) -> Box<Self> {
+        let stack = vec![0; STACK_SIZE];
+        let context = arch_thread::Context::new(stack.as_ptr_range().end as usize, thread_start);
+
+        Box::new(Self {
+            id,
+            name,
+            state: State::Ready,
+            context,
+            unpark_token: false,
+            entry: Some(entry),
+            user_space: None,
+            _stack: Some(stack),
+        })
+    }
+}
+
+impl SchedulerInner {
+    const fn new() -> Self {
+        Self {
+            current: None,
+            idle: None,
+            ready: Vec::new(),
+            parked: Vec::new(),
+            finished: Vec::new(),
+        }
+    }
+
+    fn current(&mut self) -> &mut Thread {
+        // Always set once the scheduler is started.
+        self.current.as_mut().unwrap()
+    }
+
+    /// Put the current thread into `new_state` and pick the next one to run.
+    ///
+    /// Returns the contexts to switch between and the next thread's user address space, or `None`
+    /// if the current thread keeps running.
+    #[allow(clippy::type_complexity)]
+    fn switch(
+        &mut self,
+        new_state: State,
+    ) -> Option<(
+        *mut arch_thread::Context,
+        *const arch_thread::Context,
+        Option<UserSpaceToken>,
+    )> {
+        if new_state == State::Parked && self.current().unpark_token {
+            self.current().unpark_token = false;
+            return None;
+        }
+
+        let mut next = match (self.ready.is_empty(), new_state) {
+            (false, _) => self.ready.remove(0),
+            (true, State::Ready) => return None,
+            // The idle thread only ever yields, so it is not the one running here.
+            (true, _) => self.idle.take().unwrap(),
+        };
+
+        let mut prev = self.current.take().unwrap();
+        let from = &mut prev.context as *mut _;
+        prev.state = new_state;
+
+        match new_state {
+            State::Ready if prev.id == IDLE_THREAD_ID => self.idle = Some(prev),
+            State::Ready => self.ready.push(prev),
+            State::Parked => self.parked.push(prev),
+            #[cfg(not(feature = "chainloader"))]
+            State::Finished => self.finished.push(prev),
+            State::Running => unreachable!(),
+        }
+
+        next.state = State::Running;
+        let to = &next.context as *const _;
+        let user_space = next.user_space;
+        self.current = Some(next);
+
+        Some((from, to, user_space))
+    }
+
+    fn unpark(&mut self, id: ThreadId) {
+        if let Some(index) = self.parked.iter().position(|t| t.id == id) {
+            let mut thread = self.parked.swap_remove(index);
+            thread.state = State::Ready;
+            self.ready.push(thread);
+
+            if self.current().id == IDLE_THREAD_ID {
+                NEED_RESCHED.store(true, Ordering::Relaxed);
+            }
+            return;
+        }
+
+        let thread = self
+            .current
+            .iter_mut()
+            .chain(self.ready.iter_mut())
+            .find(|t| t.id == id);
+
+        if let Some(thread) = thread {
+            thread.unpark_token = true;
+        }
+    }
+}
+
+/// Switch away from the current thread, which changes to `new_state`.
+///
+/// Returns when the thread is switched back in, or right away if it keeps running.
+fn schedule(new_state: State) {
+    exception::asynchronous::exec_with_irq_masked(|| {
+        if let Some((from, to, user_space)) = SCHEDULER.lock(|s| s.switch(new_state)) {
+            // IRQs stay masked until the switch is done, so nothing else runs on this core in
+            // between. The contexts live in boxed threads, which don't move. The owner of a user
+            // address space clears it from its thread before it is dropped.
+            unsafe {
+                mmu::activate_user_space(user_space);
+                arch_thread::switch_context(from, to);
+            }
+        }
+
+        free_finished_threads();
+    })
+}
+
+fn free_finished_threads() {
+    let finished = SCHEDULER.lock(|s| core::mem::take(&mut s.finished));
+
+    drop(finished);
+}
+
+/// Where spawned threads begin. Entered from `switch_context()` with IRQs masked.
+#[cfg(not(feature = "chainloader"))]
+extern "C" fn thread_start() -> ! {
+    free_finished_threads();
+
+    // Cannot fail, every spawned thread has an entry.
+    let entry = SCHEDULER.lock(|s| s.current().entry.take().unwrap());
+
+    exception::asynchronous::local_irq_unmask();
+    entry();
+
+    exit()
+}
+
+#[cfg(not(feature = "chainloader"))]
+fn idle() {
+    loop {
+        arch_thread::wait_for_interrupt();
+        yield_now();
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Turn the running code into the `main` thread and start preempting.
+///
+/// # Safety
+///
+/// - Must be called once, on the boot core, after the heap and the timer are up.
+#[cfg(not(feature = "chainloader"))]
+pub unsafe fn start_scheduler() {
+    let main = Box::new(Thread {
+        id: MAIN_THREAD_ID,
+        name: "main",
+        state: State::Running,
+        context: arch_thread::Context::empty(),
+        unpark_token: false,
+        entry: None,
+        user_space: None,
+        _stack: None,
+    });
+    let idle = Thread::new(IDLE_THREAD_ID, "idle", Box::new(idle));
+
+    SCHEDULER.lock(|s| {
+        s.current = Some(main);
+        s.idle = Some(idle);
+    });
+    SCHEDULER_STARTED.store(true, Ordering::Release);
+
+    time::time_manager().set_timeout_periodic(TIME_SLICE, || {
+        NEED_RESCHED.store(true, Ordering::Relaxed);
+    });
+}
+
+/// Start a new thread that runs `f`. It is scheduled after all threads that are already ready.
+///
+/// The thread ends when `f` returns.
+#[cfg(not(feature = "chainloader"))]
+pub fn spawn(name: &'static str, f: impl FnOnce() + Send + 'static) -> ThreadId {
+    let id = ThreadId(NEXT_THREAD_ID.fetch_add(1, Ordering::Relaxed));
+    let thread = Thread::new(id, name, Box::new(f));
+
+    SCHEDULER.lock(|s| s.ready.push(thread));
+
+    id
+}
+
+/// End the running thread.
+///
+/// Nothing on its stack is dropped, so the caller must release everything it owns first.
+#[cfg(not(feature = "chainloader"))]
+pub fn exit() -> ! {
+    assert!(in_thread(), "Not running in a thread");
+
+    schedule(State::Finished);
+    unreachable!()
+}
+
+/// Run the current thread in the given user address space, or only with the kernel's mappings.
+/// Takes effect right away and whenever the thread is switched in.
+///
+/// # Safety
+///
+/// - The address space must stay alive until it is replaced by another call.
+#[cfg(not(feature = "chainloader"))]
+pub unsafe fn set_user_space(user_space: Option<UserSpaceToken>) {
+    SCHEDULER.lock(|s| {
+        s.current().user_space = user_space;
+        mmu::activate_user_space(user_space);
+    })
+}
+
+/// Whether the executing code runs in a thread, i.e. whether it may block.
+///
+/// IRQ handlers run on the stack of the interrupted thread, but are not part of it.
+pub fn in_thread() -> bool {
+    SCHEDULER_STARTED.load(Ordering::Acquire)
+        && cpu::smp::core_id::<usize>() == bsp::cpu::BOOT_CORE_ID as usize
+        && !exception::asynchronous::is_in_irq()
+}
+
+/// The id of the running thread.
+///
+/// Panics if not called from a thread.
+pub fn current_id() -> ThreadId {
+    assert!(in_thread(), "Not running in a thread");
+
+    SCHEDULER.lock(|s| s.current().id)
+}
+
+/// Give the rest of the time slice to the next ready thread.
+#[cfg(not(feature = "chainloader"))]
+pub fn yield_now() {
+    if !in_thread() {
+        return;
+    }
+
+    schedule(State::Ready)
+}
+
+/// Block until another thread or an IRQ handler calls `unpark()` for this thread.
+///
+/// If `unpark()` was called since the last `park()`, this returns right away. Callers must expect
+/// to be woken up early and check their condition again.
+pub fn park() {
+    assert!(
+        !exception::asynchronous::is_in_irq(),
+        "park() called from IRQ context"
+    );
+    assert!(in_thread(), "Not running in a thread");
+
+    schedule(State::Parked)
+}
+
+/// Wake up a parked thread. Can be called from any core and from IRQ context.
+pub fn unpark(id: ThreadId) {
+    SCHEDULER.lock(|s| s.unpark(id))
+}
+
+/// Block the running thread for at least `duration`.
+pub fn sleep(duration: Duration) {
+    if !in_thread() {
+        time::time_manager().spin_for(duration);
+        return;
+    }
+
+    let id = current_id();
+    let deadline = time::time_manager().uptime() + duration;
+
+    time::time_manager().set_timeout(duration, move || unpark(id));
+
+    while time::time_manager().uptime() < deadline {
+        park();
+    }
+}
+
+/// Switch threads if the time slice is up. Called on the way out of the IRQ handler.
+pub fn preempt_on_irq_exit(_ic: &IRQContext) {
+    if !in_thread() || !NEED_RESCHED.swap(false, Ordering::Relaxed) {
+        return;
+    }
+
+    schedule(State::Ready)
+}
+
+/// Print all threads.
+#[cfg(not(feature = "chainloader"))]
+pub fn print_threads() {
+    use crate::info;
+
+    SCHEDULER.lock(|s| {
+        let threads = s
+            .current
+            .iter()
+            .chain(s.ready.iter())
+            .chain(s.parked.iter());
+
+        for thread in threads {
+            let state = match thread.state {
+                State::Ready => "ready",
+                State::Running => "running",
+                State::Parked => "parked",
+                State::Finished => "finished",
+            };
+
+            info!(
+                "      {: >3} | {: <10} | {}",
+                thread.id.0, thread.name, state
+            );
+        }
+    })
+}
@@ -8,14 +8,92 @@
 #[path = "_arch/aarch64/time.rs"]
 mod arch_time;
 
-use core::time::Duration;
+pub mod calendar;
+#[cfg(not(feature = "chainloader"))]
+mod delay;
+mod wall_clock;
+
+#[cfg(not(feature = "chainloader"))]
+pub use delay::{delay, Delay};
+pub use wall_clock::{wall_clock, TimeSource};
+
+use crate::{
+    bsp, cpu, driver, exception,
+    exception::asynchronous::{IRQHandlerDescriptor, IRQNumber},
+    synchronization,
+    synchronization::{IRQSafeLock, InitStateLock},
+    warn,
+};
+use alloc::{boxed::Box, vec::Vec};
+use core::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+struct Timeout {
+    due_time: Duration,
+    period: Option<Duration>,
+    callback: TimeoutHandler,
+}
+
+/// Timeouts ordered by due time, latest first, so that the next one to expire is at the end.
+struct OrderedTimeoutQueue {
+    queue: Vec<Timeout>,
+}
 
 //--------------------------------------------------------------------------------------------------
 // Public Definitions
 //--------------------------------------------------------------------------------------------------
 
+/// Time interfaces.
+pub mod interface {
+    use core::time::Duration;
+
+    /// A free-running counter that serves as the kernel's time base.
+    pub trait ClockSource {
+        /// Descriptive name.
+        fn name(&self) -> &'static str;
+
+        /// The counter's resolution.
+        fn resolution(&self) -> Duration;
+
+        /// The time since the counter started, usually at power-on.
+        fn uptime(&self) -> Duration;
+
+        /// Spin for a given duration.
+        fn spin_for(&self, duration: Duration) {
+            let target = self.uptime() + duration;
+
+            while self.uptime() < target {}
+        }
+    }
+
+    /// A timer that can raise an IRQ, which must end up in [`super::TimeManager::handle_alarm`].
+    pub trait Alarm {
+        /// Raise the IRQ after `delay`. Replaces a previously set alarm.
+        ///
+        /// Firing early is allowed, e.g. if the hardware can not represent the delay.
+        fn set_alarm(&self, delay: Duration);
+
+        /// Acknowledge the IRQ and disarm the alarm.
+        fn conclude_alarm(&self);
+    }
+}
+
+/// Callback invoked from IRQ context when a timeout expires.
+pub type TimeoutHandler = Box<dyn Fn() + Send>;
+
 /// Provides time management functions.
-pub struct TimeManager;
+pub struct TimeManager {
+    clock_source: InitStateLock<&'static (dyn interface::ClockSource + Sync)>,
+    alarm: InitStateLock<&'static (dyn interface::Alarm + Sync)>,
+    queue: IRQSafeLock<OrderedTimeoutQueue>,
+    timeout_set: AtomicBool,
+}
 
 //--------------------------------------------------------------------------------------------------
 // Global instances
@@ -23,6 +101,83 @@ pub struct TimeManager;
 
 static TIME_MANAGER: TimeManager = TimeManager::new();
 
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+impl OrderedTimeoutQueue {
+    pub const fn new() -> Self {
+        Self { queue: Vec::new() }
+    }
+
+    pub fn push(&mut self, timeout: Timeout) {
+        // Insert behind all timeouts that are due later, so that equal due times keep FIFO order.
+        let index = self
+            .queue
+            .partition_point(|t| t.due_time > timeout.due_time);
+
+        self.queue.insert(index, timeout);
+    }
+
+    pub fn next_due_time(&self) -> Option<Duration> {
+        self.queue.last().map(|t| t.due_time)
+    }
+
+    /// Remove the next timeout if it is due at `now`.
+    pub fn pop_expired(&mut self, now: Duration) -> Option<Timeout> {
+        match self.next_due_time() {
+            Some(due_time) if due_time <= now => self.queue.pop(),
+            _ => None,
+        }
+    }
+}
+
+impl TimeManager {
+    fn clock_source(&self) -> &'static (dyn interface::ClockSource + Sync) {
+        self.clock_source.read(|cs| *cs)
+    }
+
+    fn alarm(&self) -> &'static (dyn interface::Alarm + Sync) {
+        self.alarm.read(|alarm| *alarm)
+    }
+
+    /// Program the alarm for the next timeout in the queue, if any.
+    fn rearm(&self, queue: &OrderedTimeoutQueue) {
+        if let Some(due_time) = queue.next_due_time() {
+            self.alarm()
+                .set_alarm(due_time.saturating_sub(self.uptime()));
+        }
+    }
+
+    /// Queue a timeout and reprogram the alarm in case it became the next one to expire.
+    ///
+    /// The alarm IRQ is only routed to the boot core, so the alarm is always programmed there.
+    fn push_timeout(&self, timeout: Timeout) {
+        let boot_core_id = bsp::cpu::BOOT_CORE_ID as usize;
+        let rearm = || self.queue.lock(|queue| self.rearm(queue));
+
+        self.queue.lock(|queue| queue.push(timeout));
+
+        if cpu::smp::core_id::<usize>() == boot_core_id {
+            rearm();
+        } else if let Err(x) = cpu::smp::call_on(boot_core_id, &rearm) {
+            warn!("Timeout: Could not program the alarm: {}", x);
+        }
+    }
+
+    fn add_timeout(&self, delay: Duration, period: Option<Duration>, callback: TimeoutHandler) {
+        self.timeout_set.store(true, Ordering::Relaxed);
+
+        let timeout = Timeout {
+            due_time: self.uptime() + delay,
+            period,
+            callback,
+        };
+
+        self.push_timeout(timeout);
+    }
+}
+
 //--------------------------------------------------------------------------------------------------
 // Public Code
 //--------------------------------------------------------------------------------------------------
@@ -33,25 +188,188 @@ pub fn time_manager() -> &'static TimeManager {
 }
 
 impl TimeManager {
+    pub const COMPATIBLE: &'static str = "ARM Architectural Timer";
+
     /// Create an instance.
     pub const fn new() -> Self {
-        Self
+        Self {
+            clock_source: InitStateLock::new(&arch_time::GenericTimer),
+            alarm: InitStateLock::new(&arch_time::GenericTimer),
+            queue: IRQSafeLock::new(OrderedTimeoutQueue::new()),
+            timeout_set: AtomicBool::new(false),
+        }
+    }
+
+    /// Replace the architectural timer as time base.
+    ///
+    /// Must happen before any timeout is set, because due times are relative to the clock source.
+    #[cfg(not(feature = "chainloader"))]
+    pub fn register_clock_source(
+        &self,
+        clock_source: &'static (dyn interface::ClockSource + Sync),
+    ) -> Result<(), &'static str> {
+        if self.timeout_set.load(Ordering::Relaxed) {
+            return Err("Clock source must be registered before any timeout is set");
+        }
+
+        self.clock_source.write(|cs| *cs = clock_source);
+
+        Ok(())
+    }
+
+    /// Replace the architectural timer as alarm backend.
+    #[cfg(not(feature = "chainloader"))]
+    pub fn register_alarm(&self, alarm: &'static (dyn interface::Alarm + Sync)) {
+        self.alarm().conclude_alarm();
+        self.alarm.write(|a| *a = alarm);
+    }
+
+    /// Name of the clock source in use.
+    #[cfg(not(feature = "chainloader"))]
+    pub fn clock_source_name(&self) -> &'static str {
+        self.clock_source().name()
     }
 
     /// The timer's resolution.
+    #[cfg(not(feature = "chainloader"))]
     pub fn resolution(&self) -> Duration {
-        arch_time::resolution()
+        self.clock_source().resolution()
     }
 
     /// The uptime since power-on of the device.
     ///
     /// This includes time consumed by firmware and bootloaders.
     pub fn uptime(&self) -> Duration {
-        arch_time::uptime()
+        self.clock_source().uptime()
     }
 
     /// Spin for a given duration.
     pub fn spin_for(&self, duration: Duration) {
-        arch_time::spin_for(duration)
+        self.clock_source().spin_for(duration)
+    }
+
+    /// Measure `candidate` against the architectural timer.
+    ///
+    /// Fails if the two disagree by more than 1% over a 10 ms window, or if the architectural timer
+    /// has no frequency to measure with.
+    #[cfg(not(feature = "chainloader"))]
+    pub fn cross_check(&self, candidate: &dyn interface::ClockSource) -> Result<(), &'static str> {
+        use crate::info;
+
+        const WINDOW: Duration = Duration::from_millis(10);
+
+        if !arch_time::has_frequency() {
+            return Err("Architectural timer has no frequency, skipping the cross-check");
+        }
+
+        let reference = &arch_time::GenericTimer as &dyn interface::ClockSource;
+
+        let (ref_start, cand_start) = (reference.uptime(), candidate.uptime());
+        reference.spin_for(WINDOW);
+        let (ref_end, cand_end) = (reference.uptime(), candidate.uptime());
+
+        let ref_elapsed = (ref_end - ref_start).as_nanos();
+        let cand_elapsed = cand_end.saturating_sub(cand_start).as_nanos();
+
+        info!(
+            "{}: {} us against {} us of the {}",
+            candidate.name(),
+            cand_elapsed / 1000,
+            ref_elapsed / 1000,
+            reference.name()
+        );
+
+        if cand_elapsed.abs_diff(ref_elapsed) * 100 > ref_elapsed {
+            return Err("Clock source deviates from the architectural timer");
+        }
+
+        Ok(())
+    }
+
+    /// Call `callback` once from IRQ context after `delay` has passed.
+    pub fn set_timeout(&self, delay: Duration, callback: impl Fn() + Send + 'static) {
+        self.add_timeout(delay, None, Box::new(callback));
+    }
+
+    /// Call `callback` from IRQ context every `period`, starting one `period` from now.
+    #[cfg(not(feature = "chainloader"))]
+    pub fn set_timeout_periodic(&self, period: Duration, callback: impl Fn() + Send + 'static) {
+        if period < self.resolution() {
+            warn!("set_timeout_periodic: Period smaller than timer resolution. Skipping");
+            return;
+        }
+
+        self.add_timeout(period, Some(period), Box::new(callback));
+    }
+}
+
+//------------------------------------------------------------------------------
+// OS Interface Code
+//------------------------------------------------------------------------------
+use interface::Alarm;
+use synchronization::interface::{Mutex, ReadWriteEx};
+
+impl driver::interface::DeviceDriver for TimeManager {
+    type IRQNumberType = IRQNumber;
+
+    fn compatible(&self) -> &'static str {
+        Self::COMPATIBLE
+    }
+
+    unsafe fn init(&self) -> Result<(), &'static str> {
+        arch_time::GenericTimer.conclude_alarm();
+
+        Ok(())
+    }
+
+    fn register_and_enable_irq_handler(
+        &'static self,
+        irq_number: &Self::IRQNumberType,
+    ) -> Result<(), &'static str> {
+        use exception::asynchronous::irq_manager;
+
+        let descriptor = IRQHandlerDescriptor::new(*irq_number, Self::COMPATIBLE, self);
+
+        irq_manager().register_handler(descriptor)?;
+        irq_manager().enable(irq_number);
+
+        Ok(())
+    }
+}
+
+impl TimeManager {
+    /// Run all expired timeouts and program the alarm for the next one.
+    ///
+    /// To be called from the IRQ handler of the registered [`interface::Alarm`].
+    pub fn handle_alarm(&self) {
+        self.alarm().conclude_alarm();
+
+        let now = self.uptime();
+
+        // Callbacks run without the queue lock held, so they are free to set new timeouts.
+        while let Some(mut timeout) = self.queue.lock(|queue| queue.pop_expired(now)) {
+            (timeout.callback)();
+
+            if let Some(period) = timeout.period {
+                timeout.due_time += period;
+
+                // Don't try to catch up on periods that were missed.
+                if timeout.due_time <= now {
+                    timeout.due_time = now + period;
+                }
+
+                self.queue.lock(|queue| queue.push(timeout));
+            }
+        }
+
+        self.queue.lock(|queue| self.rearm(queue));
+    }
+}
+
+impl exception::asynchronous::interface::IRQHandler for TimeManager {
+    fn handle(&self) -> Result<(), &'static str> {
+        self.handle_alarm();
+
+        Ok(())
     }
 }
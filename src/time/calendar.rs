@@ -0,0 +1,222 @@
+//! Calendar dates in UTC.
+//!
+//! Conversion between Unix time and the proleptic Gregorian calendar, based on the `civil`
+//! algorithms from <https://howardhinnant.github.io/date_algorithms.html>. Leap seconds are ignored,
+//! just like Unix time does.
+
+use core::{fmt, str::FromStr, time::Duration};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Days from 0000-03-01 to 1970-01-01.
+const UNIX_EPOCH_DAYS: u64 = 719_468;
+
+const DAYS_PER_ERA: u64 = 146_097;
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// A point in time, broken down into calendar date and time of day.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DateTime {
+    pub year: u32,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    pub nanosecond: u32,
+}
+
+/// Day of the week.
+#[allow(missing_docs)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg(not(feature = "chainloader"))]
+pub enum Weekday {
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+fn is_leap_year(year: u32) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+fn days_in_month(year: u32, month: u8) -> u8 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Convert days since the Unix epoch to (year, month, day).
+fn civil_from_days(days: u64) -> (u32, u8, u8) {
+    let z = days + UNIX_EPOCH_DAYS;
+    let era = z / DAYS_PER_ERA;
+    let doe = z % DAYS_PER_ERA;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+
+    (year as u32, month as u8, day as u8)
+}
+
+/// Convert a date to days since the Unix epoch. The date must not be before 1970-01-01.
+#[cfg(not(feature = "chainloader"))]
+fn days_from_civil(year: u32, month: u8, day: u8) -> u64 {
+    let year = u64::from(year) - u64::from(month <= 2);
+    let era = year / 400;
+    let yoe = year % 400;
+    let mp = (u64::from(month) + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + u64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+
+    era * DAYS_PER_ERA + doe - UNIX_EPOCH_DAYS
+}
+
+/// Parse a fixed-width decimal number.
+fn parse_digits(s: &str) -> Result<u32, &'static str> {
+    if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return Err("Expected digits");
+    }
+
+    s.parse().map_err(|_| "Number too large")
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl DateTime {
+    /// Break down a Unix timestamp.
+    pub fn from_unix(unix_time: Duration) -> Self {
+        let secs = unix_time.as_secs();
+        let (year, month, day) = civil_from_days(secs / SECS_PER_DAY);
+        let secs_of_day = secs % SECS_PER_DAY;
+
+        Self {
+            year,
+            month,
+            day,
+            hour: (secs_of_day / 3600) as u8,
+            minute: (secs_of_day / 60 % 60) as u8,
+            second: (secs_of_day % 60) as u8,
+            nanosecond: unix_time.subsec_nanos(),
+        }
+    }
+
+    /// The Unix timestamp of this point in time.
+    #[cfg(not(feature = "chainloader"))]
+    pub fn to_unix(self) -> Duration {
+        let days = days_from_civil(self.year, self.month, self.day);
+        let secs = days * SECS_PER_DAY
+            + u64::from(self.hour) * 3600
+            + u64::from(self.minute) * 60
+            + u64::from(self.second);
+
+        Duration::new(secs, self.nanosecond)
+    }
+
+    /// Day of the week.
+    #[cfg(not(feature = "chainloader"))]
+    pub fn weekday(&self) -> Weekday {
+        // 1970-01-01 was a Thursday.
+        match (days_from_civil(self.year, self.month, self.day) + 3) % 7 {
+            0 => Weekday::Monday,
+            1 => Weekday::Tuesday,
+            2 => Weekday::Wednesday,
+            3 => Weekday::Thursday,
+            4 => Weekday::Friday,
+            5 => Weekday::Saturday,
+            _ => Weekday::Sunday,
+        }
+    }
+}
+
+/// Accepts `YYYY-MM-DD HH:MM:SS` or `YYYY-MM-DDTHH:MM:SS`, optionally followed by `Z`, or a Unix
+/// timestamp in seconds prefixed with `@`.
+impl FromStr for DateTime {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+
+        if let Some(secs) = s.strip_prefix('@') {
+            let secs: u64 = secs.parse().map_err(|_| "Invalid Unix timestamp")?;
+            return Ok(Self::from_unix(Duration::from_secs(secs)));
+        }
+
+        let s = s.strip_suffix('Z').unwrap_or(s);
+        if s.len() != 19 || !s.is_char_boundary(10) || !s.is_char_boundary(11) {
+            return Err("Expected YYYY-MM-DD HH:MM:SS");
+        }
+
+        let (date, time) = (&s[..10], &s[11..]);
+        if !matches!(&s[10..11], " " | "T") {
+            return Err("Expected YYYY-MM-DD HH:MM:SS");
+        }
+
+        let mut date_fields = date.split('-');
+        let mut time_fields = time.split(':');
+        let next = |fields: &mut core::str::Split<'_, char>, width: usize| match fields.next() {
+            Some(field) if field.len() == width => parse_digits(field),
+            _ => Err("Expected YYYY-MM-DD HH:MM:SS"),
+        };
+
+        let year = next(&mut date_fields, 4)?;
+        let month = next(&mut date_fields, 2)? as u8;
+        let day = next(&mut date_fields, 2)? as u8;
+        let hour = next(&mut time_fields, 2)? as u8;
+        let minute = next(&mut time_fields, 2)? as u8;
+        let second = next(&mut time_fields, 2)? as u8;
+
+        if year < 1970 {
+            return Err("Dates before 1970 are not supported");
+        }
+        if !(1..=12).contains(&month) || day == 0 || day > days_in_month(year, month) {
+            return Err("Invalid date");
+        }
+        if hour > 23 || minute > 59 || second > 59 {
+            return Err("Invalid time of day");
+        }
+
+        Ok(Self {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+            nanosecond: 0,
+        })
+    }
+}
+
+/// ISO 8601, e.g. `2022-10-13T09:41:07Z`.
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
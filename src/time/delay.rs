@@ -0,0 +1,62 @@
+//! Timer futures.
+
+use super::time_manager;
+use crate::executor::WakerSlot;
+use alloc::sync::Arc;
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// A future that completes once a point in time has passed.
+pub struct Delay {
+    deadline: Duration,
+
+    /// Shared with the timeout callback. `None` until the timeout is set on the first poll.
+    waker: Option<Arc<WakerSlot>>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Complete after at least `duration`, counted from now.
+pub fn delay(duration: Duration) -> Delay {
+    Delay {
+        deadline: time_manager().uptime() + duration,
+        waker: None,
+    }
+}
+
+impl Future for Delay {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let now = time_manager().uptime();
+        if now >= self.deadline {
+            return Poll::Ready(());
+        }
+
+        match &self.waker {
+            // The task might have handed out a new waker since the last poll.
+            Some(slot) => slot.register(cx.waker()),
+            None => {
+                let slot = Arc::new(WakerSlot::new());
+                slot.register(cx.waker());
+
+                let callback_slot = slot.clone();
+                time_manager().set_timeout(self.deadline - now, move || callback_slot.wake());
+
+                self.waker = Some(slot);
+            }
+        }
+
+        Poll::Pending
+    }
+}
@@ -0,0 +1,155 @@
+//! Wall-clock time.
+//!
+//! The Pi has no RTC, so the wall clock is the monotonic uptime plus an offset that is established
+//! from an external source.
+
+use super::{calendar::DateTime, time_manager};
+use crate::{
+    info,
+    synchronization::{interface::Mutex, IRQSafeLock},
+};
+use core::{fmt, time::Duration};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+#[derive(Copy, Clone)]
+struct WallClockInner {
+    /// Unix time at uptime zero.
+    offset: Duration,
+    #[cfg(not(feature = "chainloader"))]
+    source: TimeSource,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Where the wall-clock time came from, from least to most trustworthy.
+///
+/// The chainloader only learns the time from the host.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TimeSource {
+    /// The time the kernel was built. Only a lower bound for the actual time.
+    #[cfg(not(feature = "chainloader"))]
+    BuildTimestamp,
+
+    /// Entered by hand on the console, see the shell's `date`.
+    #[cfg(not(feature = "chainloader"))]
+    Console,
+
+    /// Provided by the host, e.g. during the chainloader handshake.
+    Host,
+}
+
+/// Realtime clock derived from the uptime.
+pub struct WallClock {
+    inner: IRQSafeLock<Option<WallClockInner>>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Global instances
+//--------------------------------------------------------------------------------------------------
+
+static WALL_CLOCK: WallClock = WallClock::new();
+
+/// Unix time of the build, see `build.rs`.
+#[cfg(not(feature = "chainloader"))]
+const BUILD_UNIX_TIME: &str = env!("RPOS_BUILD_UNIX_TIME");
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Return a reference to the global WallClock.
+pub fn wall_clock() -> &'static WallClock {
+    &WALL_CLOCK
+}
+
+impl WallClock {
+    /// Create an instance.
+    pub const fn new() -> Self {
+        Self {
+            inner: IRQSafeLock::new(None),
+        }
+    }
+
+    /// Set the current Unix time.
+    pub fn set(&self, unix_time: Duration, source: TimeSource) -> Result<(), &'static str> {
+        let offset = unix_time
+            .checked_sub(time_manager().uptime())
+            .ok_or("Wall-clock time lies before power-on")?;
+
+        self.inner.lock(|inner| {
+            *inner = Some(WallClockInner {
+                offset,
+                #[cfg(not(feature = "chainloader"))]
+                source,
+            })
+        });
+
+        info!(
+            "Wall clock set to {} ({})",
+            DateTime::from_unix(unix_time),
+            source
+        );
+
+        Ok(())
+    }
+
+    /// Set the current time from a calendar date.
+    #[cfg(not(feature = "chainloader"))]
+    pub fn set_date_time(
+        &self,
+        date_time: &DateTime,
+        source: TimeSource,
+    ) -> Result<(), &'static str> {
+        self.set(date_time.to_unix(), source)
+    }
+
+    /// Fall back to the build timestamp if no other source has set the clock yet.
+    #[cfg(not(feature = "chainloader"))]
+    pub fn init_from_build_timestamp(&self) -> Result<(), &'static str> {
+        if self.source().is_some() {
+            return Ok(());
+        }
+
+        let secs: u64 = BUILD_UNIX_TIME
+            .parse()
+            .map_err(|_| "Invalid build timestamp")?;
+
+        self.set(Duration::from_secs(secs), TimeSource::BuildTimestamp)
+    }
+
+    /// Where the current time came from. `None` if the clock was never set.
+    #[cfg(not(feature = "chainloader"))]
+    pub fn source(&self) -> Option<TimeSource> {
+        self.inner.lock(|inner| inner.map(|i| i.source))
+    }
+
+    /// The current Unix time, if the clock was set.
+    pub fn unix_time(&self) -> Option<Duration> {
+        self.inner
+            .lock(|inner| inner.map(|i| i.offset + time_manager().uptime()))
+    }
+
+    /// The current date and time in UTC, if the clock was set.
+    pub fn now(&self) -> Option<DateTime> {
+        self.unix_time().map(DateTime::from_unix)
+    }
+}
+
+impl fmt::Display for TimeSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            #[cfg(not(feature = "chainloader"))]
+            Self::BuildTimestamp => "build timestamp",
+            #[cfg(not(feature = "chainloader"))]
+            Self::Console => "console",
+            Self::Host => "host",
+        };
+
+        write!(f, "{}", name)
+    }
+}
@@ -0,0 +1,115 @@
+//! A user program that prints its arguments and environment.
+//!
+//! Exits with the number of arguments. Built by build.rs and embedded into the kernel, see
+//! `process::embedded_programs()`.
+
+#![no_std]
+#![no_main]
+
+use core::{
+    arch::asm,
+    panic::PanicInfo,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Syscall numbers, see the kernel's `process` module.
+const SYS_EXIT: u64 = 0;
+const SYS_WRITE: u64 = 1;
+
+/// Bytes written so far. Lives in .bss.
+static BYTES_WRITTEN: AtomicUsize = AtomicUsize::new(0);
+
+fn syscall(number: u64, arg0: u64, arg1: u64) -> i64 {
+    let ret: i64;
+
+    unsafe {
+        asm!(
+            "svc #0",
+            inlateout("x0") arg0 => ret,
+            in("x1") arg1,
+            in("x8") number,
+            options(nostack)
+        );
+    }
+
+    ret
+}
+
+fn exit(code: i64) -> ! {
+    syscall(SYS_EXIT, code as u64, 0);
+    unreachable!()
+}
+
+fn write(bytes: &[u8]) {
+    let written = syscall(SYS_WRITE, bytes.as_ptr() as u64, bytes.len() as u64);
+
+    if written > 0 {
+        BYTES_WRITTEN.fetch_add(written as usize, Ordering::Relaxed);
+    }
+}
+
+fn write_number(mut n: usize) {
+    let mut digits = [0; 20];
+    let mut i = digits.len();
+
+    loop {
+        i -= 1;
+        digits[i] = b'0' + (n % 10) as u8;
+        n /= 10;
+
+        if n == 0 {
+            break;
+        }
+    }
+
+    write(&digits[i..]);
+}
+
+/// A NUL terminated string from the stack.
+unsafe fn c_str<'a>(s: *const u8) -> &'a [u8] {
+    let mut len = 0;
+    while *s.add(len) != 0 {
+        len += 1;
+    }
+
+    core::slice::from_raw_parts(s, len)
+}
+
+/// Print the strings of a NULL terminated pointer array, one per line.
+unsafe fn write_list(prefix: &[u8], mut list: *const *const u8) {
+    let mut i = 0;
+    while !(*list).is_null() {
+        write(prefix);
+        write_number(i);
+        write(b"] = ");
+        write(c_str(*list));
+        write(b"\n");
+
+        list = list.add(1);
+        i += 1;
+    }
+}
+
+#[no_mangle]
+#[link_section = ".text._start"]
+extern "C" fn _start(argc: usize, argv: *const *const u8, envp: *const *const u8) -> ! {
+    write(b"args: argc = ");
+    write_number(argc);
+    write(b"\n");
+
+    unsafe {
+        write_list(b"args: argv[", argv);
+        write_list(b"args: envp[", envp);
+    }
+
+    write(b"args: wrote ");
+    write_number(BYTES_WRITTEN.load(Ordering::Relaxed));
+    write(b" bytes before this line\n");
+
+    exit(argc as i64)
+}
+
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    exit(-1)
+}